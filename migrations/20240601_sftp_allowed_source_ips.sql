@@ -0,0 +1,26 @@
+-- Optional allow-list of source IPs/CIDR ranges checked by the identity provider lookup.
+-- NULL means connections are accepted from any address.
+ALTER TABLE sftp ADD COLUMN allowed_source_ips TEXT[];
+
+-- Transfer Family looks users up by name, so usernames must be unique. Duplicates can't
+-- be merged safely, so the migration stops and lists them; rename them and run it again.
+DO $$
+DECLARE
+    duplicates TEXT;
+BEGIN
+    SELECT string_agg(username || ' (ids ' || ids || ')', ', ')
+    INTO duplicates
+    FROM (
+        SELECT username, string_agg(id::TEXT, ', ' ORDER BY id) AS ids
+        FROM sftp
+        GROUP BY username
+        HAVING count(*) > 1
+    ) d;
+
+    IF duplicates IS NOT NULL THEN
+        RAISE EXCEPTION 'duplicate SFTP usernames: %', duplicates;
+    END IF;
+END
+$$;
+
+CREATE UNIQUE INDEX sftp_username_key ON sftp (username);
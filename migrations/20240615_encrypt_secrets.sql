@@ -0,0 +1,12 @@
+-- Secrets are now stored as envelope encrypted bytes (see utils::crypto).
+-- Existing values are carried over as plain UTF-8 bytes with a NULL key_id,
+-- and are encrypted in place by the `reencrypt` binary.
+ALTER TABLE vendors
+    ALTER COLUMN password TYPE BYTEA USING convert_to(password, 'UTF8'),
+    ALTER COLUMN ssh_key TYPE BYTEA USING convert_to(ssh_key, 'UTF8'),
+    ALTER COLUMN ssh_key_password TYPE BYTEA USING convert_to(ssh_key_password, 'UTF8'),
+    ADD COLUMN key_id TEXT;
+
+ALTER TABLE sftp
+    ALTER COLUMN private_key TYPE BYTEA USING convert_to(private_key, 'UTF8'),
+    ADD COLUMN key_id TEXT;
@@ -0,0 +1,2 @@
+-- Keys issued to an agent only give access to the clients linked to that agent.
+ALTER TABLE api_keys ADD COLUMN agent_id BIGINT REFERENCES agents(id) ON DELETE CASCADE;
@@ -0,0 +1,15 @@
+-- Every mutation through the API writes one row here in the same transaction.
+-- `diff` maps each changed field to its before/after values, with secrets redacted.
+CREATE TABLE audit_events (
+    id BIGSERIAL PRIMARY KEY,
+    occurred_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    actor TEXT NOT NULL,
+    actor_key_id BIGINT,
+    action TEXT NOT NULL,
+    entity_type TEXT NOT NULL,
+    entity_id BIGINT NOT NULL,
+    diff JSONB NOT NULL
+);
+
+CREATE INDEX audit_events_entity_idx ON audit_events (entity_type, entity_id, id);
+CREATE INDEX audit_events_occurred_at_idx ON audit_events (occurred_at);
@@ -0,0 +1,4 @@
+-- The algorithm of each account's key pair (see utils::ssh::KeyType).
+-- Accounts created before this column existed all have 2048-bit RSA keys.
+ALTER TABLE sftp ADD COLUMN key_type TEXT NOT NULL DEFAULT 'rsa-2048';
+ALTER TABLE sftp ALTER COLUMN key_type DROP DEFAULT;
@@ -0,0 +1,15 @@
+-- Accounts can hold uploaded public keys instead of a generated key pair.
+ALTER TABLE sftp
+    ALTER COLUMN private_key DROP NOT NULL,
+    ALTER COLUMN public_key DROP NOT NULL,
+    ALTER COLUMN key_type DROP NOT NULL;
+
+CREATE TABLE sftp_keys (
+    id BIGSERIAL PRIMARY KEY,
+    sftp_id BIGINT NOT NULL REFERENCES sftp(id) ON DELETE CASCADE,
+    key_type TEXT NOT NULL,
+    public_key TEXT NOT NULL,
+    fingerprint TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (sftp_id, fingerprint)
+);
@@ -0,0 +1,25 @@
+-- Generated keys move to sftp_keys next to the uploaded ones, so that an account can have
+-- several keys at once while it rotates. A key works from not_before until it expires or
+-- is revoked; active_sftp_keys lists the keys that work right now.
+ALTER TABLE sftp_keys
+    ADD COLUMN source TEXT NOT NULL DEFAULT 'uploaded',
+    ADD COLUMN not_before TIMESTAMPTZ NOT NULL DEFAULT now(),
+    ADD COLUMN expires_at TIMESTAMPTZ,
+    ADD COLUMN revoked_at TIMESTAMPTZ,
+    DROP CONSTRAINT sftp_keys_sftp_id_fingerprint_key;
+ALTER TABLE sftp_keys ALTER COLUMN source DROP DEFAULT;
+
+CREATE UNIQUE INDEX sftp_keys_fingerprint_key ON sftp_keys (sftp_id, fingerprint)
+    WHERE revoked_at IS NULL;
+
+-- The fingerprint is computed the way `ssh-keygen -l` prints it.
+INSERT INTO sftp_keys (sftp_id, source, key_type, public_key, fingerprint)
+SELECT id, 'generated', key_type, public_key,
+    'SHA256:' || rtrim(encode(sha256(decode(split_part(public_key, ' ', 2), 'base64')), 'base64'), '=')
+FROM sftp WHERE public_key IS NOT NULL;
+
+ALTER TABLE sftp DROP COLUMN public_key;
+
+CREATE VIEW active_sftp_keys AS
+SELECT * FROM sftp_keys
+WHERE revoked_at IS NULL AND not_before <= now() AND (expires_at IS NULL OR expires_at > now());
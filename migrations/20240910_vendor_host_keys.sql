@@ -0,0 +1,13 @@
+-- The host keys a vendor's SFTP server is expected to present. Keys captured from a
+-- live connection wait for approval; only approved keys are enforced.
+CREATE TABLE vendor_host_keys (
+    id BIGSERIAL PRIMARY KEY,
+    vendor_id BIGINT NOT NULL REFERENCES vendors (id) ON DELETE CASCADE,
+    key_type TEXT NOT NULL,
+    public_key TEXT NOT NULL,
+    fingerprint TEXT NOT NULL,
+    source TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    approved_at TIMESTAMPTZ,
+    CONSTRAINT vendor_host_keys_fingerprint_key UNIQUE (vendor_id, fingerprint)
+);
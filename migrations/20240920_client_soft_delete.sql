@@ -0,0 +1,5 @@
+-- Deleting a client only marks it. The retention job hard-deletes it, with everything
+-- that cascades from it, once the retention window has passed.
+ALTER TABLE clients ADD COLUMN deleted_at TIMESTAMPTZ;
+
+CREATE INDEX clients_deleted_at_idx ON clients (deleted_at) WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,6 @@
+-- Every change to a row bumps its version. It is returned as the ETag and checked
+-- against If-Match, so concurrent edits can't silently overwrite each other.
+ALTER TABLE clients ADD COLUMN version BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE vendors ADD COLUMN version BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE sftp ADD COLUMN version BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE agents ADD COLUMN version BIGINT NOT NULL DEFAULT 1;
@@ -1,70 +1,150 @@
-use super::models::{Agent, AgentRepo, AgentUpdate};
-use crate::{clients::models::Client, errors::models::AppError};
-use axum::{
-    extract::{Path, Query, State},
-    Json,
+use super::models::{Agent, AgentFilter, AgentRepo, AgentUpdate};
+use crate::{
+    clients::models::Client,
+    errors::models::AppError,
+    utils::{
+        auth::Caller,
+        etag::{ETag, IfMatch},
+        extract::Path,
+        list::{ListQuery, Page},
+        response::{Created, NoContent, Updated},
+        validate::Valid,
+        version::V1Response,
+    },
 };
-use std::collections::HashMap;
+use axum::{extract::State, Extension, Json};
 
+#[utoipa::path(
+    get, path = "/agents", tag = "agents",
+    params(ListQuery<AgentFilter>),
+    responses((status = 200, body = Page<Agent>)),
+)]
 pub async fn get_agents<T: AgentRepo>(
     State(repo): State<T>,
-    Query(params): Query<HashMap<String, String>>,
-) -> Result<Json<Vec<Agent>>, AppError> {
-    let client_id = params.get("client_id").and_then(|id| id.parse().ok());
-    let agents = repo.get_all(client_id).await?;
+    Extension(caller): Extension<Caller>,
+    query: ListQuery<AgentFilter>,
+) -> Result<Json<Page<Agent>>, AppError> {
+    // The agents of a client would tell an agent about clients it isn't assigned to.
+    caller.ensure_unrestricted()?;
+    let agents = repo.get_all(query).await?;
     Ok(Json(agents))
 }
 
+#[utoipa::path(
+    get, path = "/agents/{id}", tag = "agents",
+    params(("id" = i64, Path, description = "The id of the agent")),
+    responses((status = 200, body = Agent, headers(("ETag" = String)))),
+)]
 pub async fn get_agent<T: AgentRepo>(
     State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
     Path(id): Path<i64>,
-) -> Result<Json<Agent>, AppError> {
+) -> Result<(ETag, Json<Agent>), AppError> {
+    ensure_own_agent(&caller, id)?;
     match repo.get(id).await? {
-        Some(agent) => Ok(Json(agent)),
-        None => Err(AppError::NotFound(format!(
-            "Agent with id {} not found",
-            id
-        ))),
+        Some(agent) => Ok((ETag(agent.version), Json(agent))),
+        None => Err(not_found(id)),
     }
 }
 
+#[utoipa::path(
+    post, path = "/agents", tag = "agents",
+    request_body = Agent,
+    responses((status = 201, body = Agent, headers(("Location" = String), ("ETag" = String)))),
+)]
 pub async fn create_agent<T: AgentRepo>(
     State(repo): State<T>,
-    Json(agent): Json<Agent>,
-) -> Result<Json<i64>, AppError> {
+    Extension(caller): Extension<Caller>,
+    Valid(agent): Valid<Agent>,
+) -> Result<Created<(ETag, Json<Agent>)>, AppError> {
+    caller.ensure_unrestricted()?;
     let agent_id = repo.create(agent).await?;
-    Ok(Json(agent_id))
+    let agent = repo
+        .get(agent_id)
+        .await?
+        .ok_or_else(|| not_found(agent_id))?;
+    Ok(Created {
+        location: format!("/agents/{}", agent_id),
+        response: (ETag(agent.version), Json(agent)),
+        v1: V1Response::Id(agent_id),
+    })
 }
 
+#[utoipa::path(
+    put, path = "/agents/{id}", tag = "agents",
+    params(("id" = i64, Path, description = "The id of the agent"), IfMatch),
+    request_body = AgentUpdate,
+    responses((status = 200, body = Agent, headers(("ETag" = String)))),
+)]
 pub async fn update_agent<T: AgentRepo>(
     State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
     Path(id): Path<i64>,
-    Json(agent): Json<AgentUpdate>,
-) -> Result<(), AppError> {
-    repo.update(id, agent).await?;
-    Ok(())
+    if_match: IfMatch,
+    Valid(agent): Valid<AgentUpdate>,
+) -> Result<Updated<(ETag, Json<Agent>)>, AppError> {
+    caller.ensure_unrestricted()?;
+    repo.update(id, agent, if_match).await?;
+    let agent = repo.get(id).await?.ok_or_else(|| not_found(id))?;
+    Ok(Updated((ETag(agent.version), Json(agent))))
 }
 
+#[utoipa::path(
+    delete, path = "/agents/{id}", tag = "agents",
+    params(("id" = i64, Path, description = "The id of the agent"), IfMatch),
+    responses((status = 204)),
+)]
 pub async fn delete_agent<T: AgentRepo>(
     State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
     Path(id): Path<i64>,
-) -> Result<(), AppError> {
-    repo.delete(id).await?;
-    Ok(())
+    if_match: IfMatch,
+) -> Result<NoContent, AppError> {
+    caller.ensure_unrestricted()?;
+    repo.delete(id, if_match).await?;
+    Ok(NoContent)
 }
 
+#[utoipa::path(
+    get, path = "/agents/{id}/clients", tag = "agents",
+    params(("id" = i64, Path, description = "The id of the agent")),
+    responses((status = 200, body = Vec<Client>)),
+)]
 pub async fn get_clients_for_agent<T: AgentRepo>(
     State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
     Path(id): Path<i64>,
 ) -> Result<Json<Vec<Client>>, AppError> {
+    ensure_own_agent(&caller, id)?;
     let clients = repo.get_clients_for_agent(id).await?;
     Ok(Json(clients))
 }
 
+#[utoipa::path(
+    put, path = "/agents/{id}/clients/{client_id}", tag = "agents",
+    params(("id" = i64, Path, description = "The id of the agent"), ("client_id" = i64, Path, description = "The id of the client")),
+    responses((status = 204)),
+)]
 pub async fn add_client_to_agent<T: AgentRepo>(
     State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
     Path((agent_id, client_id)): Path<(i64, i64)>,
-) -> Result<(), AppError> {
+) -> Result<NoContent, AppError> {
+    caller.ensure_unrestricted()?;
     repo.add_client_to_agent(agent_id, client_id).await?;
-    Ok(())
+    Ok(NoContent)
+}
+
+/// Fails with 403 for agent callers asking about another agent.
+fn ensure_own_agent(caller: &Caller, id: i64) -> Result<(), AppError> {
+    match &caller.agent {
+        Some(agent) if agent.agent_id != id => Err(AppError::Forbidden(
+            "Agents can only see themselves and their own clients".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn not_found(id: i64) -> AppError {
+    AppError::NotFound(format!("Agent with id {} not found", id))
 }
@@ -1,46 +1,56 @@
-use crate::{clients::models::Client, errors::models::AppError, postgres::pool::PostgresRepo};
+use crate::{
+    audit::models as audit,
+    clients::models::{lock_client, Client},
+    errors::models::AppError,
+    postgres::pool::PostgresRepo,
+    utils::{
+        etag::IfMatch,
+        filter::FilterField,
+        list::{ListFilter, ListQuery, Page},
+        validate::{rules, Errors, Validate},
+    },
+};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{FromRow, PgConnection, Postgres, QueryBuilder};
+use utoipa::ToSchema;
 
 #[async_trait]
 pub trait AgentRepo: Send + Sync + Clone + 'static {
-    async fn get_all(&self, name: Option<String>) -> Result<Vec<Agent>, AppError>;
+    async fn get_all(&self, query: ListQuery<AgentFilter>) -> Result<Page<Agent>, AppError>;
     async fn get(&self, id: i64) -> Result<Option<Agent>, AppError>;
     async fn create(&self, agent: Agent) -> Result<i64, AppError>;
-    async fn update(&self, id: i64, agent: AgentUpdate) -> Result<(), AppError>;
-    async fn delete(&self, id: i64) -> Result<(), AppError>;
+    async fn update(&self, id: i64, agent: AgentUpdate, if_match: IfMatch) -> Result<(), AppError>;
+    async fn delete(&self, id: i64, if_match: IfMatch) -> Result<(), AppError>;
     async fn get_clients_for_agent(&self, id: i64) -> Result<Vec<Client>, AppError>;
+    async fn get_client_ids_for_agent(&self, id: i64) -> Result<Vec<i64>, AppError>;
     async fn add_client_to_agent(&self, agent_id: i64, client_id: i64) -> Result<(), AppError>;
 }
 
 #[async_trait]
 impl AgentRepo for PostgresRepo {
-    async fn get_all(&self, name: Option<String>) -> Result<Vec<Agent>, AppError> {
-        let mut query = "SELECT * FROM agents".to_string();
-        let mut conditions = Vec::new();
+    async fn get_all(&self, query: ListQuery<AgentFilter>) -> Result<Page<Agent>, AppError> {
+        let mut count = QueryBuilder::new("SELECT COUNT(*) FROM agents WHERE TRUE");
+        push_filters(&mut count, &query);
+        let total: i64 = count.build_query_scalar().fetch_one(&self.pool).await?;
 
-        if let Some(name) = name {
-            conditions.push(format!("name LIKE '%{}%'", name));
-        }
-
-        if !conditions.is_empty() {
-            query.push_str(" WHERE ");
-            query.push_str(&conditions.join(" AND "));
-        }
-
-        let agents = sqlx::query_as::<_, Agent>(&query)
+        let mut select =
+            QueryBuilder::new("SELECT id, name, email, version FROM agents WHERE TRUE");
+        push_filters(&mut select, &query);
+        query.push_cursor(&mut select);
+        query.push_order_and_limit(&mut select);
+        let agents = select
+            .build_query_as::<Agent>()
             .fetch_all(&self.pool)
             .await?;
 
-        println!("Query: {}", query);
-        Ok(agents)
+        query.page(agents, total)
     }
 
     async fn get(&self, id: i64) -> Result<Option<Agent>, AppError> {
         let agent = sqlx::query_as!(
             Agent,
-            "SELECT id, name, email FROM agents WHERE id = $1",
+            "SELECT id, name, email, version FROM agents WHERE id = $1",
             id
         )
         .fetch_optional(&self.pool)
@@ -49,43 +59,72 @@ impl AgentRepo for PostgresRepo {
     }
 
     async fn create(&self, agent: Agent) -> Result<i64, AppError> {
+        let mut tx = self.pool.begin().await?;
         let id = sqlx::query!(
             "INSERT INTO agents (name, email) VALUES ($1, $2) RETURNING id",
             agent.name,
             agent.email,
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await?
         .id;
+
+        let created = Agent {
+            id: Some(id),
+            version: 1,
+            ..agent
+        };
+        audit::record(&mut tx, "create", "agent", id, None, Some(&created)).await?;
+        tx.commit().await?;
         Ok(id)
     }
 
-    async fn update(&self, id: i64, agent: AgentUpdate) -> Result<(), AppError> {
+    async fn update(&self, id: i64, agent: AgentUpdate, if_match: IfMatch) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        let before = lock_agent(&mut tx, id).await?;
+        if_match.check("Agent", id, before.version)?;
+
         sqlx::query!(
-            "UPDATE agents SET name = $1, email = $2 WHERE id = $3",
+            "UPDATE agents SET name = $1, email = $2, version = version + 1 WHERE id = $3",
             agent.name,
             agent.email,
             id
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
+
+        let after = Agent {
+            id: Some(id),
+            name: agent.name,
+            email: agent.email,
+            version: before.version + 1,
+        };
+        audit::record(&mut tx, "update", "agent", id, Some(&before), Some(&after)).await?;
+        tx.commit().await?;
         Ok(())
     }
 
-    async fn delete(&self, id: i64) -> Result<(), AppError> {
+    async fn delete(&self, id: i64, if_match: IfMatch) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        let before = lock_agent(&mut tx, id).await?;
+        if_match.check("Agent", id, before.version)?;
+
         sqlx::query!("DELETE FROM agents WHERE id = $1", id)
-            .execute(&self.pool)
+            .execute(&mut *tx)
             .await?;
+
+        audit::record(&mut tx, "delete", "agent", id, Some(&before), None).await?;
+        tx.commit().await?;
         Ok(())
     }
 
     async fn get_clients_for_agent(&self, id: i64) -> Result<Vec<Client>, AppError> {
         let clients = sqlx::query_as!(
             Client,
-            "SELECT clients.id, clients.name, clients.email, clients.bucket
+            "SELECT clients.*
             FROM clients
             INNER JOIN agent_clients ON clients.id = agent_clients.client_id
-            WHERE agent_clients.agent_id = $1",
+            WHERE agent_clients.agent_id = $1 AND clients.deleted_at IS NULL",
             id
         )
         .fetch_all(&self.pool)
@@ -94,27 +133,156 @@ impl AgentRepo for PostgresRepo {
         Ok(clients)
     }
 
-    async fn add_client_to_agent(&self, agent_id: i64, client_id: i64) -> Result<(), AppError> {
-        sqlx::query!(
-            "INSERT INTO agent_clients (agent_id, client_id) VALUES ($1, $2)",
-            agent_id,
-            client_id
+    async fn get_client_ids_for_agent(&self, id: i64) -> Result<Vec<i64>, AppError> {
+        let client_ids = sqlx::query_scalar!(
+            "SELECT client_id FROM agent_clients WHERE agent_id = $1",
+            id
         )
-        .execute(&self.pool)
+        .fetch_all(&self.pool)
         .await?;
+
+        Ok(client_ids)
+    }
+
+    async fn add_client_to_agent(&self, agent_id: i64, client_id: i64) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        // Unknown ids in the path are a 404, not a foreign key violation.
+        lock_agent(&mut tx, agent_id).await?;
+        lock_client(&mut tx, client_id).await?;
+        insert_agent_client(&mut tx, agent_id, client_id).await?;
+        tx.commit().await?;
         Ok(())
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, FromRow)]
+/// Assigns a client to an agent, recording it in the audit log.
+pub async fn insert_agent_client(
+    conn: &mut PgConnection,
+    agent_id: i64,
+    client_id: i64,
+) -> Result<(), AppError> {
+    sqlx::query!(
+        "INSERT INTO agent_clients (agent_id, client_id) VALUES ($1, $2)",
+        agent_id,
+        client_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let link = AgentClient {
+        agent_id,
+        client_id,
+    };
+    audit::record(conn, "add_client", "agent", agent_id, None, Some(&link)).await
+}
+
+/// Loads an agent inside a transaction and locks it for the rest of the transaction.
+pub async fn lock_agent(conn: &mut PgConnection, id: i64) -> Result<Agent, AppError> {
+    let agent = sqlx::query_as!(
+        Agent,
+        "SELECT id, name, email, version FROM agents WHERE id = $1 FOR UPDATE",
+        id
+    )
+    .fetch_optional(conn)
+    .await?;
+
+    agent.ok_or_else(|| AppError::NotFound(format!("Agent with id {} not found", id)))
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, FromRow, ToSchema)]
 pub struct Agent {
-    id: Option<i64>,
-    name: String,
-    email: String,
+    pub id: Option<i64>,
+    pub name: String,
+    pub email: String,
+    /// Bumped by every change, see [`ETag`](crate::utils::etag::ETag).
+    #[serde(default, skip_deserializing)]
+    pub version: i64,
 }
 
-#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, FromRow)]
+/// Filters for `GET /agents`. `client_id` returns the agents assigned to that client.
+#[derive(Debug, PartialEq, Clone)]
+pub struct AgentFilter;
+
+impl ListFilter for AgentFilter {
+    const SORT_FIELDS: &'static [&'static str] = &["id", "name", "email"];
+    const FILTER_FIELDS: &'static [FilterField] = &[
+        FilterField::int("id"),
+        FilterField::text("name"),
+        FilterField::text("email"),
+        FilterField::int("client_id"),
+    ];
+}
+
+/// Appends the query's filters. `client_id` goes through `agent_clients`.
+fn push_filters(builder: &mut QueryBuilder<'_, Postgres>, query: &ListQuery<AgentFilter>) {
+    query.filters.push_except(builder, &["client_id"]);
+    for condition in query.filters.get("client_id") {
+        builder.push(" AND EXISTS (SELECT 1 FROM agent_clients ac WHERE ac.agent_id = agents.id");
+        condition.push(builder, "ac.client_id");
+        builder.push(")");
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, FromRow, ToSchema)]
 pub struct AgentUpdate {
-    name: String,
-    email: String,
+    pub name: String,
+    pub email: String,
+}
+
+impl Validate for Agent {
+    fn validate(&self, errors: &mut Errors) {
+        errors.check("name", rules::required(&self.name));
+        errors.check("email", rules::email(&self.email));
+    }
+}
+
+impl Validate for AgentUpdate {
+    fn validate(&self, errors: &mut Errors) {
+        errors.check("name", rules::required(&self.name));
+        errors.check("email", rules::email(&self.email));
+    }
+}
+
+/// A row of the agent_clients table linking an agent to one of its clients.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, FromRow)]
+pub struct AgentClient {
+    pub agent_id: i64,
+    pub client_id: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list_sql(query_string: &str) -> Result<String, AppError> {
+        let query = ListQuery::<AgentFilter>::from_query_string(query_string)?;
+        let mut builder = QueryBuilder::new("SELECT id, name, email FROM agents WHERE TRUE");
+        push_filters(&mut builder, &query);
+        Ok(builder.sql().to_string())
+    }
+
+    #[test]
+    fn test_hostile_name_filter_is_bound() {
+        // GET /agents?name=%' UNION SELECT id, password, host FROM vendors --
+        let sql = list_sql(
+            "name=%25%27%20UNION%20SELECT%20id%2C%20password%2C%20host%20FROM%20vendors%20--",
+        )
+        .unwrap();
+        assert_eq!(
+            sql,
+            "SELECT id, name, email FROM agents WHERE TRUE AND strpos(name, $1) > 0"
+        );
+    }
+
+    #[test]
+    fn test_client_id_filter_uses_agent_clients() {
+        let sql = list_sql("client_id__in=1,2&name__eq=smith").unwrap();
+        assert_eq!(
+            sql,
+            "SELECT id, name, email FROM agents WHERE TRUE AND name = $1 \
+             AND EXISTS (SELECT 1 FROM agent_clients ac WHERE ac.agent_id = agents.id \
+             AND ac.client_id = ANY($2))"
+        );
+        assert!(list_sql("client_id=1%29%20OR%20%281%3D1").is_err());
+    }
 }
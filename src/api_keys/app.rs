@@ -0,0 +1,17 @@
+use super::{
+    handlers::{create_api_key, get_api_keys, revoke_api_key},
+    models::ApiKeyRepo,
+};
+use axum::{
+    routing::{delete, get, post},
+    Router,
+};
+
+/// Admin endpoints for managing API keys. All of them need the `admin` scope.
+pub fn router<T: ApiKeyRepo>(repo: T) -> Router {
+    Router::new()
+        .route("/api-keys", get(get_api_keys::<T>))
+        .route("/api-keys", post(create_api_key::<T>))
+        .route("/api-keys/:id", delete(revoke_api_key::<T>))
+        .with_state(repo)
+}
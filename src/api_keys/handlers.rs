@@ -0,0 +1,54 @@
+use super::models::{ApiKey, ApiKeyRepo, CreatedApiKey, NewApiKey};
+use crate::{
+    errors::models::AppError,
+    utils::{
+        auth::Caller,
+        extract::{Json, Path},
+    },
+};
+use axum::{extract::State, Extension};
+
+#[utoipa::path(
+    get, path = "/api-keys", tag = "api-keys",
+    responses((status = 200, body = Vec<ApiKey>)),
+)]
+pub async fn get_api_keys<T: ApiKeyRepo>(
+    State(repo): State<T>,
+) -> Result<Json<Vec<ApiKey>>, AppError> {
+    let keys = repo.get_all().await?;
+    Ok(Json(keys))
+}
+
+#[utoipa::path(
+    post, path = "/api-keys", tag = "api-keys",
+    request_body = NewApiKey,
+    responses((status = 200, body = CreatedApiKey)),
+)]
+pub async fn create_api_key<T: ApiKeyRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Json(key): Json<NewApiKey>,
+) -> Result<Json<CreatedApiKey>, AppError> {
+    let created = repo.create(key, &caller.name).await?;
+    tracing::info!(
+        caller = %caller.name,
+        api_key_id = created.api_key.id,
+        "API key created"
+    );
+    Ok(Json(created))
+}
+
+#[utoipa::path(
+    delete, path = "/api-keys/{id}", tag = "api-keys",
+    params(("id" = i64, Path, description = "The id of the API key")),
+    responses((status = 200)),
+)]
+pub async fn revoke_api_key<T: ApiKeyRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<i64>,
+) -> Result<(), AppError> {
+    repo.revoke(id).await?;
+    tracing::info!(caller = %caller.name, api_key_id = id, "API key revoked");
+    Ok(())
+}
@@ -0,0 +1,182 @@
+use crate::{errors::models::AppError, postgres::pool::PostgresRepo};
+use async_trait::async_trait;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use rand::{thread_rng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+/// Every scope an API key can be granted.
+/// `admin` covers all of the others, including managing API keys.
+pub const SCOPES: &[&str] = &[
+    "admin",
+    "clients:read",
+    "clients:write",
+    "vendors:read",
+    "vendors:write",
+    "sftp:read",
+    "sftp:write",
+    "sftp:keys",
+    "agents:read",
+    "agents:write",
+    "audit:read",
+];
+
+/// Interface to the api_keys table.
+/// ```text
+/// get_all: Get all API keys, including revoked and expired ones.
+/// create: Create a new key. The plaintext key is only ever returned here.
+/// revoke: Revoke a key by id.
+/// find_by_token: Look up a key by the bearer token presented by a caller.
+/// ```
+#[async_trait]
+pub trait ApiKeyRepo: Send + Sync + Clone + 'static {
+    async fn get_all(&self) -> Result<Vec<ApiKey>, AppError>;
+    async fn create(&self, key: NewApiKey, created_by: &str) -> Result<CreatedApiKey, AppError>;
+    async fn revoke(&self, id: i64) -> Result<(), AppError>;
+    async fn find_by_token(&self, token: &str) -> Result<Option<ApiKey>, AppError>;
+}
+
+#[async_trait]
+impl ApiKeyRepo for PostgresRepo {
+    async fn get_all(&self) -> Result<Vec<ApiKey>, AppError> {
+        let keys = sqlx::query_as!(
+            ApiKey,
+            "SELECT id, name, scopes, agent_id, expires_at, revoked_at, created_by, created_at
+            FROM api_keys ORDER BY id"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(keys)
+    }
+
+    async fn create(&self, key: NewApiKey, created_by: &str) -> Result<CreatedApiKey, AppError> {
+        key.validate()?;
+
+        let token = generate_token();
+        let api_key = sqlx::query_as!(
+            ApiKey,
+            "INSERT INTO api_keys (name, key_hash, scopes, agent_id, expires_at, created_by)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, name, scopes, agent_id, expires_at, revoked_at, created_by, created_at",
+            key.name,
+            hash_token(&token),
+            &key.scopes,
+            key.agent_id,
+            key.expires_at,
+            created_by,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(CreatedApiKey { api_key, token })
+    }
+
+    async fn revoke(&self, id: i64) -> Result<(), AppError> {
+        let rows_affected = sqlx::query!(
+            "UPDATE api_keys SET revoked_at = now() WHERE id = $1 AND revoked_at IS NULL",
+            id
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        if rows_affected == 0 {
+            Err(AppError::NotFound(format!(
+                "Active API key with id {} not found",
+                id
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn find_by_token(&self, token: &str) -> Result<Option<ApiKey>, AppError> {
+        let key = sqlx::query_as!(
+            ApiKey,
+            "SELECT id, name, scopes, agent_id, expires_at, revoked_at, created_by, created_at
+            FROM api_keys WHERE key_hash = $1",
+            hash_token(token)
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(key)
+    }
+}
+
+/// Generates a random 256-bit API key.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    thread_rng().fill_bytes(&mut bytes);
+    format!("umk_{}", URL_SAFE_NO_PAD.encode(bytes))
+}
+
+/// Keys are high entropy random values, so a plain SHA-256 is enough to store them.
+pub fn hash_token(token: &str) -> Vec<u8> {
+    Sha256::digest(token.as_bytes()).to_vec()
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, FromRow, ToSchema)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub agent_id: Option<i64>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ApiKey {
+    /// A key is usable until it is revoked or reaches its expiry.
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none() && self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, ToSchema)]
+pub struct NewApiKey {
+    pub name: String,
+    pub scopes: Vec<String>,
+    /// Issue the key to an agent, restricting it to that agent's clients.
+    pub agent_id: Option<i64>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl NewApiKey {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.scopes.is_empty() {
+            return Err(AppError::InvalidInput(
+                "An API key needs at least one scope".to_string(),
+            ));
+        }
+        if let Some(scope) = self
+            .scopes
+            .iter()
+            .find(|scope| !SCOPES.contains(&scope.as_str()))
+        {
+            return Err(AppError::InvalidInput(format!("Unknown scope {}", scope)));
+        }
+        if self
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+        {
+            return Err(AppError::InvalidInput(
+                "expires_at must be in the future".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Returned once when a key is created. The token can't be recovered afterwards.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, ToSchema)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    pub token: String,
+}
@@ -0,0 +1,13 @@
+use super::{handlers::get_audit_events, models::AuditRepo};
+use axum::{routing::get, Router};
+
+/// The router for the audit log.
+/// ```text
+/// GET /audit?entity=client&id=42&since=2024-07-01T00:00:00Z&limit=50&cursor=1234
+/// ```
+/// Returns the matching events and the cursor for the next page, if there is one.
+pub fn router<T: AuditRepo>(repo: T) -> Router {
+    Router::new()
+        .route("/audit", get(get_audit_events::<T>))
+        .with_state(repo)
+}
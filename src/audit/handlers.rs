@@ -0,0 +1,23 @@
+use super::models::{AuditPage, AuditQuery, AuditRepo};
+use crate::{
+    errors::models::AppError,
+    utils::{auth::Caller, extract::Query},
+};
+use axum::{extract::State, Extension, Json};
+
+/// Lists audit events, newest first.
+/// Filter with `entity`, `id` and `since`, and page with `limit` and `cursor`.
+#[utoipa::path(
+    get, path = "/audit", tag = "audit",
+    params(AuditQuery),
+    responses((status = 200, body = AuditPage)),
+)]
+pub async fn get_audit_events<T: AuditRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<AuditPage>, AppError> {
+    caller.ensure_unrestricted()?;
+    let page = repo.get_all(query).await?;
+    Ok(Json(page))
+}
@@ -0,0 +1,240 @@
+use crate::{
+    errors::models::AppError,
+    postgres::pool::PostgresRepo,
+    utils::auth::current_caller,
+    webhooks::models::{self as webhooks, WebhookEvent},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::{FromRow, PgConnection};
+use utoipa::{IntoParams, ToSchema};
+
+/// Fields that hold credentials. Their values never make it into the audit log.
+const SECRET_FIELDS: &[&str] = &["password", "ssh_key", "ssh_key_password", "private_key"];
+pub const REDACTED: &str = "[REDACTED]";
+/// Bookkeeping that changes with every write, left out of the diffs.
+const UNTRACKED_FIELDS: &[&str] = &["version"];
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 500;
+
+/// Interface to the audit_events table. Events are written by the other repos through
+/// [`record`], inside the transaction of the mutation they describe.
+#[async_trait]
+pub trait AuditRepo: Send + Sync + Clone + 'static {
+    async fn get_all(&self, query: AuditQuery) -> Result<AuditPage, AppError>;
+}
+
+#[async_trait]
+impl AuditRepo for PostgresRepo {
+    async fn get_all(&self, query: AuditQuery) -> Result<AuditPage, AppError> {
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+
+        // Newest first. The cursor is the id of the last event on the previous page.
+        let mut items = sqlx::query_as!(
+            AuditEvent,
+            "SELECT id, occurred_at, actor, actor_key_id, action, entity_type, entity_id, diff
+            FROM audit_events
+            WHERE ($1::TEXT IS NULL OR entity_type = $1)
+              AND ($2::BIGINT IS NULL OR entity_id = $2)
+              AND ($3::TIMESTAMPTZ IS NULL OR occurred_at >= $3)
+              AND ($4::BIGINT IS NULL OR id < $4)
+            ORDER BY id DESC
+            LIMIT $5",
+            query.entity,
+            query.id,
+            query.since,
+            query.cursor,
+            limit + 1,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let next_cursor = if items.len() as i64 > limit {
+            items.truncate(limit as usize);
+            items.last().map(|event| event.id)
+        } else {
+            None
+        };
+
+        Ok(AuditPage { items, next_cursor })
+    }
+}
+
+/// Writes an audit event for a mutation. `before` is `None` for creates and `after` is
+/// `None` for deletes. The actor is the caller of the request being handled.
+/// The event is also queued for the webhooks subscribed to it.
+pub async fn record<T: Serialize>(
+    conn: &mut PgConnection,
+    action: &str,
+    entity_type: &str,
+    entity_id: i64,
+    before: Option<&T>,
+    after: Option<&T>,
+) -> Result<(), AppError> {
+    let (actor, actor_key_id) = actor();
+
+    let before = before.map(serde_json::to_value).transpose();
+    let after = after.map(serde_json::to_value).transpose();
+    let (Ok(before), Ok(after)) = (before, after) else {
+        return Err(AppError::Unknown);
+    };
+
+    let data = after.clone().or_else(|| before.clone());
+    let event = sqlx::query_as!(
+        AuditEvent,
+        "INSERT INTO audit_events (actor, actor_key_id, action, entity_type, entity_id, diff)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, occurred_at, actor, actor_key_id, action, entity_type, entity_id, diff",
+        actor,
+        actor_key_id,
+        action,
+        entity_type,
+        entity_id,
+        diff(before, after),
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let event = WebhookEvent::new(event, data);
+    webhooks::enqueue(conn, &event).await
+}
+
+/// The name and key id of the caller of the current request, or "system" for background jobs.
+pub fn actor() -> (String, Option<i64>) {
+    match current_caller() {
+        Some(caller) => (caller.name, caller.key_id),
+        None => ("system".to_string(), None),
+    }
+}
+
+/// Builds `{"field": {"before": .., "after": ..}}` for every field that changed.
+/// Secret fields are compared on their real values but only ever written as `[REDACTED]`.
+/// The row version isn't a change of its own and is skipped.
+pub fn diff(before: Option<Value>, after: Option<Value>) -> Value {
+    let before = as_object(before);
+    let after = as_object(after);
+
+    let mut fields: Vec<&String> = before.keys().chain(after.keys()).collect();
+    fields.sort();
+    fields.dedup();
+
+    let mut changes = Map::new();
+    for field in fields {
+        let old = before.get(field).cloned().unwrap_or(Value::Null);
+        let new = after.get(field).cloned().unwrap_or(Value::Null);
+        if old == new || UNTRACKED_FIELDS.contains(&field.as_str()) {
+            continue;
+        }
+        let mut change = Map::new();
+        change.insert("before".to_string(), redact(field, old));
+        change.insert("after".to_string(), redact(field, new));
+        changes.insert(field.clone(), Value::Object(change));
+    }
+    Value::Object(changes)
+}
+
+/// Redacts the secret fields of an object, for payloads that carry a whole entity.
+pub fn redact_fields(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(field, value)| {
+                    let value = redact(&field, value);
+                    (field, value)
+                })
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+fn as_object(value: Option<Value>) -> Map<String, Value> {
+    match value {
+        Some(Value::Object(map)) => map,
+        _ => Map::new(),
+    }
+}
+
+fn redact(field: &str, value: Value) -> Value {
+    if SECRET_FIELDS.contains(&field) && !value.is_null() {
+        Value::String(REDACTED.to_string())
+    } else {
+        value
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, FromRow, ToSchema)]
+pub struct AuditEvent {
+    pub id: i64,
+    pub occurred_at: DateTime<Utc>,
+    pub actor: String,
+    pub actor_key_id: Option<i64>,
+    pub action: String,
+    pub entity_type: String,
+    pub entity_id: i64,
+    pub diff: Value,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    pub entity: Option<String>,
+    pub id: Option<i64>,
+    pub since: Option<DateTime<Utc>>,
+    pub cursor: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, ToSchema)]
+pub struct AuditPage {
+    pub items: Vec<AuditEvent>,
+    pub next_cursor: Option<i64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_diff_only_keeps_changed_fields() {
+        let before = json!({"id": 1, "name": "Acme", "host": "old.example.com", "version": 1});
+        let after = json!({"id": 1, "name": "Acme", "host": "new.example.com", "version": 2});
+        assert_eq!(
+            diff(Some(before), Some(after)),
+            json!({"host": {"before": "old.example.com", "after": "new.example.com"}})
+        );
+    }
+
+    #[test]
+    fn test_diff_redacts_secrets() {
+        let before = json!({"password": "hunter2", "ssh_key": null});
+        let after = json!({"password": "hunter3", "ssh_key": "-----BEGIN"});
+        assert_eq!(
+            diff(Some(before), Some(after)),
+            json!({
+                "password": {"before": "[REDACTED]", "after": "[REDACTED]"},
+                "ssh_key": {"before": null, "after": "[REDACTED]"},
+            })
+        );
+    }
+
+    #[test]
+    fn test_diff_for_create_and_delete() {
+        let client = json!({"id": 1, "name": "Acme"});
+        assert_eq!(
+            diff(None, Some(client.clone())),
+            json!({"id": {"before": null, "after": 1}, "name": {"before": null, "after": "Acme"}})
+        );
+        assert_eq!(
+            diff(Some(client), None),
+            json!({"id": {"before": 1, "after": null}, "name": {"before": "Acme", "after": null}})
+        );
+    }
+}
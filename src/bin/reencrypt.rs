@@ -0,0 +1,23 @@
+use user_manager_api::config::models::AppConfig;
+use user_manager_api::postgres::pool::PostgresRepo;
+use user_manager_api::postgres::reencrypt::reencrypt_all;
+use user_manager_api::utils::crypto::KeyRing;
+
+/// One-off job that encrypts legacy plaintext secrets and moves rows written under
+/// retired master keys onto the active one. Run it after deploying the
+/// `encrypt_secrets` migration, and again after every master key rotation.
+#[tokio::main]
+async fn main() {
+    let cfg = AppConfig::new().unwrap();
+    let keys = KeyRing::from_config(&cfg).unwrap();
+    let repo = PostgresRepo::new(&cfg.database_url, keys).await;
+
+    let stats = reencrypt_all(&repo).await.unwrap();
+    println!(
+        "Re-encrypted {} vendor rows, {} sftp rows and {} webhook rows with key {}",
+        stats.vendors,
+        stats.sftp,
+        stats.webhooks,
+        repo.keys.active_key_id()
+    );
+}
@@ -0,0 +1,20 @@
+use super::{
+    handlers::{export_records, import},
+    models::BulkRepo,
+};
+use axum::{
+    routing::{get, post},
+    Router,
+};
+
+/// The router for bulk imports and exports.
+/// ```text
+/// POST /import?entity=clients|vendors&format=csv|ndjson&mode=all_or_nothing|best_effort
+/// GET  /export?entity=clients|vendors&format=csv|ndjson
+/// ```
+pub fn router<T: BulkRepo>(repo: T) -> Router {
+    Router::new()
+        .route("/import", post(import::<T>))
+        .route("/export", get(export_records::<T>))
+        .with_state(repo)
+}
@@ -0,0 +1,108 @@
+use super::models::{
+    export, parse_rows, BulkRepo, ClientRow, Entity, ExportParams, Format, ImportParams,
+    ImportReport, VendorRow,
+};
+use crate::{
+    errors::models::AppError,
+    utils::{auth::Caller, extract::Query},
+};
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+
+/// Upserts the clients or vendors of a CSV or NDJSON file and reports the outcome per
+/// record. Only admins can import, agent keys are rejected.
+#[utoipa::path(
+    post, path = "/import", tag = "bulk",
+    params(ImportParams),
+    request_body(
+        content((String = "text/csv"), (String = "application/x-ndjson")),
+        description = "The records to import",
+    ),
+    responses((status = 200, body = ImportReport)),
+)]
+pub async fn import<T: BulkRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Query(params): Query<ImportParams>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<ImportReport>, AppError> {
+    caller.ensure_unrestricted()?;
+    caller.ensure_scope(params.entity.write_scope())?;
+    let format = params
+        .format
+        .or_else(|| {
+            headers
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(Format::from_content_type)
+        })
+        .ok_or_else(|| {
+            AppError::InvalidInput(
+                "Set ?format=csv|ndjson or a text/csv or application/x-ndjson Content-Type"
+                    .to_string(),
+            )
+        })?;
+
+    let report = match params.entity {
+        Entity::Clients => {
+            let rows = parse_rows::<ClientRow>(format, &body)?;
+            repo.import_clients(rows, params.mode).await?
+        }
+        Entity::Vendors => {
+            let rows = parse_rows::<VendorRow>(format, &body)?;
+            repo.import_vendors(rows, params.mode).await?
+        }
+    };
+    tracing::info!(
+        caller = %caller.name,
+        entity = ?report.entity,
+        total = report.total,
+        failed = report.failed,
+        committed = report.committed,
+        "Import finished"
+    );
+    Ok(Json(report))
+}
+
+/// Streams all the clients or vendors the caller can see. Secrets are redacted.
+#[utoipa::path(
+    get, path = "/export", tag = "bulk",
+    params(ExportParams),
+    responses((
+        status = 200,
+        description = "The records, one per line",
+        content((String = "text/csv"), (String = "application/x-ndjson")),
+    )),
+)]
+pub async fn export_records<T: BulkRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Query(params): Query<ExportParams>,
+) -> Result<Response, AppError> {
+    caller.ensure_scope(params.entity.read_scope())?;
+    let ExportParams { entity, format } = params;
+
+    let filename = match entity {
+        Entity::Clients => "clients",
+        Entity::Vendors => "vendors",
+    };
+    let body = Body::from_stream(export(repo, entity, format, caller.client_filter()));
+    let headers = [
+        (header::CONTENT_TYPE, format.content_type().to_string()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"{}.{}\"",
+                filename,
+                format.extension()
+            ),
+        ),
+    ];
+    Ok((headers, body).into_response())
+}
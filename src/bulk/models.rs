@@ -0,0 +1,770 @@
+use std::io::Write;
+
+use crate::{
+    audit::models::{self as audit, REDACTED},
+    clients::models::{insert_client, insert_vendor, Client},
+    errors::models::AppError,
+    postgres::pool::PostgresRepo,
+    utils::{crypto::KeyRing, validate::validate},
+    vendors::models::{Vendor, VendorRecord, VendorSecrets},
+};
+use async_trait::async_trait;
+use axum::body::Bytes;
+use futures::{stream, Stream};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sqlx::{Connection, FromRow, PgConnection};
+use utoipa::{IntoParams, ToSchema};
+
+/// How many records the export loads at a time.
+pub const EXPORT_PAGE_SIZE: i64 = 500;
+
+/// Imports and exports clients and vendors in bulk.
+/// ```text
+/// import_clients: Upsert clients by name.
+/// import_vendors: Upsert vendors by client name and vendor name.
+/// export_clients: Get the clients after an id, in id order.
+/// export_vendors: Get the vendors after an id, in id order, with their secrets redacted.
+/// ```
+#[async_trait]
+pub trait BulkRepo: Send + Sync + Clone + 'static {
+    async fn import_clients(
+        &self,
+        rows: Vec<Row<ClientRow>>,
+        mode: ImportMode,
+    ) -> Result<ImportReport, AppError>;
+    async fn import_vendors(
+        &self,
+        rows: Vec<Row<VendorRow>>,
+        mode: ImportMode,
+    ) -> Result<ImportReport, AppError>;
+    async fn export_clients(
+        &self,
+        after_id: i64,
+        limit: i64,
+        client_ids: Option<Vec<i64>>,
+    ) -> Result<Vec<Client>, AppError>;
+    async fn export_vendors(
+        &self,
+        after_id: i64,
+        limit: i64,
+        client_ids: Option<Vec<i64>>,
+    ) -> Result<Vec<VendorExport>, AppError>;
+}
+
+#[async_trait]
+impl BulkRepo for PostgresRepo {
+    async fn import_clients(
+        &self,
+        rows: Vec<Row<ClientRow>>,
+        mode: ImportMode,
+    ) -> Result<ImportReport, AppError> {
+        let mut report = ImportReport::new(Entity::Clients, mode, rows.len());
+        let mut tx = self.pool.begin().await?;
+        for row in rows {
+            let record = match row.record {
+                Ok(record) => record,
+                Err(error) => {
+                    report.failed(row.line, error);
+                    continue;
+                }
+            };
+            // Each row gets a savepoint, so a failed row doesn't abort the others.
+            let mut savepoint = tx.begin().await?;
+            match upsert_client(&mut savepoint, record).await {
+                Ok(outcome) => {
+                    savepoint.commit().await?;
+                    report.succeeded(outcome);
+                }
+                Err(error) => {
+                    savepoint.rollback().await?;
+                    report.failed(row.line, error.to_string());
+                }
+            }
+        }
+        report.finish(tx).await
+    }
+
+    async fn import_vendors(
+        &self,
+        rows: Vec<Row<VendorRow>>,
+        mode: ImportMode,
+    ) -> Result<ImportReport, AppError> {
+        let mut report = ImportReport::new(Entity::Vendors, mode, rows.len());
+        let mut tx = self.pool.begin().await?;
+        for row in rows {
+            let record = match row.record {
+                Ok(record) => record,
+                Err(error) => {
+                    report.failed(row.line, error);
+                    continue;
+                }
+            };
+            let mut savepoint = tx.begin().await?;
+            match upsert_vendor(&mut savepoint, &self.keys, record).await {
+                Ok(outcome) => {
+                    savepoint.commit().await?;
+                    report.succeeded(outcome);
+                }
+                Err(error) => {
+                    savepoint.rollback().await?;
+                    report.failed(row.line, error.to_string());
+                }
+            }
+        }
+        report.finish(tx).await
+    }
+
+    async fn export_clients(
+        &self,
+        after_id: i64,
+        limit: i64,
+        client_ids: Option<Vec<i64>>,
+    ) -> Result<Vec<Client>, AppError> {
+        let clients = sqlx::query_as!(
+            Client,
+            "SELECT * FROM clients
+            WHERE id > $1 AND ($3::BIGINT[] IS NULL OR id = ANY($3)) AND deleted_at IS NULL
+            ORDER BY id LIMIT $2",
+            after_id,
+            limit,
+            client_ids.as_deref()
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(clients)
+    }
+
+    async fn export_vendors(
+        &self,
+        after_id: i64,
+        limit: i64,
+        client_ids: Option<Vec<i64>>,
+    ) -> Result<Vec<VendorExport>, AppError> {
+        // The secrets are never decrypted, only whether they are set is exported.
+        let vendors = sqlx::query_as!(
+            VendorExport,
+            r#"SELECT v.id, v.client_id, c.name AS client, v.name, v.host, v.port, v.username,
+                CASE WHEN v.password IS NULL THEN NULL ELSE $4 END AS password,
+                CASE WHEN v.ssh_key IS NULL THEN NULL ELSE $4 END AS ssh_key,
+                CASE WHEN v.ssh_key_password IS NULL THEN NULL ELSE $4 END AS ssh_key_password
+            FROM vendors v JOIN clients c ON c.id = v.client_id
+            WHERE v.id > $1 AND ($3::BIGINT[] IS NULL OR v.client_id = ANY($3))
+                AND c.deleted_at IS NULL
+            ORDER BY v.id LIMIT $2"#,
+            after_id,
+            limit,
+            client_ids.as_deref(),
+            REDACTED
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(vendors)
+    }
+}
+
+async fn upsert_client(conn: &mut PgConnection, row: ClientRow) -> Result<Outcome, AppError> {
+    let existing = sqlx::query_as!(
+        Client,
+        "SELECT * FROM clients WHERE name = $1 AND deleted_at IS NULL ORDER BY id FOR UPDATE",
+        row.name
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let before = match existing.as_slice() {
+        [] => {
+            insert_client(conn, row.into_client(None)).await?;
+            return Ok(Outcome::Created);
+        }
+        [before] => before,
+        _ => return Err(ambiguous_client(&row.name, existing.len())),
+    };
+    let after = row.into_client(Some(before));
+    if after == *before {
+        return Ok(Outcome::Unchanged);
+    }
+    let after = Client {
+        version: before.version + 1,
+        ..after
+    };
+
+    sqlx::query!(
+        "UPDATE clients SET email = $1, bucket = $2, version = version + 1 WHERE id = $3",
+        after.email,
+        after.bucket,
+        before.id
+    )
+    .execute(&mut *conn)
+    .await?;
+    let id = before.id.unwrap_or_default();
+    audit::record(conn, "update", "client", id, Some(before), Some(&after)).await?;
+    Ok(Outcome::Updated)
+}
+
+async fn upsert_vendor(
+    conn: &mut PgConnection,
+    keys: &KeyRing,
+    row: VendorRow,
+) -> Result<Outcome, AppError> {
+    let client_ids = sqlx::query_scalar!(
+        "SELECT id FROM clients WHERE name = $1 AND deleted_at IS NULL ORDER BY id FOR UPDATE",
+        row.client
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let client_id = match client_ids.as_slice() {
+        [client_id] => *client_id,
+        [] => return Err(missing_client(&row.client)),
+        _ => return Err(ambiguous_client(&row.client, client_ids.len())),
+    };
+
+    let existing = sqlx::query_as::<_, VendorRecord>(
+        "SELECT * FROM vendors WHERE client_id = $1 AND name = $2 ORDER BY id FOR UPDATE",
+    )
+    .bind(client_id)
+    .bind(&row.name)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let before = match existing.len() {
+        0 => {
+            insert_vendor(conn, keys, client_id, row.into_vendor(client_id, None)).await?;
+            return Ok(Outcome::Created);
+        }
+        1 => existing.into_iter().next().unwrap().decrypt(keys)?,
+        count => return Err(ambiguous_vendor(&row, count)),
+    };
+    let after = row.into_vendor(client_id, Some(&before));
+    if after == before {
+        return Ok(Outcome::Unchanged);
+    }
+    let after = Vendor {
+        version: before.version + 1,
+        ..after
+    };
+
+    let id = before.id.unwrap_or_default();
+    let secrets = VendorSecrets::encrypt(&after, keys)?;
+    sqlx::query!(
+        "UPDATE vendors SET
+            host = $1,
+            port = $2,
+            username = $3,
+            password = $4,
+            ssh_key = $5,
+            ssh_key_password = $6,
+            key_id = $7,
+            version = version + 1
+         WHERE id = $8",
+        after.host,
+        after.port,
+        after.username,
+        secrets.password,
+        secrets.ssh_key,
+        secrets.ssh_key_password,
+        secrets.key_id,
+        id
+    )
+    .execute(&mut *conn)
+    .await?;
+    audit::record(conn, "update", "vendor", id, Some(&before), Some(&after)).await?;
+    Ok(Outcome::Updated)
+}
+
+pub fn missing_client(name: &str) -> AppError {
+    AppError::NotFound(format!("Client with name {} not found", name))
+}
+
+pub fn ambiguous_client(name: &str, count: usize) -> AppError {
+    AppError::InvalidInput(format!(
+        "Client name {} matches {} clients, rename them to import by name",
+        name, count
+    ))
+}
+
+pub fn ambiguous_vendor(row: &VendorRow, count: usize) -> AppError {
+    AppError::InvalidInput(format!(
+        "Vendor name {} matches {} vendors of client {}",
+        row.name, count, row.client
+    ))
+}
+
+/// The kind of records imported or exported.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Entity {
+    Clients,
+    Vendors,
+}
+
+impl Entity {
+    /// The scope needed to export the records.
+    pub fn read_scope(&self) -> &'static str {
+        match self {
+            Entity::Clients => "clients:read",
+            Entity::Vendors => "vendors:read",
+        }
+    }
+
+    /// The scope needed to import the records.
+    pub fn write_scope(&self) -> &'static str {
+        match self {
+            Entity::Clients => "clients:write",
+            Entity::Vendors => "vendors:write",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Csv,
+    Ndjson,
+}
+
+impl Format {
+    /// The format of a body sent with `content_type`, if it's one of ours.
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next().unwrap_or_default().trim();
+        match mime {
+            "text/csv" => Some(Format::Csv),
+            "application/x-ndjson" | "application/ndjson" | "application/jsonl" => {
+                Some(Format::Ndjson)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv; charset=utf-8",
+            Format::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// `all_or_nothing` keeps the changes only if every row worked. `best_effort` keeps the
+/// rows that worked and reports the others.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    #[default]
+    AllOrNothing,
+    BestEffort,
+}
+
+/// The query string of `POST /import`. `format` defaults to the one of the Content-Type.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportParams {
+    pub entity: Entity,
+    pub format: Option<Format>,
+    #[serde(default)]
+    pub mode: ImportMode,
+}
+
+/// The query string of `GET /export`.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportParams {
+    pub entity: Entity,
+    #[serde(default)]
+    pub format: Format,
+}
+
+/// A record of an import file, with the line it starts on. Records that couldn't be read
+/// or failed validation carry the reason instead.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row<T> {
+    pub line: u64,
+    pub record: Result<T, String>,
+}
+
+/// A record type that can be imported.
+pub trait ImportRecord: DeserializeOwned {
+    fn validate(&self) -> Result<(), String>;
+}
+
+/// A client of an import file. Clients are matched on `name`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct ClientRow {
+    pub name: String,
+    pub email: String,
+    pub bucket: String,
+}
+
+impl ClientRow {
+    pub fn into_client(self, existing: Option<&Client>) -> Client {
+        Client {
+            id: existing.and_then(|client| client.id),
+            name: self.name,
+            email: self.email,
+            bucket: self.bucket,
+            deleted_at: None,
+            version: existing.map(|client| client.version).unwrap_or_default(),
+        }
+    }
+}
+
+impl ImportRecord for ClientRow {
+    fn validate(&self) -> Result<(), String> {
+        validate(&self.clone().into_client(None)).map_err(|error| error.body().message)
+    }
+}
+
+/// A vendor of an import file, matched on the name of its client and its own `name`.
+/// Secrets left empty or `[REDACTED]`, as the export writes them, keep their stored value.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct VendorRow {
+    pub client: String,
+    pub name: String,
+    pub host: String,
+    pub port: i32,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub ssh_key: Option<String>,
+    pub ssh_key_password: Option<String>,
+}
+
+impl VendorRow {
+    pub fn into_vendor(self, client_id: i64, existing: Option<&Vendor>) -> Vendor {
+        let keep = |new: Option<String>, old: Option<&String>| match new {
+            Some(value) if value != REDACTED => Some(value),
+            _ => old.cloned(),
+        };
+        Vendor {
+            id: existing.and_then(|vendor| vendor.id),
+            client_id,
+            name: self.name,
+            host: self.host,
+            port: self.port,
+            username: self.username,
+            password: keep(
+                self.password,
+                existing.and_then(|vendor| vendor.password.as_ref()),
+            ),
+            ssh_key: keep(
+                self.ssh_key,
+                existing.and_then(|vendor| vendor.ssh_key.as_ref()),
+            ),
+            ssh_key_password: keep(
+                self.ssh_key_password,
+                existing.and_then(|vendor| vendor.ssh_key_password.as_ref()),
+            ),
+            version: existing.map(|vendor| vendor.version).unwrap_or_default(),
+        }
+    }
+}
+
+impl ImportRecord for VendorRow {
+    fn validate(&self) -> Result<(), String> {
+        require("client", &self.client)?;
+        validate(&self.clone().into_vendor(0, None)).map_err(|error| error.body().message)
+    }
+}
+
+fn require(field: &str, value: &str) -> Result<(), String> {
+    if value.trim().is_empty() {
+        return Err(format!("{} is required", field));
+    }
+    Ok(())
+}
+
+/// Reads the records of an import file. Only a file that can't be read at all is an
+/// error; bad records are returned as such, with their line.
+pub fn parse_rows<T: ImportRecord>(format: Format, body: &[u8]) -> Result<Vec<Row<T>>, AppError> {
+    let rows: Vec<Row<T>> = match format {
+        Format::Csv => {
+            let mut reader = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(body);
+            let headers = reader
+                .headers()
+                .map_err(|error| AppError::InvalidInput(format!("Invalid CSV header: {}", error)))?
+                .clone();
+            reader
+                .records()
+                .enumerate()
+                .map(|(index, record)| {
+                    // The header is line 1, records can span several lines.
+                    let fallback = index as u64 + 2;
+                    match record {
+                        Ok(record) => Row {
+                            line: record.position().map_or(fallback, |at| at.line()),
+                            record: record
+                                .deserialize(Some(&headers))
+                                .map_err(|error| csv_error(&error)),
+                        },
+                        Err(error) => Row {
+                            line: error.position().map_or(fallback, |at| at.line()),
+                            record: Err(csv_error(&error)),
+                        },
+                    }
+                })
+                .collect()
+        }
+        Format::Ndjson => body
+            .split(|byte| *byte == b'\n')
+            .enumerate()
+            .filter(|(_, line)| !line.trim_ascii().is_empty())
+            .map(|(index, line)| Row {
+                line: index as u64 + 1,
+                record: serde_json::from_slice(line).map_err(|error| error.to_string()),
+            })
+            .collect(),
+    };
+    if rows.is_empty() {
+        return Err(AppError::InvalidInput(
+            "The file has no records".to_string(),
+        ));
+    }
+
+    Ok(rows
+        .into_iter()
+        .map(|row| Row {
+            line: row.line,
+            record: row
+                .record
+                .and_then(|record| record.validate().map(|_| record)),
+        })
+        .collect())
+}
+
+/// The message of a CSV error without the position, which is reported as the line.
+fn csv_error(error: &csv::Error) -> String {
+    match error.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => match err.field() {
+            Some(field) => format!("field {}: {}", field + 1, err.kind()),
+            None => err.kind().to_string(),
+        },
+        csv::ErrorKind::UnequalLengths {
+            expected_len, len, ..
+        } => format!("expected {} fields, found {}", expected_len, len),
+        _ => error.to_string(),
+    }
+}
+
+/// The outcome of an imported record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Created,
+    Updated,
+    Unchanged,
+}
+
+/// Why a record of an import failed.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct RowError {
+    pub line: u64,
+    pub error: String,
+}
+
+/// The result of `POST /import`. The counts are per record; with `all_or_nothing` and any
+/// error nothing was `committed`, whatever the counts say.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct ImportReport {
+    pub entity: Entity,
+    pub mode: ImportMode,
+    pub committed: bool,
+    pub total: usize,
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub failed: usize,
+    pub errors: Vec<RowError>,
+}
+
+impl ImportReport {
+    pub fn new(entity: Entity, mode: ImportMode, total: usize) -> Self {
+        Self {
+            entity,
+            mode,
+            committed: false,
+            total,
+            created: 0,
+            updated: 0,
+            unchanged: 0,
+            failed: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn succeeded(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Created => self.created += 1,
+            Outcome::Updated => self.updated += 1,
+            Outcome::Unchanged => self.unchanged += 1,
+        }
+    }
+
+    pub fn failed(&mut self, line: u64, error: String) {
+        self.failed += 1;
+        self.errors.push(RowError { line, error });
+    }
+
+    /// Whether the changes should be kept.
+    pub fn should_commit(&self) -> bool {
+        self.mode == ImportMode::BestEffort || self.errors.is_empty()
+    }
+
+    async fn finish(mut self, tx: sqlx::Transaction<'_, sqlx::Postgres>) -> Result<Self, AppError> {
+        self.committed = self.should_commit();
+        if self.committed {
+            tx.commit().await?;
+        } else {
+            tx.rollback().await?;
+        }
+        Ok(self)
+    }
+}
+
+/// A vendor as exported, with its client's name so the file can be imported again.
+/// Secrets are `[REDACTED]` when set.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, FromRow)]
+pub struct VendorExport {
+    pub id: i64,
+    pub client_id: i64,
+    pub client: String,
+    pub name: String,
+    pub host: String,
+    pub port: i32,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub ssh_key: Option<String>,
+    pub ssh_key_password: Option<String>,
+}
+
+/// Writes records in `format`, with the CSV header if `header` is set.
+pub fn encode<T: Serialize>(
+    format: Format,
+    records: &[T],
+    header: bool,
+) -> Result<Bytes, AppError> {
+    let mut buffer = Vec::new();
+    match format {
+        Format::Csv => {
+            let mut writer = csv::WriterBuilder::new()
+                .has_headers(header)
+                .from_writer(&mut buffer);
+            for record in records {
+                writer.serialize(record).map_err(|_| AppError::Unknown)?;
+            }
+            writer.flush().map_err(|_| AppError::Unknown)?;
+        }
+        Format::Ndjson => {
+            for record in records {
+                serde_json::to_writer(&mut buffer, record).map_err(|_| AppError::Unknown)?;
+                buffer.write_all(b"\n").map_err(|_| AppError::Unknown)?;
+            }
+        }
+    }
+    Ok(Bytes::from(buffer))
+}
+
+/// The export file, loaded and written a page at a time.
+pub fn export<T: BulkRepo>(
+    repo: T,
+    entity: Entity,
+    format: Format,
+    client_ids: Option<Vec<i64>>,
+) -> impl Stream<Item = Result<Bytes, AppError>> {
+    // The id to continue after, or `None` once the last page was written.
+    stream::try_unfold(Some(0), move |after_id| {
+        let repo = repo.clone();
+        let client_ids = client_ids.clone();
+        async move {
+            let Some(after_id) = after_id else {
+                return Ok(None);
+            };
+            let header = after_id == 0;
+            let (chunk, count, last_id) = match entity {
+                Entity::Clients => {
+                    let page = repo
+                        .export_clients(after_id, EXPORT_PAGE_SIZE, client_ids)
+                        .await?;
+                    let last_id = page.last().and_then(|client| client.id);
+                    (encode(format, &page, header)?, page.len(), last_id)
+                }
+                Entity::Vendors => {
+                    let page = repo
+                        .export_vendors(after_id, EXPORT_PAGE_SIZE, client_ids)
+                        .await?;
+                    let last_id = page.last().map(|vendor| vendor.id);
+                    (encode(format, &page, header)?, page.len(), last_id)
+                }
+            };
+            let next = match count as i64 {
+                EXPORT_PAGE_SIZE => last_id,
+                _ => None,
+            };
+            Ok(Some((chunk, next)))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_reports_bad_rows() {
+        let body = b"name,email,bucket\nacme,a@example.com,acme\n,b@example.com,b-bucket\nbad\n";
+        let rows = parse_rows::<ClientRow>(Format::Csv, body).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].line, 2);
+        assert_eq!(rows[0].record.as_ref().unwrap().name, "acme");
+        assert_eq!(rows[1].line, 3);
+        assert_eq!(rows[1].record, Err("name is required".to_string()));
+        assert_eq!(rows[2].line, 4);
+        assert!(rows[2].record.is_err());
+    }
+
+    #[test]
+    fn test_parse_ndjson_skips_blank_lines() {
+        let body = b"{\"client\":\"acme\",\"name\":\"bank\",\"host\":\"h\",\"port\":22}\n\n{\"client\":\"acme\"}\n";
+        let rows = parse_rows::<VendorRow>(Format::Ndjson, body).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].record.as_ref().unwrap().password, None);
+        assert_eq!(rows[1].line, 3);
+        assert!(rows[1]
+            .record
+            .as_ref()
+            .unwrap_err()
+            .contains("missing field"));
+        assert!(parse_rows::<VendorRow>(Format::Ndjson, b"\n").is_err());
+    }
+
+    #[test]
+    fn test_redacted_secrets_are_kept() {
+        let row = VendorRow {
+            client: "acme".to_string(),
+            name: "bank".to_string(),
+            host: "h".to_string(),
+            port: 22,
+            username: None,
+            password: Some(REDACTED.to_string()),
+            ssh_key: None,
+            ssh_key_password: Some("new".to_string()),
+        };
+        let existing = Vendor {
+            id: Some(7),
+            password: Some("hunter2".to_string()),
+            ssh_key: Some("key".to_string()),
+            ssh_key_password: Some("old".to_string()),
+            ..row.clone().into_vendor(1, None)
+        };
+        let vendor = row.into_vendor(1, Some(&existing));
+        assert_eq!(vendor.id, Some(7));
+        assert_eq!(vendor.password.as_deref(), Some("hunter2"));
+        assert_eq!(vendor.ssh_key.as_deref(), Some("key"));
+        assert_eq!(vendor.ssh_key_password.as_deref(), Some("new"));
+    }
+}
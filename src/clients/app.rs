@@ -1,25 +1,39 @@
 use super::{
     handlers::{
-        add_sftp, add_vendor_to_client, create_client, delete_client, get_client, get_clients,
-        reset_keys, update_client, update_vendor,
+        add_sftp, add_vendor_to_client, create_client, delete_client, delete_client_sftp,
+        get_client, get_client_sftp, get_client_sftps, get_clients, onboard_client,
+        reset_client_sftp_keys, reset_keys, restore_client, update_client, update_vendor,
     },
     models::ClientRepo,
 };
+use crate::{sftp::models::SftpRepo, vendors::models::VendorRepo};
 use axum::{
     routing::{delete, get, post, put},
     Router,
 };
 
-pub fn router<T: ClientRepo>(repo: T) -> Router {
+pub fn router<T: ClientRepo + SftpRepo + VendorRepo>(repo: T) -> Router {
     Router::new()
         .route("/clients", post(create_client::<T>))
         .route("/clients", get(get_clients::<T>))
+        .route("/clients/onboard", post(onboard_client::<T>))
         .route("/clients/:id", get(get_client::<T>))
         .route("/clients/:id", put(update_client::<T>))
         .route("/clients/:id", delete(delete_client::<T>))
+        .route("/clients/:id/restore", post(restore_client::<T>))
         .route("/clients/:id/vendor", post(add_vendor_to_client::<T>))
         .route("/clients/:id/vendor/:id", put(update_vendor::<T>))
         .route("/clients/:id/sftp", post(add_sftp::<T>))
+        .route("/clients/:id/sftp", get(get_client_sftps::<T>))
+        .route("/clients/:id/sftp/:sftp_id", get(get_client_sftp::<T>))
+        .route(
+            "/clients/:id/sftp/:sftp_id",
+            delete(delete_client_sftp::<T>),
+        )
+        .route(
+            "/clients/:id/sftp/:sftp_id/reset-keys",
+            put(reset_client_sftp_keys::<T>),
+        )
         .route("/clients/:id/reset-sftp-keys", put(reset_keys::<T>))
         .with_state(repo)
 }
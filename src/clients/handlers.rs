@@ -1,96 +1,358 @@
-use super::models::{Client, ClientRepo};
+use super::models::{Client, ClientFilter, ClientRepo, OnboardParams, Onboarded, Onboarding};
 use crate::{
     errors::models::AppError,
-    sftp::models::{SftpResponse, SftpUpdate},
-    vendors::models::Vendor,
+    sftp::models::{
+        KeyTypeParams, NewSftp, Rotation, SftpFilter, SftpOverview, SftpRepo, SftpResponse,
+    },
+    utils::{
+        auth::Caller,
+        etag::{ETag, IfMatch},
+        extract::{Path, Query},
+        list::{ListQuery, Page},
+        response::{Created, NoContent, Updated},
+        validate::Valid,
+        version::V1Response,
+    },
+    vendors::models::{Vendor, VendorOverview, VendorRepo},
 };
 use axum::{
-    extract::{Path, Query, State},
-    Json,
+    extract::State,
+    response::{IntoResponse, Response},
+    Extension, Json,
 };
-use std::collections::HashMap;
 
-/// The get clients endpoint handler. Returns a list of all clients as JSON.
+/// The get clients endpoint handler. Returns a page of clients as JSON.
+/// Agent callers only see the clients assigned to them.
+#[utoipa::path(
+    get, path = "/clients", tag = "clients",
+    params(ListQuery<ClientFilter>),
+    responses((status = 200, body = Page<Client>)),
+)]
 pub async fn get_clients<T: ClientRepo>(
     State(repo): State<T>,
-    Query(params): Query<HashMap<String, String>>,
-) -> Result<Json<Vec<Client>>, AppError> {
-    let name_filter = params.get("name").cloned();
-    let email_filter = params.get("email").cloned();
-
-    let clients = repo.get_all(name_filter, email_filter).await?;
+    Extension(caller): Extension<Caller>,
+    query: ListQuery<ClientFilter>,
+) -> Result<Json<Page<Client>>, AppError> {
+    let clients = repo.get_all(query, caller.client_filter()).await?;
     Ok(Json(clients))
 }
 
+#[utoipa::path(
+    post, path = "/clients", tag = "clients",
+    request_body = Client,
+    responses((status = 201, body = Client, headers(("Location" = String), ("ETag" = String)))),
+)]
 pub async fn create_client<T: ClientRepo>(
     State(repo): State<T>,
-    Json(client): Json<Client>,
-) -> Result<Json<i64>, AppError> {
+    Extension(caller): Extension<Caller>,
+    Valid(client): Valid<Client>,
+) -> Result<Created<(ETag, Json<Client>)>, AppError> {
+    caller.ensure_unrestricted()?;
     let client_id = repo.create(client).await?;
-    Ok(Json(client_id))
+    let client = find_client(&repo, client_id).await?;
+    Ok(Created {
+        location: format!("/clients/{}", client_id),
+        response: (ETag(client.version), Json(client)),
+        v1: V1Response::Id(client_id),
+    })
+}
+
+/// Creates a client with its vendors, SFTP accounts and agent links in one transaction.
+/// With `?dry_run=true` everything is checked and then rolled back. Each part of the
+/// document needs the scope of the route that creates it on its own.
+#[utoipa::path(
+    post, path = "/clients/onboard", tag = "clients",
+    params(OnboardParams),
+    request_body = Onboarding,
+    responses(
+        (status = 201, body = Onboarded, headers(("Location" = String))),
+        (status = 200, description = "A dry run, nothing was created", body = Onboarded),
+    ),
+)]
+pub async fn onboard_client<T: ClientRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Query(params): Query<OnboardParams>,
+    Valid(onboarding): Valid<Onboarding>,
+) -> Result<Response, AppError> {
+    caller.ensure_unrestricted()?;
+    if !onboarding.vendors.is_empty() {
+        caller.ensure_scope("vendors:write")?;
+    }
+    if !onboarding.sftp.is_empty() {
+        caller.ensure_scope("sftp:keys")?;
+    }
+    if !onboarding.agent_ids.is_empty() {
+        caller.ensure_scope("agents:write")?;
+    }
+    let onboarded = repo.onboard(onboarding, params.dry_run).await?;
+    if onboarded.dry_run {
+        return Ok(Json(onboarded).into_response());
+    }
+    Ok(Created {
+        location: format!("/clients/{}", onboarded.client_id),
+        response: Json(onboarded),
+        v1: V1Response::Ok,
+    }
+    .into_response())
 }
 
+#[utoipa::path(
+    get, path = "/clients/{id}", tag = "clients",
+    params(("id" = i64, Path, description = "The id of the client")),
+    responses((status = 200, body = Client, headers(("ETag" = String)))),
+)]
 pub async fn get_client<T: ClientRepo>(
     State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
     Path(id): Path<i64>,
-) -> Result<Json<Client>, AppError> {
-    match repo.get(id).await? {
-        Some(client) => Ok(Json(client)),
-        None => Err(AppError::NotFound(format!(
-            "Client with id {} not found",
-            id
-        ))),
-    }
+) -> Result<(ETag, Json<Client>), AppError> {
+    caller.ensure_client(id)?;
+    let client = find_client(&repo, id).await?;
+    Ok((ETag(client.version), Json(client)))
 }
 
+#[utoipa::path(
+    put, path = "/clients/{id}", tag = "clients",
+    params(("id" = i64, Path, description = "The id of the client"), IfMatch),
+    request_body = Client,
+    responses((status = 200, body = Client, headers(("ETag" = String)))),
+)]
 pub async fn update_client<T: ClientRepo>(
     State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
     Path(id): Path<i64>,
-    Json(client): Json<Client>,
-) -> Result<(), AppError> {
-    repo.update(id, client).await?;
-    Ok(())
+    if_match: IfMatch,
+    Valid(client): Valid<Client>,
+) -> Result<Updated<(ETag, Json<Client>)>, AppError> {
+    caller.ensure_client(id)?;
+    repo.update(id, client, if_match).await?;
+    let client = find_client(&repo, id).await?;
+    Ok(Updated((ETag(client.version), Json(client))))
 }
 
+#[utoipa::path(
+    delete, path = "/clients/{id}", tag = "clients",
+    params(("id" = i64, Path, description = "The id of the client"), IfMatch),
+    responses((status = 204)),
+)]
 pub async fn delete_client<T: ClientRepo>(
     State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
     Path(id): Path<i64>,
-) -> Result<(), AppError> {
-    repo.delete(id).await?;
-    Ok(())
+    if_match: IfMatch,
+) -> Result<NoContent, AppError> {
+    caller.ensure_client(id)?;
+    repo.delete(id, if_match).await?;
+    Ok(NoContent)
 }
 
-pub async fn add_vendor_to_client<T: ClientRepo>(
+/// Undoes the soft delete of a client that has not been purged yet.
+#[utoipa::path(
+    post, path = "/clients/{id}/restore", tag = "clients",
+    params(("id" = i64, Path, description = "The id of the client")),
+    responses((status = 200, body = Client)),
+)]
+pub async fn restore_client<T: ClientRepo>(
     State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<i64>,
+) -> Result<Json<Client>, AppError> {
+    caller.ensure_client(id)?;
+    let client = repo.restore(id).await?;
+    Ok(Json(client))
+}
+
+#[utoipa::path(
+    post, path = "/clients/{id}/vendor", tag = "clients",
+    params(("id" = i64, Path, description = "The id of the client")),
+    request_body = Vendor,
+    responses((status = 201, body = VendorOverview, headers(("Location" = String), ("ETag" = String)))),
+)]
+pub async fn add_vendor_to_client<T: ClientRepo + VendorRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
     Path(client_id): Path<i64>,
-    Json(vendor): Json<Vendor>,
-) -> Result<Json<i64>, AppError> {
+    Valid(vendor): Valid<Vendor>,
+) -> Result<Created<(ETag, Json<VendorOverview>)>, AppError> {
+    caller.ensure_client(client_id)?;
     let vendor_id = repo.add_vendor(client_id, vendor).await?;
-    Ok(Json(vendor_id))
+    let vendor = find_vendor(&repo, vendor_id).await?;
+    Ok(Created {
+        location: format!("/vendors/{}", vendor_id),
+        response: (ETag(vendor.version), Json(vendor)),
+        v1: V1Response::Id(vendor_id),
+    })
 }
 
-pub async fn update_vendor<T: ClientRepo>(
+#[utoipa::path(
+    put, path = "/clients/{id}/vendor/{vendor_id}", tag = "clients",
+    params(("id" = i64, Path, description = "The id of the client"), ("vendor_id" = i64, Path, description = "The id of the vendor"), IfMatch),
+    request_body = Vendor,
+    responses((status = 200, body = VendorOverview, headers(("ETag" = String)))),
+)]
+pub async fn update_vendor<T: ClientRepo + VendorRepo>(
     State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
     Path((client_id, vendor_id)): Path<(i64, i64)>,
-    Json(vendor): Json<Vendor>,
-) -> Result<(), AppError> {
-    repo.update_vendor(client_id, vendor_id, vendor).await?;
-    Ok(())
+    if_match: IfMatch,
+    Valid(vendor): Valid<Vendor>,
+) -> Result<Updated<(ETag, Json<VendorOverview>)>, AppError> {
+    caller.ensure_client(client_id)?;
+    repo.update_vendor(client_id, vendor_id, vendor, if_match)
+        .await?;
+    let vendor = find_vendor(&repo, vendor_id).await?;
+    Ok(Updated((ETag(vendor.version), Json(vendor))))
 }
 
+/// Creates an SFTP account with a new key pair of `key_type`, or with the uploaded
+/// `public_keys` when the body has them.
+#[utoipa::path(
+    post, path = "/clients/{id}/sftp", tag = "clients",
+    params(("id" = i64, Path, description = "The id of the client"), KeyTypeParams),
+    request_body = NewSftp,
+    responses((status = 201, body = SftpResponse, headers(("Location" = String)))),
+)]
 pub async fn add_sftp<T: ClientRepo>(
     State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
     Path(client_id): Path<i64>,
-    Json(sftp): Json<SftpUpdate>,
-) -> Result<Json<SftpResponse>, AppError> {
-    let sftp_response = repo.add_sftp(client_id, sftp).await?;
-    Ok(Json(sftp_response))
+    Query(params): Query<KeyTypeParams>,
+    Valid(sftp): Valid<NewSftp>,
+) -> Result<Created<Json<SftpResponse>>, AppError> {
+    caller.ensure_client(client_id)?;
+    let sftp_response = repo.add_sftp(client_id, sftp, params.key_type).await?;
+    Ok(Created {
+        location: format!("/sftp/{}", sftp_response.id),
+        response: Json(sftp_response),
+        v1: V1Response::Ok,
+    })
 }
 
+/// Rotates the key pair of every SFTP account of the client that has a generated one. Each
+/// account gets its own key pair, and the new private keys are only returned here. The
+/// `If-Match` header takes the client's ETag.
+#[utoipa::path(
+    put, path = "/clients/{id}/reset-sftp-keys", tag = "clients",
+    params(("id" = i64, Path, description = "The id of the client"), Rotation, IfMatch),
+    responses((status = 200, body = Vec<SftpResponse>)),
+)]
 pub async fn reset_keys<T: ClientRepo>(
     State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Path(client_id): Path<i64>,
+    Query(rotation): Query<Rotation>,
+    if_match: IfMatch,
+) -> Result<Json<Vec<SftpResponse>>, AppError> {
+    caller.ensure_client(client_id)?;
+    let sftp_responses = repo.reset_keys(client_id, rotation, if_match).await?;
+    Ok(Json(sftp_responses))
+}
+
+/// Lists the SFTP accounts of a client. Takes the same query parameters as `GET /sftp`.
+#[utoipa::path(
+    get, path = "/clients/{id}/sftp", tag = "clients",
+    params(("id" = i64, Path, description = "The id of the client"), ListQuery<SftpFilter>),
+    responses((status = 200, body = Page<SftpOverview>)),
+)]
+pub async fn get_client_sftps<T: ClientRepo + SftpRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
     Path(client_id): Path<i64>,
-) -> Result<(), AppError> {
-    repo.reset_keys(client_id).await?;
-    Ok(())
+    query: ListQuery<SftpFilter>,
+) -> Result<Json<Page<SftpOverview>>, AppError> {
+    caller.ensure_client(client_id)?;
+    find_client(&repo, client_id).await?;
+    let sftps = SftpRepo::get_all(&repo, query, Some(vec![client_id])).await?;
+    Ok(Json(sftps))
+}
+
+#[utoipa::path(
+    get, path = "/clients/{id}/sftp/{sftp_id}", tag = "clients",
+    params(("id" = i64, Path, description = "The id of the client"), ("sftp_id" = i64, Path, description = "The id of the SFTP account")),
+    responses((status = 200, body = SftpOverview, headers(("ETag" = String)))),
+)]
+pub async fn get_client_sftp<T: ClientRepo + SftpRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Path((client_id, sftp_id)): Path<(i64, i64)>,
+) -> Result<(ETag, Json<SftpOverview>), AppError> {
+    let sftp = find_client_sftp(&repo, &caller, client_id, sftp_id).await?;
+    Ok((ETag(sftp.version), Json(sftp)))
+}
+
+#[utoipa::path(
+    delete, path = "/clients/{id}/sftp/{sftp_id}", tag = "clients",
+    params(("id" = i64, Path, description = "The id of the client"), ("sftp_id" = i64, Path, description = "The id of the SFTP account"), IfMatch),
+    responses((status = 204)),
+)]
+pub async fn delete_client_sftp<T: ClientRepo + SftpRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Path((client_id, sftp_id)): Path<(i64, i64)>,
+    if_match: IfMatch,
+) -> Result<NoContent, AppError> {
+    find_client_sftp(&repo, &caller, client_id, sftp_id).await?;
+    SftpRepo::delete(&repo, sftp_id, if_match).await?;
+    Ok(NoContent)
+}
+
+/// Rotates the key pair of one SFTP account, leaving the client's other accounts alone.
+/// The new private key is only returned here.
+#[utoipa::path(
+    put, path = "/clients/{id}/sftp/{sftp_id}/reset-keys", tag = "clients",
+    params(("id" = i64, Path, description = "The id of the client"), ("sftp_id" = i64, Path, description = "The id of the SFTP account"), Rotation, IfMatch),
+    responses((status = 200, body = SftpResponse)),
+)]
+pub async fn reset_client_sftp_keys<T: ClientRepo + SftpRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Path((client_id, sftp_id)): Path<(i64, i64)>,
+    Query(rotation): Query<Rotation>,
+    if_match: IfMatch,
+) -> Result<Json<SftpResponse>, AppError> {
+    find_client_sftp(&repo, &caller, client_id, sftp_id).await?;
+    let sftp_response = SftpRepo::reset_keys(&repo, sftp_id, rotation, if_match).await?;
+    Ok(Json(sftp_response))
+}
+
+/// Loads a client, reporting a missing one as not found.
+async fn find_client<T: ClientRepo>(repo: &T, id: i64) -> Result<Client, AppError> {
+    match ClientRepo::get(repo, id).await? {
+        Some(client) => Ok(client),
+        None => Err(AppError::NotFound(format!(
+            "Client with id {} not found",
+            id
+        ))),
+    }
+}
+
+/// Loads a vendor, reporting a missing one as not found.
+async fn find_vendor<T: VendorRepo>(repo: &T, id: i64) -> Result<VendorOverview, AppError> {
+    match VendorRepo::get(repo, id).await? {
+        Some(vendor) => Ok(vendor),
+        None => Err(AppError::NotFound(format!(
+            "Vendor with id {} not found",
+            id
+        ))),
+    }
+}
+
+/// Loads an SFTP account through its client. Accounts of other clients are reported as
+/// not found, so ids can't be probed through another client's path.
+async fn find_client_sftp<T: ClientRepo + SftpRepo>(
+    repo: &T,
+    caller: &Caller,
+    client_id: i64,
+    sftp_id: i64,
+) -> Result<SftpOverview, AppError> {
+    caller.ensure_client(client_id)?;
+    find_client(repo, client_id).await?;
+    match SftpRepo::get(repo, sftp_id).await? {
+        Some(sftp) if sftp.client_id == client_id => Ok(sftp),
+        _ => Err(AppError::NotFound(format!(
+            "Sftp with id {} for client with id {} not found",
+            sftp_id, client_id
+        ))),
+    }
 }
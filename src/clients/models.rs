@@ -1,85 +1,124 @@
+use std::collections::HashSet;
+
 use crate::{
+    agents::models::{insert_agent_client, lock_agent},
+    audit::models as audit,
     errors::models::AppError,
     postgres::pool::PostgresRepo,
-    sftp::models::{SftpResponse, SftpUpdate},
-    utils::ssh::SSHKeyPair,
-    vendors::models::Vendor,
+    sftp::models::{
+        insert_key, insert_public_keys, lock_sftp, rotate_key, validate_source_ip_rules, NewSftp,
+        Rotation, SftpResponse, GENERATED,
+    },
+    utils::{
+        crypto::KeyRing,
+        etag::IfMatch,
+        filter::FilterField,
+        list::{ListFilter, ListQuery, Page},
+        ssh::{parse_public_keys, KeyType, SSHKeyPair},
+        validate::{rules, Errors, Validate},
+    },
+    vendors::models::{lock_vendor, Vendor, VendorSecrets},
 };
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{FromRow, PgConnection, Postgres, QueryBuilder};
+use utoipa::{IntoParams, ToSchema};
 
 /// Interface to the client database table.
 /// Supports all CRUD operations.
-/// ```
-/// get_all: Get all clients.
+/// ```text
+/// get_all: Get a page of clients.
 /// create: Create a new client.
 /// get: Get a client by id.
 /// update: Update a client by id.
-/// delete: Delete a client by id.
+/// delete: Soft delete a client by id.
+/// restore: Undo the soft delete of a client.
+/// purge_deleted: Hard delete the clients soft deleted before a point in time.
+/// onboard: Create a client with its vendors, SFTP accounts and agents at once.
 /// ```
 #[async_trait]
 pub trait ClientRepo: Send + Sync + Clone + 'static {
     async fn get_all(
         &self,
-        name_filter: Option<String>,
-        email_filter: Option<String>,
-    ) -> Result<Vec<Client>, AppError>;
+        query: ListQuery<ClientFilter>,
+        client_ids: Option<Vec<i64>>,
+    ) -> Result<Page<Client>, AppError>;
     async fn create(&self, client: Client) -> Result<i64, AppError>;
     async fn get(&self, id: i64) -> Result<Option<Client>, AppError>;
-    async fn update(&self, id: i64, client: Client) -> Result<(), AppError>;
-    async fn delete(&self, id: i64) -> Result<(), AppError>;
+    async fn update(&self, id: i64, client: Client, if_match: IfMatch) -> Result<(), AppError>;
+    /// Marks the client deleted. Its vendors, SFTP accounts and agent links are kept until
+    /// it is purged.
+    async fn delete(&self, id: i64, if_match: IfMatch) -> Result<(), AppError>;
+    async fn restore(&self, id: i64) -> Result<Client, AppError>;
+    /// Removes the clients deleted before `deleted_before`, with everything that cascades
+    /// from them. Returns the purged ids.
+    async fn purge_deleted(&self, deleted_before: DateTime<Utc>) -> Result<Vec<i64>, AppError>;
     async fn add_vendor(&self, client_id: i64, vendor: Vendor) -> Result<i64, AppError>;
     async fn update_vendor(
         &self,
         client_id: i64,
         vendor_id: i64,
         vendor: Vendor,
+        if_match: IfMatch,
     ) -> Result<(), AppError>;
-    async fn add_sftp(&self, client_id: i64, sftp: SftpUpdate) -> Result<SftpResponse, AppError>;
-    async fn reset_keys(&self, client_id: i64) -> Result<SSHKeyPair, AppError>;
+    async fn add_sftp(
+        &self,
+        client_id: i64,
+        sftp: NewSftp,
+        key_type: KeyType,
+    ) -> Result<SftpResponse, AppError>;
+    /// `if_match` is checked against the client's version, which the reset bumps.
+    async fn reset_keys(
+        &self,
+        client_id: i64,
+        rotation: Rotation,
+        if_match: IfMatch,
+    ) -> Result<Vec<SftpResponse>, AppError>;
+    /// Creates everything in `onboarding` or nothing. With `dry_run` the changes are
+    /// checked and then discarded.
+    async fn onboard(&self, onboarding: Onboarding, dry_run: bool) -> Result<Onboarded, AppError>;
 }
 
 #[async_trait]
 impl ClientRepo for PostgresRepo {
     async fn get_all(
         &self,
-        name_filter: Option<String>,
-        email_filter: Option<String>,
-    ) -> Result<Vec<Client>, AppError> {
-        let mut clients = sqlx::query_as!(Client, "SELECT * FROM clients")
+        query: ListQuery<ClientFilter>,
+        client_ids: Option<Vec<i64>>,
+    ) -> Result<Page<Client>, AppError> {
+        let mut count = QueryBuilder::new("SELECT COUNT(*) FROM clients WHERE TRUE");
+        push_filters(&mut count, &query, &client_ids);
+        let total: i64 = count.build_query_scalar().fetch_one(&self.pool).await?;
+
+        let mut select = QueryBuilder::new("SELECT * FROM clients WHERE TRUE");
+        push_filters(&mut select, &query, &client_ids);
+        query.push_cursor(&mut select);
+        query.push_order_and_limit(&mut select);
+        let clients = select
+            .build_query_as::<Client>()
             .fetch_all(&self.pool)
             .await?;
 
-        if let Some(name) = name_filter {
-            clients.retain(|client| client.name.contains(&name));
-        }
-
-        if let Some(email) = email_filter {
-            clients.retain(|client| client.email.contains(&email));
-        }
-
-        Ok(clients)
+        query.page(clients, total)
     }
 
     async fn create(&self, client: Client) -> Result<i64, AppError> {
-        let client_id = sqlx::query!(
-            "INSERT INTO clients (name, email, bucket) VALUES ($1, $2, $3) RETURNING id",
-            client.name,
-            client.email,
-            client.bucket,
-        )
-        .fetch_one(&self.pool)
-        .await?
-        .id;
+        let mut tx = self.pool.begin().await?;
+        let client_id = insert_client(&mut tx, client).await?;
+        tx.commit().await?;
 
         Ok(client_id)
     }
 
     async fn get(&self, id: i64) -> Result<Option<Client>, AppError> {
-        let client = sqlx::query_as!(Client, "SELECT * FROM clients WHERE id = $1", id)
-            .fetch_optional(&self.pool)
-            .await?;
+        let client = sqlx::query_as!(
+            Client,
+            "SELECT * FROM clients WHERE id = $1 AND deleted_at IS NULL",
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
 
         match client {
             Some(client) => Ok(Some(client)),
@@ -90,73 +129,111 @@ impl ClientRepo for PostgresRepo {
         }
     }
 
-    async fn update(&self, id: i64, client: Client) -> Result<(), AppError> {
-        let rows_affected = sqlx::query!(
-            "UPDATE clients SET name = $1, email = $2, bucket = $3 WHERE id = $4",
+    async fn update(&self, id: i64, client: Client, if_match: IfMatch) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        let before = lock_client(&mut tx, id).await?;
+        if_match.check("Client", id, before.version)?;
+
+        sqlx::query!(
+            "UPDATE clients SET name = $1, email = $2, bucket = $3, version = version + 1
+             WHERE id = $4",
             client.name,
             client.email,
             client.bucket,
             id
         )
-        .execute(&self.pool)
-        .await?
-        .rows_affected();
+        .execute(&mut *tx)
+        .await?;
+
+        let after = Client {
+            id: Some(id),
+            deleted_at: None,
+            version: before.version + 1,
+            ..client
+        };
+        audit::record(&mut tx, "update", "client", id, Some(&before), Some(&after)).await?;
+        tx.commit().await?;
+        Ok(())
+    }
 
-        if rows_affected == 0 {
-            Err(AppError::NotFound(format!(
-                "Client with id {} not found",
-                id
-            )))
-        } else {
-            Ok(())
-        }
+    async fn delete(&self, id: i64, if_match: IfMatch) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        let before = lock_client(&mut tx, id).await?;
+        if_match.check("Client", id, before.version)?;
+
+        let after = sqlx::query_as!(
+            Client,
+            "UPDATE clients SET deleted_at = now(), version = version + 1 WHERE id = $1
+             RETURNING *",
+            id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        audit::record(&mut tx, "delete", "client", id, Some(&before), Some(&after)).await?;
+        tx.commit().await?;
+        Ok(())
     }
 
-    async fn delete(&self, id: i64) -> Result<(), AppError> {
-        let rows_affected = sqlx::query!("DELETE FROM clients WHERE id = $1", id)
-            .execute(&self.pool)
-            .await?
-            .rows_affected();
+    async fn restore(&self, id: i64) -> Result<Client, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let before = sqlx::query_as!(
+            Client,
+            "SELECT * FROM clients WHERE id = $1 AND deleted_at IS NOT NULL FOR UPDATE",
+            id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| deleted_client_not_found(id))?;
 
-        if rows_affected == 0 {
-            Err(AppError::NotFound(format!(
-                "Client with id {} not found",
-                id
-            )))
-        } else {
-            Ok(())
+        let after = sqlx::query_as!(
+            Client,
+            "UPDATE clients SET deleted_at = NULL, version = version + 1 WHERE id = $1
+             RETURNING *",
+            id
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        audit::record(
+            &mut tx,
+            "restore",
+            "client",
+            id,
+            Some(&before),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(after)
+    }
+
+    async fn purge_deleted(&self, deleted_before: DateTime<Utc>) -> Result<Vec<i64>, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let purged = sqlx::query_as!(
+            Client,
+            "DELETE FROM clients WHERE deleted_at < $1 RETURNING *",
+            deleted_before
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let mut ids = Vec::with_capacity(purged.len());
+        for before in purged {
+            let id = before.id.unwrap_or_default();
+            audit::record(&mut tx, "purge", "client", id, Some(&before), None).await?;
+            ids.push(id);
         }
+        tx.commit().await?;
+        Ok(ids)
     }
 
     async fn add_vendor(&self, client_id: i64, vendor: Vendor) -> Result<i64, AppError> {
+        let mut tx = self.pool.begin().await?;
         // Check if the client exists
-        let client_exists: bool = sqlx::query!("SELECT id FROM clients WHERE id = $1", client_id)
-            .fetch_optional(&self.pool)
-            .await?
-            .is_some();
-
-        if !client_exists {
-            return Err(AppError::NotFound(format!(
-                "Client with id {} not found",
-                client_id
-            )));
-        }
-
-        // Insert Vendor
-        let vendor_id = sqlx::query!(
-                "INSERT INTO vendors (client_id, name, host, port, username, password, ssh_key, ssh_key_password) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING id",
-                client_id,
-                vendor.name,
-                vendor.host,
-                vendor.port,
-                vendor.username,
-                vendor.password,
-                vendor.ssh_key,
-                vendor.ssh_key_password
-            )
-            .fetch_one(&self.pool)
-            .await?
-            .id;
+        lock_client(&mut tx, client_id).await?;
+        let vendor_id = insert_vendor(&mut tx, &self.keys, client_id, vendor).await?;
+        tx.commit().await?;
 
         Ok(vendor_id)
     }
@@ -166,8 +243,22 @@ impl ClientRepo for PostgresRepo {
         client_id: i64,
         vendor_id: i64,
         vendor: Vendor,
+        if_match: IfMatch,
     ) -> Result<(), AppError> {
-        let rows_affected = sqlx::query!(
+        let mut tx = self.pool.begin().await?;
+        let before = match lock_vendor(&mut tx, &self.keys, vendor_id).await? {
+            Some(before) if before.client_id == client_id => before,
+            _ => {
+                return Err(AppError::NotFound(format!(
+                    "Vendor with id {} for client with id {} not found",
+                    vendor_id, client_id
+                )))
+            }
+        };
+        if_match.check("Vendor", vendor_id, before.version)?;
+
+        let secrets = VendorSecrets::encrypt(&vendor, &self.keys)?;
+        sqlx::query!(
             "UPDATE vendors SET
                 name = $1,
                 host = $2,
@@ -175,115 +266,481 @@ impl ClientRepo for PostgresRepo {
                 username = $4,
                 password = $5,
                 ssh_key = $6,
-                ssh_key_password = $7
-             WHERE client_id = $8 AND id = $9",
+                ssh_key_password = $7,
+                key_id = $8,
+                version = version + 1
+             WHERE client_id = $9 AND id = $10",
             vendor.name,
             vendor.host,
             vendor.port,
             vendor.username,
-            vendor.password,
-            vendor.ssh_key,
-            vendor.ssh_key_password,
+            secrets.password,
+            secrets.ssh_key,
+            secrets.ssh_key_password,
+            secrets.key_id,
             client_id,
             vendor_id
         )
-        .execute(&self.pool)
-        .await?
-        .rows_affected();
+        .execute(&mut *tx)
+        .await?;
 
-        if rows_affected == 0 {
-            Err(AppError::NotFound(format!(
-                "Vendor with id {} for client with id {} not found",
-                vendor_id, client_id
-            )))
-        } else {
-            Ok(())
-        }
+        let after = Vendor {
+            id: Some(vendor_id),
+            client_id,
+            version: before.version + 1,
+            ..vendor
+        };
+        audit::record(
+            &mut tx,
+            "update",
+            "vendor",
+            vendor_id,
+            Some(&before),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
     }
 
-    async fn add_sftp(&self, client_id: i64, sftp: SftpUpdate) -> Result<SftpResponse, AppError> {
-        // For some reason sqlx doesn't like SELECT 1.
-        let client_exists: bool = sqlx::query!("SELECT * FROM clients WHERE id = $1", client_id)
-            .fetch_optional(&self.pool)
-            .await?
-            .is_some();
+    async fn add_sftp(
+        &self,
+        client_id: i64,
+        new_sftp: NewSftp,
+        key_type: KeyType,
+    ) -> Result<SftpResponse, AppError> {
+        let mut tx = self.pool.begin().await?;
+        lock_client(&mut tx, client_id).await?;
+        let response = insert_sftp(&mut tx, &self.keys, client_id, new_sftp, key_type).await?;
+        tx.commit().await?;
+
+        Ok(response)
+    }
 
-        if !client_exists {
+    /// Gives each of the client's accounts with a generated key pair a new one. Accounts
+    /// using uploaded keys are left alone. The replaced keys keep working for the grace
+    /// period.
+    async fn reset_keys(
+        &self,
+        client_id: i64,
+        rotation: Rotation,
+        if_match: IfMatch,
+    ) -> Result<Vec<SftpResponse>, AppError> {
+        let grace_period = rotation.grace_period()?;
+
+        let mut tx = self.pool.begin().await?;
+        let client = lock_client(&mut tx, client_id).await?;
+        if_match.check("Client", client_id, client.version)?;
+
+        let accounts = sqlx::query_scalar!(
+            "SELECT id FROM sftp WHERE client_id = $1 AND private_key IS NOT NULL ORDER BY id",
+            client_id
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+
+        if accounts.is_empty() {
             return Err(AppError::NotFound(format!(
-                "Client with id {} not found",
+                "SFTP accounts with generated keys for client with id {} not found",
                 client_id
             )));
         }
 
-        // Generate SSH key pair.
-        let ssh_keys = SSHKeyPair::new();
+        let mut responses = Vec::with_capacity(accounts.len());
+        for sftp_id in accounts {
+            let ssh_keys = SSHKeyPair::generate(rotation.key_type)?;
+            let private_key = self.keys.encrypt(&ssh_keys.private_key)?;
+            let before = lock_sftp(&mut tx, sftp_id).await?;
+            rotate_key(
+                &mut tx,
+                sftp_id,
+                &ssh_keys,
+                &private_key,
+                self.keys.active_key_id(),
+                grace_period,
+            )
+            .await?;
+            let after = lock_sftp(&mut tx, sftp_id).await?;
+            audit::record(
+                &mut tx,
+                "reset_keys",
+                "sftp",
+                sftp_id,
+                before.as_ref(),
+                after.as_ref(),
+            )
+            .await?;
+            responses.push(SftpResponse::new(sftp_id, client_id, ssh_keys));
+        }
 
-        let sftp_id = sqlx::query!(
-            "INSERT INTO sftp (client_id, username, private_key, public_key, bucket_name, aws_role_arn) VALUES ($1, $2, $3, $4, $5, $6) RETURNING id",
-            client_id,
-            sftp.username,
-            ssh_keys.private_key,
-            ssh_keys.public_key,
-            sftp.bucket_name,
-            sftp.aws_role_arn,
+        sqlx::query!(
+            "UPDATE clients SET version = version + 1 WHERE id = $1",
+            client_id
         )
-        .fetch_one(&self.pool)
-        .await?
-        .id;
+        .execute(&mut *tx)
+        .await?;
 
-        let response = SftpResponse {
-            id: sftp_id,
-            client_id,
-            private_key: ssh_keys.private_key,
-            public_key: ssh_keys.public_key,
-        };
-
-        Ok(response)
+        tx.commit().await?;
+        Ok(responses)
     }
 
-    async fn reset_keys(&self, client_id: i64) -> Result<SSHKeyPair, AppError> {
-        let client_exists: bool = sqlx::query!("SELECT * FROM clients WHERE id = $1", client_id)
-            .fetch_optional(&self.pool)
-            .await?
-            .is_some();
+    async fn onboard(&self, onboarding: Onboarding, dry_run: bool) -> Result<Onboarded, AppError> {
+        onboarding.check_conflicts()?;
 
-        if !client_exists {
-            return Err(AppError::NotFound(format!(
-                "Client with id {} not found",
-                client_id
-            )));
+        let mut tx = self.pool.begin().await?;
+        let client_id = insert_client(&mut tx, onboarding.client).await?;
+
+        let mut vendor_ids = Vec::with_capacity(onboarding.vendors.len());
+        for vendor in onboarding.vendors {
+            let vendor = vendor.into_vendor(client_id);
+            vendor_ids.push(insert_vendor(&mut tx, &self.keys, client_id, vendor).await?);
+        }
+
+        let mut sftp = Vec::with_capacity(onboarding.sftp.len());
+        for account in onboarding.sftp {
+            let response = insert_sftp(
+                &mut tx,
+                &self.keys,
+                client_id,
+                account.sftp,
+                account.key_type,
+            )
+            .await?;
+            sftp.push(response);
+        }
+
+        for agent_id in &onboarding.agent_ids {
+            lock_agent(&mut tx, *agent_id).await?;
+            insert_agent_client(&mut tx, *agent_id, client_id).await?;
         }
 
-        // Generate SSH key pair.
-        let ssh_keys = SSHKeyPair::new();
+        if dry_run {
+            tx.rollback().await?;
+        } else {
+            tx.commit().await?;
+        }
 
-        let rows_affected = sqlx::query!(
-            "UPDATE sftp SET private_key = $1, public_key = $2 WHERE client_id = $3",
-            ssh_keys.private_key,
-            ssh_keys.public_key,
+        Ok(Onboarded {
+            dry_run,
             client_id,
+            vendor_ids,
+            sftp,
+            agent_ids: onboarding.agent_ids,
+        })
+    }
+}
+
+/// Inserts a client, recording it in the audit log.
+pub async fn insert_client(conn: &mut PgConnection, client: Client) -> Result<i64, AppError> {
+    let client_id = sqlx::query!(
+        "INSERT INTO clients (name, email, bucket) VALUES ($1, $2, $3) RETURNING id",
+        client.name,
+        client.email,
+        client.bucket,
+    )
+    .fetch_one(&mut *conn)
+    .await?
+    .id;
+
+    let created = Client {
+        id: Some(client_id),
+        version: 1,
+        ..client
+    };
+    audit::record(conn, "create", "client", client_id, None, Some(&created)).await?;
+    Ok(client_id)
+}
+
+/// Inserts a vendor of a client locked by the caller, recording it in the audit log.
+pub async fn insert_vendor(
+    conn: &mut PgConnection,
+    keys: &KeyRing,
+    client_id: i64,
+    vendor: Vendor,
+) -> Result<i64, AppError> {
+    let secrets = VendorSecrets::encrypt(&vendor, keys)?;
+    let vendor_id = sqlx::query!(
+            "INSERT INTO vendors (client_id, name, host, port, username, password, ssh_key, ssh_key_password, key_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING id",
+            client_id,
+            vendor.name,
+            vendor.host,
+            vendor.port,
+            vendor.username,
+            secrets.password,
+            secrets.ssh_key,
+            secrets.ssh_key_password,
+            secrets.key_id
         )
-        .execute(&self.pool)
+        .fetch_one(&mut *conn)
         .await?
-        .rows_affected();
+        .id;
 
-        if rows_affected == 0 {
-            Err(AppError::NotFound(format!(
-                "SFTP keys for client with id {} not found",
-                client_id
-            )))
-        } else {
-            Ok(ssh_keys)
-        }
+    let created = Vendor {
+        id: Some(vendor_id),
+        client_id,
+        version: 1,
+        ..vendor
+    };
+    audit::record(conn, "create", "vendor", vendor_id, None, Some(&created)).await?;
+    Ok(vendor_id)
+}
+
+/// Creates an SFTP account of a client locked by the caller, with a new key pair of
+/// `key_type` or the uploaded keys, and records it in the audit log.
+async fn insert_sftp(
+    conn: &mut PgConnection,
+    keys: &KeyRing,
+    client_id: i64,
+    new_sftp: NewSftp,
+    key_type: KeyType,
+) -> Result<SftpResponse, AppError> {
+    let NewSftp { sftp, public_keys } = new_sftp;
+    if let Some(rules) = &sftp.allowed_source_ips {
+        validate_source_ip_rules(rules)?;
     }
+    let uploaded = public_keys.as_deref().map(parse_public_keys).transpose()?;
+
+    // Generate SSH key pair, unless the client brought their own public keys.
+    let ssh_keys = match uploaded {
+        Some(_) => None,
+        None => Some(SSHKeyPair::generate(key_type)?),
+    };
+    let private_key = keys.encrypt_opt(ssh_keys.as_ref().map(|keys| keys.private_key.as_str()))?;
+
+    let sftp_id = sqlx::query!(
+        "INSERT INTO sftp (client_id, username, private_key, bucket_name, aws_role_arn, allowed_source_ips, key_id, key_type) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING id",
+        client_id,
+        sftp.username,
+        private_key,
+        sftp.bucket_name,
+        sftp.aws_role_arn,
+        sftp.allowed_source_ips.as_deref(),
+        private_key.as_ref().map(|_| keys.active_key_id()),
+        ssh_keys.as_ref().map(|_| key_type.as_str()),
+    )
+    .fetch_one(&mut *conn)
+    .await?
+    .id;
+
+    let inserted = match (&ssh_keys, &uploaded) {
+        (Some(ssh_keys), _) => vec![
+            insert_key(
+                &mut *conn,
+                sftp_id,
+                GENERATED,
+                key_type.as_str(),
+                &ssh_keys.public_key,
+                &ssh_keys.fingerprint,
+            )
+            .await?,
+        ],
+        (None, Some(uploaded)) => insert_public_keys(&mut *conn, sftp_id, uploaded).await?,
+        (None, None) => Vec::new(),
+    };
+
+    let created = lock_sftp(&mut *conn, sftp_id).await?;
+    audit::record(
+        &mut *conn,
+        "create",
+        "sftp",
+        sftp_id,
+        None,
+        created.as_ref(),
+    )
+    .await?;
+
+    Ok(match ssh_keys {
+        Some(ssh_keys) => SftpResponse::new(sftp_id, client_id, ssh_keys),
+        None => SftpResponse::uploaded(sftp_id, client_id, inserted),
+    })
 }
 
-#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, FromRow)]
+/// Loads a client inside a transaction and locks it for the rest of the transaction.
+/// Deleted clients are not found.
+pub async fn lock_client(conn: &mut PgConnection, id: i64) -> Result<Client, AppError> {
+    let client = sqlx::query_as!(
+        Client,
+        "SELECT * FROM clients WHERE id = $1 AND deleted_at IS NULL FOR UPDATE",
+        id
+    )
+    .fetch_optional(conn)
+    .await?;
+
+    client.ok_or_else(|| AppError::NotFound(format!("Client with id {} not found", id)))
+}
+
+pub fn deleted_client_not_found(id: i64) -> AppError {
+    AppError::NotFound(format!("Deleted client with id {} not found", id))
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, FromRow, ToSchema)]
 pub struct Client {
     pub id: Option<i64>,
     pub name: String,
     pub email: String,
     pub bucket: String,
+    /// Set while the client is soft deleted. Only changed by delete and restore.
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Bumped by every change, see [`ETag`](crate::utils::etag::ETag).
+    #[serde(default, skip_deserializing)]
+    pub version: i64,
+}
+
+impl Validate for Client {
+    fn validate(&self, errors: &mut Errors) {
+        errors.check("name", rules::required(&self.name));
+        errors.check("email", rules::email(&self.email));
+        errors.check("bucket", rules::bucket(&self.bucket));
+    }
+}
+
+/// The body of `POST /clients/onboard`: a new client with everything it starts with.
+#[derive(Deserialize, Serialize, Debug, Clone, ToSchema)]
+pub struct Onboarding {
+    pub client: Client,
+    #[serde(default)]
+    pub vendors: Vec<OnboardingVendor>,
+    #[serde(default)]
+    pub sftp: Vec<OnboardingSftp>,
+    /// Agents the client is assigned to.
+    #[serde(default)]
+    pub agent_ids: Vec<i64>,
+}
+
+impl Validate for Onboarding {
+    fn validate(&self, errors: &mut Errors) {
+        errors.nested("client", &self.client);
+        for (i, vendor) in self.vendors.iter().enumerate() {
+            errors.nested(&format!("vendors[{}]", i), vendor);
+        }
+        for (i, account) in self.sftp.iter().enumerate() {
+            errors.nested(&format!("sftp[{}]", i), &account.sftp);
+        }
+    }
+}
+
+impl Onboarding {
+    /// Catches the conflicts within the document before anything is written.
+    pub fn check_conflicts(&self) -> Result<(), AppError> {
+        let mut usernames = HashSet::new();
+        for account in &self.sftp {
+            if let Some(username) = &account.sftp.sftp.username {
+                if !usernames.insert(username) {
+                    return Err(AppError::InvalidInput(format!(
+                        "Sftp user {} appears more than once",
+                        username
+                    )));
+                }
+            }
+        }
+        let mut agent_ids = HashSet::new();
+        if let Some(agent_id) = self.agent_ids.iter().find(|id| !agent_ids.insert(**id)) {
+            return Err(AppError::InvalidInput(format!(
+                "Agent with id {} appears more than once",
+                agent_id
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A vendor in an [`Onboarding`]. It belongs to the new client, so it has no `client_id`.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, ToSchema)]
+pub struct OnboardingVendor {
+    pub name: String,
+    pub host: String,
+    pub port: i32,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub ssh_key: Option<String>,
+    pub ssh_key_password: Option<String>,
+}
+
+impl Validate for OnboardingVendor {
+    fn validate(&self, errors: &mut Errors) {
+        // The client is checked on its own.
+        self.clone().into_vendor(0).validate(errors);
+    }
+}
+
+impl OnboardingVendor {
+    pub fn into_vendor(self, client_id: i64) -> Vendor {
+        Vendor {
+            id: None,
+            client_id,
+            name: self.name,
+            host: self.host,
+            port: self.port,
+            username: self.username,
+            password: self.password,
+            ssh_key: self.ssh_key,
+            ssh_key_password: self.ssh_key_password,
+            version: 0,
+        }
+    }
+}
+
+/// An SFTP account in an [`Onboarding`], as the body of `POST /clients/:id/sftp` with the
+/// `key_type` of its key pair.
+#[derive(Deserialize, Serialize, Debug, Clone, ToSchema)]
+pub struct OnboardingSftp {
+    #[serde(flatten)]
+    pub sftp: NewSftp,
+    #[serde(default)]
+    pub key_type: KeyType,
+}
+
+/// `?dry_run=true` for `POST /clients/onboard`.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OnboardParams {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// What `POST /clients/onboard` created. Generated private keys are only returned here.
+/// In a dry run nothing was kept: the ids were never committed and the keys are unused.
+#[derive(Deserialize, Serialize, Debug, Clone, ToSchema)]
+pub struct Onboarded {
+    pub dry_run: bool,
+    pub client_id: i64,
+    pub vendor_ids: Vec<i64>,
+    pub sftp: Vec<SftpResponse>,
+    pub agent_ids: Vec<i64>,
+}
+
+/// Filters for `GET /clients`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ClientFilter;
+
+impl ListFilter for ClientFilter {
+    const SORT_FIELDS: &'static [&'static str] = &["id", "name", "email"];
+    const FILTER_FIELDS: &'static [FilterField] = &[
+        FilterField::int("id"),
+        FilterField::text("name"),
+        FilterField::text("email"),
+        FilterField::text("bucket"),
+    ];
+    const SOFT_DELETED: bool = true;
+}
+
+/// Appends the query's filters, restricted to `client_ids` for agent callers.
+fn push_filters(
+    builder: &mut QueryBuilder<'_, Postgres>,
+    query: &ListQuery<ClientFilter>,
+    client_ids: &Option<Vec<i64>>,
+) {
+    query.filters.push(builder);
+    if !query.include_deleted {
+        builder.push(" AND deleted_at IS NULL");
+    }
+    if let Some(client_ids) = client_ids {
+        builder
+            .push(" AND id = ANY(")
+            .push_bind(client_ids.clone())
+            .push(")");
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone, FromRow)]
@@ -5,6 +5,10 @@ use axum::{Extension, Json};
 use super::models::AppConfig;
 
 /// The config endpoint handler.
+#[utoipa::path(
+    get, path = "/config", tag = "config",
+    responses((status = 200, body = AppConfig)),
+)]
 pub async fn config(Extension(config): Extension<Arc<AppConfig>>) -> Json<AppConfig> {
     Json(config.as_ref().clone())
 }
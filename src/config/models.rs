@@ -1,19 +1,130 @@
-use std::env;
+use std::{collections::HashMap, env};
 
 use config::{Config, ConfigError, Environment, File};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 /// These are the environment variables that we anticipate will be used to configure the application.
-/// ```
+/// ```text
 /// database_url: String
 /// api_key: String
 /// log_level: String
+/// master_key_id: String
+/// master_keys: HashMap<String, String>
+/// key_rotation: KeyRotationConfig
+/// webhooks: WebhookConfig
+/// retention: RetentionConfig
 /// ```
-#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+/// `master_keys` maps key ids to base64 encoded 256-bit keys used to encrypt secrets at rest.
+/// New writes use `master_key_id`; older keys stay listed until their rows are re-encrypted.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, ToSchema)]
 pub struct AppConfig {
     pub database_url: String,
     pub api_key: String,
     pub log_level: String,
+    pub master_key_id: String,
+    #[serde(skip_serializing)]
+    pub master_keys: HashMap<String, String>,
+    #[serde(default)]
+    pub key_rotation: KeyRotationConfig,
+    #[serde(default)]
+    pub webhooks: WebhookConfig,
+    #[serde(default)]
+    pub retention: RetentionConfig,
+}
+
+/// The `[key_rotation]` section, configuring the job that rotates old SFTP keys.
+/// ```text
+/// enabled: bool             run it in the background, off by default
+/// interval_minutes: u64     how often it runs, 60
+/// max_key_age_days: u32     keys created longer ago are rotated, 90
+/// grace_period_hours: u32   how long the replaced keys keep working, 24
+/// ```
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, ToSchema)]
+#[serde(default)]
+pub struct KeyRotationConfig {
+    pub enabled: bool,
+    pub interval_minutes: u64,
+    pub max_key_age_days: u32,
+    pub grace_period_hours: u32,
+}
+
+impl Default for KeyRotationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval_minutes: 60,
+            max_key_age_days: 90,
+            grace_period_hours: 24,
+        }
+    }
+}
+
+/// The `[webhooks]` section, configuring the dispatcher that delivers webhook events.
+/// ```text
+/// enabled: bool                  run it in the background, true
+/// poll_interval_seconds: u64     how often the outbox is checked, 5
+/// batch_size: u32                deliveries sent per check, 50
+/// timeout_seconds: u64           how long a receiver has to respond, 10
+/// max_attempts: u32              attempts before a delivery is dead lettered, 8
+/// initial_backoff_seconds: u64   wait after the first failure, doubled after each one, 30
+/// max_backoff_seconds: u64       longest wait between attempts, 3600
+/// allow_private_urls: bool       deliver over http and to internal addresses, and let
+///                                vendor connections reach internal hosts, false
+/// ```
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, ToSchema)]
+#[serde(default)]
+pub struct WebhookConfig {
+    pub enabled: bool,
+    pub poll_interval_seconds: u64,
+    pub batch_size: u32,
+    pub timeout_seconds: u64,
+    pub max_attempts: u32,
+    pub initial_backoff_seconds: u64,
+    pub max_backoff_seconds: u64,
+    /// Only for local receivers and vendor servers. Left off, webhooks can't be pointed at
+    /// loopback, link-local or private addresses such as the cloud metadata endpoint, and
+    /// vendor connection tests and host key captures refuse to dial them.
+    pub allow_private_urls: bool,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            poll_interval_seconds: 5,
+            batch_size: 50,
+            timeout_seconds: 10,
+            max_attempts: 8,
+            initial_backoff_seconds: 30,
+            max_backoff_seconds: 3600,
+            allow_private_urls: false,
+        }
+    }
+}
+
+/// The `[retention]` section, configuring the job that purges soft deleted clients.
+/// ```text
+/// enabled: bool             run it in the background, true
+/// interval_minutes: u64     how often it runs, 60
+/// retention_days: u32       clients deleted longer ago are purged for good, 30
+/// ```
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, ToSchema)]
+#[serde(default)]
+pub struct RetentionConfig {
+    pub enabled: bool,
+    pub interval_minutes: u64,
+    pub retention_days: u32,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_minutes: 60,
+            retention_days: 30,
+        }
+    }
 }
 
 /// The application configuration is loaded from the config/local.toml file or the environment.
@@ -0,0 +1,8 @@
+use axum::http::Uri;
+
+use super::models::AppError;
+
+/// Answers requests no route matches with the usual error body.
+pub async fn not_found(uri: Uri) -> AppError {
+    AppError::NotFound(format!("No route for {}", uri.path()))
+}
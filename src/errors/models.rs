@@ -1,31 +1,217 @@
 use axum::response::{IntoResponse, Response};
+use axum::Json;
 use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::PgDatabaseError;
 use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::utils::request_id::current_request_id;
 
 #[derive(Debug, Error)]
 pub enum AppError {
     #[error("Database error: {0}")]
-    DatabaseError(#[from] sqlx::Error),
+    DatabaseError(sqlx::Error),
     #[error("Not found: {0}")]
     NotFound(String),
     #[error("Invalid input: {0}")]
     InvalidInput(String),
+    /// A request body breaking the rules of its fields, see
+    /// [`Valid`](crate::utils::validate::Valid).
+    #[error("Validation failed: {}", join(.0))]
+    Validation(Vec<FieldError>),
+    /// A unique constraint, e.g. a taken SFTP username.
+    #[error("Conflict: {0}")]
+    Conflict(FieldError),
+    /// A reference to a row that doesn't exist, e.g. a vendor moved to an unknown client.
+    #[error("Invalid reference: {0}")]
+    InvalidReference(FieldError),
     #[error("Unauthorized")]
     Unauthorized(String),
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    #[error("Precondition failed: {0}")]
+    PreconditionFailed(String),
+    #[error("Host key mismatch: {0}")]
+    HostKeyMismatch(String),
+    /// A server we called on the caller's behalf, e.g. a vendor's SFTP server, couldn't be
+    /// reached or failed.
+    #[error("Upstream error: {0}")]
+    Upstream(String),
+    #[error("Encryption error: {0}")]
+    EncryptionError(String),
     #[error("Unknown error")]
     Unknown,
 }
 
+/// An error about a single field of the request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.field, self.message)
+    }
+}
+
+fn join(errors: &[FieldError]) -> String {
+    let errors: Vec<String> = errors.iter().map(FieldError::to_string).collect();
+    errors.join(", ")
+}
+
+/// The JSON body of every error response. Clients should branch on `code`, the
+/// `message` is for humans and may change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<FieldError>,
+    pub request_id: Option<String>,
+}
+
+/// Postgres error codes, see https://www.postgresql.org/docs/current/errcodes-appendix.html
+const UNIQUE_VIOLATION: &str = "23505";
+const FOREIGN_KEY_VIOLATION: &str = "23503";
+
+impl From<sqlx::Error> for AppError {
+    /// Maps constraint violations to errors the caller can act on. Everything else stays a
+    /// database error.
+    fn from(err: sqlx::Error) -> Self {
+        let sqlx::Error::Database(db_err) = &err else {
+            return AppError::DatabaseError(err);
+        };
+        let field = db_err
+            .try_downcast_ref::<PgDatabaseError>()
+            .and_then(|pg_err| pg_err.detail())
+            .and_then(constraint_columns)
+            .or_else(|| db_err.constraint())
+            .unwrap_or("value")
+            .to_string();
+        match db_err.code().as_deref() {
+            Some(UNIQUE_VIOLATION) => AppError::Conflict(FieldError::new(field, "already exists")),
+            Some(FOREIGN_KEY_VIOLATION) => {
+                AppError::InvalidReference(FieldError::new(field, "does not exist"))
+            }
+            _ => AppError::DatabaseError(err),
+        }
+    }
+}
+
+/// The columns from a constraint violation detail like `Key (username)=(alice) already
+/// exists.`. The values are left out, they may be secret.
+fn constraint_columns(detail: &str) -> Option<&str> {
+    let columns = detail.strip_prefix("Key (")?;
+    columns.find(")=").map(|end| &columns[..end])
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::InvalidReference(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            AppError::HostKeyMismatch(_) | AppError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            AppError::DatabaseError(_) | AppError::EncryptionError(_) | AppError::Unknown => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    /// The stable, machine readable code of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "not_found",
+            AppError::InvalidInput(_) => "invalid_input",
+            AppError::Validation(_) => "validation_failed",
+            AppError::Conflict(_) => "conflict",
+            AppError::InvalidReference(_) => "invalid_reference",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::PreconditionFailed(_) => "precondition_failed",
+            AppError::HostKeyMismatch(_) => "host_key_mismatch",
+            AppError::Upstream(_) => "upstream_error",
+            AppError::DatabaseError(_) | AppError::EncryptionError(_) | AppError::Unknown => {
+                "internal_error"
+            }
+        }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        let (message, details) = match self {
+            AppError::NotFound(message)
+            | AppError::InvalidInput(message)
+            | AppError::Forbidden(message)
+            | AppError::PreconditionFailed(message)
+            | AppError::HostKeyMismatch(message)
+            | AppError::Upstream(message) => (message.clone(), vec![]),
+            AppError::Unauthorized(_) => ("Unauthorized".to_string(), vec![]),
+            AppError::Validation(errors) => (join(errors), errors.clone()),
+            AppError::Conflict(field) | AppError::InvalidReference(field) => {
+                (field.to_string(), vec![field.clone()])
+            }
+            // Don't leak queries, constraint names or key ids to the caller.
+            AppError::DatabaseError(_) | AppError::EncryptionError(_) | AppError::Unknown => {
+                ("Internal server error".to_string(), vec![])
+            }
+        };
+        ErrorBody {
+            code: self.code().to_string(),
+            message,
+            details,
+            request_id: current_request_id(),
+        }
+    }
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            AppError::DatabaseError(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-            AppError::NotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
-            AppError::InvalidInput(_) => (StatusCode::BAD_REQUEST, self.to_string()),
-            AppError::Unauthorized(_) => (StatusCode::UNAUTHORIZED, self.to_string()),
-            AppError::Unknown => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-        };
+        let status = self.status();
+        if status.is_server_error() {
+            tracing::error!(error = %self, "request failed");
+        }
+        (status, Json(self.body())).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constraint_columns() {
+        assert_eq!(
+            constraint_columns("Key (username)=(alice) already exists."),
+            Some("username")
+        );
+        assert_eq!(
+            constraint_columns("Key (sftp_id, fingerprint)=(1, SHA256:x) already exists."),
+            Some("sftp_id, fingerprint")
+        );
+        assert_eq!(constraint_columns("Failing row contains (1)."), None);
+    }
 
-        (status, error_message).into_response()
+    #[test]
+    fn test_internal_errors_are_not_leaked() {
+        let body = AppError::EncryptionError("Master key 7 is not configured".to_string()).body();
+        assert_eq!(body.code, "internal_error");
+        assert_eq!(body.message, "Internal server error");
     }
 }
@@ -3,7 +3,7 @@ use axum::{routing::get, Router};
 use axum_tracing_opentelemetry::middleware::{OtelAxumLayer, OtelInResponseLayer};
 
 /// The router for the health check endpoint.
-/// ```
+/// ```text
 /// GET /health
 /// ```
 /// Returns:
@@ -14,6 +14,6 @@ use axum_tracing_opentelemetry::middleware::{OtelAxumLayer, OtelInResponseLayer}
 pub fn router() -> Router {
     Router::new()
         .route("/health", get(health))
-        .layer(OtelInResponseLayer::default())
+        .layer(OtelInResponseLayer)
         .layer(OtelAxumLayer::default())
 }
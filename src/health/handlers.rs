@@ -3,6 +3,10 @@ use axum::Json;
 
 /// The health check endpoint handler.
 /// Currently it simply returns a Health struct with a status of "OK".
+#[utoipa::path(
+    get, path = "/health", tag = "health",
+    responses((status = 200, body = Health)),
+)]
 pub async fn health() -> Json<Health> {
     let health_stats = Health {
         status: "OK".to_string(),
@@ -1,9 +1,17 @@
 pub mod agents;
+pub mod api_keys;
+pub mod audit;
+pub mod bulk;
 pub mod clients;
 pub mod config;
 pub mod errors;
 pub mod health;
+pub mod memory;
+pub mod openapi;
 pub mod postgres;
+pub mod retention;
+pub mod rotation;
 pub mod sftp;
 pub mod utils;
 pub mod vendors;
+pub mod webhooks;
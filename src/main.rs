@@ -1,27 +1,28 @@
 #![crate_name = "user_manager_api"]
 
-mod agents;
-mod clients;
-mod errors;
-mod postgres;
-mod sftp;
 mod shutdown;
-mod utils;
-mod vendors;
 
-use crate::postgres::pool::PostgresRepo;
 use crate::shutdown::shutdown_signal;
-use crate::utils::auth::auth;
 
 use axum::middleware;
 use axum_tracing_opentelemetry::middleware::{OtelAxumLayer, OtelInResponseLayer};
 use std::future::IntoFuture;
 use std::sync::Arc;
+use tokio::sync::watch;
 use tower::ServiceBuilder;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use user_manager_api::config::models::AppConfig;
-use user_manager_api::{config, health};
+use user_manager_api::errors::handlers::not_found;
+use user_manager_api::postgres::pool::PostgresRepo;
+use user_manager_api::utils::auth::auth;
+use user_manager_api::utils::crypto::KeyRing;
+use user_manager_api::utils::request_id::request_id;
+use user_manager_api::utils::version::versioned;
+use user_manager_api::{
+    agents, api_keys, audit, bulk, clients, config, health, openapi, retention, rotation, sftp,
+    vendors, webhooks,
+};
 
 /// The main function is the entry point of the application.
 /// TODO: We still need to enable the OpenTelemetry layer to send traces to Honeycomb.io.
@@ -37,37 +38,105 @@ async fn main() {
         .try_init()
         .unwrap();
 
-    // Setup the Postgres connection pool.
-    let pg_pool = PostgresRepo::new(&cfg.database_url).await;
+    // Setup the Postgres connection pool and the keys used to encrypt secrets at rest.
+    let keys = KeyRing::from_config(&cfg).unwrap();
+    let pg_pool = PostgresRepo::new(&cfg.database_url, keys).await;
 
     // Setup the routers for the various parts of the application.
     let client_router = clients::app::router(pg_pool.clone());
-    let vendor_router = vendors::app::router(pg_pool.clone());
+    let vendor_router = vendors::app::router(pg_pool.clone(), cfg.webhooks.allow_private_urls);
     let sftp_router = sftp::app::router(pg_pool.clone());
-    let agent_router = agents::app::router(pg_pool);
+    let agent_router = agents::app::router(pg_pool.clone());
+    let api_key_router = api_keys::app::router(pg_pool.clone());
+    let audit_router = audit::app::router(pg_pool.clone());
+    let bulk_router = bulk::app::router(pg_pool.clone());
+    let rotation_router = rotation::app::router(pg_pool.clone(), cfg.key_rotation.clone());
+    let rotation_repo = pg_pool.clone();
+    let retention_router = retention::app::router(pg_pool.clone(), cfg.retention.clone());
+    let retention_repo = pg_pool.clone();
+    let webhook_router = webhooks::app::router(pg_pool.clone(), cfg.webhooks.clone());
+    let webhook_repo = pg_pool.clone();
     let config_router = config::app::router(cfg.clone());
     let health_router = health::app::router();
 
-    // Setup the auth layer.
+    // Setup the auth layer. API keys are looked up in Postgres; the key from the config
+    // is kept as a bootstrap admin key.
     let token = Arc::new(cfg.api_key.clone());
     let auth_layer = ServiceBuilder::new()
         .layer(middleware::from_fn(move |req, next| {
             let token = token.clone();
-            async move { auth(req, next, token).await }
+            let repo = pg_pool.clone();
+            async move { auth(req, next, repo, token).await }
         }))
         .into_inner();
 
-    // Merge all the routers into a single app.
-    let app = client_router
+    // Merge all the routers into a single API.
+    let api = client_router
         .merge(vendor_router)
         .merge(sftp_router)
         .merge(agent_router)
+        .merge(api_key_router)
+        .merge(audit_router)
+        .merge(bulk_router)
+        .merge(rotation_router)
+        .merge(retention_router)
+        .merge(webhook_router)
         .merge(config_router)
         .merge(health_router)
-        .layer(OtelInResponseLayer::default())
+        .layer(OtelInResponseLayer)
         .layer(OtelAxumLayer::default())
         .layer(auth_layer);
 
+    // Both versions share the handlers; v1 answers the way it did before v2.
+    let app = versioned(api.clone(), api)
+        // Merged after the auth layer, the docs are public.
+        .merge(openapi::app::router())
+        .fallback(not_found)
+        .layer(middleware::from_fn(request_id));
+
+    // Tell the background jobs when the server starts shutting down.
+    let (shutdown_tx, shutdown_rx) = watch::channel(());
+    let shutdown = async move {
+        shutdown_signal().await;
+        let _ = shutdown_tx.send(());
+    };
+
+    // Rotate old SFTP keys in the background, when enabled.
+    let key_rotation = cfg.key_rotation.enabled.then(|| {
+        let mut shutdown_rx = shutdown_rx.clone();
+        tokio::spawn(rotation::models::run(
+            rotation_repo,
+            cfg.key_rotation.clone(),
+            async move {
+                let _ = shutdown_rx.changed().await;
+            },
+        ))
+    });
+
+    // Purge clients past the retention window in the background, when enabled.
+    let retention = cfg.retention.enabled.then(|| {
+        let mut shutdown_rx = shutdown_rx.clone();
+        tokio::spawn(retention::models::run(
+            retention_repo,
+            cfg.retention.clone(),
+            async move {
+                let _ = shutdown_rx.changed().await;
+            },
+        ))
+    });
+
+    // Deliver webhook events from the outbox in the background, when enabled.
+    let webhook_dispatcher = cfg.webhooks.enabled.then(|| {
+        let mut shutdown_rx = shutdown_rx.clone();
+        tokio::spawn(webhooks::models::run(
+            webhook_repo,
+            cfg.webhooks.clone(),
+            async move {
+                let _ = shutdown_rx.changed().await;
+            },
+        ))
+    });
+
     // Create a TCP listener and serve the app on the listener.
     let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
         .await
@@ -77,8 +146,19 @@ async fn main() {
 
     // This is the main event loop that listens for incoming requests.
     axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
+        .with_graceful_shutdown(shutdown)
         .into_future()
         .await
-        .unwrap()
+        .unwrap();
+
+    // Let a sweep, purge or dispatch that is still running finish.
+    if let Some(key_rotation) = key_rotation {
+        key_rotation.await.unwrap();
+    }
+    if let Some(retention) = retention {
+        retention.await.unwrap();
+    }
+    if let Some(webhook_dispatcher) = webhook_dispatcher {
+        webhook_dispatcher.await.unwrap();
+    }
 }
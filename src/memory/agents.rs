@@ -0,0 +1,118 @@
+use async_trait::async_trait;
+
+use super::repo::MemoryRepo;
+use crate::{
+    agents::models::{Agent, AgentClient, AgentFilter, AgentRepo, AgentUpdate},
+    clients::models::Client,
+    errors::models::{AppError, FieldError},
+    utils::{
+        etag::IfMatch,
+        list::{ListQuery, Page},
+    },
+};
+
+#[async_trait]
+impl AgentRepo for MemoryRepo {
+    async fn get_all(&self, query: ListQuery<AgentFilter>) -> Result<Page<Agent>, AppError> {
+        let state = self.state();
+        // `client_id` isn't a field of the agent, it matches any of the agent's clients.
+        let agents = state
+            .agents
+            .iter()
+            .filter(|(agent_id, _)| {
+                query.filters.get("client_id").all(|condition| {
+                    state
+                        .agent_clients
+                        .iter()
+                        .filter(|(id, _)| id == *agent_id)
+                        .any(|(_, client_id)| condition.matches(&(*client_id).into()))
+                })
+            })
+            .map(|(_, agent)| agent.clone())
+            .collect();
+
+        query.page_in_memory(agents, &["client_id"])
+    }
+
+    async fn get(&self, id: i64) -> Result<Option<Agent>, AppError> {
+        Ok(self.state().agents.get(&id).cloned())
+    }
+
+    async fn create(&self, agent: Agent) -> Result<i64, AppError> {
+        let mut state = self.state();
+        let id = state.next_id("agents");
+        let created = Agent {
+            id: Some(id),
+            version: 1,
+            ..agent
+        };
+        state.agents.insert(id, created.clone());
+        state.emit("create", "agent", id, None, Some(&created))?;
+        Ok(id)
+    }
+
+    async fn update(&self, id: i64, agent: AgentUpdate, if_match: IfMatch) -> Result<(), AppError> {
+        let mut state = self.state();
+        let before = state.agent(id)?.clone();
+        if_match.check("Agent", id, before.version)?;
+        let after = Agent {
+            id: Some(id),
+            name: agent.name,
+            email: agent.email,
+            version: before.version + 1,
+        };
+        state.agents.insert(id, after.clone());
+        state.emit("update", "agent", id, Some(&before), Some(&after))?;
+        Ok(())
+    }
+
+    async fn delete(&self, id: i64, if_match: IfMatch) -> Result<(), AppError> {
+        let mut state = self.state();
+        let before = state.agent(id)?.clone();
+        if_match.check("Agent", id, before.version)?;
+        state.delete_agent(id);
+        state.emit("delete", "agent", id, Some(&before), None)?;
+        Ok(())
+    }
+
+    async fn get_clients_for_agent(&self, id: i64) -> Result<Vec<Client>, AppError> {
+        let state = self.state();
+        let clients = state
+            .agent_clients
+            .iter()
+            .filter(|(agent_id, _)| *agent_id == id)
+            .filter_map(|(_, client_id)| state.client(*client_id).ok().cloned())
+            .collect();
+        Ok(clients)
+    }
+
+    async fn get_client_ids_for_agent(&self, id: i64) -> Result<Vec<i64>, AppError> {
+        let client_ids = self
+            .state()
+            .agent_clients
+            .iter()
+            .filter(|(agent_id, _)| *agent_id == id)
+            .map(|(_, client_id)| *client_id)
+            .collect();
+        Ok(client_ids)
+    }
+
+    async fn add_client_to_agent(&self, agent_id: i64, client_id: i64) -> Result<(), AppError> {
+        let mut state = self.state();
+        state.agent(agent_id)?;
+        state.client(client_id)?;
+        if !state.agent_clients.insert((agent_id, client_id)) {
+            return Err(AppError::Conflict(FieldError::new(
+                "agent_id, client_id",
+                "already exists",
+            )));
+        }
+
+        let link = AgentClient {
+            agent_id,
+            client_id,
+        };
+        state.emit("add_client", "agent", agent_id, None, Some(&link))?;
+        Ok(())
+    }
+}
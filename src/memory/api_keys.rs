@@ -0,0 +1,72 @@
+use async_trait::async_trait;
+use chrono::Utc;
+
+use super::repo::MemoryRepo;
+use crate::{
+    api_keys::models::{generate_token, hash_token, ApiKey, ApiKeyRepo, CreatedApiKey, NewApiKey},
+    errors::models::AppError,
+};
+
+#[async_trait]
+impl ApiKeyRepo for MemoryRepo {
+    async fn get_all(&self) -> Result<Vec<ApiKey>, AppError> {
+        let keys = self
+            .state()
+            .api_keys
+            .values()
+            .map(|(key, _)| key.clone())
+            .collect();
+        Ok(keys)
+    }
+
+    async fn create(&self, key: NewApiKey, created_by: &str) -> Result<CreatedApiKey, AppError> {
+        key.validate()?;
+
+        let mut state = self.state();
+        if let Some(agent_id) = key.agent_id {
+            state.agent(agent_id)?;
+        }
+
+        let token = generate_token();
+        let id = state.next_id("api_keys");
+        let api_key = ApiKey {
+            id,
+            name: key.name,
+            scopes: key.scopes,
+            agent_id: key.agent_id,
+            expires_at: key.expires_at,
+            revoked_at: None,
+            created_by: created_by.to_string(),
+            created_at: Utc::now(),
+        };
+        state
+            .api_keys
+            .insert(id, (api_key.clone(), hash_token(&token)));
+
+        Ok(CreatedApiKey { api_key, token })
+    }
+
+    async fn revoke(&self, id: i64) -> Result<(), AppError> {
+        match self.state().api_keys.get_mut(&id) {
+            Some((key, _)) if key.revoked_at.is_none() => {
+                key.revoked_at = Some(Utc::now());
+                Ok(())
+            }
+            _ => Err(AppError::NotFound(format!(
+                "Active API key with id {} not found",
+                id
+            ))),
+        }
+    }
+
+    async fn find_by_token(&self, token: &str) -> Result<Option<ApiKey>, AppError> {
+        let hash = hash_token(token);
+        let key = self
+            .state()
+            .api_keys
+            .values()
+            .find(|(_, key_hash)| *key_hash == hash)
+            .map(|(key, _)| key.clone());
+        Ok(key)
+    }
+}
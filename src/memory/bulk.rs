@@ -0,0 +1,205 @@
+use async_trait::async_trait;
+
+use super::repo::{MemoryRepo, State};
+use crate::{
+    audit::models::REDACTED,
+    bulk::models::{
+        ambiguous_client, ambiguous_vendor, missing_client, BulkRepo, ClientRow, Entity,
+        ImportMode, ImportReport, Outcome, Row, VendorExport, VendorRow,
+    },
+    clients::models::Client,
+    errors::models::AppError,
+    vendors::models::Vendor,
+};
+
+#[async_trait]
+impl BulkRepo for MemoryRepo {
+    async fn import_clients(
+        &self,
+        rows: Vec<Row<ClientRow>>,
+        mode: ImportMode,
+    ) -> Result<ImportReport, AppError> {
+        let report = ImportReport::new(Entity::Clients, mode, rows.len());
+        self.import(report, rows, upsert_client)
+    }
+
+    async fn import_vendors(
+        &self,
+        rows: Vec<Row<VendorRow>>,
+        mode: ImportMode,
+    ) -> Result<ImportReport, AppError> {
+        let report = ImportReport::new(Entity::Vendors, mode, rows.len());
+        self.import(report, rows, upsert_vendor)
+    }
+
+    async fn export_clients(
+        &self,
+        after_id: i64,
+        limit: i64,
+        client_ids: Option<Vec<i64>>,
+    ) -> Result<Vec<Client>, AppError> {
+        let clients = self
+            .state()
+            .clients
+            .range(after_id + 1..)
+            .filter(|(_, client)| client.deleted_at.is_none())
+            .filter(|(id, _)| client_ids.as_ref().is_none_or(|ids| ids.contains(id)))
+            .take(limit as usize)
+            .map(|(_, client)| client.clone())
+            .collect();
+        Ok(clients)
+    }
+
+    async fn export_vendors(
+        &self,
+        after_id: i64,
+        limit: i64,
+        client_ids: Option<Vec<i64>>,
+    ) -> Result<Vec<VendorExport>, AppError> {
+        let state = self.state();
+        let redact = |secret: &Option<String>| secret.as_ref().map(|_| REDACTED.to_string());
+        let vendors = state
+            .vendors
+            .range(after_id + 1..)
+            .filter(|(_, vendor)| !state.is_deleted_client(vendor.client_id))
+            .filter(|(_, vendor)| {
+                client_ids
+                    .as_ref()
+                    .is_none_or(|ids| ids.contains(&vendor.client_id))
+            })
+            .take(limit as usize)
+            .map(|(id, vendor)| VendorExport {
+                id: *id,
+                client_id: vendor.client_id,
+                client: state
+                    .clients
+                    .get(&vendor.client_id)
+                    .map(|client| client.name.clone())
+                    .unwrap_or_default(),
+                name: vendor.name.clone(),
+                host: vendor.host.clone(),
+                port: vendor.port,
+                username: vendor.username.clone(),
+                password: redact(&vendor.password),
+                ssh_key: redact(&vendor.ssh_key),
+                ssh_key_password: redact(&vendor.ssh_key_password),
+            })
+            .collect();
+        Ok(vendors)
+    }
+}
+
+impl MemoryRepo {
+    /// Applies the rows to a copy of the state, which replaces it if the import commits.
+    /// The upserts check everything before they change anything, so a failed row leaves
+    /// no trace, like the savepoints in Postgres.
+    fn import<T>(
+        &self,
+        mut report: ImportReport,
+        rows: Vec<Row<T>>,
+        upsert: fn(&mut State, T) -> Result<Outcome, AppError>,
+    ) -> Result<ImportReport, AppError> {
+        let mut state = self.state();
+        let mut draft = state.clone();
+        for row in rows {
+            let record = match row.record {
+                Ok(record) => record,
+                Err(error) => {
+                    report.failed(row.line, error);
+                    continue;
+                }
+            };
+            match upsert(&mut draft, record) {
+                Ok(outcome) => report.succeeded(outcome),
+                Err(error) => report.failed(row.line, error.to_string()),
+            }
+        }
+        report.committed = report.should_commit();
+        if report.committed {
+            *state = draft;
+        }
+        Ok(report)
+    }
+}
+
+/// The id of the only client called `name`.
+fn client_by_name(state: &State, name: &str) -> Result<Option<i64>, AppError> {
+    let ids: Vec<i64> = state
+        .clients
+        .iter()
+        .filter(|(_, client)| client.name == name && client.deleted_at.is_none())
+        .map(|(id, _)| *id)
+        .collect();
+    match ids.as_slice() {
+        [] => Ok(None),
+        [id] => Ok(Some(*id)),
+        _ => Err(ambiguous_client(name, ids.len())),
+    }
+}
+
+fn upsert_client(state: &mut State, row: ClientRow) -> Result<Outcome, AppError> {
+    let Some(id) = client_by_name(state, &row.name)? else {
+        let id = state.next_id("clients");
+        let created = Client {
+            id: Some(id),
+            version: 1,
+            ..row.into_client(None)
+        };
+        state.clients.insert(id, created.clone());
+        state.emit("create", "client", id, None, Some(&created))?;
+        return Ok(Outcome::Created);
+    };
+
+    let before = state.client(id)?.clone();
+    let after = row.into_client(Some(&before));
+    if after == before {
+        return Ok(Outcome::Unchanged);
+    }
+    let after = Client {
+        version: before.version + 1,
+        ..after
+    };
+    state.clients.insert(id, after.clone());
+    state.emit("update", "client", id, Some(&before), Some(&after))?;
+    Ok(Outcome::Updated)
+}
+
+fn upsert_vendor(state: &mut State, row: VendorRow) -> Result<Outcome, AppError> {
+    let client_id =
+        client_by_name(state, &row.client)?.ok_or_else(|| missing_client(&row.client))?;
+    let ids: Vec<i64> = state
+        .vendors
+        .iter()
+        .filter(|(_, vendor)| vendor.client_id == client_id && vendor.name == row.name)
+        .map(|(id, _)| *id)
+        .collect();
+
+    let id = match ids.as_slice() {
+        [] => {
+            let id = state.next_id("vendors");
+            let mut created = row.into_vendor(client_id, None);
+            created.id = Some(id);
+            created.version = 1;
+            state.vendors.insert(id, created.clone());
+            state.emit("create", "vendor", id, None, Some(&created))?;
+            return Ok(Outcome::Created);
+        }
+        [id] => *id,
+        _ => return Err(ambiguous_vendor(&row, ids.len())),
+    };
+
+    let Some(before) = state.vendors.get(&id).cloned() else {
+        return Err(AppError::Unknown);
+    };
+    let after = row.into_vendor(client_id, Some(&before));
+    if after == before {
+        return Ok(Outcome::Unchanged);
+    }
+    let after = Vendor {
+        version: before.version + 1,
+        ..after
+    };
+    state.vendors.insert(id, after.clone());
+    state.emit("update", "vendor", id, Some(&before), Some(&after))?;
+    Ok(Outcome::Updated)
+}
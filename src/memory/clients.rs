@@ -0,0 +1,384 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::repo::{MemoryRepo, State};
+use crate::{
+    agents::models::AgentClient,
+    clients::models::{
+        deleted_client_not_found, Client, ClientFilter, ClientRepo, Onboarded, Onboarding,
+    },
+    errors::models::AppError,
+    sftp::models::{
+        validate_source_ip_rules, NewSftp, Rotation, Sftp, SftpResponse, GENERATED, UPLOADED,
+    },
+    utils::{
+        etag::IfMatch,
+        list::{ListQuery, Page},
+        ssh::{parse_public_keys, KeyType, SSHKeyPair, UploadedKey},
+    },
+    vendors::models::Vendor,
+};
+
+#[async_trait]
+impl ClientRepo for MemoryRepo {
+    async fn get_all(
+        &self,
+        query: ListQuery<ClientFilter>,
+        client_ids: Option<Vec<i64>>,
+    ) -> Result<Page<Client>, AppError> {
+        let clients = self
+            .state()
+            .clients
+            .iter()
+            .filter(|(_, client)| query.include_deleted || client.deleted_at.is_none())
+            .filter(|(id, _)| client_ids.as_ref().is_none_or(|ids| ids.contains(id)))
+            .map(|(_, client)| client.clone())
+            .collect();
+
+        query.page_in_memory(clients, &[])
+    }
+
+    async fn create(&self, client: Client) -> Result<i64, AppError> {
+        insert_client(&mut self.state(), client)
+    }
+
+    async fn get(&self, id: i64) -> Result<Option<Client>, AppError> {
+        Ok(Some(self.state().client(id)?.clone()))
+    }
+
+    async fn update(&self, id: i64, client: Client, if_match: IfMatch) -> Result<(), AppError> {
+        let mut state = self.state();
+        let before = state.client(id)?.clone();
+        if_match.check("Client", id, before.version)?;
+        let after = Client {
+            id: Some(id),
+            deleted_at: None,
+            version: before.version + 1,
+            ..client
+        };
+        state.clients.insert(id, after.clone());
+        state.emit("update", "client", id, Some(&before), Some(&after))?;
+        Ok(())
+    }
+
+    async fn delete(&self, id: i64, if_match: IfMatch) -> Result<(), AppError> {
+        let mut state = self.state();
+        let before = state.client(id)?.clone();
+        if_match.check("Client", id, before.version)?;
+        let after = Client {
+            deleted_at: Some(Utc::now()),
+            version: before.version + 1,
+            ..before.clone()
+        };
+        state.clients.insert(id, after.clone());
+        state.emit("delete", "client", id, Some(&before), Some(&after))?;
+        Ok(())
+    }
+
+    async fn restore(&self, id: i64) -> Result<Client, AppError> {
+        let mut state = self.state();
+        if !state.is_deleted_client(id) {
+            return Err(deleted_client_not_found(id));
+        }
+        let before = state.clients[&id].clone();
+        let after = Client {
+            deleted_at: None,
+            version: before.version + 1,
+            ..before.clone()
+        };
+        state.clients.insert(id, after.clone());
+        state.emit("restore", "client", id, Some(&before), Some(&after))?;
+        Ok(after)
+    }
+
+    async fn purge_deleted(&self, deleted_before: DateTime<Utc>) -> Result<Vec<i64>, AppError> {
+        let mut state = self.state();
+        let purged: Vec<Client> = state
+            .clients
+            .values()
+            .filter(|client| client.deleted_at.is_some_and(|at| at < deleted_before))
+            .cloned()
+            .collect();
+
+        let mut ids = Vec::with_capacity(purged.len());
+        for before in purged {
+            let id = before.id.unwrap_or_default();
+            state.delete_client(id);
+            state.emit("purge", "client", id, Some(&before), None)?;
+            ids.push(id);
+        }
+        Ok(ids)
+    }
+
+    async fn add_vendor(&self, client_id: i64, vendor: Vendor) -> Result<i64, AppError> {
+        let mut state = self.state();
+        state.client(client_id)?;
+        insert_vendor(&mut state, client_id, vendor)
+    }
+
+    async fn update_vendor(
+        &self,
+        client_id: i64,
+        vendor_id: i64,
+        vendor: Vendor,
+        if_match: IfMatch,
+    ) -> Result<(), AppError> {
+        let mut state = self.state();
+        let before = match state.vendor(vendor_id) {
+            Some(before) if before.client_id == client_id => before.clone(),
+            _ => {
+                return Err(AppError::NotFound(format!(
+                    "Vendor with id {} for client with id {} not found",
+                    vendor_id, client_id
+                )))
+            }
+        };
+
+        if_match.check("Vendor", vendor_id, before.version)?;
+
+        let after = Vendor {
+            id: Some(vendor_id),
+            client_id,
+            version: before.version + 1,
+            ..vendor
+        };
+        state.vendors.insert(vendor_id, after.clone());
+        state.emit("update", "vendor", vendor_id, Some(&before), Some(&after))?;
+        Ok(())
+    }
+
+    async fn add_sftp(
+        &self,
+        client_id: i64,
+        new_sftp: NewSftp,
+        key_type: KeyType,
+    ) -> Result<SftpResponse, AppError> {
+        let prepared = PreparedSftp::new(new_sftp, key_type)?;
+        {
+            let state = self.state();
+            state.client(client_id)?;
+            state.ensure_sftp_username_free(&prepared.username, None)?;
+        }
+
+        // Generated outside the lock, it takes a while.
+        let ssh_keys = prepared.generate_keys()?;
+        insert_sftp(&mut self.state(), client_id, prepared, ssh_keys)
+    }
+
+    async fn reset_keys(
+        &self,
+        client_id: i64,
+        rotation: Rotation,
+        if_match: IfMatch,
+    ) -> Result<Vec<SftpResponse>, AppError> {
+        let grace_period = rotation.grace_period()?;
+        // Accounts using uploaded keys have no key type.
+        let accounts: Vec<i64> = {
+            let state = self.state();
+            let client = state.client(client_id)?;
+            if_match.check("Client", client_id, client.version)?;
+            state
+                .sftp
+                .values()
+                .filter(|sftp| sftp.client_id == client_id && sftp.key_type.is_some())
+                .map(|sftp| sftp.id)
+                .collect()
+        };
+        if accounts.is_empty() {
+            return Err(AppError::NotFound(format!(
+                "SFTP accounts with generated keys for client with id {} not found",
+                client_id
+            )));
+        }
+
+        let ssh_keys = accounts
+            .iter()
+            .map(|_| SSHKeyPair::generate(rotation.key_type))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut state = self.state();
+        // Checked again, the client may have changed while the keys were generated.
+        let version = state.client(client_id)?.version;
+        if_match.check("Client", client_id, version)?;
+        let mut responses = Vec::with_capacity(accounts.len());
+        for (sftp_id, ssh_keys) in accounts.into_iter().zip(ssh_keys) {
+            let before = state.sftp_snapshot(sftp_id);
+            state.rotate_key(sftp_id, &ssh_keys, grace_period);
+            let after = state.sftp_snapshot(sftp_id);
+            state.emit(
+                "reset_keys",
+                "sftp",
+                sftp_id,
+                before.as_ref(),
+                after.as_ref(),
+            )?;
+            responses.push(SftpResponse::new(sftp_id, client_id, ssh_keys));
+        }
+        if let Some(client) = state.clients.get_mut(&client_id) {
+            client.version += 1;
+        }
+        Ok(responses)
+    }
+
+    async fn onboard(&self, onboarding: Onboarding, dry_run: bool) -> Result<Onboarded, AppError> {
+        onboarding.check_conflicts()?;
+        let accounts = onboarding
+            .sftp
+            .into_iter()
+            .map(|account| PreparedSftp::new(account.sftp, account.key_type))
+            .collect::<Result<Vec<_>, _>>()?;
+        let ssh_keys = accounts
+            .iter()
+            .map(PreparedSftp::generate_keys)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Applied to a copy, which replaces the state only once everything worked.
+        let mut state = self.state();
+        let mut draft = state.clone();
+        let client_id = insert_client(&mut draft, onboarding.client)?;
+        let vendor_ids = onboarding
+            .vendors
+            .into_iter()
+            .map(|vendor| insert_vendor(&mut draft, client_id, vendor.into_vendor(client_id)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let sftp = accounts
+            .into_iter()
+            .zip(ssh_keys)
+            .map(|(prepared, ssh_keys)| {
+                draft.ensure_sftp_username_free(&prepared.username, None)?;
+                insert_sftp(&mut draft, client_id, prepared, ssh_keys)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        for agent_id in &onboarding.agent_ids {
+            draft.agent(*agent_id)?;
+            draft.agent_clients.insert((*agent_id, client_id));
+            let link = AgentClient {
+                agent_id: *agent_id,
+                client_id,
+            };
+            draft.emit("add_client", "agent", *agent_id, None, Some(&link))?;
+        }
+        if !dry_run {
+            *state = draft;
+        }
+
+        Ok(Onboarded {
+            dry_run,
+            client_id,
+            vendor_ids,
+            sftp,
+            agent_ids: onboarding.agent_ids,
+        })
+    }
+}
+
+fn insert_client(state: &mut State, client: Client) -> Result<i64, AppError> {
+    let id = state.next_id("clients");
+    let created = Client {
+        id: Some(id),
+        version: 1,
+        ..client
+    };
+    state.clients.insert(id, created.clone());
+    state.emit("create", "client", id, None, Some(&created))?;
+    Ok(id)
+}
+
+fn insert_vendor(state: &mut State, client_id: i64, vendor: Vendor) -> Result<i64, AppError> {
+    let vendor_id = state.next_id("vendors");
+    let created = Vendor {
+        id: Some(vendor_id),
+        client_id,
+        version: 1,
+        ..vendor
+    };
+    state.vendors.insert(vendor_id, created.clone());
+    state.emit("create", "vendor", vendor_id, None, Some(&created))?;
+    Ok(vendor_id)
+}
+
+/// A new account that passed the checks Postgres would make on insert.
+struct PreparedSftp {
+    username: String,
+    bucket_name: String,
+    aws_role_arn: String,
+    allowed_source_ips: Option<Vec<String>>,
+    uploaded: Option<Vec<UploadedKey>>,
+    key_type: KeyType,
+}
+
+impl PreparedSftp {
+    fn new(new_sftp: NewSftp, key_type: KeyType) -> Result<Self, AppError> {
+        let NewSftp { sftp, public_keys } = new_sftp;
+        if let Some(rules) = &sftp.allowed_source_ips {
+            validate_source_ip_rules(rules)?;
+        }
+        let uploaded = public_keys.as_deref().map(parse_public_keys).transpose()?;
+        // The columns are NOT NULL in Postgres.
+        let (Some(username), Some(bucket_name), Some(aws_role_arn)) =
+            (sftp.username, sftp.bucket_name, sftp.aws_role_arn)
+        else {
+            return Err(AppError::InvalidInput(
+                "username, bucket_name and aws_role_arn are required".to_string(),
+            ));
+        };
+        Ok(Self {
+            username,
+            bucket_name,
+            aws_role_arn,
+            allowed_source_ips: sftp.allowed_source_ips,
+            uploaded,
+            key_type,
+        })
+    }
+
+    /// The key pair of the account, unless it brought its own public keys.
+    fn generate_keys(&self) -> Result<Option<SSHKeyPair>, AppError> {
+        match self.uploaded {
+            Some(_) => Ok(None),
+            None => SSHKeyPair::generate(self.key_type).map(Some),
+        }
+    }
+}
+
+fn insert_sftp(
+    state: &mut State,
+    client_id: i64,
+    prepared: PreparedSftp,
+    ssh_keys: Option<SSHKeyPair>,
+) -> Result<SftpResponse, AppError> {
+    let sftp_id = state.next_id("sftp");
+    state.sftp.insert(
+        sftp_id,
+        Sftp {
+            id: sftp_id,
+            client_id,
+            username: prepared.username,
+            public_keys: Vec::new(),
+            bucket_name: prepared.bucket_name,
+            aws_role_arn: prepared.aws_role_arn,
+            allowed_source_ips: prepared.allowed_source_ips,
+            key_type: ssh_keys.as_ref().map(|_| prepared.key_type.to_string()),
+            version: 1,
+        },
+    );
+
+    let response = match (ssh_keys, prepared.uploaded) {
+        (Some(ssh_keys), _) => {
+            state.insert_key(sftp_id, GENERATED, (&ssh_keys).into());
+            SftpResponse::new(sftp_id, client_id, ssh_keys)
+        }
+        (None, uploaded) => {
+            let keys = uploaded
+                .unwrap_or_default()
+                .into_iter()
+                .map(|key| state.insert_key(sftp_id, UPLOADED, key))
+                .collect();
+            SftpResponse::uploaded(sftp_id, client_id, keys)
+        }
+    };
+
+    let created = state.sftp_snapshot(sftp_id);
+    state.emit("create", "sftp", sftp_id, None, created.as_ref())?;
+    Ok(response)
+}
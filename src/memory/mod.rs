@@ -0,0 +1,9 @@
+mod agents;
+mod api_keys;
+mod bulk;
+mod clients;
+pub mod repo;
+mod rotation;
+mod sftp;
+mod vendors;
+mod webhooks;
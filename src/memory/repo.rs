@@ -0,0 +1,331 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    agents::models::Agent,
+    api_keys::models::ApiKey,
+    audit::models::{self as audit, AuditEvent},
+    clients::models::Client,
+    errors::models::{AppError, FieldError},
+    sftp::models::{Sftp, SftpKey, GENERATED},
+    utils::ssh::{SSHKeyPair, UploadedKey},
+    vendors::models::{HostKeyPins, Vendor, VendorHostKey},
+    webhooks::models::{subscribed, DeadLetter, Webhook, WebhookEvent},
+};
+
+/// An in-memory backend implementing the same repo traits as [`PostgresRepo`], so the
+/// routers can be driven without a database. Clones share the same state.
+///
+/// It mirrors the constraints of the schema: deleting a client removes its vendors, SFTP
+/// accounts and agent links, deleting an agent removes its links and API keys, and
+/// missing rows give the same `NotFound` errors. Audit events aren't recorded, but the
+/// webhook events they would produce are queued.
+///
+/// [`PostgresRepo`]: crate::postgres::pool::PostgresRepo
+#[derive(Debug, Clone, Default)]
+pub struct MemoryRepo {
+    state: Arc<Mutex<State>>,
+}
+
+impl MemoryRepo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Locks the state. The lock is never held across an await.
+    pub(crate) fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The tables, keyed by id.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct State {
+    sequences: HashMap<&'static str, i64>,
+    pub clients: BTreeMap<i64, Client>,
+    pub vendors: BTreeMap<i64, Vendor>,
+    pub vendor_host_keys: BTreeMap<i64, VendorHostKey>,
+    /// Accounts are stored without their `public_keys`, which live in `sftp_keys`.
+    pub sftp: BTreeMap<i64, Sftp>,
+    pub sftp_keys: BTreeMap<i64, SftpKey>,
+    pub agents: BTreeMap<i64, Agent>,
+    /// (agent_id, client_id) pairs.
+    pub agent_clients: BTreeSet<(i64, i64)>,
+    /// Keys with the hash of their token.
+    pub api_keys: BTreeMap<i64, (ApiKey, Vec<u8>)>,
+    /// Subscriptions with their secret.
+    pub webhooks: BTreeMap<i64, (Webhook, String)>,
+    pub webhook_outbox: BTreeMap<i64, OutboxEntry>,
+    pub webhook_dead_letters: BTreeMap<i64, DeadLetter>,
+}
+
+/// A row of `webhook_outbox`.
+#[derive(Debug, Clone)]
+pub(crate) struct OutboxEntry {
+    pub subscription_id: i64,
+    pub event_id: i64,
+    pub event_type: String,
+    pub payload: Value,
+    pub created_at: DateTime<Utc>,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
+
+impl State {
+    /// The next value of a table's BIGSERIAL id.
+    pub fn next_id(&mut self, table: &'static str) -> i64 {
+        let id = self.sequences.entry(table).or_default();
+        *id += 1;
+        *id
+    }
+
+    /// Deleted clients are not found, as with `lock_client`.
+    pub fn client(&self, id: i64) -> Result<&Client, AppError> {
+        self.clients
+            .get(&id)
+            .filter(|client| client.deleted_at.is_none())
+            .ok_or_else(|| AppError::NotFound(format!("Client with id {} not found", id)))
+    }
+
+    pub fn is_deleted_client(&self, id: i64) -> bool {
+        self.clients
+            .get(&id)
+            .is_some_and(|client| client.deleted_at.is_some())
+    }
+
+    /// Vendors of deleted clients are not found, as with `lock_vendor`.
+    pub fn vendor(&self, id: i64) -> Option<&Vendor> {
+        self.vendors
+            .get(&id)
+            .filter(|vendor| !self.is_deleted_client(vendor.client_id))
+    }
+
+    pub fn agent(&self, id: i64) -> Result<&Agent, AppError> {
+        self.agents
+            .get(&id)
+            .ok_or_else(|| AppError::NotFound(format!("Agent with id {} not found", id)))
+    }
+
+    /// Usernames are unique, as enforced by `sftp_username_key`.
+    pub fn ensure_sftp_username_free(
+        &self,
+        username: &str,
+        except_id: Option<i64>,
+    ) -> Result<(), AppError> {
+        let taken = self
+            .sftp
+            .values()
+            .any(|sftp| sftp.username == username && Some(sftp.id) != except_id);
+        if taken {
+            return Err(AppError::Conflict(FieldError::new(
+                "username",
+                "already exists",
+            )));
+        }
+        Ok(())
+    }
+
+    /// The keys of an account that are active right now, as `active_sftp_keys` lists them.
+    pub fn active_keys(&self, sftp_id: i64) -> Vec<SftpKey> {
+        let now = Utc::now();
+        self.sftp_keys
+            .values()
+            .filter(|key| key.sftp_id == sftp_id && key.is_active(now))
+            .cloned()
+            .collect()
+    }
+
+    /// An account with its active keys filled in, as the Postgres queries return it.
+    pub fn sftp_with_keys(&self, sftp: &Sftp) -> Sftp {
+        Sftp {
+            public_keys: self
+                .active_keys(sftp.id)
+                .into_iter()
+                .map(|key| key.public_key)
+                .collect(),
+            ..sftp.clone()
+        }
+    }
+
+    /// An account with its active keys, as `lock_sftp` loads it for the audit log.
+    pub fn sftp_snapshot(&self, id: i64) -> Option<Sftp> {
+        self.sftp
+            .get(&id)
+            .filter(|sftp| !self.is_deleted_client(sftp.client_id))
+            .map(|sftp| self.sftp_with_keys(sftp))
+    }
+
+    /// The pins of a vendor, as `lock_host_keys` loads them for the audit log.
+    pub fn host_key_pins(&self, vendor_id: i64) -> Result<HostKeyPins, AppError> {
+        if self.vendor(vendor_id).is_none() {
+            return Err(AppError::NotFound(format!(
+                "Vendor with id {} not found",
+                vendor_id
+            )));
+        }
+        let host_keys = self
+            .vendor_host_keys
+            .values()
+            .filter(|host_key| host_key.vendor_id == vendor_id)
+            .cloned()
+            .collect();
+        Ok(HostKeyPins { host_keys })
+    }
+
+    /// Deletes a vendor and its host keys.
+    pub fn delete_vendor(&mut self, id: i64) -> Option<Vendor> {
+        self.vendor_host_keys
+            .retain(|_, host_key| host_key.vendor_id != id);
+        self.vendors.remove(&id)
+    }
+
+    /// Queues the webhook event for a mutation, like `audit::record` does in Postgres.
+    pub fn emit<T: Serialize>(
+        &mut self,
+        action: &str,
+        entity_type: &str,
+        entity_id: i64,
+        before: Option<&T>,
+        after: Option<&T>,
+    ) -> Result<(), AppError> {
+        let before = before.map(serde_json::to_value).transpose();
+        let after = after.map(serde_json::to_value).transpose();
+        let (Ok(before), Ok(after)) = (before, after) else {
+            return Err(AppError::Unknown);
+        };
+
+        let now = Utc::now();
+        let (actor, actor_key_id) = audit::actor();
+        let data = after.clone().or_else(|| before.clone());
+        let audit_event = AuditEvent {
+            id: self.next_id("audit_events"),
+            occurred_at: now,
+            actor,
+            actor_key_id,
+            action: action.to_string(),
+            entity_type: entity_type.to_string(),
+            entity_id,
+            diff: audit::diff(before, after),
+        };
+        let event = WebhookEvent::new(audit_event, data);
+        let payload = serde_json::to_value(&event).map_err(|_| AppError::Unknown)?;
+        let subscriptions: Vec<i64> = self
+            .webhooks
+            .values()
+            .filter(|(webhook, _)| subscribed(&webhook.event_types, &event.event_type))
+            .map(|(webhook, _)| webhook.id)
+            .collect();
+        for subscription_id in subscriptions {
+            let id = self.next_id("webhook_outbox");
+            self.webhook_outbox.insert(
+                id,
+                OutboxEntry {
+                    subscription_id,
+                    event_id: event.id,
+                    event_type: event.event_type.clone(),
+                    payload: payload.clone(),
+                    created_at: now,
+                    attempts: 0,
+                    next_attempt_at: now,
+                    last_error: None,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    pub fn insert_key(&mut self, sftp_id: i64, source: &str, key: UploadedKey) -> SftpKey {
+        let now = Utc::now();
+        let key = SftpKey {
+            id: self.next_id("sftp_keys"),
+            sftp_id,
+            source: source.to_string(),
+            key_type: key.key_type,
+            public_key: key.public_key,
+            fingerprint: key.fingerprint,
+            created_at: now,
+            not_before: now,
+            expires_at: None,
+            revoked_at: None,
+        };
+        self.sftp_keys.insert(key.id, key.clone());
+        key
+    }
+
+    /// Adds a generated key to an account and gives its earlier generated keys
+    /// `grace_period`, like `sftp::models::rotate_key`.
+    pub fn rotate_key(&mut self, sftp_id: i64, ssh_keys: &SSHKeyPair, grace_period: Duration) {
+        let generated: Vec<i64> = self
+            .sftp_keys
+            .values()
+            .filter(|key| {
+                key.sftp_id == sftp_id && key.source == GENERATED && key.revoked_at.is_none()
+            })
+            .map(|key| key.id)
+            .collect();
+        self.expire_keys(sftp_id, &generated, Utc::now() + grace_period);
+        if let Some(sftp) = self.sftp.get_mut(&sftp_id) {
+            sftp.key_type = Some(ssh_keys.key_type.to_string());
+        }
+        self.insert_key(sftp_id, GENERATED, ssh_keys.into());
+    }
+
+    /// Makes keys of an account expire at `expires_at`, unless they already expire sooner,
+    /// and bumps its version, like `sftp::models::expire_keys`.
+    pub fn expire_keys(&mut self, sftp_id: i64, key_ids: &[i64], expires_at: DateTime<Utc>) {
+        for key in self.sftp_keys.values_mut() {
+            if key.sftp_id == sftp_id
+                && key_ids.contains(&key.id)
+                && key.revoked_at.is_none()
+                && key.expires_at.is_none_or(|at| at > expires_at)
+            {
+                key.expires_at = Some(expires_at);
+            }
+        }
+        if let Some(sftp) = self.sftp.get_mut(&sftp_id) {
+            sftp.version += 1;
+        }
+    }
+
+    /// Deletes an SFTP account and its uploaded keys.
+    pub fn delete_sftp(&mut self, id: i64) -> Option<Sftp> {
+        self.sftp_keys.retain(|_, key| key.sftp_id != id);
+        self.sftp.remove(&id)
+    }
+
+    /// Deletes a client and everything that references it with `ON DELETE CASCADE`.
+    pub fn delete_client(&mut self, id: i64) {
+        self.clients.remove(&id);
+        let vendor_ids: Vec<i64> = self
+            .vendors
+            .values()
+            .filter(|vendor| vendor.client_id == id)
+            .filter_map(|vendor| vendor.id)
+            .collect();
+        for vendor_id in vendor_ids {
+            self.delete_vendor(vendor_id);
+        }
+        let sftp_ids: Vec<i64> = self
+            .sftp
+            .values()
+            .filter(|sftp| sftp.client_id == id)
+            .map(|sftp| sftp.id)
+            .collect();
+        for sftp_id in sftp_ids {
+            self.delete_sftp(sftp_id);
+        }
+        self.agent_clients.retain(|(_, client_id)| *client_id != id);
+    }
+
+    /// Deletes an agent and everything that references it with `ON DELETE CASCADE`.
+    pub fn delete_agent(&mut self, id: i64) {
+        self.agents.remove(&id);
+        self.agent_clients.retain(|(agent_id, _)| *agent_id != id);
+        self.api_keys.retain(|_, (key, _)| key.agent_id != Some(id));
+    }
+}
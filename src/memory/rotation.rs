@@ -0,0 +1,50 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use super::repo::MemoryRepo;
+use crate::{
+    errors::models::AppError,
+    rotation::models::{RotationRepo, StaleKey},
+};
+
+#[async_trait]
+impl RotationRepo for MemoryRepo {
+    async fn stale_keys(&self, created_before: DateTime<Utc>) -> Result<Vec<StaleKey>, AppError> {
+        let state = self.state();
+        let now = Utc::now();
+        let keys = state
+            .sftp_keys
+            .values()
+            .filter(|key| {
+                key.is_active(now) && key.expires_at.is_none() && key.created_at < created_before
+            })
+            .filter_map(|key| {
+                let sftp = state.sftp.get(&key.sftp_id)?;
+                if state.is_deleted_client(sftp.client_id) {
+                    return None;
+                }
+                Some(StaleKey {
+                    id: key.id,
+                    sftp_id: key.sftp_id,
+                    client_id: sftp.client_id,
+                    source: key.source.clone(),
+                    fingerprint: key.fingerprint.clone(),
+                })
+            })
+            .collect();
+        Ok(keys)
+    }
+
+    async fn expire_key(&self, id: i64, expires_at: DateTime<Utc>) -> Result<(), AppError> {
+        let mut state = self.state();
+        let Some(before) = state.sftp_keys.get(&id).cloned() else {
+            return Err(AppError::NotFound(format!(
+                "Sftp key with id {} not found",
+                id
+            )));
+        };
+        state.expire_keys(before.sftp_id, &[id], expires_at);
+        let after = state.sftp_keys.get(&id).cloned();
+        state.emit("expire", "sftp_key", id, Some(&before), after.as_ref())
+    }
+}
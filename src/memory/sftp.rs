@@ -0,0 +1,196 @@
+use async_trait::async_trait;
+
+use super::repo::MemoryRepo;
+use crate::{
+    errors::models::AppError,
+    sftp::models::{
+        validate_source_ip_rules, Rotation, Sftp, SftpFilter, SftpKey, SftpOverview, SftpRepo,
+        SftpResponse, SftpUpdate, GENERATED, UPLOADED,
+    },
+    utils::{
+        etag::IfMatch,
+        list::{ListQuery, Page},
+        ssh::{parse_public_keys, SSHKeyPair},
+    },
+};
+use chrono::Utc;
+
+#[async_trait]
+impl SftpRepo for MemoryRepo {
+    async fn get_all(
+        &self,
+        query: ListQuery<SftpFilter>,
+        client_ids: Option<Vec<i64>>,
+    ) -> Result<Page<SftpOverview>, AppError> {
+        let state = self.state();
+        let sftps = state
+            .sftp
+            .values()
+            .filter(|sftp| query.include_deleted || !state.is_deleted_client(sftp.client_id))
+            .filter(|sftp| {
+                client_ids
+                    .as_ref()
+                    .is_none_or(|ids| ids.contains(&sftp.client_id))
+            })
+            .map(SftpOverview::from)
+            .collect();
+
+        query.page_in_memory(sftps, &[])
+    }
+
+    async fn get(&self, id: i64) -> Result<Option<SftpOverview>, AppError> {
+        let state = self.state();
+        let sftp = state
+            .sftp
+            .get(&id)
+            .filter(|sftp| !state.is_deleted_client(sftp.client_id))
+            .map(SftpOverview::from);
+        Ok(sftp)
+    }
+
+    async fn get_by_username(&self, username: &str) -> Result<Option<Sftp>, AppError> {
+        let state = self.state();
+        let sftp = state
+            .sftp
+            .values()
+            .find(|sftp| sftp.username == username && !state.is_deleted_client(sftp.client_id))
+            .map(|sftp| state.sftp_with_keys(sftp));
+        Ok(sftp)
+    }
+
+    async fn update(&self, id: i64, update: SftpUpdate, if_match: IfMatch) -> Result<(), AppError> {
+        if let Some(rules) = &update.allowed_source_ips {
+            validate_source_ip_rules(rules)?;
+        }
+
+        let mut state = self.state();
+        let Some(before) = state.sftp_snapshot(id) else {
+            return Err(AppError::NotFound(format!("Sftp with id {} not found", id)));
+        };
+        if_match.check("Sftp", id, before.version)?;
+        if let Some(username) = &update.username {
+            state.ensure_sftp_username_free(username, Some(id))?;
+        }
+
+        let Some(sftp) = state.sftp.get_mut(&id) else {
+            return Err(AppError::NotFound(format!("Sftp with id {} not found", id)));
+        };
+        if let Some(username) = update.username {
+            sftp.username = username;
+        }
+        if let Some(bucket_name) = update.bucket_name {
+            sftp.bucket_name = bucket_name;
+        }
+        if let Some(aws_role_arn) = update.aws_role_arn {
+            sftp.aws_role_arn = aws_role_arn;
+        }
+        if let Some(allowed_source_ips) = update.allowed_source_ips {
+            sftp.allowed_source_ips = Some(allowed_source_ips);
+        }
+        sftp.version += 1;
+
+        let after = state.sftp_snapshot(id);
+        state.emit("update", "sftp", id, Some(&before), after.as_ref())?;
+        Ok(())
+    }
+
+    async fn delete(&self, id: i64, if_match: IfMatch) -> Result<(), AppError> {
+        let mut state = self.state();
+        let Some(before) = state.sftp_snapshot(id) else {
+            return Err(AppError::NotFound(format!("Sftp with id {} not found", id)));
+        };
+        if_match.check("Sftp", id, before.version)?;
+        state.delete_sftp(id);
+        state.emit("delete", "sftp", id, Some(&before), None)
+    }
+
+    async fn reset_keys(
+        &self,
+        id: i64,
+        rotation: Rotation,
+        if_match: IfMatch,
+    ) -> Result<SftpResponse, AppError> {
+        let grace_period = rotation.grace_period()?;
+        if !self.state().sftp.contains_key(&id) {
+            return Err(AppError::NotFound(format!("Sftp with id {} not found", id)));
+        }
+
+        let ssh_keys = SSHKeyPair::generate(rotation.key_type)?;
+
+        let mut state = self.state();
+        let Some(before) = state.sftp_snapshot(id) else {
+            return Err(AppError::NotFound(format!("Sftp with id {} not found", id)));
+        };
+        if_match.check("Sftp", id, before.version)?;
+        state.rotate_key(id, &ssh_keys, grace_period);
+        let after = state.sftp_snapshot(id);
+        state.emit("reset_keys", "sftp", id, Some(&before), after.as_ref())?;
+
+        Ok(SftpResponse::new(id, before.client_id, ssh_keys))
+    }
+
+    async fn get_public_keys(&self, id: i64) -> Result<Vec<SftpKey>, AppError> {
+        let state = self.state();
+        if state.sftp_snapshot(id).is_none() {
+            return Ok(Vec::new());
+        }
+        Ok(state.active_keys(id))
+    }
+
+    async fn set_public_keys(
+        &self,
+        id: i64,
+        public_keys: Vec<String>,
+        if_match: IfMatch,
+    ) -> Result<Vec<SftpKey>, AppError> {
+        let uploaded = parse_public_keys(&public_keys)?;
+
+        let mut state = self.state();
+        let Some(before) = state.sftp_snapshot(id) else {
+            return Err(AppError::NotFound(format!("Sftp with id {} not found", id)));
+        };
+        if_match.check("Sftp", id, before.version)?;
+        let generated = state.sftp_keys.values().find(|key| {
+            key.sftp_id == id
+                && key.source == GENERATED
+                && key.revoked_at.is_none()
+                && uploaded
+                    .iter()
+                    .any(|new| new.fingerprint == key.fingerprint)
+        });
+        if let Some(key) = generated {
+            return Err(AppError::InvalidInput(format!(
+                "Public key {} is already the account's generated key",
+                key.fingerprint
+            )));
+        }
+
+        let now = Utc::now();
+        let mut kept = Vec::new();
+        for key in state.sftp_keys.values_mut() {
+            if key.sftp_id != id || key.source != UPLOADED || key.revoked_at.is_some() {
+                continue;
+            }
+            let listed = uploaded
+                .iter()
+                .any(|new| new.fingerprint == key.fingerprint);
+            if listed && key.is_active(now) {
+                kept.push(key.fingerprint.clone());
+            } else {
+                key.revoked_at = Some(now);
+            }
+        }
+        for key in uploaded {
+            if !kept.contains(&key.fingerprint) {
+                state.insert_key(id, UPLOADED, key);
+            }
+        }
+        if let Some(sftp) = state.sftp.get_mut(&id) {
+            sftp.version += 1;
+        }
+
+        let after = state.sftp_snapshot(id);
+        state.emit("set_public_keys", "sftp", id, Some(&before), after.as_ref())?;
+        Ok(state.active_keys(id))
+    }
+}
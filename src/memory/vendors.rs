@@ -0,0 +1,251 @@
+use async_trait::async_trait;
+use chrono::Utc;
+
+use super::repo::MemoryRepo;
+use crate::{
+    errors::models::{AppError, FieldError},
+    utils::{
+        etag::IfMatch,
+        list::{ListQuery, Page},
+    },
+    vendors::{
+        connection::HostKey,
+        models::{
+            host_key_not_found, Vendor, VendorFilter, VendorHostKey, VendorOverview, VendorRepo,
+            MANUAL,
+        },
+    },
+};
+
+#[async_trait]
+impl VendorRepo for MemoryRepo {
+    async fn get_all(
+        &self,
+        query: ListQuery<VendorFilter>,
+        client_ids: Option<Vec<i64>>,
+    ) -> Result<Page<Vendor>, AppError> {
+        let state = self.state();
+        let vendors = state
+            .vendors
+            .values()
+            .filter(|vendor| query.include_deleted || !state.is_deleted_client(vendor.client_id))
+            .filter(|vendor| {
+                client_ids
+                    .as_ref()
+                    .is_none_or(|ids| ids.contains(&vendor.client_id))
+            })
+            .cloned()
+            .collect();
+
+        query.page_in_memory(vendors, &[])
+    }
+
+    async fn get(&self, id: i64) -> Result<Option<VendorOverview>, AppError> {
+        let vendor = self.state().vendor(id).map(|vendor| VendorOverview {
+            id,
+            client_id: vendor.client_id,
+            name: vendor.name.clone(),
+            host: vendor.host.clone(),
+            port: vendor.port,
+            version: vendor.version,
+        });
+        Ok(vendor)
+    }
+
+    async fn get_with_credentials(&self, id: i64) -> Result<Option<Vendor>, AppError> {
+        Ok(self.state().vendor(id).cloned())
+    }
+
+    async fn update(&self, id: i64, vendor: Vendor, if_match: IfMatch) -> Result<(), AppError> {
+        let mut state = self.state();
+        let Some(before) = state.vendor(id).cloned() else {
+            return Err(AppError::NotFound(format!(
+                "Vendor with id {} not found",
+                id
+            )));
+        };
+        if_match.check("Vendor", id, before.version)?;
+        if state.client(vendor.client_id).is_err() {
+            return Err(AppError::InvalidReference(FieldError::new(
+                "client_id",
+                "does not exist",
+            )));
+        }
+
+        let after = Vendor {
+            id: Some(id),
+            version: before.version + 1,
+            ..vendor
+        };
+        state.vendors.insert(id, after.clone());
+        state.emit("update", "vendor", id, Some(&before), Some(&after))
+    }
+
+    async fn delete(&self, id: i64, if_match: IfMatch) -> Result<(), AppError> {
+        let mut state = self.state();
+        let Some(version) = state.vendor(id).map(|vendor| vendor.version) else {
+            return Err(AppError::NotFound(format!(
+                "Vendor with id {} not found",
+                id
+            )));
+        };
+        if_match.check("Vendor", id, version)?;
+        let before = state.delete_vendor(id);
+        state.emit("delete", "vendor", id, before.as_ref(), None)
+    }
+
+    async fn get_host_keys(&self, vendor_id: i64) -> Result<Vec<VendorHostKey>, AppError> {
+        let host_keys = self
+            .state()
+            .vendor_host_keys
+            .values()
+            .filter(|host_key| host_key.vendor_id == vendor_id)
+            .cloned()
+            .collect();
+        Ok(host_keys)
+    }
+
+    async fn add_host_key(
+        &self,
+        vendor_id: i64,
+        host_key: HostKey,
+        source: &str,
+        approve: bool,
+    ) -> Result<VendorHostKey, AppError> {
+        let mut state = self.state();
+        let before = state.host_key_pins(vendor_id)?;
+
+        let existing = before
+            .host_keys
+            .iter()
+            .find(|pinned| pinned.fingerprint == host_key.fingerprint)
+            .map(|pinned| pinned.id);
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                let id = state.next_id("vendor_host_keys");
+                let pinned = VendorHostKey {
+                    id,
+                    vendor_id,
+                    key_type: host_key.key_type,
+                    public_key: host_key.public_key,
+                    fingerprint: host_key.fingerprint,
+                    source: source.to_string(),
+                    created_at: Utc::now(),
+                    approved_at: None,
+                };
+                state.vendor_host_keys.insert(id, pinned);
+                id
+            }
+        };
+        let Some(pinned) = state.vendor_host_keys.get_mut(&id) else {
+            return Err(AppError::Unknown);
+        };
+        if approve && pinned.approved_at.is_none() {
+            pinned.approved_at = Some(Utc::now());
+        }
+        let pinned = pinned.clone();
+
+        let after = state.host_key_pins(vendor_id)?;
+        if after != before {
+            state.emit(
+                "add_host_key",
+                "vendor",
+                vendor_id,
+                Some(&before),
+                Some(&after),
+            )?;
+        }
+        Ok(pinned)
+    }
+
+    async fn approve_host_key(&self, vendor_id: i64, id: i64) -> Result<VendorHostKey, AppError> {
+        let mut state = self.state();
+        let before = state.host_key_pins(vendor_id)?;
+        let approved = match state.vendor_host_keys.get_mut(&id) {
+            Some(host_key) if host_key.vendor_id == vendor_id => {
+                host_key.approved_at.get_or_insert_with(Utc::now);
+                host_key.clone()
+            }
+            _ => return Err(host_key_not_found(vendor_id, id)),
+        };
+
+        let after = state.host_key_pins(vendor_id)?;
+        if after != before {
+            state.emit(
+                "approve_host_key",
+                "vendor",
+                vendor_id,
+                Some(&before),
+                Some(&after),
+            )?;
+        }
+        Ok(approved)
+    }
+
+    async fn replace_host_keys(
+        &self,
+        vendor_id: i64,
+        host_keys: Vec<HostKey>,
+    ) -> Result<Vec<VendorHostKey>, AppError> {
+        let mut state = self.state();
+        let before = state.host_key_pins(vendor_id)?;
+
+        state
+            .vendor_host_keys
+            .retain(|_, host_key| host_key.vendor_id != vendor_id);
+        let now = Utc::now();
+        for host_key in host_keys {
+            let pinned = state.vendor_host_keys.values().any(|pinned| {
+                pinned.vendor_id == vendor_id && pinned.fingerprint == host_key.fingerprint
+            });
+            if pinned {
+                continue;
+            }
+            let id = state.next_id("vendor_host_keys");
+            state.vendor_host_keys.insert(
+                id,
+                VendorHostKey {
+                    id,
+                    vendor_id,
+                    key_type: host_key.key_type,
+                    public_key: host_key.public_key,
+                    fingerprint: host_key.fingerprint,
+                    source: MANUAL.to_string(),
+                    created_at: now,
+                    approved_at: Some(now),
+                },
+            );
+        }
+
+        let after = state.host_key_pins(vendor_id)?;
+        state.emit(
+            "replace_host_keys",
+            "vendor",
+            vendor_id,
+            Some(&before),
+            Some(&after),
+        )?;
+        Ok(after.host_keys)
+    }
+
+    async fn delete_host_key(&self, vendor_id: i64, id: i64) -> Result<(), AppError> {
+        let mut state = self.state();
+        let before = state.host_key_pins(vendor_id)?;
+        match state.vendor_host_keys.get(&id) {
+            Some(host_key) if host_key.vendor_id == vendor_id => {
+                state.vendor_host_keys.remove(&id);
+            }
+            _ => return Err(host_key_not_found(vendor_id, id)),
+        }
+
+        let after = state.host_key_pins(vendor_id)?;
+        state.emit(
+            "delete_host_key",
+            "vendor",
+            vendor_id,
+            Some(&before),
+            Some(&after),
+        )
+    }
+}
@@ -0,0 +1,204 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+
+use super::repo::{MemoryRepo, OutboxEntry};
+use crate::{
+    api_keys::models::generate_token,
+    errors::models::AppError,
+    webhooks::models::{CreatedWebhook, DeadLetter, Delivery, NewWebhook, Webhook, WebhookRepo},
+};
+
+#[async_trait]
+impl WebhookRepo for MemoryRepo {
+    async fn get_all(&self) -> Result<Vec<Webhook>, AppError> {
+        let webhooks = self
+            .state()
+            .webhooks
+            .values()
+            .map(|(webhook, _)| webhook.clone())
+            .collect();
+        Ok(webhooks)
+    }
+
+    async fn get(&self, id: i64) -> Result<Option<Webhook>, AppError> {
+        let webhook = self
+            .state()
+            .webhooks
+            .get(&id)
+            .map(|(webhook, _)| webhook.clone());
+        Ok(webhook)
+    }
+
+    async fn create(&self, webhook: NewWebhook) -> Result<CreatedWebhook, AppError> {
+        webhook.validate()?;
+
+        let secret = webhook.secret.unwrap_or_else(generate_token);
+        let mut state = self.state();
+        let id = state.next_id("webhook_subscriptions");
+        let created = Webhook {
+            id,
+            url: webhook.url,
+            event_types: webhook.event_types,
+            created_at: Utc::now(),
+        };
+        state.webhooks.insert(id, (created.clone(), secret.clone()));
+
+        Ok(CreatedWebhook {
+            webhook: created,
+            secret,
+        })
+    }
+
+    async fn update(&self, id: i64, webhook: NewWebhook) -> Result<Webhook, AppError> {
+        webhook.validate()?;
+
+        let mut state = self.state();
+        let Some((stored, secret)) = state.webhooks.get_mut(&id) else {
+            return Err(not_found(id));
+        };
+        stored.url = webhook.url;
+        stored.event_types = webhook.event_types;
+        if let Some(new_secret) = webhook.secret {
+            *secret = new_secret;
+        }
+        Ok(stored.clone())
+    }
+
+    async fn delete(&self, id: i64) -> Result<(), AppError> {
+        let mut state = self.state();
+        if state.webhooks.remove(&id).is_none() {
+            return Err(not_found(id));
+        }
+        state
+            .webhook_outbox
+            .retain(|_, entry| entry.subscription_id != id);
+        state
+            .webhook_dead_letters
+            .retain(|_, dead_letter| dead_letter.subscription_id != id);
+        Ok(())
+    }
+
+    async fn claim_deliveries(
+        &self,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<Delivery>, AppError> {
+        let mut guard = self.state();
+        let state = &mut *guard;
+        let now = Utc::now();
+        let mut deliveries = Vec::new();
+        for (id, entry) in state.webhook_outbox.iter_mut() {
+            if deliveries.len() as i64 >= limit {
+                break;
+            }
+            if entry.next_attempt_at > now {
+                continue;
+            }
+            let Some((webhook, secret)) = state.webhooks.get(&entry.subscription_id) else {
+                continue;
+            };
+            entry.next_attempt_at = now + lease;
+            deliveries.push(Delivery {
+                id: *id,
+                subscription_id: entry.subscription_id,
+                url: webhook.url.clone(),
+                secret: secret.clone(),
+                event_id: entry.event_id,
+                event_type: entry.event_type.clone(),
+                payload: entry.payload.clone(),
+                attempts: entry.attempts,
+            });
+        }
+        Ok(deliveries)
+    }
+
+    async fn delivered(&self, id: i64) -> Result<(), AppError> {
+        self.state().webhook_outbox.remove(&id);
+        Ok(())
+    }
+
+    async fn failed(
+        &self,
+        id: i64,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<(), AppError> {
+        let mut state = self.state();
+        match retry_at {
+            Some(retry_at) => {
+                if let Some(entry) = state.webhook_outbox.get_mut(&id) {
+                    entry.attempts += 1;
+                    entry.last_error = Some(error.to_string());
+                    entry.next_attempt_at = retry_at;
+                }
+            }
+            None => {
+                if let Some(entry) = state.webhook_outbox.remove(&id) {
+                    let dead_letter_id = state.next_id("webhook_dead_letters");
+                    state.webhook_dead_letters.insert(
+                        dead_letter_id,
+                        DeadLetter {
+                            id: dead_letter_id,
+                            subscription_id: entry.subscription_id,
+                            event_id: entry.event_id,
+                            event_type: entry.event_type,
+                            payload: entry.payload,
+                            created_at: entry.created_at,
+                            attempts: entry.attempts + 1,
+                            last_error: Some(error.to_string()),
+                            failed_at: Utc::now(),
+                        },
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_dead_letters(&self, webhook_id: i64) -> Result<Vec<DeadLetter>, AppError> {
+        let dead_letters = self
+            .state()
+            .webhook_dead_letters
+            .values()
+            .filter(|dead_letter| dead_letter.subscription_id == webhook_id)
+            .cloned()
+            .collect();
+        Ok(dead_letters)
+    }
+
+    async fn retry_dead_letter(&self, webhook_id: i64, id: i64) -> Result<(), AppError> {
+        let mut state = self.state();
+        let dead_letter = match state.webhook_dead_letters.get(&id) {
+            Some(dead_letter) if dead_letter.subscription_id == webhook_id => {
+                state.webhook_dead_letters.remove(&id)
+            }
+            _ => None,
+        };
+        let Some(dead_letter) = dead_letter else {
+            return Err(AppError::NotFound(format!(
+                "Dead letter with id {} for webhook with id {} not found",
+                id, webhook_id
+            )));
+        };
+
+        let outbox_id = state.next_id("webhook_outbox");
+        state.webhook_outbox.insert(
+            outbox_id,
+            OutboxEntry {
+                subscription_id: dead_letter.subscription_id,
+                event_id: dead_letter.event_id,
+                event_type: dead_letter.event_type,
+                payload: dead_letter.payload,
+                created_at: dead_letter.created_at,
+                attempts: 0,
+                next_attempt_at: Utc::now(),
+                last_error: None,
+            },
+        );
+        Ok(())
+    }
+}
+
+fn not_found(id: i64) -> AppError {
+    AppError::NotFound(format!("Webhook with id {} not found", id))
+}
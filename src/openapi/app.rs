@@ -0,0 +1,15 @@
+use super::models::ApiDoc;
+use axum::Router;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+/// The router for the API documentation.
+/// ```text
+/// GET /openapi.json
+/// GET /docs
+/// ```
+/// Serves the OpenAPI document and a Swagger UI to browse it. Merge it outside the auth
+/// layer, the docs are public.
+pub fn router() -> Router {
+    Router::new().merge(SwaggerUi::new("/docs").url("/openapi.json", ApiDoc::openapi()))
+}
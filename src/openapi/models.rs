@@ -0,0 +1,128 @@
+use utoipa::openapi::response::ResponseBuilder;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::{ContentBuilder, Ref};
+use utoipa::{Modify, OpenApi};
+
+use crate::errors::models::ErrorBody;
+use crate::{
+    agents, api_keys, audit, bulk, clients, config, health, retention, rotation, sftp, vendors,
+    webhooks,
+};
+
+/// The OpenAPI document of every route. A route missing here fails the `openapi` test.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "User Manager API"),
+    servers(
+        (url = "/v2"),
+        (url = "/v1", description = "Creates answer 200 with just the id, updates and deletes an empty 200"),
+    ),
+    paths(
+        clients::handlers::get_clients,
+        clients::handlers::create_client,
+        clients::handlers::onboard_client,
+        clients::handlers::get_client,
+        clients::handlers::update_client,
+        clients::handlers::delete_client,
+        clients::handlers::restore_client,
+        clients::handlers::add_vendor_to_client,
+        clients::handlers::update_vendor,
+        clients::handlers::add_sftp,
+        clients::handlers::get_client_sftps,
+        clients::handlers::get_client_sftp,
+        clients::handlers::delete_client_sftp,
+        clients::handlers::reset_client_sftp_keys,
+        clients::handlers::reset_keys,
+        vendors::handlers::get_vendors,
+        vendors::handlers::get_vendor,
+        vendors::handlers::update_vendor,
+        vendors::handlers::delete_vendor,
+        vendors::handlers::test_vendor_connection,
+        vendors::handlers::get_host_keys,
+        vendors::handlers::get_host_key,
+        vendors::handlers::pin_host_key,
+        vendors::handlers::replace_host_keys,
+        vendors::handlers::capture_host_key,
+        vendors::handlers::approve_host_key,
+        vendors::handlers::delete_host_key,
+        sftp::handlers::get_sftp,
+        sftp::handlers::get_sftp_by_id,
+        sftp::handlers::update_sftp,
+        sftp::handlers::delete_sftp,
+        sftp::handlers::get_public_keys,
+        sftp::handlers::set_public_keys,
+        sftp::handlers::get_identity,
+        agents::handlers::get_agents,
+        agents::handlers::get_agent,
+        agents::handlers::create_agent,
+        agents::handlers::update_agent,
+        agents::handlers::delete_agent,
+        agents::handlers::get_clients_for_agent,
+        agents::handlers::add_client_to_agent,
+        api_keys::handlers::get_api_keys,
+        api_keys::handlers::create_api_key,
+        api_keys::handlers::revoke_api_key,
+        audit::handlers::get_audit_events,
+        bulk::handlers::import,
+        bulk::handlers::export_records,
+        rotation::handlers::run_now,
+        retention::handlers::run_now,
+        webhooks::handlers::get_webhooks,
+        webhooks::handlers::create_webhook,
+        webhooks::handlers::get_webhook,
+        webhooks::handlers::update_webhook,
+        webhooks::handlers::delete_webhook,
+        webhooks::handlers::get_dead_letters,
+        webhooks::handlers::retry_dead_letter,
+        config::handlers::config,
+        health::handlers::health,
+    ),
+    components(schemas(ErrorBody)),
+    modifiers(&BearerAuth, &ErrorResponses),
+    security(("api_key" = [])),
+)]
+pub struct ApiDoc;
+
+/// Every route takes an API key as a bearer token.
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+/// Every route fails with an [`ErrorBody`].
+struct ErrorResponses;
+
+impl Modify for ErrorResponses {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let error = ResponseBuilder::new()
+            .description("The request failed, see `code`")
+            .content(
+                "application/json",
+                ContentBuilder::new()
+                    .schema(Some(Ref::from_schema_name("ErrorBody")))
+                    .build(),
+            )
+            .build();
+        for item in openapi.paths.paths.values_mut() {
+            let operations = [
+                &mut item.get,
+                &mut item.put,
+                &mut item.post,
+                &mut item.delete,
+            ];
+            for operation in operations.into_iter().flatten() {
+                operation
+                    .responses
+                    .responses
+                    .insert("default".to_string(), error.clone().into());
+            }
+        }
+    }
+}
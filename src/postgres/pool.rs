@@ -1,17 +1,25 @@
+use std::sync::Arc;
+
 use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
 
+use crate::utils::crypto::KeyRing;
+
 #[derive(Debug, Clone)]
 pub struct PostgresRepo {
     pub pool: Pool<Postgres>,
+    pub keys: Arc<KeyRing>,
 }
 
 impl PostgresRepo {
-    pub async fn new(db_url: &str) -> Self {
+    pub async fn new(db_url: &str, keys: KeyRing) -> Self {
         let pool = PgPoolOptions::new()
             .max_connections(5)
             .connect(db_url)
             .await
             .unwrap();
-        Self { pool }
+        Self {
+            pool,
+            keys: Arc::new(keys),
+        }
     }
 }
@@ -0,0 +1,98 @@
+use serde::Serialize;
+
+use super::pool::PostgresRepo;
+use crate::{errors::models::AppError, vendors::models::VendorRecord};
+
+/// Counts of rows rewritten by a re-encryption pass.
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct ReencryptStats {
+    pub vendors: u64,
+    pub sftp: u64,
+    pub webhooks: u64,
+}
+
+/// Re-encrypts every secret that isn't already under the active master key.
+///
+/// This covers rows carried over as plaintext by the `encrypt_secrets` migration as
+/// well as rows written under a master key that is being rotated out. Each row is only
+/// rewritten if its key id hasn't changed since it was read, so it is safe to run while
+/// the API is serving traffic, and safe to run again if it is interrupted.
+pub async fn reencrypt_all(repo: &PostgresRepo) -> Result<ReencryptStats, AppError> {
+    let active_key_id = repo.keys.active_key_id();
+    let mut stats = ReencryptStats::default();
+
+    let vendors =
+        sqlx::query_as::<_, VendorRecord>("SELECT * FROM vendors WHERE key_id IS DISTINCT FROM $1")
+            .bind(active_key_id)
+            .fetch_all(&repo.pool)
+            .await?;
+
+    for record in vendors {
+        let old_key_id = record.key_id.clone();
+        let vendor = record.decrypt(&repo.keys)?;
+        stats.vendors += sqlx::query(
+            "UPDATE vendors SET password = $1, ssh_key = $2, ssh_key_password = $3, key_id = $4
+             WHERE id = $5 AND key_id IS NOT DISTINCT FROM $6",
+        )
+        .bind(repo.keys.encrypt_opt(vendor.password.as_deref())?)
+        .bind(repo.keys.encrypt_opt(vendor.ssh_key.as_deref())?)
+        .bind(repo.keys.encrypt_opt(vendor.ssh_key_password.as_deref())?)
+        .bind(active_key_id)
+        .bind(vendor.id)
+        .bind(old_key_id)
+        .execute(&repo.pool)
+        .await?
+        .rows_affected();
+    }
+
+    let sftps = sqlx::query!(
+        r#"SELECT id, private_key as "private_key!", key_id FROM sftp
+        WHERE private_key IS NOT NULL AND key_id IS DISTINCT FROM $1"#,
+        active_key_id
+    )
+    .fetch_all(&repo.pool)
+    .await?;
+
+    for sftp in sftps {
+        let private_key = repo
+            .keys
+            .decrypt(sftp.key_id.as_deref(), &sftp.private_key)?;
+        stats.sftp += sqlx::query!(
+            "UPDATE sftp SET private_key = $1, key_id = $2
+             WHERE id = $3 AND key_id IS NOT DISTINCT FROM $4",
+            repo.keys.encrypt(&private_key)?,
+            active_key_id,
+            sftp.id,
+            sftp.key_id,
+        )
+        .execute(&repo.pool)
+        .await?
+        .rows_affected();
+    }
+
+    let webhooks = sqlx::query!(
+        "SELECT id, secret, key_id FROM webhook_subscriptions WHERE key_id IS DISTINCT FROM $1",
+        active_key_id
+    )
+    .fetch_all(&repo.pool)
+    .await?;
+
+    for webhook in webhooks {
+        let secret = repo
+            .keys
+            .decrypt(webhook.key_id.as_deref(), &webhook.secret)?;
+        stats.webhooks += sqlx::query!(
+            "UPDATE webhook_subscriptions SET secret = $1, key_id = $2
+             WHERE id = $3 AND key_id IS NOT DISTINCT FROM $4",
+            repo.keys.encrypt(&secret)?,
+            active_key_id,
+            webhook.id,
+            webhook.key_id,
+        )
+        .execute(&repo.pool)
+        .await?
+        .rows_affected();
+    }
+
+    Ok(stats)
+}
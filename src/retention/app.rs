@@ -0,0 +1,17 @@
+use std::sync::Arc;
+
+use super::handlers::run_now;
+use crate::{clients::models::ClientRepo, config::models::RetentionConfig};
+use axum::{routing::post, Extension, Router};
+
+/// The router for the retention job.
+/// ```text
+/// POST /retention/run
+/// ```
+/// Purges the clients past the retention window right away and returns their ids.
+pub fn router<T: ClientRepo>(repo: T, config: RetentionConfig) -> Router {
+    Router::new()
+        .route("/retention/run", post(run_now::<T>))
+        .layer(Extension(Arc::new(config)))
+        .with_state(repo)
+}
@@ -0,0 +1,20 @@
+use std::sync::Arc;
+
+use super::models::{purge, PurgeReport};
+use crate::{
+    clients::models::ClientRepo, config::models::RetentionConfig, errors::models::AppError,
+};
+use axum::{extract::State, Extension, Json};
+
+/// Runs the retention purge now, whether or not the background job is enabled.
+#[utoipa::path(
+    post, path = "/retention/run", tag = "retention",
+    responses((status = 200, body = PurgeReport)),
+)]
+pub async fn run_now<T: ClientRepo>(
+    State(repo): State<T>,
+    Extension(config): Extension<Arc<RetentionConfig>>,
+) -> Result<Json<PurgeReport>, AppError> {
+    let report = purge(&repo, &config).await?;
+    Ok(Json(report))
+}
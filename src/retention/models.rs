@@ -0,0 +1,58 @@
+use std::{future::Future, time::Duration as StdDuration};
+
+use crate::{
+    clients::models::ClientRepo, config::models::RetentionConfig, errors::models::AppError,
+};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::time::MissedTickBehavior;
+use utoipa::ToSchema;
+
+/// What a purge removed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, ToSchema)]
+pub struct PurgeReport {
+    /// Clients that were deleted more than `retention_days` ago, purged with their vendors,
+    /// SFTP accounts and agent links.
+    pub purged_clients: Vec<i64>,
+}
+
+/// Hard deletes the clients that were soft deleted more than `retention_days` ago. Until
+/// then they can be restored.
+pub async fn purge<T: ClientRepo>(
+    repo: &T,
+    config: &RetentionConfig,
+) -> Result<PurgeReport, AppError> {
+    let deleted_before = Utc::now() - Duration::days(config.retention_days.into());
+    let purged_clients = repo.purge_deleted(deleted_before).await?;
+    if !purged_clients.is_empty() {
+        tracing::info!(?purged_clients, "Purged deleted clients");
+    }
+
+    Ok(PurgeReport { purged_clients })
+}
+
+/// Runs [`purge`] every `interval_minutes`, starting right away, until `shutdown`
+/// completes. A purge that is already running when shutdown starts is finished first.
+pub async fn run<T: ClientRepo>(
+    repo: T,
+    config: RetentionConfig,
+    shutdown: impl Future<Output = ()>,
+) {
+    let period = StdDuration::from_secs(config.interval_minutes.max(1) * 60);
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = interval.tick() => {
+                if let Err(error) = purge(&repo, &config).await {
+                    tracing::error!(%error, "Retention purge failed");
+                }
+            }
+        }
+    }
+
+    tracing::info!("Retention purge stopped");
+}
@@ -0,0 +1,18 @@
+use std::sync::Arc;
+
+use super::{handlers::run_now, models::RotationRepo};
+use crate::{config::models::KeyRotationConfig, sftp::models::SftpRepo};
+use axum::{routing::post, Extension, Router};
+
+/// The router for the key rotation job.
+/// ```text
+/// POST /key-rotation/run
+/// ```
+/// Runs a sweep right away and returns the accounts it rotated and the keys it marked
+/// to expire.
+pub fn router<T: SftpRepo + RotationRepo>(repo: T, config: KeyRotationConfig) -> Router {
+    Router::new()
+        .route("/key-rotation/run", post(run_now::<T>))
+        .layer(Extension(Arc::new(config)))
+        .with_state(repo)
+}
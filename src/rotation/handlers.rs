@@ -0,0 +1,18 @@
+use std::sync::Arc;
+
+use super::models::{sweep, RotationRepo, SweepReport};
+use crate::{config::models::KeyRotationConfig, errors::models::AppError, sftp::models::SftpRepo};
+use axum::{extract::State, Extension, Json};
+
+/// Runs the key rotation sweep now, whether or not the background job is enabled.
+#[utoipa::path(
+    post, path = "/key-rotation/run", tag = "key-rotation",
+    responses((status = 200, body = SweepReport)),
+)]
+pub async fn run_now<T: SftpRepo + RotationRepo>(
+    State(repo): State<T>,
+    Extension(config): Extension<Arc<KeyRotationConfig>>,
+) -> Result<Json<SweepReport>, AppError> {
+    let report = sweep(&repo, &config).await?;
+    Ok(Json(report))
+}
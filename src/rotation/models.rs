@@ -0,0 +1,191 @@
+use std::{collections::BTreeSet, future::Future, time::Duration as StdDuration};
+
+use crate::{
+    audit::models as audit,
+    config::models::KeyRotationConfig,
+    errors::models::AppError,
+    postgres::pool::PostgresRepo,
+    sftp::models::{expire_keys, Rotation, SftpKey, SftpRepo, GENERATED},
+    utils::{etag::IfMatch, ssh::KeyType},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection};
+use tokio::time::MissedTickBehavior;
+use utoipa::ToSchema;
+
+/// Finds the SFTP keys that are due for rotation and marks uploaded ones to expire.
+/// Generated keys are rotated through [`SftpRepo::reset_keys`].
+#[async_trait]
+pub trait RotationRepo: Send + Sync + Clone + 'static {
+    /// Active keys without an expiry that were created before `created_before`.
+    async fn stale_keys(&self, created_before: DateTime<Utc>) -> Result<Vec<StaleKey>, AppError>;
+    /// Makes a key expire at `expires_at`, unless it already expires sooner, through
+    /// [`expire_keys`].
+    async fn expire_key(&self, id: i64, expires_at: DateTime<Utc>) -> Result<(), AppError>;
+}
+
+#[async_trait]
+impl RotationRepo for PostgresRepo {
+    async fn stale_keys(&self, created_before: DateTime<Utc>) -> Result<Vec<StaleKey>, AppError> {
+        let keys = sqlx::query_as!(
+            StaleKey,
+            r#"SELECT k.id as "id!", k.sftp_id as "sftp_id!", s.client_id,
+                k.source as "source!", k.fingerprint as "fingerprint!"
+            FROM active_sftp_keys k
+                JOIN sftp s ON s.id = k.sftp_id
+                JOIN clients c ON c.id = s.client_id
+            WHERE k.expires_at IS NULL AND k.created_at < $1 AND c.deleted_at IS NULL
+            ORDER BY k.id"#,
+            created_before
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(keys)
+    }
+
+    async fn expire_key(&self, id: i64, expires_at: DateTime<Utc>) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        let before = lock_key(&mut tx, id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Sftp key with id {} not found", id)))?;
+
+        expire_keys(&mut tx, before.sftp_id, &[id], expires_at).await?;
+
+        let after = lock_key(&mut tx, id).await?;
+        audit::record(
+            &mut tx,
+            "expire",
+            "sftp_key",
+            id,
+            Some(&before),
+            after.as_ref(),
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+/// Loads a key inside a transaction, locking the row for the rest of it.
+async fn lock_key(conn: &mut PgConnection, id: i64) -> Result<Option<SftpKey>, AppError> {
+    let key = sqlx::query_as!(
+        SftpKey,
+        "SELECT id, sftp_id, source, key_type, public_key, fingerprint, created_at,
+            not_before, expires_at, revoked_at
+        FROM sftp_keys WHERE id = $1 FOR UPDATE",
+        id
+    )
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(key)
+}
+
+/// A key found by [`RotationRepo::stale_keys`], with the client owning its account.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow)]
+pub struct StaleKey {
+    pub id: i64,
+    pub sftp_id: i64,
+    pub client_id: i64,
+    pub source: String,
+    pub fingerprint: String,
+}
+
+/// What a sweep changed. Failures are logged and retried by the next sweep.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, ToSchema)]
+pub struct SweepReport {
+    /// Accounts that got a new generated key pair.
+    pub rotated_accounts: Vec<i64>,
+    /// Uploaded keys that were given an expiry.
+    pub expiring_keys: Vec<i64>,
+}
+
+/// Rotates keys older than `max_key_age_days`. Accounts with an old generated key get a new
+/// key pair through [`SftpRepo::reset_keys`], the same path as
+/// `PUT /clients/:id/sftp/:sftp_id/reset-keys`, and their old generated keys expire after
+/// the grace period. Uploaded keys can't be replaced by us, so they are only marked to
+/// expire. Either way the audit log, and so the webhooks, get an event for every account
+/// or key that changed, and the client can fetch a key pair of its own with a reset or
+/// upload new keys before the old ones stop working.
+pub async fn sweep<T: SftpRepo + RotationRepo>(
+    repo: &T,
+    config: &KeyRotationConfig,
+) -> Result<SweepReport, AppError> {
+    let rotation = Rotation {
+        key_type: KeyType::default(),
+        grace_period_hours: config.grace_period_hours,
+    };
+    let grace_period = rotation.grace_period()?;
+    let now = Utc::now();
+    let keys = repo
+        .stale_keys(now - Duration::days(config.max_key_age_days.into()))
+        .await?;
+
+    let mut report = SweepReport::default();
+    let accounts: BTreeSet<(i64, i64)> = keys
+        .iter()
+        .filter(|key| key.source == GENERATED)
+        .map(|key| (key.sftp_id, key.client_id))
+        .collect();
+    for (sftp_id, client_id) in accounts {
+        match SftpRepo::reset_keys(repo, sftp_id, rotation, IfMatch::any()).await {
+            Ok(account) => {
+                tracing::info!(
+                    sftp_id,
+                    client_id,
+                    fingerprint = ?account.fingerprint,
+                    "Rotated SFTP keys"
+                );
+                report.rotated_accounts.push(sftp_id);
+            }
+            Err(error) => tracing::error!(sftp_id, %error, "Failed to rotate SFTP keys"),
+        }
+    }
+
+    for key in keys.iter().filter(|key| key.source != GENERATED) {
+        match repo.expire_key(key.id, now + grace_period).await {
+            Ok(()) => {
+                tracing::info!(
+                    key_id = key.id,
+                    sftp_id = key.sftp_id,
+                    client_id = key.client_id,
+                    fingerprint = %key.fingerprint,
+                    "SFTP key expiring"
+                );
+                report.expiring_keys.push(key.id);
+            }
+            Err(error) => tracing::error!(key_id = key.id, %error, "Failed to expire SFTP key"),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Runs [`sweep`] every `interval_minutes`, starting right away, until `shutdown`
+/// completes. A sweep that is already running when shutdown starts is finished first.
+pub async fn run<T: SftpRepo + RotationRepo>(
+    repo: T,
+    config: KeyRotationConfig,
+    shutdown: impl Future<Output = ()>,
+) {
+    let period = StdDuration::from_secs(config.interval_minutes.max(1) * 60);
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = interval.tick() => {
+                if let Err(error) = sweep(&repo, &config).await {
+                    tracing::error!(%error, "Key rotation sweep failed");
+                }
+            }
+        }
+    }
+
+    tracing::info!("Key rotation stopped");
+}
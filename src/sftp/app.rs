@@ -1,5 +1,8 @@
 use super::{
-    handlers::{delete_sftp, get_sftp, get_sftp_by_id, update_sftp},
+    handlers::{
+        delete_sftp, get_identity, get_public_keys, get_sftp, get_sftp_by_id, set_public_keys,
+        update_sftp,
+    },
     models::SftpRepo,
 };
 use axum::{
@@ -13,5 +16,8 @@ pub fn router<T: SftpRepo>(repo: T) -> Router {
         .route("/sftp/:id", get(get_sftp_by_id::<T>))
         .route("/sftp/:id", put(update_sftp::<T>))
         .route("/sftp/:id", delete(delete_sftp::<T>))
+        .route("/sftp/:id/public-keys", get(get_public_keys::<T>))
+        .route("/sftp/:id/public-keys", put(set_public_keys::<T>))
+        .route("/sftp/identity/:username", get(get_identity::<T>))
         .with_state(repo)
 }
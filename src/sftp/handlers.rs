@@ -1,49 +1,175 @@
-use super::models::{SftpOverview, SftpRepo, SftpUpdate};
-use crate::errors::models::AppError;
-use axum::{
-    extract::{Path, Query, State},
-    Json,
+use super::models::{
+    PublicKeys, SftpFilter, SftpIdentity, SftpKey, SftpOverview, SftpRepo, SftpUpdate,
 };
-use std::collections::HashMap;
+use crate::{
+    errors::models::AppError,
+    utils::{
+        auth::Caller,
+        etag::{ETag, IfMatch},
+        extract::{Path, Query},
+        list::{ListQuery, Page},
+        response::{NoContent, Updated},
+        validate::Valid,
+    },
+};
+use axum::{extract::State, http::HeaderMap, Extension, Json};
+use std::{collections::HashMap, net::IpAddr};
 
+#[utoipa::path(
+    get, path = "/sftp", tag = "sftp",
+    params(ListQuery<SftpFilter>),
+    responses((status = 200, body = Page<SftpOverview>)),
+)]
 pub async fn get_sftp<T: SftpRepo>(
     State(repo): State<T>,
-    Query(params): Query<HashMap<String, String>>,
-) -> Result<Json<Vec<SftpOverview>>, AppError> {
-    let client_id = params.get("client_id").and_then(|id| id.parse().ok());
-    let sftps = repo.get_all(client_id).await?;
+    Extension(caller): Extension<Caller>,
+    query: ListQuery<SftpFilter>,
+) -> Result<Json<Page<SftpOverview>>, AppError> {
+    let sftps = repo.get_all(query, caller.client_filter()).await?;
     Ok(Json(sftps))
 }
 
+#[utoipa::path(
+    get, path = "/sftp/{id}", tag = "sftp",
+    params(("id" = i64, Path, description = "The id of the SFTP account")),
+    responses((status = 200, body = SftpOverview, headers(("ETag" = String)))),
+)]
 pub async fn get_sftp_by_id<T: SftpRepo>(
     State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
     Path(id): Path<i64>,
-) -> Result<Json<SftpOverview>, AppError> {
-    match repo.get(id).await? {
-        Some(sftp) => Ok(Json(SftpOverview {
-            id: sftp.id,
-            client_id: sftp.client_id,
-            username: sftp.username,
-            bucket_name: sftp.bucket_name,
-            aws_role_arn: sftp.aws_role_arn,
-        })),
-        None => Err(AppError::NotFound(format!("Sftp with id {} not found", id))),
-    }
+) -> Result<(ETag, Json<SftpOverview>), AppError> {
+    let sftp = find_sftp(&repo, &caller, id).await?;
+    Ok((ETag(sftp.version), Json(sftp)))
 }
 
+#[utoipa::path(
+    put, path = "/sftp/{id}", tag = "sftp",
+    params(("id" = i64, Path, description = "The id of the SFTP account"), IfMatch),
+    request_body = SftpUpdate,
+    responses((status = 200, body = SftpOverview, headers(("ETag" = String)))),
+)]
 pub async fn update_sftp<T: SftpRepo>(
     State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
     Path(id): Path<i64>,
-    Json(update): Json<SftpUpdate>,
-) -> Result<(), AppError> {
-    repo.update(id, update).await?;
-    Ok(())
+    if_match: IfMatch,
+    Valid(update): Valid<SftpUpdate>,
+) -> Result<Updated<(ETag, Json<SftpOverview>)>, AppError> {
+    find_sftp(&repo, &caller, id).await?;
+    repo.update(id, update, if_match).await?;
+    let sftp = find_sftp(&repo, &caller, id).await?;
+    Ok(Updated((ETag(sftp.version), Json(sftp))))
 }
 
+#[utoipa::path(
+    delete, path = "/sftp/{id}", tag = "sftp",
+    params(("id" = i64, Path, description = "The id of the SFTP account"), IfMatch),
+    responses((status = 204)),
+)]
 pub async fn delete_sftp<T: SftpRepo>(
     State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<i64>,
+    if_match: IfMatch,
+) -> Result<NoContent, AppError> {
+    find_sftp(&repo, &caller, id).await?;
+    repo.delete(id, if_match).await?;
+    Ok(NoContent)
+}
+
+/// Lists the public keys of an account that are active right now, generated or uploaded.
+#[utoipa::path(
+    get, path = "/sftp/{id}/public-keys", tag = "sftp",
+    params(("id" = i64, Path, description = "The id of the SFTP account")),
+    responses((status = 200, body = Vec<SftpKey>)),
+)]
+pub async fn get_public_keys<T: SftpRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<i64>,
+) -> Result<Json<Vec<SftpKey>>, AppError> {
+    find_sftp(&repo, &caller, id).await?;
+    let keys = repo.get_public_keys(id).await?;
+    Ok(Json(keys))
+}
+
+/// Replaces the uploaded public keys of an account. Returns the account's active keys
+/// with their fingerprints.
+#[utoipa::path(
+    put, path = "/sftp/{id}/public-keys", tag = "sftp",
+    params(("id" = i64, Path, description = "The id of the SFTP account"), IfMatch),
+    request_body = PublicKeys,
+    responses((status = 200, body = Vec<SftpKey>)),
+)]
+pub async fn set_public_keys<T: SftpRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
     Path(id): Path<i64>,
-) -> Result<(), AppError> {
-    repo.delete(id).await?;
-    Ok(())
+    if_match: IfMatch,
+    Valid(upload): Valid<PublicKeys>,
+) -> Result<Json<Vec<SftpKey>>, AppError> {
+    find_sftp(&repo, &caller, id).await?;
+    let keys = repo
+        .set_public_keys(id, upload.public_keys, if_match)
+        .await?;
+    Ok(Json(keys))
+}
+
+/// Loads an SFTP account and checks the caller has access to the client it belongs to.
+async fn find_sftp<T: SftpRepo>(
+    repo: &T,
+    caller: &Caller,
+    id: i64,
+) -> Result<SftpOverview, AppError> {
+    match repo.get(id).await? {
+        Some(sftp) => {
+            caller.ensure_client(sftp.client_id)?;
+            Ok(sftp)
+        }
+        None => Err(AppError::NotFound(format!("Sftp with id {} not found", id))),
+    }
+}
+
+/// Identity provider lookup for the AWS Transfer Family custom IdP lambda.
+/// The lambda forwards the `sourceIp` it receives as the `source_ip` query parameter
+/// and, for password logins, the password in the `Password` header.
+/// Unknown users return 404, failed password or source IP checks return 403.
+#[utoipa::path(
+    get, path = "/sftp/identity/{username}", tag = "sftp",
+    params(
+        ("username" = String, Path, description = "The SFTP username"),
+        ("source_ip" = Option<String>, Query, description = "The IP the login comes from"),
+        ("Password" = Option<String>, Header, description = "The password of a password login"),
+    ),
+    responses((status = 200, body = SftpIdentity)),
+)]
+pub async fn get_identity<T: SftpRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Path(username): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Result<Json<SftpIdentity>, AppError> {
+    let source_ip = match params.get("source_ip") {
+        Some(ip) => Some(
+            ip.parse::<IpAddr>()
+                .map_err(|_| AppError::InvalidInput(format!("{} is not a valid IP address", ip)))?,
+        ),
+        None => None,
+    };
+    let password = headers
+        .get("Password")
+        .and_then(|password| password.to_str().ok());
+
+    match repo.get_by_username(&username).await? {
+        Some(sftp) => {
+            caller.ensure_client(sftp.client_id)?;
+            Ok(Json(sftp.identity(source_ip, password)?))
+        }
+        None => Err(AppError::NotFound(format!(
+            "Sftp user {} not found",
+            username
+        ))),
+    }
 }
@@ -1,45 +1,80 @@
-use crate::{errors::models::AppError, postgres::pool::PostgresRepo};
+use crate::{
+    audit::models as audit,
+    errors::models::AppError,
+    postgres::pool::PostgresRepo,
+    utils::{
+        etag::IfMatch,
+        filter::FilterField,
+        list::{ListFilter, ListQuery, Page},
+        ssh::{parse_public_keys, KeyType, SSHKeyPair, UploadedKey},
+        validate::{rules, Errors, Validate},
+    },
+};
 use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, Postgres, Transaction};
+use sqlx::{FromRow, PgConnection, Postgres, QueryBuilder, Transaction};
+use std::net::IpAddr;
+use utoipa::{IntoParams, ToSchema};
 
 #[async_trait]
 pub trait SftpRepo: Send + Sync + Clone + 'static {
-    async fn get_all(&self, client_id: Option<i64>) -> Result<Vec<SftpOverview>, AppError>;
+    async fn get_all(
+        &self,
+        query: ListQuery<SftpFilter>,
+        client_ids: Option<Vec<i64>>,
+    ) -> Result<Page<SftpOverview>, AppError>;
     async fn get(&self, id: i64) -> Result<Option<SftpOverview>, AppError>;
-    async fn update(&self, id: i64, sftp: SftpUpdate) -> Result<(), AppError>;
-    async fn delete(&self, id: i64) -> Result<(), AppError>;
+    async fn get_by_username(&self, username: &str) -> Result<Option<Sftp>, AppError>;
+    async fn update(&self, id: i64, sftp: SftpUpdate, if_match: IfMatch) -> Result<(), AppError>;
+    async fn delete(&self, id: i64, if_match: IfMatch) -> Result<(), AppError>;
+    async fn reset_keys(
+        &self,
+        id: i64,
+        rotation: Rotation,
+        if_match: IfMatch,
+    ) -> Result<SftpResponse, AppError>;
+    async fn get_public_keys(&self, id: i64) -> Result<Vec<SftpKey>, AppError>;
+    async fn set_public_keys(
+        &self,
+        id: i64,
+        public_keys: Vec<String>,
+        if_match: IfMatch,
+    ) -> Result<Vec<SftpKey>, AppError>;
 }
 
 #[async_trait]
 impl SftpRepo for PostgresRepo {
-    async fn get_all(&self, client_id: Option<i64>) -> Result<Vec<SftpOverview>, AppError> {
-        let sftps = match client_id {
-            Some(client_id) => {
-                sqlx::query_as!(
-                    SftpOverview,
-                    "SELECT id, client_id, username, bucket_name, aws_role_arn FROM sftp WHERE client_id = $1",
-                    client_id
-                )
-                .fetch_all(&self.pool)
-                .await?
-            },
-            None => {
-                sqlx::query_as!(
-                    SftpOverview,
-                    "SELECT id, client_id, username, bucket_name, aws_role_arn FROM sftp"
-                )
-                .fetch_all(&self.pool)
-                .await?
-            }
-        };
-        Ok(sftps)
+    async fn get_all(
+        &self,
+        query: ListQuery<SftpFilter>,
+        client_ids: Option<Vec<i64>>,
+    ) -> Result<Page<SftpOverview>, AppError> {
+        let mut count = QueryBuilder::new("SELECT COUNT(*) FROM sftp WHERE TRUE");
+        push_filters(&mut count, &query, &client_ids);
+        let total: i64 = count.build_query_scalar().fetch_one(&self.pool).await?;
+
+        let mut select = QueryBuilder::new(
+            "SELECT id, client_id, username, bucket_name, aws_role_arn, key_type, version
+             FROM sftp WHERE TRUE",
+        );
+        push_filters(&mut select, &query, &client_ids);
+        query.push_cursor(&mut select);
+        query.push_order_and_limit(&mut select);
+        let sftps = select
+            .build_query_as::<SftpOverview>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        query.page(sftps, total)
     }
 
     async fn get(&self, id: i64) -> Result<Option<SftpOverview>, AppError> {
         let sftp = sqlx::query_as!(
             SftpOverview,
-            "SELECT id, client_id, username, bucket_name, aws_role_arn FROM sftp WHERE id = $1",
+            "SELECT id, client_id, username, bucket_name, aws_role_arn, key_type, version
+             FROM sftp WHERE id = $1
+                AND client_id IN (SELECT id FROM clients WHERE deleted_at IS NULL)",
             id
         )
         .fetch_optional(&self.pool)
@@ -48,8 +83,33 @@ impl SftpRepo for PostgresRepo {
         Ok(sftp)
     }
 
-    async fn update(&self, id: i64, sftp: SftpUpdate) -> Result<(), AppError> {
+    async fn get_by_username(&self, username: &str) -> Result<Option<Sftp>, AppError> {
+        let sftp = sqlx::query_as!(
+            Sftp,
+            r#"SELECT id, client_id, username,
+                ARRAY(SELECT public_key FROM active_sftp_keys WHERE sftp_id = sftp.id ORDER BY id)
+                    as "public_keys!: Vec<String>",
+                bucket_name, aws_role_arn, allowed_source_ips, key_type, version
+            FROM sftp WHERE username = $1
+                AND client_id IN (SELECT id FROM clients WHERE deleted_at IS NULL)"#,
+            username
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(sftp)
+    }
+
+    async fn update(&self, id: i64, sftp: SftpUpdate, if_match: IfMatch) -> Result<(), AppError> {
+        if let Some(rules) = &sftp.allowed_source_ips {
+            validate_source_ip_rules(rules)?;
+        }
+
         let mut tx: Transaction<'_, Postgres> = self.pool.begin().await?;
+        let before = lock_sftp(&mut tx, id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Sftp with id {} not found", id)))?;
+        if_match.check("Sftp", id, before.version)?;
 
         if let Some(username) = sftp.username {
             sqlx::query!("UPDATE sftp SET username = $1 WHERE id = $2", username, id)
@@ -77,22 +137,334 @@ impl SftpRepo for PostgresRepo {
             .await?;
         }
 
+        if let Some(allowed_source_ips) = sftp.allowed_source_ips {
+            sqlx::query!(
+                "UPDATE sftp SET allowed_source_ips = $1 WHERE id = $2",
+                &allowed_source_ips,
+                id
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query!("UPDATE sftp SET version = version + 1 WHERE id = $1", id)
+            .execute(&mut *tx)
+            .await?;
+
+        let after = lock_sftp(&mut tx, id).await?;
+        audit::record(&mut tx, "update", "sftp", id, Some(&before), after.as_ref()).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn delete(&self, id: i64, if_match: IfMatch) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        let before = lock_sftp(&mut tx, id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Sftp with id {} not found", id)))?;
+        if_match.check("Sftp", id, before.version)?;
+
+        sqlx::query!("DELETE FROM sftp WHERE id = $1", id)
+            .execute(&mut *tx)
+            .await?;
+
+        audit::record(&mut tx, "delete", "sftp", id, Some(&before), None).await?;
         tx.commit().await?;
         Ok(())
     }
 
-    async fn delete(&self, id: i64) -> Result<(), AppError> {
-        let rows_affected = sqlx::query!("DELETE FROM sftp WHERE id = $1", id)
-            .execute(&self.pool)
+    /// Rotates the key pair of a single account. Returns the new private key, which
+    /// isn't stored in a readable form. The replaced key keeps working for the grace period.
+    async fn reset_keys(
+        &self,
+        id: i64,
+        rotation: Rotation,
+        if_match: IfMatch,
+    ) -> Result<SftpResponse, AppError> {
+        let grace_period = rotation.grace_period()?;
+
+        let mut tx = self.pool.begin().await?;
+        let before = lock_sftp(&mut tx, id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Sftp with id {} not found", id)))?;
+        if_match.check("Sftp", id, before.version)?;
+
+        let ssh_keys = SSHKeyPair::generate(rotation.key_type)?;
+        let private_key = self.keys.encrypt(&ssh_keys.private_key)?;
+        rotate_key(
+            &mut tx,
+            id,
+            &ssh_keys,
+            &private_key,
+            self.keys.active_key_id(),
+            grace_period,
+        )
+        .await?;
+
+        let after = lock_sftp(&mut tx, id).await?;
+        audit::record(
+            &mut tx,
+            "reset_keys",
+            "sftp",
+            id,
+            Some(&before),
+            after.as_ref(),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(SftpResponse::new(id, before.client_id, ssh_keys))
+    }
+
+    /// Empty for accounts of deleted clients.
+    async fn get_public_keys(&self, id: i64) -> Result<Vec<SftpKey>, AppError> {
+        let keys = sqlx::query_as!(
+            SftpKey,
+            r#"SELECT id as "id!", sftp_id as "sftp_id!", source as "source!",
+                key_type as "key_type!", public_key as "public_key!",
+                fingerprint as "fingerprint!", created_at as "created_at!",
+                not_before as "not_before!", expires_at, revoked_at
+            FROM active_sftp_keys WHERE sftp_id = $1
+                AND EXISTS (SELECT 1 FROM sftp
+                    JOIN clients c ON c.id = sftp.client_id AND c.deleted_at IS NULL
+                    WHERE sftp.id = $1)
+            ORDER BY id"#,
+            id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(keys)
+    }
+
+    /// Replaces the uploaded public keys of an account. Keys that are still listed keep
+    /// their row, the others are revoked. Generated keys aren't touched.
+    async fn set_public_keys(
+        &self,
+        id: i64,
+        public_keys: Vec<String>,
+        if_match: IfMatch,
+    ) -> Result<Vec<SftpKey>, AppError> {
+        let uploaded = parse_public_keys(&public_keys)?;
+        let fingerprints: Vec<String> =
+            uploaded.iter().map(|key| key.fingerprint.clone()).collect();
+
+        let mut tx = self.pool.begin().await?;
+        let before = lock_sftp(&mut tx, id)
             .await?
-            .rows_affected();
+            .ok_or_else(|| AppError::NotFound(format!("Sftp with id {} not found", id)))?;
+        if_match.check("Sftp", id, before.version)?;
 
-        if rows_affected == 0 {
-            Err(AppError::NotFound(format!("Sftp with id {} not found", id)))
-        } else {
-            Ok(())
+        let generated = sqlx::query_scalar!(
+            "SELECT fingerprint FROM sftp_keys
+            WHERE sftp_id = $1 AND source = 'generated' AND revoked_at IS NULL
+                AND fingerprint = ANY($2)",
+            id,
+            &fingerprints,
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        if let Some(fingerprint) = generated {
+            return Err(AppError::InvalidInput(format!(
+                "Public key {} is already the account's generated key",
+                fingerprint
+            )));
+        }
+
+        // Expired uploads are revoked too, so that they can be uploaded again.
+        sqlx::query!(
+            "UPDATE sftp_keys SET revoked_at = now()
+            WHERE sftp_id = $1 AND source = 'uploaded' AND revoked_at IS NULL
+                AND (NOT fingerprint = ANY($2)
+                    OR id NOT IN (SELECT id FROM active_sftp_keys WHERE sftp_id = $1))",
+            id,
+            &fingerprints,
+        )
+        .execute(&mut *tx)
+        .await?;
+        let kept = sqlx::query_scalar!(
+            "SELECT fingerprint FROM sftp_keys
+            WHERE sftp_id = $1 AND source = 'uploaded' AND revoked_at IS NULL",
+            id,
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        for key in uploaded
+            .iter()
+            .filter(|key| !kept.contains(&key.fingerprint))
+        {
+            insert_key(
+                &mut tx,
+                id,
+                UPLOADED,
+                &key.key_type,
+                &key.public_key,
+                &key.fingerprint,
+            )
+            .await?;
         }
+
+        sqlx::query!("UPDATE sftp SET version = version + 1 WHERE id = $1", id)
+            .execute(&mut *tx)
+            .await?;
+
+        let after = lock_sftp(&mut tx, id).await?;
+        audit::record(
+            &mut tx,
+            "set_public_keys",
+            "sftp",
+            id,
+            Some(&before),
+            after.as_ref(),
+        )
+        .await?;
+        tx.commit().await?;
+
+        self.get_public_keys(id).await
+    }
+}
+
+/// The `source` of keys generated by us.
+pub const GENERATED: &str = "generated";
+/// The `source` of keys uploaded by the client.
+pub const UPLOADED: &str = "uploaded";
+
+/// Stores a public key for an account. It's active from now on.
+pub async fn insert_key(
+    conn: &mut PgConnection,
+    sftp_id: i64,
+    source: &str,
+    key_type: &str,
+    public_key: &str,
+    fingerprint: &str,
+) -> Result<SftpKey, AppError> {
+    let key = sqlx::query_as!(
+        SftpKey,
+        "INSERT INTO sftp_keys (sftp_id, source, key_type, public_key, fingerprint)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, sftp_id, source, key_type, public_key, fingerprint, created_at,
+            not_before, expires_at, revoked_at",
+        sftp_id,
+        source,
+        key_type,
+        public_key,
+        fingerprint,
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(key)
+}
+
+/// Stores uploaded public keys for an account.
+pub async fn insert_public_keys(
+    conn: &mut PgConnection,
+    sftp_id: i64,
+    uploaded: &[UploadedKey],
+) -> Result<Vec<SftpKey>, AppError> {
+    let mut keys = Vec::with_capacity(uploaded.len());
+    for key in uploaded {
+        let key = insert_key(
+            &mut *conn,
+            sftp_id,
+            UPLOADED,
+            &key.key_type,
+            &key.public_key,
+            &key.fingerprint,
+        )
+        .await?;
+        keys.push(key);
     }
+    Ok(keys)
+}
+
+/// Gives an account a new generated key pair. Its earlier generated keys keep working for
+/// `grace_period`, so that the client can switch over without downtime.
+pub async fn rotate_key(
+    conn: &mut PgConnection,
+    sftp_id: i64,
+    ssh_keys: &SSHKeyPair,
+    private_key: &[u8],
+    key_id: &str,
+    grace_period: Duration,
+) -> Result<SftpKey, AppError> {
+    let generated = sqlx::query_scalar!(
+        "SELECT id FROM sftp_keys
+        WHERE sftp_id = $1 AND source = 'generated' AND revoked_at IS NULL",
+        sftp_id,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    expire_keys(&mut *conn, sftp_id, &generated, Utc::now() + grace_period).await?;
+
+    sqlx::query!(
+        "UPDATE sftp SET private_key = $1, key_id = $2, key_type = $3 WHERE id = $4",
+        private_key,
+        key_id,
+        ssh_keys.key_type.as_str(),
+        sftp_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    insert_key(
+        conn,
+        sftp_id,
+        GENERATED,
+        ssh_keys.key_type.as_str(),
+        &ssh_keys.public_key,
+        &ssh_keys.fingerprint,
+    )
+    .await
+}
+
+/// Makes keys of an account expire at `expires_at`, unless they already expire sooner, and
+/// bumps the account's version. Shared by [`rotate_key`] and the rotation sweep.
+pub async fn expire_keys(
+    conn: &mut PgConnection,
+    sftp_id: i64,
+    key_ids: &[i64],
+    expires_at: DateTime<Utc>,
+) -> Result<(), AppError> {
+    sqlx::query!(
+        "UPDATE sftp_keys SET expires_at = $3
+        WHERE sftp_id = $1 AND id = ANY($2) AND revoked_at IS NULL
+            AND (expires_at IS NULL OR expires_at > $3)",
+        sftp_id,
+        key_ids,
+        expires_at,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE sftp SET version = version + 1 WHERE id = $1",
+        sftp_id
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Loads an SFTP account inside a transaction, locking the row for the rest of it.
+/// Accounts of deleted clients aren't found.
+pub async fn lock_sftp(conn: &mut PgConnection, id: i64) -> Result<Option<Sftp>, AppError> {
+    let sftp = sqlx::query_as!(
+        Sftp,
+        r#"SELECT id, client_id, username,
+            ARRAY(SELECT public_key FROM active_sftp_keys WHERE sftp_id = sftp.id ORDER BY id)
+                as "public_keys!: Vec<String>",
+            bucket_name, aws_role_arn, allowed_source_ips, key_type, version
+        FROM sftp WHERE id = $1
+            AND client_id IN (SELECT id FROM clients WHERE deleted_at IS NULL)
+        FOR UPDATE"#,
+        id
+    )
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(sftp)
 }
 
 /// This struct represents the SFTP details for a client.
@@ -106,37 +478,496 @@ impl SftpRepo for PostgresRepo {
 /// sftp.prep.api.tpv.ntrs.com
 /// ```
 /// Each SFTP object is associated with a client vi the client_id field.
+/// The private key is encrypted at rest and never read back, so it isn't part of this struct.
+/// The public keys, generated or uploaded, live in `sftp_keys`; `public_keys` holds the
+/// ones that are active right now.
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone, FromRow)]
-struct Sftp {
-    pub id: Option<i64>,
+pub struct Sftp {
+    pub id: i64,
     pub client_id: i64,
     pub username: String,
-    pub private_key: Option<String>,
-    pub public_key: Option<String>,
+    pub public_keys: Vec<String>,
     pub bucket_name: String,
     pub aws_role_arn: String,
+    pub allowed_source_ips: Option<Vec<String>>,
+    /// The type of the latest generated key pair: one of the [`KeyType`] names, or
+    /// `rsa-2048` for accounts created before key types. `None` when no key pair was
+    /// generated.
+    pub key_type: Option<String>,
+    /// Bumped by every change, see [`ETag`](crate::utils::etag::ETag).
+    pub version: i64,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl Sftp {
+    /// Builds the Transfer Family identity provider response for this user.
+    ///
+    /// Our SFTP users are key-only, so any password attempt is refused. When the
+    /// account has an IP allow-list, the caller's source IP must be present and match.
+    pub fn identity(
+        &self,
+        source_ip: Option<IpAddr>,
+        password: Option<&str>,
+    ) -> Result<SftpIdentity, AppError> {
+        if password.is_some_and(|password| !password.is_empty()) {
+            return Err(AppError::Forbidden(format!(
+                "Password authentication is not enabled for user {}",
+                self.username
+            )));
+        }
+
+        if let Some(rules) = &self.allowed_source_ips {
+            let allowed = source_ip.is_some_and(|ip| {
+                rules
+                    .iter()
+                    .any(|rule| source_ip_matches(rule, ip).unwrap_or(false))
+            });
+
+            if !allowed {
+                return Err(AppError::Forbidden(format!(
+                    "Source IP is not allowed for user {}",
+                    self.username
+                )));
+            }
+        }
+
+        let home_directory = vec![HomeDirectoryEntry {
+            entry: "/".to_string(),
+            target: format!("/{}", self.bucket_name.trim_matches('/')),
+        }];
+
+        Ok(SftpIdentity {
+            role: self.aws_role_arn.clone(),
+            public_keys: self.public_keys.clone(),
+            home_directory_type: "LOGICAL".to_string(),
+            home_directory_details: serde_json::to_string(&home_directory)
+                .map_err(|_| AppError::Unknown)?,
+        })
+    }
+}
+
+/// Checks that every allow-list entry is either an IP address or a CIDR range.
+pub fn validate_source_ip_rules(rules: &[String]) -> Result<(), AppError> {
+    let unspecified = IpAddr::from([0, 0, 0, 0]);
+    for rule in rules {
+        if source_ip_matches(rule, unspecified).is_none() {
+            return Err(AppError::InvalidInput(format!(
+                "{} is not a valid IP address or CIDR range",
+                rule
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Matches an IP against a single allow-list entry such as `10.0.0.0/8` or `203.0.113.7`.
+/// Returns `None` when the entry can't be parsed.
+fn source_ip_matches(rule: &str, ip: IpAddr) -> Option<bool> {
+    let (network, prefix) = match rule.split_once('/') {
+        Some((network, prefix)) => (network.parse::<IpAddr>().ok()?, prefix.parse().ok()?),
+        None => {
+            let network = rule.parse::<IpAddr>().ok()?;
+            let prefix = if network.is_ipv4() { 32 } else { 128 };
+            (network, prefix)
+        }
+    };
+
+    match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) if prefix <= 32 => {
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            Some(u32::from(network) & mask == u32::from(ip) & mask)
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) if prefix <= 128 => {
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+            Some(u128::from(network) & mask == u128::from(ip) & mask)
+        }
+        (IpAddr::V4(_), IpAddr::V6(_)) if prefix <= 32 => Some(false),
+        (IpAddr::V6(_), IpAddr::V4(_)) if prefix <= 128 => Some(false),
+        _ => None,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow, ToSchema)]
 pub struct SftpOverview {
     pub id: i64,
     pub client_id: i64,
     pub username: String,
     pub bucket_name: String,
     pub aws_role_arn: String,
+    pub key_type: Option<String>,
+    pub version: i64,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl From<&Sftp> for SftpOverview {
+    fn from(sftp: &Sftp) -> Self {
+        Self {
+            id: sftp.id,
+            client_id: sftp.client_id,
+            username: sftp.username.clone(),
+            bucket_name: sftp.bucket_name.clone(),
+            aws_role_arn: sftp.aws_role_arn.clone(),
+            key_type: sftp.key_type.clone(),
+            version: sftp.version,
+        }
+    }
+}
+
+/// Filters for `GET /sftp`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SftpFilter;
+
+impl ListFilter for SftpFilter {
+    const SORT_FIELDS: &'static [&'static str] = &["id", "client_id", "username", "bucket_name"];
+    const FILTER_FIELDS: &'static [FilterField] = &[
+        FilterField::int("id"),
+        FilterField::int("client_id"),
+        FilterField::text("username"),
+        FilterField::text("bucket_name"),
+    ];
+    const SOFT_DELETED: bool = true;
+}
+
+/// Appends the query's filters, restricted to `client_ids` for agent callers.
+fn push_filters(
+    builder: &mut QueryBuilder<'_, Postgres>,
+    query: &ListQuery<SftpFilter>,
+    client_ids: &Option<Vec<i64>>,
+) {
+    query.filters.push(builder);
+    if !query.include_deleted {
+        builder.push(" AND client_id IN (SELECT id FROM clients WHERE deleted_at IS NULL)");
+    }
+    if let Some(client_ids) = client_ids {
+        builder
+            .push(" AND client_id = ANY(")
+            .push_bind(client_ids.clone())
+            .push(")");
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct SftpUpdate {
     pub username: Option<String>,
     pub bucket_name: Option<String>,
     pub aws_role_arn: Option<String>,
+    pub allowed_source_ips: Option<Vec<String>>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// The body of `POST /clients/:id/sftp`. With `public_keys` the account uses those keys
+/// and no key pair is generated.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct NewSftp {
+    #[serde(flatten)]
+    pub sftp: SftpUpdate,
+    #[serde(default)]
+    pub public_keys: Option<Vec<String>>,
+}
+
+/// The body of `PUT /sftp/:id/public-keys`.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct PublicKeys {
+    pub public_keys: Vec<String>,
+}
+
+impl Validate for SftpUpdate {
+    fn validate(&self, errors: &mut Errors) {
+        if let Some(username) = &self.username {
+            errors.check("username", rules::sftp_username(username));
+        }
+        if let Some(bucket_name) = &self.bucket_name {
+            errors.check("bucket_name", rules::bucket(bucket_name));
+        }
+        if let Some(aws_role_arn) = &self.aws_role_arn {
+            errors.check("aws_role_arn", rules::role_arn(aws_role_arn));
+        }
+        for (i, rule) in self.allowed_source_ips.iter().flatten().enumerate() {
+            let field = format!("allowed_source_ips[{}]", i);
+            errors.check(&field, rules::source_ip_rule(rule));
+        }
+    }
+}
+
+impl Validate for NewSftp {
+    /// Like an update, but `username`, `bucket_name` and `aws_role_arn` are required.
+    fn validate(&self, errors: &mut Errors) {
+        let required = [
+            ("username", &self.sftp.username),
+            ("bucket_name", &self.sftp.bucket_name),
+            ("aws_role_arn", &self.sftp.aws_role_arn),
+        ];
+        for (field, value) in required {
+            if value.is_none() {
+                errors.check(field, Err("is required".to_string()));
+            }
+        }
+        self.sftp.validate(errors);
+        if let Some(public_keys) = &self.public_keys {
+            validate_public_keys(public_keys, errors);
+        }
+    }
+}
+
+impl Validate for PublicKeys {
+    fn validate(&self, errors: &mut Errors) {
+        validate_public_keys(&self.public_keys, errors);
+    }
+}
+
+fn validate_public_keys(public_keys: &[String], errors: &mut Errors) {
+    if public_keys.is_empty() {
+        errors.check("public_keys", Err("must not be empty".to_string()));
+    }
+    for (i, public_key) in public_keys.iter().enumerate() {
+        let field = format!("public_keys[{}]", i);
+        errors.check(&field, rules::public_key(public_key));
+    }
+}
+
+/// A public key of an account, either [`GENERATED`] or [`UPLOADED`]. It works from
+/// `not_before` until it expires or is revoked.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow, ToSchema)]
+pub struct SftpKey {
+    pub id: i64,
+    pub sftp_id: i64,
+    pub source: String,
+    pub key_type: String,
+    pub public_key: String,
+    pub fingerprint: String,
+    pub created_at: DateTime<Utc>,
+    pub not_before: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl SftpKey {
+    /// Mirrors the `active_sftp_keys` view.
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        self.revoked_at.is_none()
+            && self.not_before <= now
+            && self.expires_at.is_none_or(|expires_at| expires_at > now)
+    }
+}
+
+/// Returned when an account is created or its keys are generated. When a key pair was
+/// generated its private keys are only returned here, see [`SSHKeyPair`] for the formats.
+/// Uploaded keys are listed in `public_keys`.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
 pub struct SftpResponse {
     pub id: i64,
     pub client_id: i64,
-    pub private_key: String,
-    pub public_key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_type: Option<KeyType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub private_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub private_key_openssh: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub public_keys: Vec<SftpKey>,
+}
+
+impl SftpResponse {
+    pub fn new(id: i64, client_id: i64, ssh_keys: SSHKeyPair) -> Self {
+        Self {
+            id,
+            client_id,
+            key_type: Some(ssh_keys.key_type),
+            private_key: Some(ssh_keys.private_key),
+            private_key_openssh: Some(ssh_keys.private_key_openssh),
+            public_key: Some(ssh_keys.public_key),
+            fingerprint: Some(ssh_keys.fingerprint),
+            public_keys: Vec::new(),
+        }
+    }
+
+    pub fn uploaded(id: i64, client_id: i64, public_keys: Vec<SftpKey>) -> Self {
+        Self {
+            id,
+            client_id,
+            key_type: None,
+            private_key: None,
+            private_key_openssh: None,
+            public_key: None,
+            fingerprint: None,
+            public_keys,
+        }
+    }
+}
+
+/// The `key_type` query parameter of the endpoints generating keys. Defaults to ed25519.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct KeyTypeParams {
+    #[serde(default)]
+    pub key_type: KeyType,
+}
+
+/// The longest grace period a rotation can give the replaced keys.
+const MAX_GRACE_PERIOD_HOURS: u32 = 30 * 24;
+
+/// The query parameters of the endpoints rotating keys: the type of the new key pair and
+/// how long the keys it replaces keep working. `grace_period_hours=0` cuts them off now.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Rotation {
+    #[serde(default)]
+    pub key_type: KeyType,
+    #[serde(default = "default_grace_period_hours")]
+    pub grace_period_hours: u32,
+}
+
+fn default_grace_period_hours() -> u32 {
+    24
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Self {
+            key_type: KeyType::default(),
+            grace_period_hours: default_grace_period_hours(),
+        }
+    }
+}
+
+impl Rotation {
+    pub fn grace_period(&self) -> Result<Duration, AppError> {
+        if self.grace_period_hours > MAX_GRACE_PERIOD_HOURS {
+            return Err(AppError::InvalidInput(format!(
+                "grace_period_hours can be at most {}",
+                MAX_GRACE_PERIOD_HOURS
+            )));
+        }
+        Ok(Duration::hours(self.grace_period_hours.into()))
+    }
+}
+
+/// The response shape expected by the AWS Transfer Family custom identity provider.
+/// `HomeDirectoryDetails` is itself a JSON encoded string, as Transfer Family requires.
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+#[serde(rename_all = "PascalCase")]
+pub struct SftpIdentity {
+    pub role: String,
+    pub public_keys: Vec<String>,
+    pub home_directory_type: String,
+    pub home_directory_details: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct HomeDirectoryEntry {
+    pub entry: String,
+    pub target: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sftp(allowed_source_ips: Option<Vec<String>>) -> Sftp {
+        Sftp {
+            id: 1,
+            client_id: 1,
+            username: "acme".to_string(),
+            public_keys: vec!["ssh-rsa AAAA".to_string()],
+            bucket_name: "acme-bucket".to_string(),
+            aws_role_arn: "arn:aws:iam::123456789012:role/acme".to_string(),
+            allowed_source_ips,
+            key_type: Some("ssh-ed25519".to_string()),
+            version: 1,
+        }
+    }
+
+    #[test]
+    fn test_identity_maps_bucket_to_home_directory() {
+        let identity = sftp(None).identity(None, None).unwrap();
+        assert_eq!(identity.public_keys, vec!["ssh-rsa AAAA".to_string()]);
+        assert_eq!(
+            identity.home_directory_details,
+            r#"[{"Entry":"/","Target":"/acme-bucket"}]"#
+        );
+    }
+
+    #[test]
+    fn test_key_is_active_between_not_before_and_expiry() {
+        let now = Utc::now();
+        let key = SftpKey {
+            id: 1,
+            sftp_id: 1,
+            source: GENERATED.to_string(),
+            key_type: "ssh-ed25519".to_string(),
+            public_key: "ssh-ed25519 AAAA".to_string(),
+            fingerprint: "SHA256:abc".to_string(),
+            created_at: now,
+            not_before: now,
+            expires_at: None,
+            revoked_at: None,
+        };
+        assert!(key.is_active(now));
+        assert!(!key.is_active(now - Duration::seconds(1)));
+
+        let expiring = SftpKey {
+            expires_at: Some(now + Duration::hours(1)),
+            ..key.clone()
+        };
+        assert!(expiring.is_active(now));
+        assert!(!expiring.is_active(now + Duration::hours(1)));
+
+        let revoked = SftpKey {
+            revoked_at: Some(now),
+            ..key
+        };
+        assert!(!revoked.is_active(now));
+    }
+
+    #[test]
+    fn test_rotation_grace_period() {
+        assert_eq!(
+            Rotation::default().grace_period().unwrap(),
+            Duration::hours(24)
+        );
+        let rotation = Rotation {
+            grace_period_hours: MAX_GRACE_PERIOD_HOURS + 1,
+            ..Rotation::default()
+        };
+        assert!(rotation.grace_period().is_err());
+    }
+
+    #[test]
+    fn test_identity_source_ip_checks() {
+        let sftp = sftp(Some(vec![
+            "10.0.0.0/8".to_string(),
+            "2001:db8::1".to_string(),
+        ]));
+        assert!(sftp
+            .identity(Some("10.1.2.3".parse().unwrap()), None)
+            .is_ok());
+        assert!(sftp
+            .identity(Some("2001:db8::1".parse().unwrap()), None)
+            .is_ok());
+        assert!(matches!(
+            sftp.identity(Some("192.168.1.1".parse().unwrap()), None),
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(matches!(
+            sftp.identity(None, None),
+            Err(AppError::Forbidden(_))
+        ));
+    }
+
+    #[test]
+    fn test_identity_rejects_passwords() {
+        assert!(matches!(
+            sftp(None).identity(None, Some("hunter2")),
+            Err(AppError::Forbidden(_))
+        ));
+    }
+
+    #[test]
+    fn test_validate_source_ip_rules() {
+        assert!(validate_source_ip_rules(&["10.0.0.0/8".to_string()]).is_ok());
+        assert!(validate_source_ip_rules(&["10.0.0.0/33".to_string()]).is_err());
+        assert!(validate_source_ip_rules(&["not-an-ip".to_string()]).is_err());
+    }
 }
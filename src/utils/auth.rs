@@ -1,28 +1,286 @@
 use std::sync::Arc;
 
+use crate::agents::models::AgentRepo;
+use crate::api_keys::models::{hash_token, ApiKeyRepo};
 use crate::errors::models::AppError;
 use axum::extract::FromRequestParts;
+use axum::http::Method;
 use axum::{body::Body, extract::Request, middleware::Next, response::Response};
 use axum_extra::headers::{authorization::Bearer, Authorization};
 use axum_extra::TypedHeader;
+use chrono::Utc;
+use subtle::ConstantTimeEq;
 
-pub async fn auth(
+/// The identity of the authenticated caller.
+/// The auth middleware puts this into the request extensions for handlers and logs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Caller {
+    /// `None` for the bootstrap key from the config.
+    pub key_id: Option<i64>,
+    pub name: String,
+    pub scopes: Vec<String>,
+    /// Set for keys issued to an agent. Admin keys leave this empty and see every client.
+    pub agent: Option<AgentAccess>,
+}
+
+tokio::task_local! {
+    /// The caller of the request being handled. Set by the auth middleware so the repos
+    /// can attribute the writes they make, e.g. in the audit log.
+    pub static CURRENT_CALLER: Caller;
+}
+
+/// The caller of the current request, or `None` outside of a request (background jobs).
+pub fn current_caller() -> Option<Caller> {
+    CURRENT_CALLER.try_with(|caller| caller.clone()).ok()
+}
+
+/// The clients an agent caller is allowed to see, loaded when the request is authenticated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentAccess {
+    pub agent_id: i64,
+    pub client_ids: Vec<i64>,
+}
+
+impl Caller {
+    /// The caller authenticated with `AppConfig::api_key`. It has full access so the
+    /// first real API keys can be created.
+    pub fn bootstrap() -> Self {
+        Self {
+            key_id: None,
+            name: "bootstrap".to_string(),
+            scopes: vec!["admin".to_string()],
+            agent: None,
+        }
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == "admin" || s == scope)
+    }
+
+    /// Fails with 403 unless the caller has `scope`. For routes where the scope depends on
+    /// more than the path, see [`required_scope`].
+    pub fn ensure_scope(&self, scope: &str) -> Result<(), AppError> {
+        if !self.has_scope(scope) {
+            return Err(AppError::Forbidden(format!(
+                "API key {} is missing the {} scope",
+                self.name, scope
+            )));
+        }
+        Ok(())
+    }
+
+    /// The client ids list endpoints should be filtered to, or `None` for full access.
+    pub fn client_filter(&self) -> Option<Vec<i64>> {
+        self.agent.as_ref().map(|agent| agent.client_ids.clone())
+    }
+
+    /// Fails with 403 unless the caller may access the given client.
+    pub fn ensure_client(&self, client_id: i64) -> Result<(), AppError> {
+        match &self.agent {
+            Some(agent) if !agent.client_ids.contains(&client_id) => Err(AppError::Forbidden(
+                format!("Client with id {} is not assigned to this agent", client_id),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails with 403 for agent callers. Used for operations that aren't tied to one of
+    /// the agent's clients, like creating clients or changing agent assignments.
+    pub fn ensure_unrestricted(&self) -> Result<(), AppError> {
+        match &self.agent {
+            Some(_) => Err(AppError::Forbidden(
+                "Agent keys can't perform this operation".to_string(),
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The scope a caller needs for a route. `None` means any authenticated caller is allowed.
+/// Anything not listed here needs `admin`.
+pub fn required_scope(method: &Method, path: &str) -> Option<&'static str> {
+    let read = method == Method::GET || method == Method::HEAD;
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    let (read_scope, write_scope) = match segments.as_slice() {
+        ["health"] => return None,
+        // The entity is in the query string, the handlers check its scope.
+        ["import" | "export"] => return None,
+        ["clients", _, "sftp", _] => ("sftp:read", "sftp:write"),
+        ["clients", _, "sftp" | "reset-sftp-keys", ..] => ("sftp:read", "sftp:keys"),
+        ["clients", _, "vendor", ..] => ("vendors:read", "vendors:write"),
+        ["clients", ..] => ("clients:read", "clients:write"),
+        ["vendors", ..] => ("vendors:read", "vendors:write"),
+        ["sftp", _, "public-keys"] => ("sftp:read", "sftp:keys"),
+        ["sftp", ..] => ("sftp:read", "sftp:write"),
+        ["agents", ..] => ("agents:read", "agents:write"),
+        ["audit", ..] => ("audit:read", "admin"),
+        _ => return Some("admin"),
+    };
+
+    Some(if read { read_scope } else { write_scope })
+}
+
+/// Compares the hashes in constant time, so the time taken doesn't tell how much of the
+/// bootstrap token was guessed right, nor its length.
+fn is_bootstrap_token(token: &str, bootstrap_token: &str) -> bool {
+    hash_token(token).ct_eq(&hash_token(bootstrap_token)).into()
+}
+
+/// Authenticates the bearer token against the API keys table (or the bootstrap key from
+/// the config) and checks that the key's scopes cover the route.
+/// For agent keys the agent's client ids are loaded so handlers can filter on them.
+pub async fn auth<T: ApiKeyRepo + AgentRepo>(
     req: Request<Body>,
     next: Next,
-    token: Arc<String>,
+    repo: T,
+    bootstrap_token: Arc<String>,
 ) -> Result<Response, AppError> {
     let (mut parts, body) = req.into_parts();
     let auth_header =
         TypedHeader::<Authorization<Bearer>>::from_request_parts(&mut parts, &()).await;
 
-    match auth_header {
-        Ok(TypedHeader(Authorization(bearer))) if bearer.token() == token.as_str() => {
-            // Reconstruct the request and pass it to the next service
-            let req = Request::from_parts(parts, body);
-            Ok(next.run(req).await)
+    let token = match auth_header {
+        Ok(TypedHeader(Authorization(bearer))) => bearer.token().to_string(),
+        Err(_) => {
+            return Err(AppError::Unauthorized(
+                "Invalid token provided.".to_string(),
+            ))
+        }
+    };
+
+    let caller = if is_bootstrap_token(&token, &bootstrap_token) {
+        Caller::bootstrap()
+    } else {
+        match repo.find_by_token(&token).await? {
+            Some(key) if key.is_active(Utc::now()) => {
+                let agent = match key.agent_id {
+                    Some(agent_id) => Some(AgentAccess {
+                        agent_id,
+                        client_ids: repo.get_client_ids_for_agent(agent_id).await?,
+                    }),
+                    None => None,
+                };
+                Caller {
+                    key_id: Some(key.id),
+                    name: key.name,
+                    scopes: key.scopes,
+                    agent,
+                }
+            }
+            _ => {
+                return Err(AppError::Unauthorized(
+                    "Invalid token provided.".to_string(),
+                ))
+            }
         }
-        _ => Err(AppError::Unauthorized(
-            "Invalid token provided.".to_string(),
-        )),
+    };
+
+    if let Some(scope) = required_scope(&parts.method, parts.uri.path()) {
+        caller.ensure_scope(scope)?;
+    }
+
+    tracing::debug!(
+        caller = %caller.name,
+        method = %parts.method,
+        path = %parts.uri.path(),
+        "Authorized request"
+    );
+
+    // Reconstruct the request and pass it to the next service
+    parts.extensions.insert(caller.clone());
+    let req = Request::from_parts(parts, body);
+    Ok(CURRENT_CALLER.scope(caller, next.run(req)).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_bootstrap_token() {
+        assert!(is_bootstrap_token("secret", "secret"));
+        assert!(!is_bootstrap_token("secre", "secret"));
+        assert!(!is_bootstrap_token("", "secret"));
+    }
+
+    #[test]
+    fn test_required_scope() {
+        assert_eq!(
+            required_scope(&Method::GET, "/clients"),
+            Some("clients:read")
+        );
+        assert_eq!(
+            required_scope(&Method::PUT, "/clients/1"),
+            Some("clients:write")
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/clients/1/sftp"),
+            Some("sftp:keys")
+        );
+        assert_eq!(
+            required_scope(&Method::PUT, "/clients/1/reset-sftp-keys"),
+            Some("sftp:keys")
+        );
+        assert_eq!(
+            required_scope(&Method::DELETE, "/clients/1/sftp/2"),
+            Some("sftp:write")
+        );
+        assert_eq!(
+            required_scope(&Method::PUT, "/clients/1/sftp/2/reset-keys"),
+            Some("sftp:keys")
+        );
+        assert_eq!(
+            required_scope(&Method::POST, "/clients/1/vendor"),
+            Some("vendors:write")
+        );
+        assert_eq!(
+            required_scope(&Method::PUT, "/sftp/1/public-keys"),
+            Some("sftp:keys")
+        );
+        assert_eq!(
+            required_scope(&Method::GET, "/sftp/identity/bob"),
+            Some("sftp:read")
+        );
+        assert_eq!(required_scope(&Method::GET, "/api-keys"), Some("admin"));
+        assert_eq!(required_scope(&Method::GET, "/config"), Some("admin"));
+        assert_eq!(required_scope(&Method::GET, "/health"), None);
+        assert_eq!(required_scope(&Method::POST, "/import"), None);
+        assert_eq!(required_scope(&Method::GET, "/audit"), Some("audit:read"));
+    }
+
+    #[test]
+    fn test_admin_scope_covers_everything() {
+        let caller = Caller {
+            key_id: Some(1),
+            name: "ops".to_string(),
+            scopes: vec!["clients:read".to_string()],
+            agent: None,
+        };
+        assert!(caller.has_scope("clients:read"));
+        assert!(!caller.has_scope("clients:write"));
+        assert!(Caller::bootstrap().has_scope("vendors:write"));
+    }
+
+    #[test]
+    fn test_agent_callers_are_limited_to_their_clients() {
+        let caller = Caller {
+            key_id: Some(2),
+            name: "agent-smith".to_string(),
+            scopes: vec!["clients:write".to_string()],
+            agent: Some(AgentAccess {
+                agent_id: 7,
+                client_ids: vec![1, 2],
+            }),
+        };
+        assert!(caller.ensure_client(1).is_ok());
+        assert!(matches!(
+            caller.ensure_client(3),
+            Err(AppError::Forbidden(_))
+        ));
+        assert!(caller.ensure_unrestricted().is_err());
+        assert_eq!(caller.client_filter(), Some(vec![1, 2]));
+        assert!(Caller::bootstrap().ensure_client(3).is_ok());
+        assert_eq!(Caller::bootstrap().client_filter(), None);
     }
 }
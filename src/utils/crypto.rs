@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use crate::config::models::AppConfig;
+use crate::errors::models::AppError;
+
+const FORMAT_VERSION: u8 = 1;
+const NONCE_LEN: usize = 12;
+const WRAPPED_KEY_LEN: usize = 32 + 16;
+const HEADER_LEN: usize = 1 + NONCE_LEN + WRAPPED_KEY_LEN + NONCE_LEN;
+
+/// Envelope encryption for secrets stored in Postgres.
+///
+/// Every value is encrypted with a fresh AES-256-GCM data key, and the data key is
+/// wrapped with one of the master keys from `AppConfig`. The master key id is stored
+/// next to the ciphertext (the `key_id` column) so old master keys can be kept around
+/// for decryption while new writes use the active one.
+///
+/// Encrypted values are laid out as:
+/// ```text
+/// version (1) | wrap nonce (12) | wrapped data key (48) | data nonce (12) | ciphertext
+/// ```
+#[derive(Clone)]
+pub struct KeyRing {
+    active_key_id: String,
+    keys: HashMap<String, Key<Aes256Gcm>>,
+}
+
+impl KeyRing {
+    /// Builds the key ring from the base64 encoded 256-bit master keys in the config.
+    pub fn from_config(config: &AppConfig) -> Result<Self, AppError> {
+        let mut keys = HashMap::new();
+        for (key_id, encoded) in &config.master_keys {
+            let bytes = STANDARD.decode(encoded).map_err(|_| {
+                AppError::EncryptionError(format!("Master key {} is not valid base64", key_id))
+            })?;
+            if bytes.len() != 32 {
+                return Err(AppError::EncryptionError(format!(
+                    "Master key {} must be 32 bytes",
+                    key_id
+                )));
+            }
+            keys.insert(key_id.clone(), *Key::<Aes256Gcm>::from_slice(&bytes));
+        }
+
+        if !keys.contains_key(&config.master_key_id) {
+            return Err(AppError::EncryptionError(format!(
+                "Active master key {} is not configured",
+                config.master_key_id
+            )));
+        }
+
+        Ok(Self {
+            active_key_id: config.master_key_id.clone(),
+            keys,
+        })
+    }
+
+    /// The id of the master key used for all new writes.
+    pub fn active_key_id(&self) -> &str {
+        &self.active_key_id
+    }
+
+    /// Encrypts a value with the active master key.
+    pub fn encrypt(&self, plaintext: &str) -> Result<Vec<u8>, AppError> {
+        let master = Aes256Gcm::new(&self.keys[&self.active_key_id]);
+        let data_key = Aes256Gcm::generate_key(OsRng);
+
+        let wrap_nonce = Aes256Gcm::generate_nonce(OsRng);
+        let wrapped_key = master
+            .encrypt(&wrap_nonce, data_key.as_slice())
+            .map_err(|_| AppError::EncryptionError("Failed to wrap data key".to_string()))?;
+
+        let data_nonce = Aes256Gcm::generate_nonce(OsRng);
+        let ciphertext = Aes256Gcm::new(&data_key)
+            .encrypt(&data_nonce, plaintext.as_bytes())
+            .map_err(|_| AppError::EncryptionError("Failed to encrypt value".to_string()))?;
+
+        let mut blob = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+        blob.push(FORMAT_VERSION);
+        blob.extend_from_slice(&wrap_nonce);
+        blob.extend_from_slice(&wrapped_key);
+        blob.extend_from_slice(&data_nonce);
+        blob.extend_from_slice(&ciphertext);
+        Ok(blob)
+    }
+
+    /// Decrypts a value written with the given master key.
+    /// Rows that predate encryption have no key id and hold plain UTF-8 bytes.
+    pub fn decrypt(&self, key_id: Option<&str>, blob: &[u8]) -> Result<String, AppError> {
+        let Some(key_id) = key_id else {
+            return String::from_utf8(blob.to_vec())
+                .map_err(|_| AppError::EncryptionError("Legacy value is not UTF-8".to_string()));
+        };
+
+        let master_key = self.keys.get(key_id).ok_or_else(|| {
+            AppError::EncryptionError(format!("Master key {} is not configured", key_id))
+        })?;
+
+        if blob.len() < HEADER_LEN || blob[0] != FORMAT_VERSION {
+            return Err(AppError::EncryptionError(
+                "Unrecognised ciphertext format".to_string(),
+            ));
+        }
+
+        let (wrap_nonce, rest) = blob[1..].split_at(NONCE_LEN);
+        let (wrapped_key, rest) = rest.split_at(WRAPPED_KEY_LEN);
+        let (data_nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+        let data_key = Aes256Gcm::new(master_key)
+            .decrypt(Nonce::from_slice(wrap_nonce), wrapped_key)
+            .map_err(|_| AppError::EncryptionError("Failed to unwrap data key".to_string()))?;
+
+        let plaintext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&data_key))
+            .decrypt(Nonce::from_slice(data_nonce), ciphertext)
+            .map_err(|_| AppError::EncryptionError("Failed to decrypt value".to_string()))?;
+
+        String::from_utf8(plaintext)
+            .map_err(|_| AppError::EncryptionError("Decrypted value is not UTF-8".to_string()))
+    }
+
+    pub fn encrypt_opt(&self, plaintext: Option<&str>) -> Result<Option<Vec<u8>>, AppError> {
+        plaintext.map(|value| self.encrypt(value)).transpose()
+    }
+
+    pub fn decrypt_opt(
+        &self,
+        key_id: Option<&str>,
+        blob: Option<&[u8]>,
+    ) -> Result<Option<String>, AppError> {
+        blob.map(|blob| self.decrypt(key_id, blob)).transpose()
+    }
+}
+
+impl std::fmt::Debug for KeyRing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyRing")
+            .field("active_key_id", &self.active_key_id)
+            .field("keys", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_ring(active_key_id: &str) -> KeyRing {
+        let config = AppConfig {
+            database_url: String::new(),
+            api_key: String::new(),
+            log_level: "info".to_string(),
+            master_key_id: active_key_id.to_string(),
+            master_keys: HashMap::from([
+                ("k1".to_string(), STANDARD.encode([1u8; 32])),
+                ("k2".to_string(), STANDARD.encode([2u8; 32])),
+            ]),
+            key_rotation: Default::default(),
+            webhooks: Default::default(),
+            retention: Default::default(),
+        };
+        KeyRing::from_config(&config).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_and_rotation() {
+        let old = key_ring("k1");
+        let blob = old.encrypt("s3cret").unwrap();
+        assert!(!blob.windows(6).any(|window| window == b"s3cret"));
+
+        // After rotating to k2, values written under k1 can still be read.
+        let rotated = key_ring("k2");
+        assert_eq!(rotated.decrypt(Some("k1"), &blob).unwrap(), "s3cret");
+        assert!(rotated.decrypt(Some("k2"), &blob).is_err());
+    }
+
+    #[test]
+    fn test_legacy_plaintext() {
+        assert_eq!(key_ring("k1").decrypt(None, b"plain").unwrap(), "plain");
+    }
+}
@@ -0,0 +1,116 @@
+use async_trait::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::header::{ETAG, IF_MATCH};
+use axum::http::request::Parts;
+use axum::http::HeaderValue;
+use axum::response::{IntoResponseParts, ResponseParts};
+use utoipa::openapi::path::{Parameter, ParameterBuilder, ParameterIn};
+use utoipa::openapi::{Object, Type};
+use utoipa::IntoParams;
+
+use crate::errors::models::AppError;
+
+/// The `ETag` of a row: its version as a strong entity tag, e.g. `"3"`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ETag(pub i64);
+
+impl IntoResponseParts for ETag {
+    type Error = AppError;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        let value =
+            HeaderValue::from_str(&format!("\"{}\"", self.0)).map_err(|_| AppError::Unknown)?;
+        res.headers_mut().insert(ETAG, value);
+        Ok(res)
+    }
+}
+
+/// The `If-Match` header of a PUT or DELETE. Without the header, or with `*`, any version
+/// matches. Otherwise the row's [`ETag`] must be one of the listed tags; weak tags never
+/// match, as If-Match uses the strong comparison.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IfMatch(Option<Vec<i64>>);
+
+impl IfMatch {
+    pub fn any() -> Self {
+        Self(None)
+    }
+
+    pub fn parse(value: &str) -> Self {
+        if value.trim() == "*" {
+            return Self::any();
+        }
+        let versions = value
+            .split(',')
+            .filter_map(|tag| {
+                tag.trim()
+                    .strip_prefix('"')?
+                    .strip_suffix('"')?
+                    .parse()
+                    .ok()
+            })
+            .collect();
+        Self(Some(versions))
+    }
+
+    /// Fails with `PreconditionFailed` when `version`, the current version of `entity`
+    /// `id`, isn't one the caller expects.
+    pub fn check(&self, entity: &str, id: i64, version: i64) -> Result<(), AppError> {
+        match &self.0 {
+            Some(versions) if !versions.contains(&version) => {
+                Err(AppError::PreconditionFailed(format!(
+                    "{} with id {} has been changed, its ETag is now \"{}\"",
+                    entity, id, version
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for IfMatch {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let values: Vec<&str> = parts
+            .headers
+            .get_all(IF_MATCH)
+            .iter()
+            .map(|value| value.to_str().unwrap_or_default())
+            .collect();
+        if values.is_empty() {
+            return Ok(Self::any());
+        }
+        Ok(Self::parse(&values.join(",")))
+    }
+}
+
+impl IntoParams for IfMatch {
+    fn into_params(_: impl Fn() -> Option<ParameterIn>) -> Vec<Parameter> {
+        vec![ParameterBuilder::new()
+            .name("If-Match")
+            .parameter_in(ParameterIn::Header)
+            .description(Some(
+                "The `ETag` the change is based on. Fails with 412 if the row changed since.",
+            ))
+            .schema(Some(Object::with_type(Type::String)))
+            .build()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_if_match() {
+        assert!(IfMatch::parse("*").check("Client", 1, 7).is_ok());
+        assert!(IfMatch::parse("\"7\"").check("Client", 1, 7).is_ok());
+        assert!(IfMatch::parse("\"6\", \"7\"").check("Client", 1, 7).is_ok());
+        assert!(IfMatch::parse("\"6\"").check("Client", 1, 7).is_err());
+        assert!(IfMatch::parse("W/\"7\"").check("Client", 1, 7).is_err());
+        assert!(IfMatch::parse("7").check("Client", 1, 7).is_err());
+        assert!(IfMatch::any().check("Client", 1, 7).is_ok());
+    }
+}
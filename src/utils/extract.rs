@@ -0,0 +1,84 @@
+use async_trait::async_trait;
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{FromRequest, FromRequestParts, Request};
+use axum::http::request::Parts;
+use axum::response::{IntoResponse, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::errors::models::{AppError, FieldError};
+
+/// Like [`axum::extract::Path`], but a path that doesn't parse, e.g. `/clients/abc`, is
+/// rejected with 400 and the usual error body.
+#[derive(Debug, Clone)]
+pub struct Path<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Path(value) = axum::extract::Path::from_request_parts(parts, state)
+            .await
+            .map_err(|rejection| AppError::InvalidInput(rejection.body_text()))?;
+        Ok(Self(value))
+    }
+}
+
+/// Like [`axum::extract::Query`], but a query string that doesn't parse is rejected with
+/// 400 and the usual error body.
+#[derive(Debug, Clone)]
+pub struct Query<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Query(value) = axum::extract::Query::from_request_parts(parts, state)
+            .await
+            .map_err(|rejection| AppError::InvalidInput(rejection.body_text()))?;
+        Ok(Self(value))
+    }
+}
+
+/// Like [`axum::Json`], but a body that doesn't parse is rejected with the usual error
+/// body: 422 when a field has the wrong type, 400 otherwise. Responds like [`axum::Json`].
+#[derive(Debug, Clone)]
+pub struct Json<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::Json(value) =
+            axum::Json::from_request(req, state)
+                .await
+                .map_err(|rejection| match rejection {
+                    JsonRejection::JsonDataError(_) => {
+                        AppError::Validation(vec![FieldError::new("body", rejection.body_text())])
+                    }
+                    _ => AppError::InvalidInput(rejection.body_text()),
+                })?;
+        Ok(Self(value))
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
@@ -0,0 +1,331 @@
+use sqlx::{Postgres, QueryBuilder};
+
+use crate::errors::models::AppError;
+
+/// The type of a filterable column. Values are parsed into it before they're bound,
+/// so `client_id=abc` fails with a 400 instead of reaching the database.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldType {
+    Int,
+    Text,
+}
+
+/// A column a list endpoint can be filtered on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilterField {
+    pub name: &'static str,
+    pub kind: FieldType,
+}
+
+impl FilterField {
+    pub const fn int(name: &'static str) -> Self {
+        Self {
+            name,
+            kind: FieldType::Int,
+        }
+    }
+
+    pub const fn text(name: &'static str) -> Self {
+        Self {
+            name,
+            kind: FieldType::Text,
+        }
+    }
+}
+
+/// The operator of a filter, taken from the suffix of the query parameter:
+/// ```text
+/// name__eq=acme          Eq
+/// name__contains=acme    Contains
+/// name__icontains=ACME   IContains
+/// client_id__in=1,2,3    In
+/// ```
+/// Without a suffix text columns use `Contains` and numbers use `Eq`, so `name=acme`
+/// matches substrings. `Contains` and `IContains` only apply to text columns.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    Contains,
+    IContains,
+    In,
+}
+
+impl Op {
+    fn parse(op: &str) -> Option<Self> {
+        match op {
+            "eq" => Some(Op::Eq),
+            "contains" => Some(Op::Contains),
+            "icontains" => Some(Op::IContains),
+            "in" => Some(Op::In),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterValue {
+    Int(i64),
+    Text(String),
+    IntList(Vec<i64>),
+    TextList(Vec<String>),
+}
+
+/// One parsed filter. `field` always comes from the endpoint's [`FilterField`] list, so it's
+/// safe to use as a column name; the value is only ever bound as a parameter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub field: &'static str,
+    pub op: Op,
+    pub value: FilterValue,
+}
+
+impl Condition {
+    /// Parses a `field[__op]=value` query parameter against the allowed fields.
+    pub fn parse(fields: &[FilterField], key: &str, value: &str) -> Result<Self, AppError> {
+        let (name, op) = match key.split_once("__") {
+            Some((name, op)) => (name, Op::parse(op)),
+            None => (key, None),
+        };
+        let field = fields
+            .iter()
+            .find(|field| field.name == name)
+            .ok_or_else(|| {
+                AppError::InvalidInput(format!(
+                    "Can't filter by {}, expected one of {}",
+                    name,
+                    fields
+                        .iter()
+                        .map(|field| field.name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            })?;
+        let op = match (op, key.contains("__"), field.kind) {
+            (Some(op), _, _) => op,
+            (None, false, FieldType::Text) => Op::Contains,
+            (None, false, FieldType::Int) => Op::Eq,
+            (None, true, _) => {
+                return Err(AppError::InvalidInput(format!(
+                    "Unknown filter {}, expected eq, contains, icontains or in",
+                    key
+                )))
+            }
+        };
+
+        let value = match (field.kind, op) {
+            (FieldType::Int, Op::Eq) => FilterValue::Int(parse_int(name, value)?),
+            (FieldType::Int, Op::In) => FilterValue::IntList(
+                value
+                    .split(',')
+                    .map(|value| parse_int(name, value.trim()))
+                    .collect::<Result<_, _>>()?,
+            ),
+            (FieldType::Int, _) => {
+                return Err(AppError::InvalidInput(format!(
+                    "{} is a number and can't be filtered with {}",
+                    name, key
+                )))
+            }
+            (FieldType::Text, Op::In) => FilterValue::TextList(
+                value
+                    .split(',')
+                    .map(|value| value.trim().to_string())
+                    .collect(),
+            ),
+            (FieldType::Text, _) => FilterValue::Text(value.to_string()),
+        };
+
+        Ok(Self {
+            field: field.name,
+            op,
+            value,
+        })
+    }
+
+    /// Appends ` AND <condition>` on `column`. The column defaults to the field name but
+    /// can be qualified, e.g. `ac.client_id` inside a subquery.
+    pub fn push(&self, builder: &mut QueryBuilder<'_, Postgres>, column: &str) {
+        builder.push(" AND ");
+        match (&self.op, &self.value) {
+            (Op::Contains, FilterValue::Text(value)) => {
+                builder
+                    .push(format_args!("strpos({}, ", column))
+                    .push_bind(value.clone())
+                    .push(") > 0");
+            }
+            (Op::IContains, FilterValue::Text(value)) => {
+                builder
+                    .push(format_args!("strpos(lower({}), lower(", column))
+                    .push_bind(value.clone())
+                    .push(")) > 0");
+            }
+            (_, FilterValue::Int(value)) => {
+                builder
+                    .push(format_args!("{} = ", column))
+                    .push_bind(*value);
+            }
+            (_, FilterValue::Text(value)) => {
+                builder
+                    .push(format_args!("{} = ", column))
+                    .push_bind(value.clone());
+            }
+            (_, FilterValue::IntList(values)) => {
+                builder
+                    .push(format_args!("{} = ANY(", column))
+                    .push_bind(values.clone())
+                    .push(")");
+            }
+            (_, FilterValue::TextList(values)) => {
+                builder
+                    .push(format_args!("{} = ANY(", column))
+                    .push_bind(values.clone())
+                    .push(")");
+            }
+        }
+    }
+}
+
+impl Condition {
+    /// Evaluates the condition against a field of a row held in memory, with the same
+    /// semantics as [`Condition::push`]. Nulls never match, as in SQL.
+    pub fn matches(&self, field: &serde_json::Value) -> bool {
+        match (&self.op, &self.value) {
+            (Op::Contains, FilterValue::Text(value)) => field
+                .as_str()
+                .is_some_and(|field| field.contains(value.as_str())),
+            (Op::IContains, FilterValue::Text(value)) => field
+                .as_str()
+                .is_some_and(|field| field.to_lowercase().contains(&value.to_lowercase())),
+            (_, FilterValue::Int(value)) => field.as_i64() == Some(*value),
+            (_, FilterValue::Text(value)) => field.as_str() == Some(value.as_str()),
+            (_, FilterValue::IntList(values)) => {
+                field.as_i64().is_some_and(|field| values.contains(&field))
+            }
+            (_, FilterValue::TextList(values)) => field
+                .as_str()
+                .is_some_and(|field| values.iter().any(|value| value == field)),
+        }
+    }
+}
+
+fn parse_int(name: &str, value: &str) -> Result<i64, AppError> {
+    value
+        .parse()
+        .map_err(|_| AppError::InvalidInput(format!("{} must be a number", name)))
+}
+
+/// The filters of a list request. All of them have to match.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Filters(pub Vec<Condition>);
+
+impl Filters {
+    pub fn get(&self, field: &str) -> impl Iterator<Item = &Condition> {
+        let field = field.to_string();
+        self.0
+            .iter()
+            .filter(move |condition| condition.field == field)
+    }
+
+    /// Appends every condition on its own column.
+    pub fn push(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        self.push_except(builder, &[]);
+    }
+
+    /// Like [`Filters::push`], leaving out fields that aren't plain columns of the table
+    /// so the repo can add those itself.
+    pub fn push_except(&self, builder: &mut QueryBuilder<'_, Postgres>, skip: &[&str]) {
+        for condition in &self.0 {
+            if !skip.contains(&condition.field) {
+                condition.push(builder, condition.field);
+            }
+        }
+    }
+
+    /// Checks a serialized row against every condition, except the `skip`ped fields.
+    pub fn matches_except(&self, row: &serde_json::Value, skip: &[&str]) -> bool {
+        self.0
+            .iter()
+            .filter(|condition| !skip.contains(&condition.field))
+            .all(|condition| {
+                condition.matches(row.get(condition.field).unwrap_or(&serde_json::Value::Null))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: &[FilterField] = &[FilterField::int("client_id"), FilterField::text("name")];
+
+    fn sql(filters: &[(&str, &str)]) -> Result<String, AppError> {
+        let conditions = filters
+            .iter()
+            .map(|(key, value)| Condition::parse(FIELDS, key, value))
+            .collect::<Result<_, _>>()?;
+        let mut builder = QueryBuilder::new("SELECT * FROM t WHERE TRUE");
+        Filters(conditions).push(&mut builder);
+        Ok(builder.sql().to_string())
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(
+            sql(&[("name__eq", "acme"), ("client_id", "1")]).unwrap(),
+            "SELECT * FROM t WHERE TRUE AND name = $1 AND client_id = $2"
+        );
+        assert_eq!(
+            sql(&[("name", "ac")]).unwrap(),
+            "SELECT * FROM t WHERE TRUE AND strpos(name, $1) > 0"
+        );
+        assert_eq!(
+            sql(&[("name__icontains", "AC")]).unwrap(),
+            "SELECT * FROM t WHERE TRUE AND strpos(lower(name), lower($1)) > 0"
+        );
+        assert_eq!(
+            sql(&[("client_id__in", "1, 2,3")]).unwrap(),
+            "SELECT * FROM t WHERE TRUE AND client_id = ANY($1)"
+        );
+        assert_eq!(
+            Condition::parse(FIELDS, "client_id__in", "1, 2,3")
+                .unwrap()
+                .value,
+            FilterValue::IntList(vec![1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn test_values_are_bound_not_interpolated() {
+        let hostile = "x' OR '1'='1'; DROP TABLE vendors; --";
+        for op in ["name", "name__eq", "name__icontains", "name__in"] {
+            let sql = sql(&[(op, hostile)]).unwrap();
+            assert!(!sql.contains("DROP"), "{}", sql);
+            assert!(!sql.contains('\''), "{}", sql);
+        }
+    }
+
+    #[test]
+    fn test_matches_in_memory() {
+        let row = serde_json::json!({"client_id": 2, "name": "Acme Corp"});
+        let matches = |key: &str, value: &str| {
+            Filters(vec![Condition::parse(FIELDS, key, value).unwrap()]).matches_except(&row, &[])
+        };
+        assert!(matches("name", "Acme"));
+        assert!(!matches("name", "acme"));
+        assert!(matches("name__icontains", "acme"));
+        assert!(matches("name__eq", "Acme Corp"));
+        assert!(!matches("name__eq", "Acme"));
+        assert!(matches("client_id__in", "1,2"));
+        assert!(!matches("client_id", "1"));
+    }
+
+    #[test]
+    fn test_rejects_unknown_fields_and_bad_values() {
+        assert!(sql(&[("name; DROP TABLE vendors", "x")]).is_err());
+        assert!(sql(&[("password", "x")]).is_err());
+        assert!(sql(&[("name__like", "x")]).is_err());
+        assert!(sql(&[("client_id", "1 OR 1=1")]).is_err());
+        assert!(sql(&[("client_id__contains", "1")]).is_err());
+        assert!(sql(&[("client_id__in", "1,two")]).is_err());
+    }
+}
@@ -0,0 +1,541 @@
+use async_trait::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, QueryBuilder};
+use std::marker::PhantomData;
+use utoipa::openapi::path::{Parameter, ParameterBuilder, ParameterIn};
+use utoipa::openapi::{Object, Type};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::errors::models::AppError;
+use crate::utils::filter::{Condition, FieldType, FilterField, Filters};
+
+pub const DEFAULT_LIMIT: i64 = 50;
+pub const MAX_LIMIT: i64 = 1000;
+
+/// Describes what a list endpoint can be sorted and filtered by.
+pub trait ListFilter: Send + Sync + 'static {
+    /// Columns the list can be sorted by. They must be NOT NULL for the cursor to work.
+    const SORT_FIELDS: &'static [&'static str];
+    /// Fields accepted as filters, see [`Condition::parse`].
+    const FILTER_FIELDS: &'static [FilterField];
+    /// The list hides soft deleted clients, or rows of them, unless `include_deleted=true`.
+    const SOFT_DELETED: bool = false;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Sort {
+    pub field: String,
+    pub descending: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, PartialOrd)]
+#[serde(untagged)]
+pub enum CursorValue {
+    Int(i64),
+    Text(String),
+}
+
+/// Position after the last item of a page: the sort value and id of that item.
+/// Handed to clients as an opaque base64 string.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Cursor {
+    pub sort: Sort,
+    pub value: CursorValue,
+    pub id: i64,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap_or_default())
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, AppError> {
+        URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .ok_or_else(|| AppError::InvalidInput("Invalid cursor".to_string()))
+    }
+}
+
+/// Parses `limit`, `cursor` and `sort` plus the entity's filters from the query string.
+/// ```text
+/// GET /clients?name__icontains=acme&sort=-name&limit=100&cursor=eyJzb3J0Ijp7...
+/// ```
+/// `sort` takes a field name, prefixed with `-` for descending order. Results are always
+/// ordered by `id` as a tie breaker so the cursor is stable.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListQuery<F> {
+    pub limit: i64,
+    pub cursor: Option<Cursor>,
+    pub sort: Sort,
+    pub filters: Filters,
+    /// Only accepted by lists with [`ListFilter::SOFT_DELETED`].
+    pub include_deleted: bool,
+    _filter: PhantomData<F>,
+}
+
+impl<F: ListFilter> Default for ListQuery<F> {
+    fn default() -> Self {
+        Self {
+            limit: DEFAULT_LIMIT,
+            cursor: None,
+            sort: Sort {
+                field: "id".to_string(),
+                descending: false,
+            },
+            filters: Filters::default(),
+            include_deleted: false,
+            _filter: PhantomData,
+        }
+    }
+}
+
+impl<F: ListFilter> ListQuery<F> {
+    pub fn from_query_string(query: &str) -> Result<Self, AppError> {
+        let params: Vec<(String, String)> = serde_urlencoded::from_str(query)
+            .map_err(|_| AppError::InvalidInput("Invalid query string".to_string()))?;
+
+        let mut list = Self::default();
+        let mut cursor = None;
+        for (key, value) in params {
+            match key.as_str() {
+                "limit" => {
+                    list.limit = value
+                        .parse::<i64>()
+                        .ok()
+                        .filter(|limit| (1..=MAX_LIMIT).contains(limit))
+                        .ok_or_else(|| {
+                            AppError::InvalidInput(format!(
+                                "limit must be between 1 and {}",
+                                MAX_LIMIT
+                            ))
+                        })?;
+                }
+                "sort" => {
+                    let (field, descending) = match value.strip_prefix('-') {
+                        Some(field) => (field, true),
+                        None => (value.as_str(), false),
+                    };
+                    if !F::SORT_FIELDS.contains(&field) {
+                        return Err(AppError::InvalidInput(format!(
+                            "Can't sort by {}, expected one of {}",
+                            field,
+                            F::SORT_FIELDS.join(", ")
+                        )));
+                    }
+                    list.sort = Sort {
+                        field: field.to_string(),
+                        descending,
+                    };
+                }
+                "cursor" => cursor = Some(Cursor::decode(&value)?),
+                "include_deleted" if F::SOFT_DELETED => {
+                    list.include_deleted = value.parse().map_err(|_| {
+                        AppError::InvalidInput("include_deleted must be true or false".to_string())
+                    })?;
+                }
+                _ => list
+                    .filters
+                    .0
+                    .push(Condition::parse(F::FILTER_FIELDS, &key, &value)?),
+            }
+        }
+
+        if let Some(cursor) = cursor {
+            if cursor.sort != list.sort {
+                return Err(AppError::InvalidInput(
+                    "The cursor was issued for a different sort order".to_string(),
+                ));
+            }
+            list.cursor = Some(cursor);
+        }
+
+        Ok(list)
+    }
+
+    /// Appends the keyset condition that skips everything up to and including the cursor.
+    /// Expects the builder to be inside a `WHERE` clause.
+    pub fn push_cursor(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        let Some(cursor) = &self.cursor else {
+            return;
+        };
+        let op = if self.sort.descending { "<" } else { ">" };
+
+        builder.push(format_args!(" AND ({}, id) {} (", self.sort.field, op));
+        match &cursor.value {
+            CursorValue::Int(value) => builder.push_bind(*value),
+            CursorValue::Text(value) => builder.push_bind(value.clone()),
+        };
+        builder.push(", ").push_bind(cursor.id).push(")");
+    }
+
+    /// Appends `ORDER BY` and `LIMIT`. One extra row is fetched to tell if there's a next page.
+    pub fn push_order_and_limit(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        let direction = if self.sort.descending { "DESC" } else { "ASC" };
+        builder.push(format_args!(
+            " ORDER BY {} {}, id {} LIMIT ",
+            self.sort.field, direction, direction
+        ));
+        builder.push_bind(self.limit + 1);
+    }
+
+    /// Builds the page from rows fetched with [`ListQuery::push_order_and_limit`].
+    pub fn page<T: Serialize>(&self, mut items: Vec<T>, total: i64) -> Result<Page<T>, AppError> {
+        let mut next_cursor = None;
+        if items.len() as i64 > self.limit {
+            items.truncate(self.limit as usize);
+            if let Some(last) = items.last() {
+                next_cursor = Some(self.cursor_after(last)?.encode());
+            }
+        }
+
+        Ok(Page {
+            items,
+            next_cursor,
+            total,
+        })
+    }
+
+    /// Filters, sorts and pages rows held in memory the same way the SQL helpers do.
+    /// Filters on the `skip`ped fields have to be applied by the caller beforehand.
+    pub fn page_in_memory<T: Serialize>(
+        &self,
+        items: Vec<T>,
+        skip: &[&str],
+    ) -> Result<Page<T>, AppError> {
+        let mut rows = Vec::new();
+        for item in items {
+            let row = serde_json::to_value(&item).map_err(|_| AppError::Unknown)?;
+            if self.filters.matches_except(&row, skip) {
+                rows.push((self.sort_key(&row)?, item));
+            }
+        }
+        let total = rows.len() as i64;
+
+        rows.sort_by(|(a, _), (b, _)| {
+            let ordering = a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal);
+            if self.sort.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+        if let Some(cursor) = &self.cursor {
+            let after = (cursor.value.clone(), cursor.id);
+            rows.retain(|(key, _)| match self.sort.descending {
+                true => *key < after,
+                false => *key > after,
+            });
+        }
+
+        let items = rows
+            .into_iter()
+            .take(self.limit as usize + 1)
+            .map(|(_, item)| item)
+            .collect();
+        self.page(items, total)
+    }
+
+    fn cursor_after<T: Serialize>(&self, item: &T) -> Result<Cursor, AppError> {
+        let item = serde_json::to_value(item).map_err(|_| AppError::Unknown)?;
+        let (value, id) = self.sort_key(&item)?;
+        Ok(Cursor {
+            sort: self.sort.clone(),
+            value,
+            id,
+        })
+    }
+
+    /// The sort value and id of a serialized row.
+    fn sort_key(&self, item: &serde_json::Value) -> Result<(CursorValue, i64), AppError> {
+        let id = item.get("id").and_then(|id| id.as_i64());
+        let value = item
+            .get(&self.sort.field)
+            .cloned()
+            .and_then(|value| serde_json::from_value(value).ok());
+
+        match (value, id) {
+            (Some(value), Some(id)) => Ok((value, id)),
+            _ => Err(AppError::Unknown),
+        }
+    }
+}
+
+#[async_trait]
+impl<S, F> FromRequestParts<S> for ListQuery<F>
+where
+    S: Send + Sync,
+    F: ListFilter,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Self::from_query_string(parts.uri.query().unwrap_or_default())
+    }
+}
+
+/// Documents the query parameters a list takes, filters included.
+impl<F: ListFilter> IntoParams for ListQuery<F> {
+    fn into_params(_: impl Fn() -> Option<ParameterIn>) -> Vec<Parameter> {
+        let param = |name: &str, kind: Type, description: String| {
+            ParameterBuilder::new()
+                .name(name)
+                .parameter_in(ParameterIn::Query)
+                .description(Some(description))
+                .schema(Some(Object::with_type(kind)))
+                .build()
+        };
+        let mut params = vec![
+            param(
+                "limit",
+                Type::Integer,
+                format!(
+                    "Page size, {} by default and at most {}.",
+                    DEFAULT_LIMIT, MAX_LIMIT
+                ),
+            ),
+            param(
+                "sort",
+                Type::String,
+                format!(
+                    "One of {}, prefixed with `-` for descending order.",
+                    F::SORT_FIELDS.join(", ")
+                ),
+            ),
+            param(
+                "cursor",
+                Type::String,
+                "The `next_cursor` of the previous page.".to_string(),
+            ),
+        ];
+        for field in F::FILTER_FIELDS {
+            let (kind, operators) = match field.kind {
+                FieldType::Int => (Type::Integer, "`__eq` or `__in`"),
+                FieldType::Text => (
+                    Type::String,
+                    "`__eq`, `__contains`, `__icontains` or `__in`",
+                ),
+            };
+            params.push(param(
+                field.name,
+                kind,
+                format!(
+                    "Filters on {}. Append {} to the name to pick the operator.",
+                    field.name, operators
+                ),
+            ));
+        }
+        if F::SOFT_DELETED {
+            params.push(param(
+                "include_deleted",
+                Type::Boolean,
+                "Includes deleted clients and their rows.".to_string(),
+            ));
+        }
+        params
+    }
+}
+
+/// The envelope returned by every list endpoint.
+/// `next_cursor` is `None` on the last page and `total` counts every matching row.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub total: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::filter::{FilterValue, Op};
+
+    #[derive(Debug, PartialEq)]
+    struct TestFilter;
+
+    impl ListFilter for TestFilter {
+        const SORT_FIELDS: &'static [&'static str] = &["id", "name"];
+        const FILTER_FIELDS: &'static [FilterField] =
+            &[FilterField::text("name"), FilterField::int("client_id")];
+    }
+
+    #[derive(Serialize)]
+    struct Row {
+        id: i64,
+        name: String,
+    }
+
+    #[test]
+    fn test_parse_list_query() {
+        let query = ListQuery::<TestFilter>::from_query_string(
+            "limit=2&sort=-name&name=ac%20me&client_id=7",
+        )
+        .unwrap();
+        assert_eq!(query.limit, 2);
+        assert_eq!(
+            query.sort,
+            Sort {
+                field: "name".to_string(),
+                descending: true
+            }
+        );
+        assert_eq!(
+            query.filters,
+            Filters(vec![
+                Condition {
+                    field: "name",
+                    op: Op::Contains,
+                    value: FilterValue::Text("ac me".to_string()),
+                },
+                Condition {
+                    field: "client_id",
+                    op: Op::Eq,
+                    value: FilterValue::Int(7),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_rejects_bad_params() {
+        assert!(ListQuery::<TestFilter>::from_query_string("sort=email").is_err());
+        assert!(ListQuery::<TestFilter>::from_query_string("limit=0").is_err());
+        assert!(ListQuery::<TestFilter>::from_query_string("client_id=abc").is_err());
+        assert!(ListQuery::<TestFilter>::from_query_string("cursor=nope").is_err());
+        assert!(ListQuery::<TestFilter>::from_query_string("email=x").is_err());
+        // Only lists of soft deleted rows accept it.
+        assert!(ListQuery::<TestFilter>::from_query_string("include_deleted=true").is_err());
+    }
+
+    fn page_sql(query: &ListQuery<TestFilter>) -> String {
+        let mut builder = QueryBuilder::new("SELECT * FROM t WHERE TRUE");
+        query.filters.push(&mut builder);
+        query.push_cursor(&mut builder);
+        query.push_order_and_limit(&mut builder);
+        builder.sql().to_string()
+    }
+
+    #[test]
+    fn test_hostile_cursor_is_bound() {
+        let hostile = "x') OR 1=1; DROP TABLE t; --";
+        let cursor = Cursor {
+            sort: Sort {
+                field: "name".to_string(),
+                descending: true,
+            },
+            value: CursorValue::Text(hostile.to_string()),
+            id: 7,
+        };
+        let query = ListQuery::<TestFilter>::from_query_string(&format!(
+            "sort=-name&name__eq=a%27%3B%20DROP%20TABLE%20t%3B%20--&cursor={}",
+            cursor.encode()
+        ))
+        .unwrap();
+        assert_eq!(
+            page_sql(&query),
+            "SELECT * FROM t WHERE TRUE AND name = $1 AND (name, id) < ($2, $3) \
+             ORDER BY name DESC, id DESC LIMIT $4"
+        );
+        assert_eq!(
+            query.cursor.unwrap().value,
+            CursorValue::Text(hostile.to_string())
+        );
+    }
+
+    #[test]
+    fn test_rejects_hostile_sort() {
+        for sort in [
+            "name%3B%20DROP%20TABLE%20t",
+            "-id%20DESC%2C%20(SELECT%201)",
+            "--name",
+        ] {
+            assert!(ListQuery::<TestFilter>::from_query_string(&format!("sort={}", sort)).is_err());
+        }
+
+        // The sort field carried by a forged cursor never reaches the SQL either: it has
+        // to equal the validated `sort` parameter.
+        let cursor = Cursor {
+            sort: Sort {
+                field: "name; DROP TABLE t".to_string(),
+                descending: false,
+            },
+            value: CursorValue::Int(1),
+            id: 1,
+        };
+        assert!(
+            ListQuery::<TestFilter>::from_query_string(&format!("cursor={}", cursor.encode()))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_next_cursor_round_trip() {
+        let query = ListQuery::<TestFilter>::from_query_string("limit=1&sort=name").unwrap();
+        let rows = vec![
+            Row {
+                id: 4,
+                name: "acme".to_string(),
+            },
+            Row {
+                id: 2,
+                name: "zeta".to_string(),
+            },
+        ];
+        let page = query.page(rows, 2).unwrap();
+        assert_eq!(page.items.len(), 1);
+
+        let next = ListQuery::<TestFilter>::from_query_string(&format!(
+            "limit=1&sort=name&cursor={}",
+            page.next_cursor.unwrap()
+        ))
+        .unwrap();
+        let cursor = next.cursor.clone().unwrap();
+        assert_eq!(cursor.value, CursorValue::Text("acme".to_string()));
+        assert_eq!(cursor.id, 4);
+
+        // Paging in memory gives the same result.
+        let rows = vec![
+            Row {
+                id: 2,
+                name: "zeta".to_string(),
+            },
+            Row {
+                id: 4,
+                name: "acme".to_string(),
+            },
+        ];
+        let page = query.page_in_memory(rows, &[]).unwrap();
+        assert_eq!(page.items[0].id, 4);
+        assert_eq!(page.total, 2);
+        let page = next
+            .page_in_memory(
+                vec![
+                    Row {
+                        id: 2,
+                        name: "zeta".to_string(),
+                    },
+                    Row {
+                        id: 4,
+                        name: "acme".to_string(),
+                    },
+                ],
+                &[],
+            )
+            .unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].id, 2);
+        assert_eq!(page.next_cursor, None);
+
+        // A cursor can't be reused with a different sort order.
+        assert!(ListQuery::<TestFilter>::from_query_string(&format!(
+            "sort=-name&cursor={}",
+            cursor.encode()
+        ))
+        .is_err());
+    }
+}
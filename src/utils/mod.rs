@@ -1,2 +1,12 @@
 pub mod auth;
+pub mod crypto;
+pub mod etag;
+pub mod extract;
+pub mod filter;
+pub mod list;
+pub mod net;
+pub mod request_id;
+pub mod response;
 pub mod ssh;
+pub mod validate;
+pub mod version;
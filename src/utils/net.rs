@@ -0,0 +1,52 @@
+use std::net::{IpAddr, SocketAddr};
+
+/// Whether an address belongs on the public internet, as opposed to loopback, link-local
+/// (which includes the cloud metadata endpoint), RFC 1918, shared, unique local and other
+/// special-purpose ranges.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                || (a == 100 && (b & 0xc0) == 64)
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(ip.into()),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_unspecified()
+                    || ip.is_loopback()
+                    || ip.is_multicast()
+                    || (first & 0xfe00) == 0xfc00
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Resolves `host` and keeps the public addresses, or all of them with `allow_private`.
+/// Connecting to the returned addresses, instead of the name, means a second lookup can't
+/// swap in an internal one.
+pub async fn resolve_public(
+    host: &str,
+    port: u16,
+    allow_private: bool,
+) -> Result<Vec<SocketAddr>, String> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|error| format!("Could not resolve {}: {}", host, error))?
+        .filter(|addr| allow_private || is_public(addr.ip()))
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("{} doesn't resolve to a public address", host));
+    }
+    Ok(addrs)
+}
@@ -0,0 +1,47 @@
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+
+use crate::utils::version::V1Response;
+
+/// 201 with the `Location` of the new resource and `response`, usually the resource
+/// itself. `v1` is what `/v1` answers instead.
+pub struct Created<R> {
+    pub location: String,
+    pub response: R,
+    pub v1: V1Response,
+}
+
+impl<R: IntoResponse> IntoResponse for Created<R> {
+    fn into_response(self) -> Response {
+        let mut response = (
+            StatusCode::CREATED,
+            [(header::LOCATION, self.location)],
+            self.response,
+        )
+            .into_response();
+        response.extensions_mut().insert(self.v1);
+        response
+    }
+}
+
+/// The updated resource. `/v1` answers an empty 200 instead.
+pub struct Updated<R>(pub R);
+
+impl<R: IntoResponse> IntoResponse for Updated<R> {
+    fn into_response(self) -> Response {
+        let mut response = self.0.into_response();
+        response.extensions_mut().insert(V1Response::Empty);
+        response
+    }
+}
+
+/// 204, e.g. for a delete. `/v1` answers an empty 200 instead.
+pub struct NoContent;
+
+impl IntoResponse for NoContent {
+    fn into_response(self) -> Response {
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        response.extensions_mut().insert(V1Response::Empty);
+        response
+    }
+}
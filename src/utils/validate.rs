@@ -0,0 +1,270 @@
+use std::net::{IpAddr, Ipv4Addr};
+
+use axum::async_trait;
+use axum::extract::{FromRequest, Request};
+use serde::de::DeserializeOwned;
+
+use crate::errors::models::{AppError, FieldError};
+use crate::utils::extract::Json;
+
+/// A request body with rules about its fields, checked by [`Valid`].
+pub trait Validate {
+    fn validate(&self, errors: &mut Errors);
+}
+
+/// The rule violations of a request body. Every field is checked, so the caller learns
+/// about all of them at once.
+#[derive(Debug, Default)]
+pub struct Errors {
+    prefix: String,
+    errors: Vec<FieldError>,
+}
+
+impl Errors {
+    /// Records the violation of `rule`, if any, against `field`.
+    pub fn check(&mut self, field: &str, rule: Result<(), String>) {
+        if let Err(message) = rule {
+            self.errors.push(FieldError::new(
+                format!("{}{}", self.prefix, field),
+                message,
+            ));
+        }
+    }
+
+    /// Checks a nested body, e.g. the vendors of an onboarding document as `vendors[0].port`.
+    pub fn nested(&mut self, field: &str, value: &impl Validate) {
+        let outer = std::mem::take(&mut self.prefix);
+        self.prefix = format!("{}{}.", outer, field);
+        value.validate(self);
+        self.prefix = outer;
+    }
+
+    pub fn into_result(self) -> Result<(), AppError> {
+        if self.errors.is_empty() {
+            return Ok(());
+        }
+        Err(AppError::Validation(self.errors))
+    }
+}
+
+/// Checks `value` against its rules.
+pub fn validate(value: &impl Validate) -> Result<(), AppError> {
+    let mut errors = Errors::default();
+    value.validate(&mut errors);
+    errors.into_result()
+}
+
+/// Like [`Json`], but the body must also pass its [`Validate`] rules. Violations are
+/// rejected with 422 and one error per field.
+#[derive(Debug, Clone)]
+pub struct Valid<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Valid<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state).await?;
+        validate(&value)?;
+        Ok(Self(value))
+    }
+}
+
+/// The rules fields are checked against. They return the message of the violation.
+pub mod rules {
+    use super::*;
+    use crate::sftp::models::validate_source_ip_rules;
+    use crate::utils::ssh::parse_public_key;
+
+    pub fn required(value: &str) -> Result<(), String> {
+        if value.trim().is_empty() {
+            return Err("is required".to_string());
+        }
+        Ok(())
+    }
+
+    /// A plain `local@example.com` address, without display names or IP literals.
+    pub fn email(value: &str) -> Result<(), String> {
+        let invalid = || Err("must be an email address".to_string());
+        let Some((local, domain)) = value.split_once('@') else {
+            return invalid();
+        };
+        let local_ok = !local.is_empty()
+            && local.len() <= 64
+            && local
+                .chars()
+                .all(|c| c.is_ascii_graphic() && !"@()<>[]:;,\\\"".contains(c));
+        if value.len() > 254 || !local_ok || !domain.contains('.') || hostname(domain).is_err() {
+            return invalid();
+        }
+        Ok(())
+    }
+
+    /// The S3 bucket naming rules, see
+    /// https://docs.aws.amazon.com/AmazonS3/latest/userguide/bucketnamingrules.html
+    pub fn bucket(value: &str) -> Result<(), String> {
+        if !(3..=63).contains(&value.len()) {
+            return Err("must be between 3 and 63 characters long".to_string());
+        }
+        if !value
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '.' || c == '-')
+        {
+            return Err("can only contain lowercase letters, digits, dots and hyphens".to_string());
+        }
+        let edges = [value.chars().next(), value.chars().last()];
+        if edges.iter().flatten().any(|c| !c.is_ascii_alphanumeric()) {
+            return Err("must begin and end with a letter or digit".to_string());
+        }
+        if value.contains("..") {
+            return Err("must not contain two adjacent dots".to_string());
+        }
+        if value.parse::<Ipv4Addr>().is_ok() {
+            return Err("must not be formatted as an IP address".to_string());
+        }
+        if value.starts_with("xn--") || value.ends_with("-s3alias") || value.ends_with("--ol-s3") {
+            return Err("uses a prefix or suffix reserved by S3".to_string());
+        }
+        Ok(())
+    }
+
+    /// An IAM role ARN like `arn:aws:iam::123456789012:role/sftp`.
+    pub fn role_arn(value: &str) -> Result<(), String> {
+        let invalid = || Err("must be an IAM role ARN".to_string());
+        let parts: Vec<&str> = value.splitn(6, ':').collect();
+        let ["arn", partition, "iam", "", account, resource] = parts[..] else {
+            return invalid();
+        };
+        let Some(name) = resource.strip_prefix("role/") else {
+            return invalid();
+        };
+        let name_ok = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+=,.@_-/".contains(c));
+        if !matches!(partition, "aws" | "aws-cn" | "aws-us-gov")
+            || account.len() != 12
+            || !account.chars().all(|c| c.is_ascii_digit())
+            || !name_ok
+        {
+            return invalid();
+        }
+        Ok(())
+    }
+
+    pub fn port(value: i32) -> Result<(), String> {
+        if !(1..=65535).contains(&value) {
+            return Err("must be between 1 and 65535".to_string());
+        }
+        Ok(())
+    }
+
+    /// A hostname or an IP address.
+    pub fn host(value: &str) -> Result<(), String> {
+        if value.parse::<IpAddr>().is_ok() {
+            return Ok(());
+        }
+        hostname(value).map_err(|_| "must be a hostname or an IP address".to_string())
+    }
+
+    fn hostname(value: &str) -> Result<(), ()> {
+        let value = value.strip_suffix('.').unwrap_or(value);
+        let label_ok = |label: &str| {
+            (1..=63).contains(&label.len())
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+        };
+        if value.len() > 253 || !value.split('.').all(label_ok) {
+            return Err(());
+        }
+        Ok(())
+    }
+
+    /// The usernames AWS Transfer Family accepts.
+    pub fn sftp_username(value: &str) -> Result<(), String> {
+        let word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        let valid = (3..=100).contains(&value.len())
+            && value.chars().next().is_some_and(word)
+            && value.chars().all(|c| word(c) || "@.-".contains(c));
+        if !valid {
+            return Err(
+                "must be 3 to 100 letters, digits, underscores, @, dots or hyphens, starting \
+                 with a letter, digit or underscore"
+                    .to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    pub fn source_ip_rule(value: &str) -> Result<(), String> {
+        validate_source_ip_rules(&[value.to_string()])
+            .map_err(|_| "must be an IP address or a CIDR range".to_string())
+    }
+
+    pub fn public_key(value: &str) -> Result<(), String> {
+        parse_public_key(value)
+            .map(|_| ())
+            .map_err(|error| error.body().message)
+    }
+
+    /// An OpenSSH or PEM private key, readable with `passphrase`.
+    pub fn private_key(value: &str, passphrase: Option<&str>) -> Result<(), String> {
+        russh_keys::decode_secret_key(value, passphrase)
+            .map(|_| ())
+            .map_err(|error| format!("could not be read: {}", error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::rules::*;
+
+    #[test]
+    fn test_rules() {
+        assert!(email("ops@acme.example.com").is_ok());
+        for value in [
+            "acme",
+            "@acme.com",
+            "ops@acme",
+            "o ps@acme.com",
+            "ops@-acme.com",
+        ] {
+            assert!(email(value).is_err(), "{}", value);
+        }
+
+        assert!(bucket("acme-uploads.2024").is_ok());
+        for value in [
+            "ab",
+            "Acme",
+            "-acme",
+            "acme..uploads",
+            "192.168.1.1",
+            "xn--acme",
+        ] {
+            assert!(bucket(value).is_err(), "{}", value);
+        }
+
+        assert!(role_arn("arn:aws:iam::123456789012:role/service/sftp").is_ok());
+        for value in [
+            "arn:aws:iam::123456789012:user/sftp",
+            "arn:aws:iam::1234:role/sftp",
+            "arn:aws:s3:::bucket",
+            "arn:azure:iam::123456789012:role/sftp",
+        ] {
+            assert!(role_arn(value).is_err(), "{}", value);
+        }
+
+        assert!(host("sftp.bank.example.com").is_ok());
+        assert!(host("2001:db8::1").is_ok());
+        assert!(host("sftp_bank.example.com").is_err());
+        assert!(port(0).is_err());
+        assert!(port(65536).is_err());
+        assert!(sftp_username("acme-upload@bank").is_ok());
+        assert!(sftp_username("-acme").is_err());
+    }
+}
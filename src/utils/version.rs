@@ -0,0 +1,106 @@
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{header, HeaderName, HeaderValue, StatusCode};
+use axum::{extract::Request, middleware, middleware::Next, response::Response, Router};
+
+pub static DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+pub static SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+/// When the unversioned paths were deprecated, 2026-10-18 00:00 UTC, as an RFC 9745
+/// `@<unix time>`.
+const DEPRECATED_SINCE: &str = "@1792281600";
+
+/// When the unversioned paths go away, six months after [`DEPRECATED_SINCE`], as the
+/// RFC 8594 HTTP-date.
+const SUNSET_AT: &str = "Sun, 18 Apr 2027 00:00:00 GMT";
+
+/// Serves `v1` under `/v1` and `v2` under `/v2`. The unversioned paths still reach `v1`, so
+/// existing consumers keep working, but are flagged as deprecated by [`deprecated`].
+///
+/// The handlers answer the way v2 does; [`v1`] turns their responses back into what v1
+/// answered. v1 is the API as of the release that introduced versions, paginated lists and
+/// JSON error bodies included, not the API before them.
+pub fn versioned(v1: Router, v2: Router) -> Router {
+    let v1 = v1.layer(middleware::from_fn(self::v1));
+    Router::new()
+        .nest("/v1", v1.clone())
+        .nest(
+            "/v2",
+            v2.layer(middleware::from_fn_with_state("/v2", prefix_location)),
+        )
+        .merge(v1.layer(middleware::from_fn(deprecated)))
+}
+
+/// Adds the `Deprecation` and `Sunset` headers and a `Link` to the same path under `/v1`.
+pub async fn deprecated(req: Request, next: Next) -> Response {
+    let path = req
+        .uri()
+        .path_and_query()
+        .map_or(req.uri().path(), |path| path.as_str());
+    let successor = format!("</v1{}>; rel=\"successor-version\"", path);
+
+    let mut response = next.run(req).await;
+    let headers = response.headers_mut();
+    headers.insert(
+        DEPRECATION.clone(),
+        HeaderValue::from_static(DEPRECATED_SINCE),
+    );
+    headers.insert(SUNSET.clone(), HeaderValue::from_static(SUNSET_AT));
+    if let Ok(link) = HeaderValue::from_str(&successor) {
+        headers.insert(header::LINK, link);
+    }
+    response
+}
+
+/// How v1 answered a route whose response changed in v2, set as a response extension by
+/// the handler.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum V1Response {
+    /// 200 with the body of the v2 response.
+    Ok,
+    /// 200 with the id of the new resource.
+    Id(i64),
+    /// 200 without a body.
+    Empty,
+}
+
+/// Answers like v1 did when the handler says how, see [`V1Response`].
+pub async fn v1(req: Request, next: Next) -> Response {
+    let mut response = next.run(req).await;
+    let Some(v1) = response.extensions_mut().remove::<V1Response>() else {
+        return response;
+    };
+
+    let (mut parts, body) = response.into_parts();
+    parts.status = StatusCode::OK;
+    parts.headers.remove(header::LOCATION);
+    let body = match v1 {
+        V1Response::Ok => body,
+        V1Response::Id(id) => {
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Body::from(id.to_string())
+        }
+        V1Response::Empty => {
+            parts.headers.remove(header::CONTENT_LENGTH);
+            parts.headers.remove(header::CONTENT_TYPE);
+            Body::empty()
+        }
+    };
+    Response::from_parts(parts, body)
+}
+
+/// Resolves the `Location` of a new resource within the version, e.g. `/clients/1` becomes
+/// `/v2/clients/1`.
+async fn prefix_location(State(prefix): State<&'static str>, req: Request, next: Next) -> Response {
+    let mut response = next.run(req).await;
+    let location = response
+        .headers()
+        .get(header::LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .filter(|location| location.starts_with('/'))
+        .and_then(|location| HeaderValue::from_str(&format!("{}{}", prefix, location)).ok());
+    if let Some(location) = location {
+        response.headers_mut().insert(header::LOCATION, location);
+    }
+    response
+}
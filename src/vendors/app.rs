@@ -1,17 +1,61 @@
 use super::{
-    handlers::{delete_vendor, get_vendor, get_vendors, update_vendor},
+    connection::AllowPrivateHosts,
+    handlers::{
+        approve_host_key, capture_host_key, delete_host_key, delete_vendor, get_host_key,
+        get_host_keys, get_vendor, get_vendors, pin_host_key, replace_host_keys,
+        test_vendor_connection, update_vendor,
+    },
     models::VendorRepo,
 };
 use axum::{
-    routing::{delete, get, put},
-    Router,
+    routing::{delete, get, post, put},
+    Extension, Router,
 };
 
-pub fn router<T: VendorRepo>(repo: T) -> Router {
+/// ```text
+/// POST /vendors/:id/test-connection?timeout_seconds=10
+/// ```
+/// Dials the vendor's SFTP server and reports how far it got.
+///
+/// ```text
+/// GET    /vendors/:id/host-keys
+/// GET    /vendors/:id/host-keys/:key_id
+/// POST   /vendors/:id/host-keys                    {"public_key": "ssh-ed25519 AAAA..."}
+/// PUT    /vendors/:id/host-keys                    {"public_keys": [...]}
+/// POST   /vendors/:id/host-keys/capture?timeout_seconds=10
+/// PUT    /vendors/:id/host-keys/:key_id/approve
+/// DELETE /vendors/:id/host-keys/:key_id
+/// ```
+/// Manages the host keys the vendor's server has to present once any are approved.
+///
+/// Both dial the vendor's host, which has to resolve to a public address unless
+/// `allow_private` is set.
+pub fn router<T: VendorRepo>(repo: T, allow_private: bool) -> Router {
     Router::new()
         .route("/vendors", get(get_vendors::<T>))
         .route("/vendors/:id", get(get_vendor::<T>))
         .route("/vendors/:id", put(update_vendor::<T>))
         .route("/vendors/:id", delete(delete_vendor::<T>))
+        .route(
+            "/vendors/:id/test-connection",
+            post(test_vendor_connection::<T>),
+        )
+        .route("/vendors/:id/host-keys", get(get_host_keys::<T>))
+        .route("/vendors/:id/host-keys", post(pin_host_key::<T>))
+        .route("/vendors/:id/host-keys", put(replace_host_keys::<T>))
+        .route(
+            "/vendors/:id/host-keys/capture",
+            post(capture_host_key::<T>),
+        )
+        .route(
+            "/vendors/:id/host-keys/:key_id/approve",
+            put(approve_host_key::<T>),
+        )
+        .route("/vendors/:id/host-keys/:key_id", get(get_host_key::<T>))
+        .route(
+            "/vendors/:id/host-keys/:key_id",
+            delete(delete_host_key::<T>),
+        )
+        .layer(Extension(AllowPrivateHosts(allow_private)))
         .with_state(repo)
 }
@@ -0,0 +1,401 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use russh::{client, Disconnect};
+use russh_keys::{key::PublicKey, PublicKeyBase64};
+use russh_sftp::client::SftpSession;
+use serde::{Deserialize, Serialize};
+use ssh_key::HashAlg;
+use tokio::net::TcpStream;
+
+use super::models::Vendor;
+use crate::errors::models::AppError;
+use crate::utils::net::resolve_public;
+use utoipa::{IntoParams, ToSchema};
+
+pub const DEFAULT_TIMEOUT_SECONDS: u64 = 10;
+pub const MAX_TIMEOUT_SECONDS: u64 = 60;
+/// How many entries of the login directory are returned.
+pub const LISTING_SAMPLE: usize = 20;
+
+/// `?timeout_seconds=` for `POST /vendors/:id/test-connection`.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ConnectionParams {
+    pub timeout_seconds: Option<u64>,
+}
+
+impl ConnectionParams {
+    pub fn timeout(&self) -> Result<Duration, AppError> {
+        match self.timeout_seconds.unwrap_or(DEFAULT_TIMEOUT_SECONDS) {
+            seconds @ 1..=MAX_TIMEOUT_SECONDS => Ok(Duration::from_secs(seconds)),
+            _ => Err(AppError::InvalidInput(format!(
+                "timeout_seconds must be between 1 and {}",
+                MAX_TIMEOUT_SECONDS
+            ))),
+        }
+    }
+}
+
+/// Whether vendor servers may be on internal addresses, from `webhooks.allow_private_urls`.
+/// Left off, vendors resolving to loopback, link-local or private addresses are refused
+/// before anything is dialed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct AllowPrivateHosts(pub bool);
+
+/// A server's host key, as presented during the SSH handshake.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct HostKey {
+    pub key_type: String,
+    /// In the OpenSSH format, as in `known_hosts`.
+    pub public_key: String,
+    /// SHA256, as printed by `ssh-keygen -l`.
+    pub fingerprint: String,
+}
+
+impl HostKey {
+    /// Reads a key in the OpenSSH format, e.g. a line from `ssh-keyscan` without the host.
+    pub fn parse(public_key: &str) -> Result<Self, AppError> {
+        let key = ssh_key::PublicKey::from_openssh(public_key.trim()).map_err(|_| {
+            AppError::InvalidInput(format!("Invalid host key {}", public_key.trim()))
+        })?;
+        Self::from_ssh_key(key)
+            .ok_or_else(|| AppError::InvalidInput(format!("Invalid host key {}", public_key)))
+    }
+
+    fn from_russh(key: &PublicKey) -> Option<Self> {
+        Self::from_ssh_key(ssh_key::PublicKey::from_bytes(&key.public_key_bytes()).ok()?)
+    }
+
+    /// Drops the comment, which isn't part of the key.
+    fn from_ssh_key(mut key: ssh_key::PublicKey) -> Option<Self> {
+        key.set_comment("");
+        Some(Self {
+            key_type: key.algorithm().as_str().to_string(),
+            public_key: key.to_openssh().ok()?,
+            fingerprint: key.fingerprint(HashAlg::Sha256).to_string(),
+        })
+    }
+}
+
+/// What a connection test got through. Each step is only tried when the one before it
+/// worked, and `error` says why the first failing step failed.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, ToSchema)]
+pub struct ConnectionTest {
+    pub ok: bool,
+    /// The TCP connection was accepted.
+    pub reachable: bool,
+    pub host_key: Option<HostKey>,
+    /// The host key matched one of the vendor's approved pins. Without pins any key is
+    /// accepted and this is false.
+    pub host_key_pinned: bool,
+    pub authenticated: bool,
+    /// `publickey` or `password`, whichever was accepted.
+    pub auth_method: Option<String>,
+    /// The directory the SFTP session starts in.
+    pub directory: Option<String>,
+    /// Up to [`LISTING_SAMPLE`] names from `directory`.
+    pub entries: Vec<String>,
+    pub error: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+/// Dials the vendor's SFTP server with its stored credentials: connects, checks the host
+/// key, logs in with the SSH key (falling back to the password) and lists the login
+/// directory. Everything, including DNS, has to finish within `timeout`.
+///
+/// `pins` are the fingerprints of the vendor's approved host keys. When there are any, a
+/// server presenting another key fails with [`AppError::HostKeyMismatch`] before the
+/// credentials are sent. Without pins any host key is accepted.
+pub async fn test_connection(
+    vendor: &Vendor,
+    pins: &[String],
+    timeout: Duration,
+    allow_private: AllowPrivateHosts,
+) -> Result<ConnectionTest, AppError> {
+    let started = Instant::now();
+    let mut report = ConnectionTest::default();
+    let pins = (!pins.is_empty()).then(|| pins.to_vec());
+
+    let probe = probe(vendor, pins.clone(), allow_private, &mut report);
+    let result = tokio::time::timeout(timeout, probe).await;
+    if let (Some(pins), Some(host_key)) = (&pins, &report.host_key) {
+        if !pins.contains(&host_key.fingerprint) {
+            return Err(mismatch(vendor, host_key));
+        }
+        report.host_key_pinned = true;
+    }
+    let error = match result {
+        Ok(Ok(())) => None,
+        Ok(Err(error)) => Some(error),
+        Err(_) => Some(format!("Timed out after {} seconds", timeout.as_secs())),
+    };
+
+    report.ok = error.is_none();
+    report.error = error;
+    report.elapsed_ms = started.elapsed().as_millis() as u64;
+    Ok(report)
+}
+
+/// Connects to a host only to read its host key. No credentials are sent. A host that
+/// can't be reached in time fails with [`AppError::Upstream`].
+pub async fn fetch_host_key(
+    host: &str,
+    port: i32,
+    timeout: Duration,
+    allow_private: AllowPrivateHosts,
+) -> Result<HostKey, AppError> {
+    let fetch = async {
+        let stream = connect(host, port, allow_private).await?;
+        // Rejecting every key ends the handshake as soon as the key has been seen.
+        let handler = Probe {
+            pins: Some(Vec::new()),
+            ..Probe::default()
+        };
+        let host_key = handler.host_key.clone();
+        let _ = client::connect_stream(Arc::new(client::Config::default()), stream, handler).await;
+        let host_key = host_key.lock().unwrap().take();
+        host_key.ok_or_else(|| format!("{}:{} didn't present a host key", host, port))
+    };
+
+    match tokio::time::timeout(timeout, fetch).await {
+        Ok(Ok(host_key)) => Ok(host_key),
+        Ok(Err(error)) => Err(AppError::Upstream(error)),
+        Err(_) => Err(AppError::Upstream(format!(
+            "Timed out after {} seconds",
+            timeout.as_secs()
+        ))),
+    }
+}
+
+fn mismatch(vendor: &Vendor, host_key: &HostKey) -> AppError {
+    AppError::HostKeyMismatch(format!(
+        "{}:{} presented {} {}, which isn't pinned for vendor {}",
+        vendor.host,
+        vendor.port,
+        host_key.key_type,
+        host_key.fingerprint,
+        vendor.id.unwrap_or_default()
+    ))
+}
+
+/// Dials the addresses `host` resolved to once they passed the check, so the name isn't
+/// looked up a second time.
+async fn connect(
+    host: &str,
+    port: i32,
+    AllowPrivateHosts(allow_private): AllowPrivateHosts,
+) -> Result<TcpStream, String> {
+    let port = u16::try_from(port).map_err(|_| format!("Invalid port {}", port))?;
+    let addrs = resolve_public(host, port, allow_private).await?;
+    TcpStream::connect(addrs.as_slice())
+        .await
+        .map_err(|error| format!("Could not connect to {}:{}: {}", host, port, error))
+}
+
+/// Fills in `report` as the steps succeed, so a timeout still shows how far it got.
+async fn probe(
+    vendor: &Vendor,
+    pins: Option<Vec<String>>,
+    allow_private: AllowPrivateHosts,
+    report: &mut ConnectionTest,
+) -> Result<(), String> {
+    let stream = connect(&vendor.host, vendor.port, allow_private).await?;
+    report.reachable = true;
+
+    let handler = Probe {
+        pins,
+        ..Probe::default()
+    };
+    let host_key = handler.host_key.clone();
+    let session =
+        client::connect_stream(Arc::new(client::Config::default()), stream, handler).await;
+    report.host_key = host_key.lock().unwrap().take();
+    let mut session = session.map_err(|error| format!("SSH handshake failed: {}", error))?;
+
+    let method = authenticate(&mut session, vendor).await?;
+    report.authenticated = true;
+    report.auth_method = Some(method.to_string());
+
+    let channel = session
+        .channel_open_session()
+        .await
+        .map_err(|error| format!("Could not open a session: {}", error))?;
+    channel
+        .request_subsystem(true, "sftp")
+        .await
+        .map_err(|error| format!("Could not start SFTP: {}", error))?;
+    let sftp = SftpSession::new(channel.into_stream())
+        .await
+        .map_err(|error| format!("Could not start SFTP: {}", error))?;
+    let directory = sftp
+        .canonicalize(".")
+        .await
+        .map_err(|error| format!("Could not resolve the login directory: {}", error))?;
+    let entries = sftp
+        .read_dir(directory.as_str())
+        .await
+        .map_err(|error| format!("Could not list {}: {}", directory, error))?;
+    report.entries = entries
+        .take(LISTING_SAMPLE)
+        .map(|entry| entry.file_name())
+        .collect();
+    report.directory = Some(directory);
+
+    let _ = sftp.close().await;
+    let _ = session
+        .disconnect(Disconnect::ByApplication, "", "en")
+        .await;
+    Ok(())
+}
+
+/// Tries the SSH key first and the password second, and returns the method that worked.
+async fn authenticate(
+    session: &mut client::Handle<Probe>,
+    vendor: &Vendor,
+) -> Result<&'static str, String> {
+    let Some(username) = vendor.username.as_deref() else {
+        return Err("The vendor has no username".to_string());
+    };
+    if vendor.ssh_key.is_none() && vendor.password.is_none() {
+        return Err("The vendor has no password or SSH key".to_string());
+    }
+
+    if let Some(ssh_key) = &vendor.ssh_key {
+        let key = russh_keys::decode_secret_key(ssh_key, vendor.ssh_key_password.as_deref())
+            .map_err(|error| format!("Could not read the vendor's SSH key: {}", error))?;
+        let accepted = session
+            .authenticate_publickey(username, Arc::new(key))
+            .await
+            .map_err(|error| format!("Authentication failed: {}", error))?;
+        if accepted {
+            return Ok("publickey");
+        }
+    }
+    if let Some(password) = &vendor.password {
+        let accepted = session
+            .authenticate_password(username, password)
+            .await
+            .map_err(|error| format!("Authentication failed: {}", error))?;
+        if accepted {
+            return Ok("password");
+        }
+    }
+    Err(format!(
+        "The server rejected the credentials for {}",
+        username
+    ))
+}
+
+/// Keeps the host key for the report and accepts it if its fingerprint is one of `pins`,
+/// or whatever it is when there are no pins.
+#[derive(Default)]
+struct Probe {
+    host_key: Arc<Mutex<Option<HostKey>>>,
+    pins: Option<Vec<String>>,
+}
+
+#[async_trait]
+impl client::Handler for Probe {
+    type Error = russh::Error;
+
+    async fn check_server_key(&mut self, key: &PublicKey) -> Result<bool, Self::Error> {
+        let host_key = HostKey::from_russh(key);
+        let accepted = match (&self.pins, &host_key) {
+            (None, _) => true,
+            (Some(pins), Some(host_key)) => pins.contains(&host_key.fingerprint),
+            (Some(_), None) => false,
+        };
+        *self.host_key.lock().unwrap() = host_key;
+        Ok(accepted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeout_bounds() {
+        let params = |timeout_seconds| ConnectionParams {
+            timeout_seconds: Some(timeout_seconds),
+        };
+        assert_eq!(
+            ConnectionParams::default().timeout().unwrap(),
+            Duration::from_secs(DEFAULT_TIMEOUT_SECONDS)
+        );
+        assert_eq!(params(60).timeout().unwrap(), Duration::from_secs(60));
+        assert!(params(0).timeout().is_err());
+        assert!(params(61).timeout().is_err());
+    }
+
+    #[test]
+    fn test_parse_host_key() {
+        let public_key =
+            "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIJdD7y3aLq454yWBdwLWbieU1ebz9/cu7/QEXn9OIeZJ";
+        let host_key = HostKey::parse(&format!("{} root@bank", public_key)).unwrap();
+        assert_eq!(host_key.key_type, "ssh-ed25519");
+        assert_eq!(host_key.public_key, public_key);
+        assert!(host_key.fingerprint.starts_with("SHA256:"));
+        assert!(HostKey::parse("ssh-ed25519 not-base64").is_err());
+    }
+
+    fn vendor(host: &str, port: i32) -> Vendor {
+        Vendor {
+            id: Some(1),
+            client_id: 1,
+            name: "bank".to_string(),
+            host: host.to_string(),
+            port,
+            username: Some("upload".to_string()),
+            password: Some("hunter2".to_string()),
+            ssh_key: None,
+            ssh_key_password: None,
+            version: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_host() {
+        // Nothing listens on port 1 locally, so the connection is refused right away.
+        let vendor = vendor("127.0.0.1", 1);
+        let allow_private = AllowPrivateHosts(true);
+        let report = test_connection(&vendor, &[], Duration::from_secs(5), allow_private)
+            .await
+            .unwrap();
+        assert!(!report.ok);
+        assert!(!report.reachable);
+        assert!(report.error.unwrap().starts_with("Could not connect"));
+    }
+
+    #[tokio::test]
+    async fn test_internal_hosts_are_refused() {
+        let timeout = Duration::from_secs(5);
+        for host in [
+            "127.0.0.1",
+            "localhost",
+            "10.0.0.1",
+            "169.254.169.254",
+            "::1",
+        ] {
+            let report = test_connection(&vendor(host, 22), &[], timeout, AllowPrivateHosts(false))
+                .await
+                .unwrap();
+            assert!(!report.reachable, "{}", host);
+            let error = report.error.unwrap();
+            assert!(
+                error.contains("doesn't resolve to a public address"),
+                "{}",
+                error
+            );
+
+            let error = fetch_host_key(host, 22, timeout, AllowPrivateHosts(false))
+                .await
+                .unwrap_err();
+            assert!(matches!(error, AppError::Upstream(_)), "{}", host);
+        }
+    }
+}
@@ -1,48 +1,284 @@
-use std::collections::HashMap;
-
 use crate::errors::models::AppError;
+use crate::utils::auth::Caller;
+use crate::utils::etag::{ETag, IfMatch};
+use crate::utils::extract::{Json, Path, Query};
+use crate::utils::list::{ListQuery, Page};
+use crate::utils::response::{Created, NoContent, Updated};
+use crate::utils::validate::Valid;
+use crate::utils::version::V1Response;
 
-use super::models::{Vendor, VendorOverview, VendorRepo};
-use axum::extract::{Path, Query, State};
-use axum::Json;
+use super::connection::{
+    fetch_host_key, test_connection, AllowPrivateHosts, ConnectionParams, ConnectionTest, HostKey,
+};
+use super::models::{
+    host_key_not_found, HostKeysUpdate, NewHostKey, Vendor, VendorFilter, VendorHostKey,
+    VendorOverview, VendorRepo, MANUAL, PROBED,
+};
+use axum::extract::State;
+use axum::Extension;
 
+#[utoipa::path(
+    get, path = "/vendors", tag = "vendors",
+    params(ListQuery<VendorFilter>),
+    responses((status = 200, body = Page<Vendor>)),
+)]
 pub async fn get_vendors<T: VendorRepo>(
     State(repo): State<T>,
-    Query(params): Query<HashMap<String, String>>,
-) -> Result<Json<Vec<Vendor>>, AppError> {
-    let client_id_filter = params.get("client_id").and_then(|id| id.parse().ok());
-    let name_filter = params.get("name").cloned();
-
-    let vendors = repo.get_all(client_id_filter, name_filter).await?;
+    Extension(caller): Extension<Caller>,
+    query: ListQuery<VendorFilter>,
+) -> Result<Json<Page<Vendor>>, AppError> {
+    let vendors = repo.get_all(query, caller.client_filter()).await?;
     Ok(Json(vendors))
 }
 
+#[utoipa::path(
+    get, path = "/vendors/{id}", tag = "vendors",
+    params(("id" = i64, Path, description = "The id of the vendor")),
+    responses((status = 200, body = VendorOverview, headers(("ETag" = String)))),
+)]
 pub async fn get_vendor<T: VendorRepo>(
     State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
     Path(id): Path<i64>,
-) -> Result<Json<VendorOverview>, AppError> {
-    match repo.get(id).await? {
-        Some(vendor) => Ok(Json(vendor)),
-        None => Err(AppError::NotFound(format!(
-            "Vendor with id {} not found",
-            id
-        ))),
-    }
+) -> Result<(ETag, Json<VendorOverview>), AppError> {
+    let vendor = find_vendor(&repo, &caller, id).await?;
+    Ok((ETag(vendor.version), Json(vendor)))
 }
 
+#[utoipa::path(
+    put, path = "/vendors/{id}", tag = "vendors",
+    params(("id" = i64, Path, description = "The id of the vendor"), IfMatch),
+    request_body = Vendor,
+    responses((status = 200, body = VendorOverview, headers(("ETag" = String)))),
+)]
 pub async fn update_vendor<T: VendorRepo>(
     State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
     Path(id): Path<i64>,
-    Json(vendor): Json<Vendor>,
-) -> Result<(), AppError> {
-    repo.update(id, vendor).await?;
-    Ok(())
+    if_match: IfMatch,
+    Valid(vendor): Valid<Vendor>,
+) -> Result<Updated<(ETag, Json<VendorOverview>)>, AppError> {
+    find_vendor(&repo, &caller, id).await?;
+    // The update can move the vendor to another client, which must also be accessible.
+    caller.ensure_client(vendor.client_id)?;
+    repo.update(id, vendor, if_match).await?;
+    let vendor = find_vendor(&repo, &caller, id).await?;
+    Ok(Updated((ETag(vendor.version), Json(vendor))))
 }
 
+#[utoipa::path(
+    delete, path = "/vendors/{id}", tag = "vendors",
+    params(("id" = i64, Path, description = "The id of the vendor"), IfMatch),
+    responses((status = 204)),
+)]
 pub async fn delete_vendor<T: VendorRepo>(
     State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<i64>,
+    if_match: IfMatch,
+) -> Result<NoContent, AppError> {
+    find_vendor(&repo, &caller, id).await?;
+    repo.delete(id, if_match).await?;
+    Ok(NoContent)
+}
+
+/// Connects to the vendor's SFTP server with its stored credentials. Failures to connect
+/// or log in are part of the result, not errors, except for a host key that doesn't match
+/// the vendor's approved pins.
+#[utoipa::path(
+    post, path = "/vendors/{id}/test-connection", tag = "vendors",
+    params(("id" = i64, Path, description = "The id of the vendor"), ConnectionParams),
+    responses((status = 200, body = ConnectionTest)),
+)]
+pub async fn test_vendor_connection<T: VendorRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Extension(allow_private): Extension<AllowPrivateHosts>,
     Path(id): Path<i64>,
-) -> Result<(), AppError> {
-    repo.delete(id).await?;
-    Ok(())
+    Query(params): Query<ConnectionParams>,
+) -> Result<Json<ConnectionTest>, AppError> {
+    let timeout = params.timeout()?;
+    find_vendor(&repo, &caller, id).await?;
+    let vendor = repo
+        .get_with_credentials(id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Vendor with id {} not found", id)))?;
+
+    let pins: Vec<String> = repo
+        .get_host_keys(id)
+        .await?
+        .into_iter()
+        .filter(|host_key| host_key.approved_at.is_some())
+        .map(|host_key| host_key.fingerprint)
+        .collect();
+
+    let report = match test_connection(&vendor, &pins, timeout, allow_private).await {
+        Ok(report) => report,
+        Err(error) => {
+            tracing::warn!(caller = %caller.name, vendor_id = id, %error, "Vendor host key mismatch");
+            return Err(error);
+        }
+    };
+    tracing::info!(
+        caller = %caller.name,
+        vendor_id = id,
+        ok = report.ok,
+        host_key_pinned = report.host_key_pinned,
+        error = report.error.as_deref(),
+        "Vendor connection tested"
+    );
+    Ok(Json(report))
+}
+
+#[utoipa::path(
+    get, path = "/vendors/{id}/host-keys", tag = "vendors",
+    params(("id" = i64, Path, description = "The id of the vendor")),
+    responses((status = 200, body = Vec<VendorHostKey>)),
+)]
+pub async fn get_host_keys<T: VendorRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<i64>,
+) -> Result<Json<Vec<VendorHostKey>>, AppError> {
+    find_vendor(&repo, &caller, id).await?;
+    let host_keys = repo.get_host_keys(id).await?;
+    Ok(Json(host_keys))
+}
+
+#[utoipa::path(
+    get, path = "/vendors/{id}/host-keys/{key_id}", tag = "vendors",
+    params(("id" = i64, Path, description = "The id of the vendor"), ("key_id" = i64, Path, description = "The id of the host key")),
+    responses((status = 200, body = VendorHostKey)),
+)]
+pub async fn get_host_key<T: VendorRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Path((id, key_id)): Path<(i64, i64)>,
+) -> Result<Json<VendorHostKey>, AppError> {
+    find_vendor(&repo, &caller, id).await?;
+    // A vendor has a handful of pins at most.
+    let host_key = repo
+        .get_host_keys(id)
+        .await?
+        .into_iter()
+        .find(|host_key| host_key.id == key_id)
+        .ok_or_else(|| host_key_not_found(id, key_id))?;
+    Ok(Json(host_key))
+}
+
+/// Pins a key given in the OpenSSH format. It is approved right away.
+#[utoipa::path(
+    post, path = "/vendors/{id}/host-keys", tag = "vendors",
+    params(("id" = i64, Path, description = "The id of the vendor")),
+    request_body = NewHostKey,
+    responses((status = 201, body = VendorHostKey, headers(("Location" = String)))),
+)]
+pub async fn pin_host_key<T: VendorRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<i64>,
+    Json(body): Json<NewHostKey>,
+) -> Result<Created<Json<VendorHostKey>>, AppError> {
+    find_vendor(&repo, &caller, id).await?;
+    let host_key = HostKey::parse(&body.public_key)?;
+    let pinned = repo.add_host_key(id, host_key, MANUAL, true).await?;
+    Ok(Created {
+        location: format!("/vendors/{}/host-keys/{}", id, pinned.id),
+        response: Json(pinned),
+        v1: V1Response::Ok,
+    })
+}
+
+#[utoipa::path(
+    put, path = "/vendors/{id}/host-keys", tag = "vendors",
+    params(("id" = i64, Path, description = "The id of the vendor")),
+    request_body = HostKeysUpdate,
+    responses((status = 200, body = Vec<VendorHostKey>)),
+)]
+pub async fn replace_host_keys<T: VendorRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<i64>,
+    Json(body): Json<HostKeysUpdate>,
+) -> Result<Json<Vec<VendorHostKey>>, AppError> {
+    find_vendor(&repo, &caller, id).await?;
+    let host_keys = body
+        .public_keys
+        .iter()
+        .map(|public_key| HostKey::parse(public_key))
+        .collect::<Result<Vec<_>, _>>()?;
+    let pinned = repo.replace_host_keys(id, host_keys).await?;
+    Ok(Json(pinned))
+}
+
+/// Reads the key the vendor's server presents and adds it as a pin waiting for approval.
+#[utoipa::path(
+    post, path = "/vendors/{id}/host-keys/capture", tag = "vendors",
+    params(("id" = i64, Path, description = "The id of the vendor"), ConnectionParams),
+    responses((status = 201, body = VendorHostKey, headers(("Location" = String)))),
+)]
+pub async fn capture_host_key<T: VendorRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Extension(allow_private): Extension<AllowPrivateHosts>,
+    Path(id): Path<i64>,
+    Query(params): Query<ConnectionParams>,
+) -> Result<Created<Json<VendorHostKey>>, AppError> {
+    let timeout = params.timeout()?;
+    let vendor = find_vendor(&repo, &caller, id).await?;
+    let host_key = fetch_host_key(&vendor.host, vendor.port, timeout, allow_private).await?;
+    let pinned = repo.add_host_key(id, host_key, PROBED, false).await?;
+    Ok(Created {
+        location: format!("/vendors/{}/host-keys/{}", id, pinned.id),
+        response: Json(pinned),
+        v1: V1Response::Ok,
+    })
+}
+
+#[utoipa::path(
+    put, path = "/vendors/{id}/host-keys/{key_id}/approve", tag = "vendors",
+    params(("id" = i64, Path, description = "The id of the vendor"), ("key_id" = i64, Path, description = "The id of the host key")),
+    responses((status = 200, body = VendorHostKey)),
+)]
+pub async fn approve_host_key<T: VendorRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Path((id, key_id)): Path<(i64, i64)>,
+) -> Result<Json<VendorHostKey>, AppError> {
+    find_vendor(&repo, &caller, id).await?;
+    let approved = repo.approve_host_key(id, key_id).await?;
+    Ok(Json(approved))
+}
+
+#[utoipa::path(
+    delete, path = "/vendors/{id}/host-keys/{key_id}", tag = "vendors",
+    params(("id" = i64, Path, description = "The id of the vendor"), ("key_id" = i64, Path, description = "The id of the host key")),
+    responses((status = 204)),
+)]
+pub async fn delete_host_key<T: VendorRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Path((id, key_id)): Path<(i64, i64)>,
+) -> Result<NoContent, AppError> {
+    find_vendor(&repo, &caller, id).await?;
+    repo.delete_host_key(id, key_id).await?;
+    Ok(NoContent)
+}
+
+/// Loads a vendor and checks the caller has access to the client it belongs to.
+async fn find_vendor<T: VendorRepo>(
+    repo: &T,
+    caller: &Caller,
+    id: i64,
+) -> Result<VendorOverview, AppError> {
+    match repo.get(id).await? {
+        Some(vendor) => {
+            caller.ensure_client(vendor.client_id)?;
+            Ok(vendor)
+        }
+        None => Err(AppError::NotFound(format!(
+            "Vendor with id {} not found",
+            id
+        ))),
+    }
 }
@@ -1,55 +1,90 @@
-use crate::{errors::models::AppError, postgres::pool::PostgresRepo};
+use super::connection::HostKey;
+use crate::{
+    audit::models as audit,
+    errors::models::AppError,
+    postgres::pool::PostgresRepo,
+    utils::{
+        crypto::KeyRing,
+        etag::IfMatch,
+        filter::FilterField,
+        list::{ListFilter, ListQuery, Page},
+        validate::{rules, Errors, Validate},
+    },
+};
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use sqlx::{FromRow, PgConnection, Postgres, QueryBuilder};
+use utoipa::ToSchema;
+
+/// A host key read from the vendor's server by `POST /vendors/:id/host-keys/capture`.
+pub const PROBED: &str = "probe";
+/// A host key given by an admin.
+pub const MANUAL: &str = "manual";
 
 #[async_trait]
 pub trait VendorRepo: Send + Sync + Clone + 'static {
     async fn get_all(
         &self,
-        client_id: Option<i64>,
-        name: Option<String>,
-    ) -> Result<Vec<Vendor>, AppError>;
+        query: ListQuery<VendorFilter>,
+        client_ids: Option<Vec<i64>>,
+    ) -> Result<Page<Vendor>, AppError>;
     async fn get(&self, id: i64) -> Result<Option<VendorOverview>, AppError>;
-    async fn update(&self, id: i64, vendor: Vendor) -> Result<(), AppError>;
-    async fn delete(&self, id: i64) -> Result<(), AppError>;
+    /// The vendor with its decrypted credentials, for connecting to it.
+    async fn get_with_credentials(&self, id: i64) -> Result<Option<Vendor>, AppError>;
+    async fn update(&self, id: i64, vendor: Vendor, if_match: IfMatch) -> Result<(), AppError>;
+    async fn delete(&self, id: i64, if_match: IfMatch) -> Result<(), AppError>;
+    async fn get_host_keys(&self, vendor_id: i64) -> Result<Vec<VendorHostKey>, AppError>;
+    /// Pins a key. Adding a key that is already pinned returns the existing pin, approving
+    /// it if `approve` is set.
+    async fn add_host_key(
+        &self,
+        vendor_id: i64,
+        host_key: HostKey,
+        source: &str,
+        approve: bool,
+    ) -> Result<VendorHostKey, AppError>;
+    async fn approve_host_key(&self, vendor_id: i64, id: i64) -> Result<VendorHostKey, AppError>;
+    /// Replaces all the pins of a vendor with `host_keys`, approved.
+    async fn replace_host_keys(
+        &self,
+        vendor_id: i64,
+        host_keys: Vec<HostKey>,
+    ) -> Result<Vec<VendorHostKey>, AppError>;
+    async fn delete_host_key(&self, vendor_id: i64, id: i64) -> Result<(), AppError>;
 }
 
 #[async_trait]
 impl VendorRepo for PostgresRepo {
     async fn get_all(
         &self,
-        client_id: Option<i64>,
-        name: Option<String>,
-    ) -> Result<Vec<Vendor>, AppError> {
-        let mut query = "SELECT * FROM vendors".to_string();
-        let mut conditions = Vec::new();
-
-        if let Some(client_id) = client_id {
-            conditions.push(format!("client_id = {}", client_id));
-        }
-
-        if let Some(name) = name {
-            conditions.push(format!("name LIKE '%{}%'", name));
-        }
-
-        if !conditions.is_empty() {
-            query.push_str(" WHERE ");
-            query.push_str(&conditions.join(" AND "));
-        }
+        query: ListQuery<VendorFilter>,
+        client_ids: Option<Vec<i64>>,
+    ) -> Result<Page<Vendor>, AppError> {
+        let mut count = QueryBuilder::new("SELECT COUNT(*) FROM vendors WHERE TRUE");
+        push_filters(&mut count, &query, &client_ids);
+        let total: i64 = count.build_query_scalar().fetch_one(&self.pool).await?;
 
-        let vendors = sqlx::query_as::<_, Vendor>(&query)
+        let mut select = QueryBuilder::new("SELECT * FROM vendors WHERE TRUE");
+        push_filters(&mut select, &query, &client_ids);
+        query.push_cursor(&mut select);
+        query.push_order_and_limit(&mut select);
+        let vendors = select
+            .build_query_as::<VendorRecord>()
             .fetch_all(&self.pool)
-            .await?;
+            .await?
+            .into_iter()
+            .map(|vendor| vendor.decrypt(&self.keys))
+            .collect::<Result<Vec<_>, _>>()?;
 
-        println!("Query: {}", query);
-        Ok(vendors)
+        query.page(vendors, total)
     }
 
     async fn get(&self, id: i64) -> Result<Option<VendorOverview>, AppError> {
         let vendor = sqlx::query_as!(
             VendorOverview,
-            "SELECT id, client_id, name, host, port FROM vendors WHERE id = $1",
+            "SELECT id, client_id, name, host, port, version FROM vendors WHERE id = $1
+                AND client_id IN (SELECT id FROM clients WHERE deleted_at IS NULL)",
             id
         )
         .fetch_optional(&self.pool)
@@ -57,8 +92,27 @@ impl VendorRepo for PostgresRepo {
         Ok(vendor)
     }
 
-    async fn update(&self, id: i64, vendor: Vendor) -> Result<(), AppError> {
-        let rows_affected = sqlx::query!(
+    async fn get_with_credentials(&self, id: i64) -> Result<Option<Vendor>, AppError> {
+        let record = sqlx::query_as::<_, VendorRecord>(
+            "SELECT * FROM vendors WHERE id = $1
+                AND client_id IN (SELECT id FROM clients WHERE deleted_at IS NULL)",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        record.map(|record| record.decrypt(&self.keys)).transpose()
+    }
+
+    async fn update(&self, id: i64, vendor: Vendor, if_match: IfMatch) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        let before = lock_vendor(&mut tx, &self.keys, id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Vendor with id {} not found", id)))?;
+        if_match.check("Vendor", id, before.version)?;
+
+        let secrets = VendorSecrets::encrypt(&vendor, &self.keys)?;
+        sqlx::query!(
             "UPDATE vendors SET
                 client_id = $1,
                 name = $2,
@@ -67,50 +121,265 @@ impl VendorRepo for PostgresRepo {
                 username = $5,
                 password = $6,
                 ssh_key = $7,
-                ssh_key_password = $8
-             WHERE id = $9",
+                ssh_key_password = $8,
+                key_id = $9,
+                version = version + 1
+             WHERE id = $10",
             vendor.client_id,
             vendor.name,
             vendor.host,
             vendor.port,
             vendor.username,
-            vendor.password,
-            vendor.ssh_key,
-            vendor.ssh_key_password,
+            secrets.password,
+            secrets.ssh_key,
+            secrets.ssh_key_password,
+            secrets.key_id,
             id
         )
-        .execute(&self.pool)
+        .execute(&mut *tx)
+        .await?;
+
+        let after = Vendor {
+            id: Some(id),
+            version: before.version + 1,
+            ..vendor
+        };
+        audit::record(&mut tx, "update", "vendor", id, Some(&before), Some(&after)).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn delete(&self, id: i64, if_match: IfMatch) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        let before = lock_vendor(&mut tx, &self.keys, id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Vendor with id {} not found", id)))?;
+        if_match.check("Vendor", id, before.version)?;
+
+        sqlx::query!("DELETE FROM vendors WHERE id = $1", id)
+            .execute(&mut *tx)
+            .await?;
+
+        audit::record(&mut tx, "delete", "vendor", id, Some(&before), None).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_host_keys(&self, vendor_id: i64) -> Result<Vec<VendorHostKey>, AppError> {
+        let host_keys = sqlx::query_as!(
+            VendorHostKey,
+            "SELECT * FROM vendor_host_keys WHERE vendor_id = $1 ORDER BY id",
+            vendor_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(host_keys)
+    }
+
+    async fn add_host_key(
+        &self,
+        vendor_id: i64,
+        host_key: HostKey,
+        source: &str,
+        approve: bool,
+    ) -> Result<VendorHostKey, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let before = lock_host_keys(&mut tx, vendor_id).await?;
+
+        // Re-pinning a known key only ever approves it, it never withdraws an approval.
+        let pinned = sqlx::query_as!(
+            VendorHostKey,
+            "INSERT INTO vendor_host_keys
+                (vendor_id, key_type, public_key, fingerprint, source, approved_at)
+            VALUES ($1, $2, $3, $4, $5, CASE WHEN $6 THEN now() END)
+            ON CONFLICT ON CONSTRAINT vendor_host_keys_fingerprint_key DO UPDATE
+                SET approved_at = COALESCE(
+                    vendor_host_keys.approved_at,
+                    CASE WHEN $6 THEN now() END
+                )
+            RETURNING *",
+            vendor_id,
+            host_key.key_type,
+            host_key.public_key,
+            host_key.fingerprint,
+            source,
+            approve
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let after = lock_host_keys(&mut tx, vendor_id).await?;
+        if after != before {
+            audit::record(
+                &mut tx,
+                "add_host_key",
+                "vendor",
+                vendor_id,
+                Some(&before),
+                Some(&after),
+            )
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(pinned)
+    }
+
+    async fn approve_host_key(&self, vendor_id: i64, id: i64) -> Result<VendorHostKey, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let before = lock_host_keys(&mut tx, vendor_id).await?;
+
+        let approved = sqlx::query_as!(
+            VendorHostKey,
+            "UPDATE vendor_host_keys SET approved_at = COALESCE(approved_at, now())
+            WHERE id = $1 AND vendor_id = $2
+            RETURNING *",
+            id,
+            vendor_id
+        )
+        .fetch_optional(&mut *tx)
         .await?
-        .rows_affected();
-
-        if rows_affected == 0 {
-            Err(AppError::NotFound(format!(
-                "Vendor with id {} not found",
-                id
-            )))
-        } else {
-            Ok(())
+        .ok_or_else(|| host_key_not_found(vendor_id, id))?;
+
+        let after = lock_host_keys(&mut tx, vendor_id).await?;
+        if after != before {
+            audit::record(
+                &mut tx,
+                "approve_host_key",
+                "vendor",
+                vendor_id,
+                Some(&before),
+                Some(&after),
+            )
+            .await?;
         }
+        tx.commit().await?;
+        Ok(approved)
     }
 
-    async fn delete(&self, id: i64) -> Result<(), AppError> {
-        let rows_affected = sqlx::query!("DELETE FROM vendors WHERE id = $1", id)
-            .execute(&self.pool)
-            .await?
-            .rows_affected();
-
-        if rows_affected == 0 {
-            Err(AppError::NotFound(format!(
-                "Vendor with id {} not found",
-                id
-            )))
-        } else {
-            Ok(())
+    async fn replace_host_keys(
+        &self,
+        vendor_id: i64,
+        host_keys: Vec<HostKey>,
+    ) -> Result<Vec<VendorHostKey>, AppError> {
+        let mut tx = self.pool.begin().await?;
+        let before = lock_host_keys(&mut tx, vendor_id).await?;
+
+        sqlx::query!(
+            "DELETE FROM vendor_host_keys WHERE vendor_id = $1",
+            vendor_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        for host_key in &host_keys {
+            sqlx::query!(
+                "INSERT INTO vendor_host_keys
+                    (vendor_id, key_type, public_key, fingerprint, source, approved_at)
+                VALUES ($1, $2, $3, $4, $5, now())
+                ON CONFLICT ON CONSTRAINT vendor_host_keys_fingerprint_key DO NOTHING",
+                vendor_id,
+                host_key.key_type,
+                host_key.public_key,
+                host_key.fingerprint,
+                MANUAL
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        let after = lock_host_keys(&mut tx, vendor_id).await?;
+        audit::record(
+            &mut tx,
+            "replace_host_keys",
+            "vendor",
+            vendor_id,
+            Some(&before),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(after.host_keys)
+    }
+
+    async fn delete_host_key(&self, vendor_id: i64, id: i64) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await?;
+        let before = lock_host_keys(&mut tx, vendor_id).await?;
+
+        let deleted = sqlx::query!(
+            "DELETE FROM vendor_host_keys WHERE id = $1 AND vendor_id = $2",
+            id,
+            vendor_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(host_key_not_found(vendor_id, id));
         }
+
+        let after = lock_host_keys(&mut tx, vendor_id).await?;
+        audit::record(
+            &mut tx,
+            "delete_host_key",
+            "vendor",
+            vendor_id,
+            Some(&before),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(())
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, FromRow)]
+/// Locks a vendor and loads its pins inside a transaction, for the audit log. Vendors of
+/// deleted clients aren't found.
+async fn lock_host_keys(conn: &mut PgConnection, vendor_id: i64) -> Result<HostKeyPins, AppError> {
+    sqlx::query_scalar!(
+        "SELECT id FROM vendors WHERE id = $1
+            AND client_id IN (SELECT id FROM clients WHERE deleted_at IS NULL)
+        FOR UPDATE",
+        vendor_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Vendor with id {} not found", vendor_id)))?;
+
+    let host_keys = sqlx::query_as!(
+        VendorHostKey,
+        "SELECT * FROM vendor_host_keys WHERE vendor_id = $1 ORDER BY id",
+        vendor_id
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(HostKeyPins { host_keys })
+}
+
+pub fn host_key_not_found(vendor_id: i64, id: i64) -> AppError {
+    AppError::NotFound(format!(
+        "Host key with id {} for vendor with id {} not found",
+        id, vendor_id
+    ))
+}
+
+/// Loads and decrypts a vendor inside a transaction, locking the row for the rest of it.
+/// Vendors of deleted clients aren't found.
+pub async fn lock_vendor(
+    conn: &mut PgConnection,
+    keys: &KeyRing,
+    id: i64,
+) -> Result<Option<Vendor>, AppError> {
+    let record = sqlx::query_as::<_, VendorRecord>(
+        "SELECT * FROM vendors WHERE id = $1
+            AND client_id IN (SELECT id FROM clients WHERE deleted_at IS NULL)
+        FOR UPDATE",
+    )
+    .bind(id)
+    .fetch_optional(conn)
+    .await?;
+
+    record.map(|record| record.decrypt(keys)).transpose()
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, FromRow, ToSchema)]
 pub struct Vendor {
     pub id: Option<i64>, // Use i64 to match Postgres BIGSERIAL
     pub client_id: i64,
@@ -121,13 +390,184 @@ pub struct Vendor {
     pub password: Option<String>,
     pub ssh_key: Option<String>,
     pub ssh_key_password: Option<String>,
+    /// Bumped by every change, see [`ETag`](crate::utils::etag::ETag).
+    #[serde(default, skip_deserializing)]
+    pub version: i64,
 }
 
-#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, FromRow)]
+impl Validate for Vendor {
+    fn validate(&self, errors: &mut Errors) {
+        errors.check("name", rules::required(&self.name));
+        errors.check("host", rules::host(&self.host));
+        errors.check("port", rules::port(self.port));
+        if let Some(ssh_key) = &self.ssh_key {
+            let passphrase = self.ssh_key_password.as_deref();
+            errors.check("ssh_key", rules::private_key(ssh_key, passphrase));
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, FromRow, ToSchema)]
 pub struct VendorOverview {
     pub id: i64,
     pub client_id: i64,
     pub name: String,
     pub host: String,
     pub port: i32,
+    pub version: i64,
+}
+
+/// A host key the vendor's server is expected to present. Only approved keys are
+/// enforced; keys captured from the server wait for an admin to approve them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, FromRow, ToSchema)]
+pub struct VendorHostKey {
+    pub id: i64,
+    pub vendor_id: i64,
+    pub key_type: String,
+    pub public_key: String,
+    pub fingerprint: String,
+    pub source: String,
+    pub created_at: DateTime<Utc>,
+    pub approved_at: Option<DateTime<Utc>>,
+}
+
+/// The pins of a vendor, as recorded in the audit log.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct HostKeyPins {
+    pub host_keys: Vec<VendorHostKey>,
+}
+
+/// The body of `POST /vendors/:id/host-keys`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct NewHostKey {
+    pub public_key: String,
+}
+
+/// The body of `PUT /vendors/:id/host-keys`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct HostKeysUpdate {
+    pub public_keys: Vec<String>,
+}
+
+/// Filters for `GET /vendors`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct VendorFilter;
+
+impl ListFilter for VendorFilter {
+    const SORT_FIELDS: &'static [&'static str] = &["id", "client_id", "name", "host", "port"];
+    const FILTER_FIELDS: &'static [FilterField] = &[
+        FilterField::int("id"),
+        FilterField::int("client_id"),
+        FilterField::text("name"),
+        FilterField::text("host"),
+        FilterField::int("port"),
+    ];
+    const SOFT_DELETED: bool = true;
+}
+
+/// Appends the query's filters, restricted to `client_ids` for agent callers.
+fn push_filters(
+    builder: &mut QueryBuilder<'_, Postgres>,
+    query: &ListQuery<VendorFilter>,
+    client_ids: &Option<Vec<i64>>,
+) {
+    query.filters.push(builder);
+    if !query.include_deleted {
+        builder.push(" AND client_id IN (SELECT id FROM clients WHERE deleted_at IS NULL)");
+    }
+    if let Some(client_ids) = client_ids {
+        builder
+            .push(" AND client_id = ANY(")
+            .push_bind(client_ids.clone())
+            .push(")");
+    }
+}
+
+/// A vendor row as stored in Postgres, with its secrets still encrypted.
+#[derive(Debug, Clone, FromRow)]
+pub struct VendorRecord {
+    pub id: i64,
+    pub client_id: i64,
+    pub name: String,
+    pub host: String,
+    pub port: i32,
+    pub username: Option<String>,
+    pub password: Option<Vec<u8>>,
+    pub ssh_key: Option<Vec<u8>>,
+    pub ssh_key_password: Option<Vec<u8>>,
+    pub key_id: Option<String>,
+    pub version: i64,
+}
+
+impl VendorRecord {
+    pub fn decrypt(self, keys: &KeyRing) -> Result<Vendor, AppError> {
+        let key_id = self.key_id.as_deref();
+        Ok(Vendor {
+            id: Some(self.id),
+            client_id: self.client_id,
+            name: self.name,
+            host: self.host,
+            port: self.port,
+            username: self.username,
+            password: keys.decrypt_opt(key_id, self.password.as_deref())?,
+            ssh_key: keys.decrypt_opt(key_id, self.ssh_key.as_deref())?,
+            ssh_key_password: keys.decrypt_opt(key_id, self.ssh_key_password.as_deref())?,
+            version: self.version,
+        })
+    }
+}
+
+/// The encrypted form of a vendor's credentials, ready to be written.
+#[derive(Debug, Clone)]
+pub struct VendorSecrets {
+    pub password: Option<Vec<u8>>,
+    pub ssh_key: Option<Vec<u8>>,
+    pub ssh_key_password: Option<Vec<u8>>,
+    pub key_id: String,
+}
+
+impl VendorSecrets {
+    pub fn encrypt(vendor: &Vendor, keys: &KeyRing) -> Result<Self, AppError> {
+        Ok(Self {
+            password: keys.encrypt_opt(vendor.password.as_deref())?,
+            ssh_key: keys.encrypt_opt(vendor.ssh_key.as_deref())?,
+            ssh_key_password: keys.encrypt_opt(vendor.ssh_key_password.as_deref())?,
+            key_id: keys.active_key_id().to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list_sql(query_string: &str) -> Result<String, AppError> {
+        let query = ListQuery::<VendorFilter>::from_query_string(query_string)?;
+        let mut builder = QueryBuilder::new("SELECT * FROM vendors WHERE TRUE");
+        push_filters(&mut builder, &query, &Some(vec![1]));
+        Ok(builder.sql().to_string())
+    }
+
+    #[test]
+    fn test_hostile_name_filter_is_bound() {
+        // GET /vendors?name=x' OR '1'='1' --
+        let sql = list_sql("name=x%27%20OR%20%271%27%3D%271%27%20--").unwrap();
+        assert_eq!(
+            sql,
+            "SELECT * FROM vendors WHERE TRUE AND strpos(name, $1) > 0 \
+             AND client_id IN (SELECT id FROM clients WHERE deleted_at IS NULL) \
+             AND client_id = ANY($2)"
+        );
+
+        let sql = list_sql("name__icontains=%27%3B%20DROP%20TABLE%20vendors%3B%20--").unwrap();
+        assert!(!sql.contains("DROP"));
+    }
+
+    #[test]
+    fn test_rejects_hostile_filter_names() {
+        assert!(list_sql("name%3B%20DROP%20TABLE%20vendors=x").is_err());
+        assert!(list_sql("password=x").is_err());
+        assert!(list_sql("client_id=1%20OR%201%3D1").is_err());
+        assert!(list_sql("sort=name%3B%20DROP%20TABLE%20vendors").is_err());
+    }
 }
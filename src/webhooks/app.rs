@@ -0,0 +1,42 @@
+use std::sync::Arc;
+
+use super::{
+    handlers::{
+        create_webhook, delete_webhook, get_dead_letters, get_webhook, get_webhooks,
+        retry_dead_letter, update_webhook,
+    },
+    models::WebhookRepo,
+};
+use crate::config::models::WebhookConfig;
+use axum::{
+    routing::{delete, get, post, put},
+    Extension, Router,
+};
+
+/// Admin endpoints for managing webhook subscriptions. All of them need the `admin` scope.
+/// ```text
+/// GET /webhooks
+/// POST /webhooks
+/// GET /webhooks/:id
+/// PUT /webhooks/:id
+/// DELETE /webhooks/:id
+/// GET /webhooks/:id/dead-letters
+/// POST /webhooks/:id/dead-letters/:dead_letter_id/retry
+/// ```
+/// Urls have to be https and point at a public address, unless the config allows
+/// private urls.
+pub fn router<T: WebhookRepo>(repo: T, config: WebhookConfig) -> Router {
+    Router::new()
+        .route("/webhooks", get(get_webhooks::<T>))
+        .route("/webhooks", post(create_webhook::<T>))
+        .route("/webhooks/:id", get(get_webhook::<T>))
+        .route("/webhooks/:id", put(update_webhook::<T>))
+        .route("/webhooks/:id", delete(delete_webhook::<T>))
+        .route("/webhooks/:id/dead-letters", get(get_dead_letters::<T>))
+        .route(
+            "/webhooks/:id/dead-letters/:dead_letter_id/retry",
+            post(retry_dead_letter::<T>),
+        )
+        .layer(Extension(Arc::new(config)))
+        .with_state(repo)
+}
@@ -0,0 +1,123 @@
+use std::sync::Arc;
+
+use super::models::{check_target, CreatedWebhook, DeadLetter, NewWebhook, Webhook, WebhookRepo};
+use crate::{
+    config::models::WebhookConfig,
+    errors::models::AppError,
+    utils::{
+        auth::Caller,
+        extract::{Json, Path},
+    },
+};
+use axum::{extract::State, Extension};
+
+#[utoipa::path(
+    get, path = "/webhooks", tag = "webhooks",
+    responses((status = 200, body = Vec<Webhook>)),
+)]
+pub async fn get_webhooks<T: WebhookRepo>(
+    State(repo): State<T>,
+) -> Result<Json<Vec<Webhook>>, AppError> {
+    let webhooks = repo.get_all().await?;
+    Ok(Json(webhooks))
+}
+
+#[utoipa::path(
+    post, path = "/webhooks", tag = "webhooks",
+    request_body = NewWebhook,
+    responses((status = 200, body = CreatedWebhook)),
+)]
+pub async fn create_webhook<T: WebhookRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Extension(config): Extension<Arc<WebhookConfig>>,
+    Json(webhook): Json<NewWebhook>,
+) -> Result<Json<CreatedWebhook>, AppError> {
+    check_target(&webhook.url, &config)?;
+    let created = repo.create(webhook).await?;
+    tracing::info!(
+        caller = %caller.name,
+        webhook_id = created.webhook.id,
+        "Webhook created"
+    );
+    Ok(Json(created))
+}
+
+#[utoipa::path(
+    get, path = "/webhooks/{id}", tag = "webhooks",
+    params(("id" = i64, Path, description = "The id of the webhook")),
+    responses((status = 200, body = Webhook)),
+)]
+pub async fn get_webhook<T: WebhookRepo>(
+    State(repo): State<T>,
+    Path(id): Path<i64>,
+) -> Result<Json<Webhook>, AppError> {
+    match repo.get(id).await? {
+        Some(webhook) => Ok(Json(webhook)),
+        None => Err(AppError::NotFound(format!(
+            "Webhook with id {} not found",
+            id
+        ))),
+    }
+}
+
+/// Replaces the url and event types. The secret only changes when a new one is given.
+#[utoipa::path(
+    put, path = "/webhooks/{id}", tag = "webhooks",
+    params(("id" = i64, Path, description = "The id of the webhook")),
+    request_body = NewWebhook,
+    responses((status = 200, body = Webhook)),
+)]
+pub async fn update_webhook<T: WebhookRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Extension(config): Extension<Arc<WebhookConfig>>,
+    Path(id): Path<i64>,
+    Json(webhook): Json<NewWebhook>,
+) -> Result<Json<Webhook>, AppError> {
+    check_target(&webhook.url, &config)?;
+    let updated = repo.update(id, webhook).await?;
+    tracing::info!(caller = %caller.name, webhook_id = id, "Webhook updated");
+    Ok(Json(updated))
+}
+
+#[utoipa::path(
+    delete, path = "/webhooks/{id}", tag = "webhooks",
+    params(("id" = i64, Path, description = "The id of the webhook")),
+    responses((status = 200)),
+)]
+pub async fn delete_webhook<T: WebhookRepo>(
+    State(repo): State<T>,
+    Extension(caller): Extension<Caller>,
+    Path(id): Path<i64>,
+) -> Result<(), AppError> {
+    repo.delete(id).await?;
+    tracing::info!(caller = %caller.name, webhook_id = id, "Webhook deleted");
+    Ok(())
+}
+
+#[utoipa::path(
+    get, path = "/webhooks/{id}/dead-letters", tag = "webhooks",
+    params(("id" = i64, Path, description = "The id of the webhook")),
+    responses((status = 200, body = Vec<DeadLetter>)),
+)]
+pub async fn get_dead_letters<T: WebhookRepo>(
+    State(repo): State<T>,
+    Path(id): Path<i64>,
+) -> Result<Json<Vec<DeadLetter>>, AppError> {
+    let dead_letters = repo.get_dead_letters(id).await?;
+    Ok(Json(dead_letters))
+}
+
+/// Puts a dead letter back into the outbox, with a fresh set of attempts.
+#[utoipa::path(
+    post, path = "/webhooks/{id}/dead-letters/{dead_letter_id}/retry", tag = "webhooks",
+    params(("id" = i64, Path, description = "The id of the webhook"), ("dead_letter_id" = i64, Path, description = "The id of the dead letter")),
+    responses((status = 200)),
+)]
+pub async fn retry_dead_letter<T: WebhookRepo>(
+    State(repo): State<T>,
+    Path((id, dead_letter_id)): Path<(i64, i64)>,
+) -> Result<(), AppError> {
+    repo.retry_dead_letter(id, dead_letter_id).await
+}
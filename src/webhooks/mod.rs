@@ -0,0 +1,3 @@
+pub mod app;
+pub mod handlers;
+pub mod models;
@@ -0,0 +1,761 @@
+use std::{future::Future, net::IpAddr, sync::Arc, time::Duration as StdDuration};
+
+use crate::{
+    api_keys::models::generate_token,
+    audit::models::{redact_fields, AuditEvent},
+    config::models::WebhookConfig,
+    errors::models::AppError,
+    postgres::pool::PostgresRepo,
+    utils::{
+        crypto::KeyRing,
+        net::{is_public, resolve_public},
+    },
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use sqlx::{FromRow, PgConnection};
+use tokio::time::MissedTickBehavior;
+use utoipa::ToSchema;
+
+/// Every event a subscription can ask for, named `{entity_type}.{action}` after the
+/// audit event it comes from.
+pub const EVENT_TYPES: &[&str] = &[
+    "client.create",
+    "client.update",
+    "client.delete",
+    "client.restore",
+    "client.purge",
+    "vendor.create",
+    "vendor.update",
+    "vendor.delete",
+    "vendor.add_host_key",
+    "vendor.approve_host_key",
+    "vendor.replace_host_keys",
+    "vendor.delete_host_key",
+    "sftp.create",
+    "sftp.update",
+    "sftp.delete",
+    "sftp.reset_keys",
+    "sftp.set_public_keys",
+    "sftp_key.expire",
+    "agent.create",
+    "agent.update",
+    "agent.delete",
+    "agent.add_client",
+];
+
+/// Sent with every delivery: `t=<unix timestamp>,v1=<hex HMAC-SHA256>`. See [`sign`].
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+/// The id of the event, the same for every attempt. Receivers can use it to drop duplicates.
+pub const EVENT_ID_HEADER: &str = "X-Webhook-Id";
+pub const EVENT_TYPE_HEADER: &str = "X-Webhook-Event";
+
+/// Interface to the webhook tables.
+/// ```text
+/// get_all: Get all subscriptions.
+/// get: Get a subscription by id.
+/// create: Create a subscription. The signing secret is only ever returned here.
+/// update: Change the url and event types of a subscription, and its secret if one is given.
+/// delete: Delete a subscription with its pending and dead deliveries.
+/// claim_deliveries: Take deliveries that are due, hiding them from other dispatchers for `lease`.
+/// delivered: Remove a delivery from the outbox.
+/// failed: Schedule a delivery for another attempt at `retry_at`, or dead letter it.
+/// get_dead_letters: Get the deliveries of a subscription that ran out of attempts.
+/// retry_dead_letter: Put a dead letter back into the outbox.
+/// ```
+/// Events are added to the outbox through [`enqueue`], inside the transaction of the
+/// mutation they describe.
+#[async_trait]
+pub trait WebhookRepo: Send + Sync + Clone + 'static {
+    async fn get_all(&self) -> Result<Vec<Webhook>, AppError>;
+    async fn get(&self, id: i64) -> Result<Option<Webhook>, AppError>;
+    async fn create(&self, webhook: NewWebhook) -> Result<CreatedWebhook, AppError>;
+    async fn update(&self, id: i64, webhook: NewWebhook) -> Result<Webhook, AppError>;
+    async fn delete(&self, id: i64) -> Result<(), AppError>;
+    async fn claim_deliveries(
+        &self,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<Delivery>, AppError>;
+    async fn delivered(&self, id: i64) -> Result<(), AppError>;
+    async fn failed(
+        &self,
+        id: i64,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<(), AppError>;
+    async fn get_dead_letters(&self, webhook_id: i64) -> Result<Vec<DeadLetter>, AppError>;
+    async fn retry_dead_letter(&self, webhook_id: i64, id: i64) -> Result<(), AppError>;
+}
+
+#[async_trait]
+impl WebhookRepo for PostgresRepo {
+    async fn get_all(&self) -> Result<Vec<Webhook>, AppError> {
+        let webhooks = sqlx::query_as!(
+            Webhook,
+            "SELECT id, url, event_types, created_at FROM webhook_subscriptions ORDER BY id"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(webhooks)
+    }
+
+    async fn get(&self, id: i64) -> Result<Option<Webhook>, AppError> {
+        let webhook = sqlx::query_as!(
+            Webhook,
+            "SELECT id, url, event_types, created_at FROM webhook_subscriptions WHERE id = $1",
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(webhook)
+    }
+
+    async fn create(&self, webhook: NewWebhook) -> Result<CreatedWebhook, AppError> {
+        webhook.validate()?;
+
+        let secret = webhook.secret.unwrap_or_else(generate_token);
+        let created = sqlx::query_as!(
+            Webhook,
+            "INSERT INTO webhook_subscriptions (url, event_types, secret, key_id)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, url, event_types, created_at",
+            webhook.url,
+            &webhook.event_types,
+            self.keys.encrypt(&secret)?,
+            self.keys.active_key_id(),
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(CreatedWebhook {
+            webhook: created,
+            secret,
+        })
+    }
+
+    async fn update(&self, id: i64, webhook: NewWebhook) -> Result<Webhook, AppError> {
+        webhook.validate()?;
+
+        let secret = webhook
+            .secret
+            .as_deref()
+            .map(|secret| self.keys.encrypt(secret))
+            .transpose()?;
+        let updated = sqlx::query_as!(
+            Webhook,
+            "UPDATE webhook_subscriptions SET
+                url = $2,
+                event_types = $3,
+                secret = COALESCE($4, secret),
+                key_id = CASE WHEN $4::BYTEA IS NULL THEN key_id ELSE $5 END
+            WHERE id = $1
+            RETURNING id, url, event_types, created_at",
+            id,
+            webhook.url,
+            &webhook.event_types,
+            secret,
+            self.keys.active_key_id(),
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        updated.ok_or_else(|| not_found(id))
+    }
+
+    async fn delete(&self, id: i64) -> Result<(), AppError> {
+        let rows_affected = sqlx::query!("DELETE FROM webhook_subscriptions WHERE id = $1", id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        if rows_affected == 0 {
+            Err(not_found(id))
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn claim_deliveries(
+        &self,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<Delivery>, AppError> {
+        // Pushing next_attempt_at past the lease hides the rows from other dispatchers.
+        // If this one dies mid-delivery, they are picked up again once the lease is over.
+        let records = sqlx::query_as!(
+            DeliveryRecord,
+            "UPDATE webhook_outbox o SET next_attempt_at = now() + $2::INTERVAL
+            FROM webhook_subscriptions s
+            WHERE s.id = o.subscription_id AND o.id IN (
+                SELECT id FROM webhook_outbox
+                WHERE next_attempt_at <= now()
+                ORDER BY id
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING o.id, o.subscription_id, s.url, s.secret, s.key_id, o.event_id,
+                o.event_type, o.payload, o.attempts",
+            limit,
+            sqlx::postgres::types::PgInterval::try_from(lease).map_err(|_| AppError::Unknown)?,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut deliveries = records
+            .into_iter()
+            .map(|record| record.decrypt(&self.keys))
+            .collect::<Result<Vec<_>, _>>()?;
+        deliveries.sort_by_key(|delivery| delivery.id);
+        Ok(deliveries)
+    }
+
+    async fn delivered(&self, id: i64) -> Result<(), AppError> {
+        sqlx::query!("DELETE FROM webhook_outbox WHERE id = $1", id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn failed(
+        &self,
+        id: i64,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<(), AppError> {
+        match retry_at {
+            Some(retry_at) => sqlx::query!(
+                "UPDATE webhook_outbox
+                SET attempts = attempts + 1, last_error = $2, next_attempt_at = $3
+                WHERE id = $1",
+                id,
+                error,
+                retry_at,
+            )
+            .execute(&self.pool)
+            .await?,
+            None => sqlx::query!(
+                "WITH moved AS (DELETE FROM webhook_outbox WHERE id = $1 RETURNING *)
+                INSERT INTO webhook_dead_letters
+                    (subscription_id, event_id, event_type, payload, created_at, attempts, last_error)
+                SELECT subscription_id, event_id, event_type, payload, created_at, attempts + 1, $2
+                FROM moved",
+                id,
+                error,
+            )
+            .execute(&self.pool)
+            .await?,
+        };
+        Ok(())
+    }
+
+    async fn get_dead_letters(&self, webhook_id: i64) -> Result<Vec<DeadLetter>, AppError> {
+        let dead_letters = sqlx::query_as!(
+            DeadLetter,
+            "SELECT id, subscription_id, event_id, event_type, payload, created_at, attempts,
+                last_error, failed_at
+            FROM webhook_dead_letters WHERE subscription_id = $1 ORDER BY id",
+            webhook_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(dead_letters)
+    }
+
+    async fn retry_dead_letter(&self, webhook_id: i64, id: i64) -> Result<(), AppError> {
+        let rows_affected = sqlx::query!(
+            "WITH moved AS (
+                DELETE FROM webhook_dead_letters WHERE id = $1 AND subscription_id = $2
+                RETURNING *
+            )
+            INSERT INTO webhook_outbox (subscription_id, event_id, event_type, payload, created_at)
+            SELECT subscription_id, event_id, event_type, payload, created_at FROM moved",
+            id,
+            webhook_id,
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        if rows_affected == 0 {
+            Err(AppError::NotFound(format!(
+                "Dead letter with id {} for webhook with id {} not found",
+                id, webhook_id
+            )))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+fn not_found(id: i64) -> AppError {
+    AppError::NotFound(format!("Webhook with id {} not found", id))
+}
+
+/// Adds an event to the outbox of every subscription that wants it. Called by
+/// `audit::record`, so it runs in the transaction of the mutation.
+pub async fn enqueue(conn: &mut PgConnection, event: &WebhookEvent) -> Result<(), AppError> {
+    let payload = serde_json::to_value(event).map_err(|_| AppError::Unknown)?;
+    sqlx::query!(
+        "INSERT INTO webhook_outbox (subscription_id, event_id, event_type, payload)
+        SELECT id, $1, $2, $3 FROM webhook_subscriptions
+        WHERE event_types = '{}' OR $2 = ANY(event_types)",
+        event.id,
+        event.event_type,
+        payload,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Whether a subscription to `event_types` gets events of `event_type`.
+pub fn subscribed(event_types: &[String], event_type: &str) -> bool {
+    event_types.is_empty() || event_types.iter().any(|t| t == event_type)
+}
+
+/// Signs a payload as sent in [`SIGNATURE_HEADER`]. The MAC covers `{timestamp}.{body}`,
+/// so receivers can reject old deliveries by checking the timestamp.
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!(
+        "t={},v1={}",
+        timestamp,
+        hex::encode(mac.finalize().into_bytes())
+    )
+}
+
+/// How long to wait before the next attempt, after `attempts` failed ones:
+/// `initial_backoff_seconds` doubled for every attempt after the first, up to
+/// `max_backoff_seconds`.
+pub fn backoff(config: &WebhookConfig, attempts: u32) -> Duration {
+    let factor = 2u64.saturating_pow(attempts.saturating_sub(1));
+    let seconds = config
+        .initial_backoff_seconds
+        .saturating_mul(factor)
+        .min(config.max_backoff_seconds);
+    Duration::seconds(seconds as i64)
+}
+
+/// What a dispatch pass did.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct DispatchReport {
+    pub delivered: u32,
+    pub retrying: u32,
+    pub dead_lettered: u32,
+}
+
+/// Builds the HTTP client used for deliveries. Unless `allow_private_urls` is set, host
+/// names are resolved with [`PublicResolver`] and redirects aren't followed, so a delivery
+/// can't end up at an internal address that [`check_target`] would have refused.
+pub fn http_client(config: &WebhookConfig) -> Result<reqwest::Client, AppError> {
+    let mut builder =
+        reqwest::Client::builder().timeout(StdDuration::from_secs(config.timeout_seconds));
+    if !config.allow_private_urls {
+        builder = builder
+            .dns_resolver(Arc::new(PublicResolver))
+            .redirect(reqwest::redirect::Policy::none());
+    }
+    builder.build().map_err(|error| {
+        tracing::error!(%error, "Failed to build the webhook HTTP client");
+        AppError::Unknown
+    })
+}
+
+/// Sends up to `batch_size` due deliveries, one at a time. Anything but a 2xx response is
+/// a failure. Failed deliveries are retried with exponential backoff until they have had
+/// `max_attempts`, after which they go to the dead letters. Deliveries aren't ordered,
+/// and an event can arrive more than once.
+pub async fn dispatch<T: WebhookRepo>(
+    repo: &T,
+    client: &reqwest::Client,
+    config: &WebhookConfig,
+) -> Result<DispatchReport, AppError> {
+    let lease = Duration::seconds(config.timeout_seconds as i64 * 2 + 30);
+    let deliveries = repo
+        .claim_deliveries(config.batch_size.into(), lease)
+        .await?;
+
+    let mut report = DispatchReport::default();
+    for delivery in deliveries {
+        // Urls stored before the check existed, or while private urls were allowed.
+        let sent = match check_target(&delivery.url, config) {
+            Ok(()) => send(client, &delivery).await,
+            Err(error) => Err(error.to_string()),
+        };
+        let error = match sent {
+            Ok(()) => {
+                repo.delivered(delivery.id).await?;
+                report.delivered += 1;
+                continue;
+            }
+            Err(error) => error,
+        };
+
+        let attempts = delivery.attempts.max(0) as u32 + 1;
+        if attempts < config.max_attempts {
+            let retry_at = Utc::now() + backoff(config, attempts);
+            tracing::warn!(delivery_id = delivery.id, attempts, %error, "Webhook delivery failed");
+            repo.failed(delivery.id, &error, Some(retry_at)).await?;
+            report.retrying += 1;
+        } else {
+            tracing::error!(delivery_id = delivery.id, attempts, %error, "Webhook delivery dead lettered");
+            repo.failed(delivery.id, &error, None).await?;
+            report.dead_lettered += 1;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Resolves host names with the system resolver, leaving out internal addresses.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs = resolve_public(&host, 0, false).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Checks that a webhook url can be delivered to: https, and not a loopback, link-local,
+/// private or otherwise internal address, unless `allow_private_urls` is set. Host names
+/// are checked again on every delivery, when [`PublicResolver`] resolves them.
+pub fn check_target(url: &str, config: &WebhookConfig) -> Result<(), AppError> {
+    if config.allow_private_urls {
+        return Ok(());
+    }
+
+    let parsed = reqwest::Url::parse(url)
+        .map_err(|_| AppError::InvalidInput(format!("Webhook url {} is not a valid url", url)))?;
+    if parsed.scheme() != "https" {
+        return Err(AppError::InvalidInput(format!(
+            "Webhook url {} must be an https url",
+            url
+        )));
+    }
+
+    let host = parsed.host_str().unwrap_or_default();
+    let internal = match host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+    {
+        Ok(ip) => !is_public(ip),
+        Err(_) => {
+            let host = host.trim_end_matches('.').to_ascii_lowercase();
+            host.is_empty() || host == "localhost" || host.ends_with(".localhost")
+        }
+    };
+    if internal {
+        return Err(AppError::InvalidInput(format!(
+            "Webhook url {} points at an internal address",
+            url
+        )));
+    }
+    Ok(())
+}
+
+async fn send(client: &reqwest::Client, delivery: &Delivery) -> Result<(), String> {
+    let body = serde_json::to_vec(&delivery.payload).map_err(|error| error.to_string())?;
+    let signature = sign(&delivery.secret, Utc::now().timestamp(), &body);
+    let response = client
+        .post(&delivery.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(EVENT_ID_HEADER, delivery.event_id)
+        .header(EVENT_TYPE_HEADER, &delivery.event_type)
+        .header(SIGNATURE_HEADER, signature)
+        .body(body)
+        .send()
+        .await
+        .map_err(|error| error.to_string())?;
+
+    let status = response.status();
+    if status.is_success() {
+        Ok(())
+    } else {
+        Err(format!("HTTP {}", status))
+    }
+}
+
+/// Runs [`dispatch`] every `poll_interval_seconds` until `shutdown` completes. A pass that
+/// is already running when shutdown starts is finished first.
+pub async fn run<T: WebhookRepo>(
+    repo: T,
+    config: WebhookConfig,
+    shutdown: impl Future<Output = ()>,
+) {
+    let Ok(client) = http_client(&config) else {
+        return;
+    };
+    let period = StdDuration::from_secs(config.poll_interval_seconds.max(1));
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => break,
+            _ = interval.tick() => {
+                if let Err(error) = dispatch(&repo, &client, &config).await {
+                    tracing::error!(%error, "Webhook dispatch failed");
+                }
+            }
+        }
+    }
+
+    tracing::info!("Webhook dispatcher stopped");
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, FromRow, ToSchema)]
+pub struct Webhook {
+    pub id: i64,
+    pub url: String,
+    /// Empty for every event.
+    pub event_types: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, ToSchema)]
+pub struct NewWebhook {
+    pub url: String,
+    #[serde(default)]
+    pub event_types: Vec<String>,
+    /// The key payloads are signed with. One is generated when it is left out on create.
+    pub secret: Option<String>,
+}
+
+impl NewWebhook {
+    pub fn validate(&self) -> Result<(), AppError> {
+        match reqwest::Url::parse(&self.url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+            _ => {
+                return Err(AppError::InvalidInput(format!(
+                    "Webhook url {} must be an http or https url",
+                    self.url
+                )))
+            }
+        }
+        if let Some(event_type) = self
+            .event_types
+            .iter()
+            .find(|event_type| !EVENT_TYPES.contains(&event_type.as_str()))
+        {
+            return Err(AppError::InvalidInput(format!(
+                "Unknown event type {}",
+                event_type
+            )));
+        }
+        if self.secret.as_ref().is_some_and(|secret| secret.len() < 16) {
+            return Err(AppError::InvalidInput(
+                "A webhook secret needs at least 16 characters".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Returned once when a subscription is created. The secret can't be read back afterwards.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, ToSchema)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    pub secret: String,
+}
+
+/// The body of a delivery. `data` is the entity after the change (before it, for
+/// deletes) and `changes` has the changed fields, both with secrets redacted.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct WebhookEvent {
+    /// The id of the audit event.
+    pub id: i64,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub occurred_at: DateTime<Utc>,
+    pub actor: String,
+    pub entity_type: String,
+    pub entity_id: i64,
+    pub data: Value,
+    pub changes: Value,
+}
+
+impl WebhookEvent {
+    /// The event for an audit event, with `data` being the entity as serialized for it.
+    pub fn new(event: AuditEvent, data: Option<Value>) -> Self {
+        Self {
+            id: event.id,
+            event_type: format!("{}.{}", event.entity_type, event.action),
+            occurred_at: event.occurred_at,
+            actor: event.actor,
+            entity_type: event.entity_type,
+            entity_id: event.entity_id,
+            data: data.map_or(Value::Null, redact_fields),
+            changes: event.diff,
+        }
+    }
+}
+
+/// A delivery taken from the outbox, with what is needed to send it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delivery {
+    pub id: i64,
+    pub subscription_id: i64,
+    pub url: String,
+    pub secret: String,
+    pub event_id: i64,
+    pub event_type: String,
+    pub payload: Value,
+    /// Failed attempts so far.
+    pub attempts: i32,
+}
+
+/// A webhook_outbox row joined with its subscription, as stored.
+#[derive(Debug, Clone, FromRow)]
+struct DeliveryRecord {
+    id: i64,
+    subscription_id: i64,
+    url: String,
+    secret: Vec<u8>,
+    key_id: Option<String>,
+    event_id: i64,
+    event_type: String,
+    payload: Value,
+    attempts: i32,
+}
+
+impl DeliveryRecord {
+    fn decrypt(self, keys: &KeyRing) -> Result<Delivery, AppError> {
+        Ok(Delivery {
+            id: self.id,
+            subscription_id: self.subscription_id,
+            url: self.url,
+            secret: keys.decrypt(self.key_id.as_deref(), &self.secret)?,
+            event_id: self.event_id,
+            event_type: self.event_type,
+            payload: self.payload,
+            attempts: self.attempts,
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, FromRow, ToSchema)]
+pub struct DeadLetter {
+    pub id: i64,
+    pub subscription_id: i64,
+    pub event_id: i64,
+    pub event_type: String,
+    pub payload: Value,
+    pub created_at: DateTime<Utc>,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub failed_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sign() {
+        // echo -n '1700000000.{"id":1}' | openssl dgst -sha256 -hmac 'whsec_0123456789abcdef'
+        assert_eq!(
+            sign("whsec_0123456789abcdef", 1700000000, br#"{"id":1}"#),
+            "t=1700000000,v1=22f267bc13c9c3f35f76035954c196f8ad4cf971af76120dcbcbbb84458514d0"
+        );
+    }
+
+    #[test]
+    fn test_check_target_refuses_internal_urls() {
+        let config = WebhookConfig::default();
+        for url in [
+            "https://hooks.example.com/events",
+            "https://93.184.216.34/hook",
+            "https://[2606:4700::1111]/hook",
+        ] {
+            assert!(check_target(url, &config).is_ok(), "{}", url);
+        }
+        for url in [
+            "http://hooks.example.com/events",
+            "https://169.254.169.254/latest/meta-data/",
+            "https://127.0.0.1:8080/hook",
+            "https://10.0.0.5/hook",
+            "https://172.16.0.1/hook",
+            "https://192.168.1.1/hook",
+            "https://100.64.0.1/hook",
+            "https://0.0.0.0/hook",
+            "https://[::1]/hook",
+            "https://[fd00::1]/hook",
+            "https://[fe80::1]/hook",
+            "https://[::ffff:169.254.169.254]/hook",
+            "https://localhost/hook",
+            "https://api.localhost./hook",
+        ] {
+            assert!(check_target(url, &config).is_err(), "{}", url);
+        }
+
+        let config = WebhookConfig {
+            allow_private_urls: true,
+            ..WebhookConfig::default()
+        };
+        assert!(check_target("http://127.0.0.1:8080/hook", &config).is_ok());
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_max() {
+        let config = WebhookConfig {
+            initial_backoff_seconds: 30,
+            max_backoff_seconds: 300,
+            ..WebhookConfig::default()
+        };
+        let seconds: Vec<i64> = (1..=6)
+            .map(|attempts| backoff(&config, attempts).num_seconds())
+            .collect();
+        assert_eq!(seconds, vec![30, 60, 120, 240, 300, 300]);
+        assert_eq!(backoff(&config, 64).num_seconds(), 300);
+    }
+
+    #[test]
+    fn test_event_redacts_data() {
+        let audit_event = AuditEvent {
+            id: 7,
+            occurred_at: Utc::now(),
+            actor: "bootstrap".to_string(),
+            actor_key_id: None,
+            action: "update".to_string(),
+            entity_type: "vendor".to_string(),
+            entity_id: 1,
+            diff: json!({"host": {"before": "old.example.com", "after": "new.example.com"}}),
+        };
+        let data = json!({"id": 1, "host": "new.example.com", "password": "hunter2"});
+        let event = WebhookEvent::new(audit_event, Some(data));
+        assert_eq!(event.event_type, "vendor.update");
+        assert_eq!(
+            event.data,
+            json!({"id": 1, "host": "new.example.com", "password": "[REDACTED]"})
+        );
+        assert_eq!(
+            event.changes,
+            json!({"host": {"before": "old.example.com", "after": "new.example.com"}})
+        );
+    }
+
+    #[test]
+    fn test_subscribed() {
+        assert!(subscribed(&[], "client.create"));
+        assert!(subscribed(&["client.create".to_string()], "client.create"));
+        assert!(!subscribed(&["client.delete".to_string()], "client.create"));
+    }
+}
@@ -0,0 +1,249 @@
+mod common;
+
+use axum::http::{
+    header::{ETAG, IF_MATCH, LOCATION},
+    Method, StatusCode,
+};
+use common::{TestApp, ADMIN};
+use serde_json::json;
+use user_manager_api::agents::models::{AgentFilter, AgentRepo};
+use user_manager_api::utils::list::{Cursor, CursorValue, ListQuery, Sort};
+
+#[tokio::test]
+async fn test_agent_client_links() {
+    let app = TestApp::new();
+    let agent_id = app.create_agent("smith").await;
+    let acme = app.create_client("acme").await;
+    let globex = app.create_client("globex").await;
+
+    for client_id in [acme, globex] {
+        let (status, _) = app
+            .put(&format!("/agents/{}/clients/{}", agent_id, client_id), None)
+            .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    let (status, _) = app
+        .put(&format!("/agents/{}/clients/42", agent_id), None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (_, clients) = app.get(&format!("/agents/{}/clients", agent_id)).await;
+    assert_eq!(clients.as_array().unwrap().len(), 2);
+
+    let (_, page) = app.get(&format!("/agents?client_id={}", globex)).await;
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["items"][0]["name"], "smith");
+    let (_, page) = app.get("/agents?client_id__in=41,42").await;
+    assert_eq!(page["total"], 0);
+
+    let (status, _) = app.delete(&format!("/agents/{}", agent_id)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.get(&format!("/agents/{}", agent_id)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.delete(&format!("/agents/{}", agent_id)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    // The clients themselves are left alone.
+    let (status, _) = app.get(&format!("/clients/{}", acme)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_hostile_name_filter_matches_nothing() {
+    let app = TestApp::new();
+    app.create_agent("smith").await;
+
+    let (status, page) = app
+        .get("/agents?name=%25%27%20OR%20%271%27%3D%271%27%20--")
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["total"], 0);
+
+    let (status, _) = app.get("/agents?name%3B%20DROP%20TABLE%20agents=x").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+#[ignore = "needs a migrated Postgres database"]
+async fn test_hostile_list_params_against_postgres() {
+    let repo = common::postgres().await;
+    let all = repo.get_all(ListQuery::default()).await.unwrap().total;
+
+    let hostile = "%' UNION SELECT id, password, host FROM vendors --";
+    let cursor = Cursor {
+        sort: Sort {
+            field: "email".to_string(),
+            descending: true,
+        },
+        value: CursorValue::Text(hostile.to_string()),
+        id: i64::MAX,
+    };
+    let query = ListQuery::<AgentFilter>::from_query_string(&format!(
+        "sort=-email&name=%25%27%20OR%20%271%27%3D%271%27%20--&cursor={}",
+        cursor.encode()
+    ))
+    .unwrap();
+    assert_eq!(repo.get_all(query).await.unwrap().total, 0);
+
+    let query = ListQuery::<AgentFilter>::from_query_string("client_id__in=-1,-2").unwrap();
+    assert_eq!(repo.get_all(query).await.unwrap().total, 0);
+    assert_eq!(repo.get_all(ListQuery::default()).await.unwrap().total, all);
+}
+
+#[tokio::test]
+async fn test_agent_keys_only_see_their_clients() {
+    let app = TestApp::new();
+    let agent_id = app.create_agent("smith").await;
+    let acme = app.create_client("acme").await;
+    let globex = app.create_client("globex").await;
+    app.create_vendor(acme, "bank").await;
+    let globex_vendor = app.create_vendor(globex, "bank").await;
+    app.put(&format!("/agents/{}/clients/{}", agent_id, acme), None)
+        .await;
+
+    let token = app
+        .create_key(&["clients:read", "vendors:read"], Some(agent_id))
+        .await;
+
+    let (_, page) = app.request(Method::GET, "/clients", &token, None).await;
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["items"][0]["id"], acme);
+    let (_, page) = app.request(Method::GET, "/vendors", &token, None).await;
+    assert_eq!(page["total"], 1);
+
+    let (status, _) = app
+        .request(Method::GET, &format!("/clients/{}", globex), &token, None)
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = app
+        .request(
+            Method::GET,
+            &format!("/vendors/{}", globex_vendor),
+            &token,
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Deleting the agent removes its keys.
+    app.delete(&format!("/agents/{}", agent_id)).await;
+    let (status, _) = app.request(Method::GET, "/clients", &token, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (_, keys) = app.get("/api-keys").await;
+    assert_eq!(keys, json!([]));
+}
+
+#[tokio::test]
+async fn test_agent_keys_only_see_themselves() {
+    let app = TestApp::new();
+    let smith = app.create_agent("smith").await;
+    let jones = app.create_agent("jones").await;
+    let acme = app.create_client("acme").await;
+    let globex = app.create_client("globex").await;
+    app.put(&format!("/agents/{}/clients/{}", smith, acme), None)
+        .await;
+    app.put(&format!("/agents/{}/clients/{}", jones, globex), None)
+        .await;
+
+    let token = app.create_key(&["agents:read"], Some(smith)).await;
+
+    // Listing agents by client would tell who serves globex.
+    let uri = format!("/agents?client_id={}", globex);
+    let (status, _) = app.request(Method::GET, &uri, &token, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = app.request(Method::GET, "/agents", &token, None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = app
+        .request(Method::GET, &format!("/agents/{}", jones), &token, None)
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, agent) = app
+        .request(Method::GET, &format!("/agents/{}", smith), &token, None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(agent["name"], "smith");
+}
+
+#[tokio::test]
+async fn test_if_match_guards_agent_changes() {
+    let app = TestApp::new();
+    let agent_id = app.create_agent("smith").await;
+    let uri = format!("/agents/{}", agent_id);
+    let update = json!({"name": "jones", "email": "jones@example.com"});
+
+    let (status, _, _) = app
+        .send(
+            Method::PUT,
+            &uri,
+            ADMIN,
+            &[(IF_MATCH, "\"2\"")],
+            Some(update.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    let (status, _, _) = app
+        .send(
+            Method::PUT,
+            &uri,
+            ADMIN,
+            &[(IF_MATCH, "\"1\"")],
+            Some(update),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let etag = app.etag(&uri).await;
+    assert_eq!(etag, "\"2\"");
+    let (status, _, _) = app
+        .send(Method::DELETE, &uri, ADMIN, &[(IF_MATCH, &etag)], None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_v2_status_codes() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+
+    let (status, headers, agent) = app
+        .send(
+            Method::POST,
+            "/v2/agents",
+            ADMIN,
+            &[],
+            Some(json!({"name": "smith", "email": "smith@example.com"})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let id = agent["id"].as_i64().unwrap();
+    assert_eq!(headers[LOCATION], format!("/v2/agents/{}", id));
+    assert_eq!(agent["name"], "smith");
+
+    let (status, headers, agent) = app
+        .send(
+            Method::PUT,
+            &format!("/v2/agents/{}", id),
+            ADMIN,
+            &[],
+            Some(json!({"name": "smith", "email": "agent.smith@example.com"})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[ETAG], "\"2\"");
+    assert_eq!(agent["email"], "agent.smith@example.com");
+
+    let (status, _) = app
+        .request(
+            Method::PUT,
+            &format!("/v2/agents/{}/clients/{}", id, client_id),
+            ADMIN,
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    let (status, _) = app
+        .request(Method::DELETE, &format!("/v2/agents/{}", id), ADMIN, None)
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
@@ -0,0 +1,149 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use common::TestApp;
+use serde_json::Value;
+
+async fn import(app: &TestApp, query: &str, content_type: &str, body: &str) -> (StatusCode, Value) {
+    let (status, _, body) = app
+        .send_text(
+            Method::POST,
+            &format!("/import?{}", query),
+            Some(content_type),
+            body,
+        )
+        .await;
+    let body = serde_json::from_str(&body).unwrap_or(Value::String(body));
+    (status, body)
+}
+
+#[tokio::test]
+async fn test_import_clients_upserts_by_name() {
+    let app = TestApp::new();
+    app.create_client("acme").await;
+
+    let csv = "name,email,bucket\n\
+        acme,ops@acme.example.com,acme-bucket\n\
+        globex,ops@globex.example.com,globex\n";
+    let (status, report) = import(&app, "entity=clients", "text/csv", csv).await;
+    assert_eq!(status, StatusCode::OK, "{}", report);
+    assert_eq!(report["committed"], true);
+    assert_eq!(report["created"], 1);
+    assert_eq!(report["updated"], 1);
+
+    let (_, page) = app.get("/clients?name=acme").await;
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["items"][0]["bucket"], "acme-bucket");
+
+    // Importing the same file again changes nothing.
+    let (_, report) = import(&app, "entity=clients&format=csv", "text/plain", csv).await;
+    assert_eq!(report["unchanged"], 2);
+}
+
+#[tokio::test]
+async fn test_import_modes() {
+    let app = TestApp::new();
+    app.create_client("acme").await;
+    let ndjson = r#"{"client": "acme", "name": "bank", "host": "sftp.bank.example.com", "port": 22, "password": "hunter2"}
+{"client": "nobody", "name": "bank", "host": "sftp.bank.example.com", "port": 22}
+{"client": "acme", "name": "broker", "host": "sftp.broker.example.com", "port": 0}
+not json
+"#;
+
+    let (status, report) = import(&app, "entity=vendors", "application/x-ndjson", ndjson).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["committed"], false);
+    assert_eq!(report["created"], 1);
+    assert_eq!(report["failed"], 3);
+    let lines: Vec<&Value> = report["errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| &error["line"])
+        .collect();
+    assert_eq!(lines, [2, 3, 4]);
+    assert!(report["errors"][0]["error"]
+        .as_str()
+        .unwrap()
+        .contains("nobody"));
+    let (_, page) = app.get("/vendors").await;
+    assert_eq!(page["total"], 0);
+
+    let (_, report) = import(
+        &app,
+        "entity=vendors&mode=best_effort",
+        "application/x-ndjson",
+        ndjson,
+    )
+    .await;
+    assert_eq!(report["committed"], true);
+    assert_eq!(report["created"], 1);
+    let (_, page) = app.get("/vendors").await;
+    assert_eq!(page["total"], 1);
+
+    let (status, _) = import(&app, "entity=vendors", "application/json", ndjson).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = import(&app, "entity=vendors", "text/csv", "").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_export_redacts_secrets_and_round_trips() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+    app.create_vendor(client_id, "bank").await;
+
+    let (status, headers, csv) = app
+        .send_text(Method::GET, "/export?entity=vendors&format=csv", None, "")
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["content-type"], "text/csv; charset=utf-8");
+    assert!(!csv.contains("hunter2"), "{}", csv);
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "id,client_id,client,name,host,port,username,password,ssh_key,ssh_key_password"
+    );
+    assert!(lines[1].ends_with(",upload,[REDACTED],,"), "{}", lines[1]);
+
+    // Importing the export keeps the stored secrets.
+    let (_, report) = import(&app, "entity=vendors", "text/csv", &csv).await;
+    assert_eq!(report["unchanged"], 1, "{}", report);
+    let (_, page) = app.get("/vendors").await;
+    assert_eq!(page["items"][0]["password"], "hunter2");
+
+    let (_, _, ndjson) = app
+        .send_text(
+            Method::GET,
+            "/export?entity=clients&format=ndjson",
+            None,
+            "",
+        )
+        .await;
+    let client: Value = serde_json::from_str(ndjson.lines().next().unwrap()).unwrap();
+    assert_eq!(client["name"], "acme");
+}
+
+#[tokio::test]
+async fn test_bulk_scopes() {
+    let app = TestApp::new();
+    let token = app.create_key(&["clients:read"], None).await;
+
+    let (status, _) = app
+        .request(Method::GET, "/export?entity=clients", &token, None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app
+        .request(Method::GET, "/export?entity=vendors", &token, None)
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = app
+        .request(
+            Method::POST,
+            "/import?entity=clients&format=ndjson",
+            &token,
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,524 @@
+mod common;
+
+use axum::http::{
+    header::{CONTENT_TYPE, ETAG, IF_MATCH, LOCATION},
+    HeaderName, Method, StatusCode,
+};
+use common::{TestApp, ADMIN};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn test_client_crud() {
+    let app = TestApp::new();
+    let id = app.create_client("acme").await;
+
+    let (status, client) = app.get(&format!("/clients/{}", id)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(client["name"], "acme");
+
+    let (status, _) = app
+        .put(
+            &format!("/clients/{}", id),
+            Some(json!({"id": null, "name": "acme2", "email": "a@example.com", "bucket": "acme2-bucket"})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, client) = app.get(&format!("/clients/{}", id)).await;
+    assert_eq!(client["name"], "acme2");
+    assert_eq!(client["id"], id);
+
+    let (status, _) = app.delete(&format!("/clients/{}", id)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.get(&format!("/clients/{}", id)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.delete(&format!("/clients/{}", id)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_list_clients_pages_and_filters() {
+    let app = TestApp::new();
+    for name in ["carol", "alice", "bob", "Alina"] {
+        app.create_client(name).await;
+    }
+
+    let (status, page) = app.get("/clients?limit=2&sort=-name").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["total"], 4);
+    assert_eq!(page["items"][0]["name"], "carol");
+    assert_eq!(page["items"][1]["name"], "bob");
+
+    let cursor = page["next_cursor"].as_str().unwrap();
+    let (_, page) = app
+        .get(&format!("/clients?limit=2&sort=-name&cursor={}", cursor))
+        .await;
+    assert_eq!(page["items"][0]["name"], "alice");
+    assert_eq!(page["items"][1]["name"], "Alina");
+    assert_eq!(page["next_cursor"], json!(null));
+
+    let (_, page) = app.get("/clients?name__icontains=ali").await;
+    assert_eq!(page["total"], 2);
+    let (_, page) = app.get("/clients?name=ali").await;
+    assert_eq!(page["total"], 1);
+
+    let (status, _) = app.get("/clients?password=x").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_deleted_client_is_hidden_until_restored() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+    let other_id = app.create_client("globex").await;
+    app.create_vendor(client_id, "bank").await;
+    app.create_vendor(other_id, "bank").await;
+    let agent_id = app.create_agent("smith").await;
+    app.put(&format!("/agents/{}/clients/{}", agent_id, client_id), None)
+        .await;
+
+    let (status, _) = app.delete(&format!("/clients/{}", client_id)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, page) = app.get("/clients").await;
+    assert_eq!(page["total"], 1);
+    let (_, page) = app.get("/vendors").await;
+    assert_eq!(page["total"], 1);
+    let (_, clients) = app.get(&format!("/agents/{}/clients", agent_id)).await;
+    assert_eq!(clients, json!([]));
+    let (status, _) = app.get(&format!("/clients/{}", client_id)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app
+        .put(
+            &format!("/clients/{}", client_id),
+            Some(json!({"id": null, "name": "acme2", "email": "a@example.com", "bucket": "acme2-bucket"})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (_, page) = app.get("/clients?include_deleted=true").await;
+    assert_eq!(page["total"], 2);
+    assert!(page["items"][0]["deleted_at"].is_string());
+    let (_, page) = app.get("/vendors?include_deleted=true").await;
+    assert_eq!(page["total"], 2);
+    let (status, _) = app.get("/agents?include_deleted=true").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, client) = app
+        .post(&format!("/clients/{}/restore", client_id), json!(null))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(client["name"], "acme");
+    assert_eq!(client.get("deleted_at"), None);
+    let (status, _) = app
+        .post(&format!("/clients/{}/restore", client_id), json!(null))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (_, page) = app.get("/vendors").await;
+    assert_eq!(page["total"], 2);
+    let (_, clients) = app.get(&format!("/agents/{}/clients", agent_id)).await;
+    assert_eq!(clients[0]["id"], client_id);
+}
+
+#[tokio::test]
+async fn test_purging_a_deleted_client_cascades() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+    let kept_id = app.create_client("globex").await;
+    let vendor_id = app.create_vendor(client_id, "bank").await;
+    let agent_id = app.create_agent("smith").await;
+    app.put(&format!("/agents/{}/clients/{}", agent_id, client_id), None)
+        .await;
+
+    app.delete(&format!("/clients/{}", client_id)).await;
+    let (_, page) = app.get("/vendors?include_deleted=true").await;
+    assert_eq!(page["items"][0]["id"], vendor_id);
+
+    let (status, report) = app.post("/retention/run", json!(null)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(report["purged_clients"], json!([client_id]));
+
+    let (_, page) = app.get("/vendors?include_deleted=true").await;
+    assert_eq!(page["total"], 0);
+    let (_, page) = app.get("/clients?include_deleted=true").await;
+    assert_eq!(page["items"][0]["id"], kept_id);
+    assert_eq!(page["total"], 1);
+    let (status, _) = app
+        .post(&format!("/clients/{}/restore", client_id), json!(null))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.get(&format!("/agents/{}", agent_id)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_requests_need_a_valid_key() {
+    let app = TestApp::new();
+    let (status, _) = app
+        .request(Method::GET, "/clients", "not-a-key", None)
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let token = app.create_key(&["clients:read"], None).await;
+    let (status, _) = app.request(Method::GET, "/clients", &token, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app
+        .request(
+            Method::POST,
+            "/clients",
+            &token,
+            Some(json!({"name": "acme", "email": "a@example.com", "bucket": "acme-bucket"})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+fn onboarding(agent_id: i64) -> serde_json::Value {
+    json!({
+        "client": {"id": null, "name": "acme", "email": "a@example.com", "bucket": "acme-bucket"},
+        "vendors": [{
+            "name": "bank",
+            "host": "sftp.bank.example.com",
+            "port": 22,
+            "username": "upload",
+            "password": "hunter2",
+            "ssh_key": null,
+            "ssh_key_password": null,
+        }],
+        "sftp": [{
+            "username": "acme-upload",
+            "bucket_name": "acme-bucket",
+            "aws_role_arn": "arn:aws:iam::123456789012:role/acme",
+            "key_type": "ecdsa-sha2-nistp256",
+        }],
+        "agent_ids": [agent_id],
+    })
+}
+
+#[tokio::test]
+async fn test_onboarding_needs_the_scope_of_each_part() {
+    let app = TestApp::new();
+    let agent_id = app.create_agent("smith").await;
+    let token = app.create_key(&["clients:write"], None).await;
+
+    let full = onboarding(agent_id);
+    let parts = [
+        json!({"vendors": full["vendors"]}),
+        json!({"sftp": full["sftp"]}),
+        json!({"agent_ids": full["agent_ids"]}),
+    ];
+    for part in parts {
+        let mut document = json!({"client": full["client"]});
+        document
+            .as_object_mut()
+            .unwrap()
+            .extend(part.as_object().unwrap().clone());
+        let (status, error) = app
+            .request(Method::POST, "/clients/onboard", &token, Some(document))
+            .await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{}", error);
+    }
+    let (status, error) = app
+        .request(Method::POST, "/clients/onboard", &token, Some(full))
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "{}", error);
+
+    // The client on its own only needs clients:write.
+    let document = json!({"client": onboarding(agent_id)["client"]});
+    let (status, _) = app
+        .request(Method::POST, "/clients/onboard", &token, Some(document))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, page) = app.get("/clients").await;
+    assert_eq!(page["total"], 1);
+}
+
+#[tokio::test]
+async fn test_onboarding_creates_everything() {
+    let app = TestApp::new();
+    let agent_id = app.create_agent("smith").await;
+
+    let (status, onboarded) = app.post("/clients/onboard", onboarding(agent_id)).await;
+    assert_eq!(status, StatusCode::OK, "{}", onboarded);
+    assert_eq!(onboarded["dry_run"], false);
+    let client_id = onboarded["client_id"].as_i64().unwrap();
+    assert_eq!(onboarded["sftp"][0]["key_type"], "ecdsa-sha2-nistp256");
+    assert!(onboarded["sftp"][0]["private_key_openssh"].is_string());
+
+    let (_, vendor) = app
+        .get(&format!("/vendors/{}", onboarded["vendor_ids"][0]))
+        .await;
+    assert_eq!(vendor["client_id"], client_id);
+    let (_, sftp) = app.get(&format!("/clients/{}/sftp", client_id)).await;
+    assert_eq!(sftp["total"], 1);
+    let (_, clients) = app.get(&format!("/agents/{}/clients", agent_id)).await;
+    assert_eq!(clients.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_onboarding_dry_run_and_failures_leave_nothing_behind() {
+    let app = TestApp::new();
+    let agent_id = app.create_agent("smith").await;
+
+    let (status, onboarded) = app
+        .post("/clients/onboard?dry_run=true", onboarding(agent_id))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", onboarded);
+    assert_eq!(onboarded["dry_run"], true);
+
+    // The agent is checked after the rest was written.
+    let (status, _) = app.post("/clients/onboard", onboarding(999)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let mut repeated = onboarding(agent_id);
+    let account = repeated["sftp"][0].clone();
+    repeated["sftp"].as_array_mut().unwrap().push(account);
+    let (status, _) = app.post("/clients/onboard", repeated).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, clients) = app.get("/clients").await;
+    assert_eq!(clients["total"], 0);
+    let (_, vendors) = app.get("/vendors").await;
+    assert_eq!(vendors["total"], 0);
+    let (_, clients) = app.get(&format!("/agents/{}/clients", agent_id)).await;
+    assert_eq!(clients, json!([]));
+}
+
+#[tokio::test]
+async fn test_if_match_guards_client_changes() {
+    let app = TestApp::new();
+    let id = app.create_client("acme").await;
+    let uri = format!("/clients/{}", id);
+    let body =
+        json!({"id": null, "name": "acme2", "email": "a@example.com", "bucket": "acme2-bucket"});
+    assert_eq!(app.etag(&uri).await, "\"1\"");
+
+    let (status, _, _) = app
+        .send(
+            Method::PUT,
+            &uri,
+            ADMIN,
+            &[(IF_MATCH, "\"1\"")],
+            Some(body.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.etag(&uri).await, "\"2\"");
+
+    // A second writer still holding the first version is turned away.
+    let (status, _, error) = app
+        .send(
+            Method::PUT,
+            &uri,
+            ADMIN,
+            &[(IF_MATCH, "\"1\"")],
+            Some(body.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    assert_eq!(error["code"], "precondition_failed");
+    assert!(error["message"].as_str().unwrap().contains("\"2\""));
+    let (status, _, _) = app
+        .send(Method::DELETE, &uri, ADMIN, &[(IF_MATCH, "\"1\"")], None)
+        .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+
+    // Without If-Match, or with `*`, the change goes through.
+    let (status, _) = app.put(&uri, Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = app
+        .send(Method::DELETE, &uri, ADMIN, &[(IF_MATCH, "*")], None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_errors_carry_a_code_and_the_request_id() {
+    let app = TestApp::new();
+    let request_id = HeaderName::from_static("x-request-id");
+
+    let (status, headers, error) = app
+        .send(
+            Method::GET,
+            "/clients/42",
+            ADMIN,
+            &[(request_id.clone(), "lb-1234")],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(headers[&request_id], "lb-1234");
+    assert_eq!(
+        error,
+        json!({
+            "code": "not_found",
+            "message": "Client with id 42 not found",
+            "request_id": "lb-1234",
+        })
+    );
+
+    // Without one from the caller, an id is made up.
+    let (status, headers, error) = app
+        .send(Method::GET, "/clients", "wrong-key", &[], None)
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(error["code"], "unauthorized");
+    assert_eq!(error["request_id"], headers[&request_id].to_str().unwrap());
+}
+
+#[tokio::test]
+async fn test_malformed_requests_carry_an_error_body() {
+    let app = TestApp::new();
+
+    let (status, error) = app.get("/clients/abc").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["code"], "invalid_input");
+    let client_id = app.create_client("acme").await;
+    let (status, error) = app
+        .put(
+            &format!(
+                "/clients/{}/reset-sftp-keys?grace_period_hours=soon",
+                client_id
+            ),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["code"], "invalid_input");
+
+    let (status, _, error) = app
+        .send_text(Method::POST, "/api-keys", Some("application/json"), "{")
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(
+        serde_json::from_str::<Value>(&error).unwrap()["code"],
+        "invalid_input"
+    );
+    let (status, error) = app.post("/api-keys", json!({"scopes": 42})).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error["code"], "validation_failed");
+
+    let (status, error) = app.get("/nothing/here").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(error["code"], "not_found");
+    assert!(error["request_id"].is_string());
+}
+
+#[tokio::test]
+async fn test_invalid_fields_are_all_reported() {
+    let app = TestApp::new();
+
+    let (status, error) = app
+        .post(
+            "/clients",
+            json!({"id": null, "name": " ", "email": "acme", "bucket": "Acme_Uploads"}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error["code"], "validation_failed");
+    let fields: Vec<&str> = error["details"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|detail| detail["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, ["name", "email", "bucket"]);
+
+    // Nested documents name the offending entry.
+    let (status, error) = app
+        .post(
+            "/clients/onboard",
+            json!({
+                "client": {"id": null, "name": "acme", "email": "a@example.com", "bucket": "acme-bucket"},
+                "vendors": [{
+                    "name": "bank",
+                    "host": "sftp.bank.example.com",
+                    "port": 70000,
+                    "username": null,
+                    "password": null,
+                    "ssh_key": null,
+                    "ssh_key_password": null,
+                }],
+                "sftp": [{
+                    "username": "acme",
+                    "bucket_name": "acme-bucket",
+                    "aws_role_arn": "arn:aws:iam::123456789012:user/acme",
+                    "allowed_source_ips": null,
+                }],
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error["details"][0]["field"], "vendors[0].port");
+    assert_eq!(error["details"][1]["field"], "sftp[0].aws_role_arn");
+    let (_, page) = app.get("/clients").await;
+    assert_eq!(page["total"], 0);
+}
+
+#[tokio::test]
+async fn test_v2_status_codes() {
+    let app = TestApp::new();
+
+    let (status, headers, client) = app
+        .send(
+            Method::POST,
+            "/v2/clients",
+            ADMIN,
+            &[],
+            Some(json!({"name": "acme", "email": "a@example.com", "bucket": "acme-bucket"})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let id = client["id"].as_i64().unwrap();
+    assert_eq!(headers[LOCATION], format!("/v2/clients/{}", id));
+    assert_eq!(headers[ETAG], "\"1\"");
+    assert_eq!(client["name"], "acme");
+
+    let (status, headers, client) = app
+        .send(
+            Method::PUT,
+            &format!("/v2/clients/{}", id),
+            ADMIN,
+            &[(IF_MATCH, "\"1\"")],
+            Some(json!({"name": "acme2", "email": "a@example.com", "bucket": "acme-bucket"})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[ETAG], "\"2\"");
+    assert_eq!(client["name"], "acme2");
+    assert_eq!(client["version"], 2);
+
+    let (status, headers, vendor) = app
+        .send(
+            Method::POST,
+            &format!("/v2/clients/{}/vendor", id),
+            ADMIN,
+            &[],
+            Some(json!({
+                "client_id": id,
+                "name": "bank",
+                "host": "sftp.bank.example.com",
+                "port": 22,
+                "username": "upload",
+                "password": "hunter2",
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let vendor_id = vendor["id"].as_i64().unwrap();
+    assert_eq!(headers[LOCATION], format!("/v2/vendors/{}", vendor_id));
+    assert_eq!(vendor["client_id"], id);
+
+    let (status, headers, body) = app
+        .send(
+            Method::DELETE,
+            &format!("/v2/clients/{}", id),
+            ADMIN,
+            &[],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(!headers.contains_key(CONTENT_TYPE));
+    assert_eq!(body, Value::Null);
+}
@@ -0,0 +1,270 @@
+//! Test harness running the routers against the in-memory backend, behind the real auth
+//! middleware.
+
+// Each test crate uses a different subset of the helpers.
+#![allow(dead_code)]
+
+pub mod sftp_server;
+
+use std::sync::Arc;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, HeaderMap, HeaderName, Method, Request, StatusCode},
+    middleware, Router,
+};
+use serde_json::Value;
+use tower::ServiceExt;
+use user_manager_api::{
+    agents, api_keys, bulk, clients,
+    config::models::{AppConfig, KeyRotationConfig, RetentionConfig, WebhookConfig},
+    errors::handlers::not_found,
+    memory::repo::MemoryRepo,
+    openapi,
+    postgres::pool::PostgresRepo,
+    retention, rotation, sftp,
+    utils::{auth::auth, crypto::KeyRing, request_id::request_id, version::versioned},
+    vendors, webhooks,
+};
+
+/// The bootstrap admin key of the test app.
+pub const ADMIN: &str = "test-admin-key";
+
+pub struct TestApp {
+    pub repo: MemoryRepo,
+    router: Router,
+}
+
+impl TestApp {
+    pub fn new() -> Self {
+        let repo = MemoryRepo::new();
+        let token = Arc::new(ADMIN.to_string());
+        let auth_repo = repo.clone();
+
+        let api = clients::app::router(repo.clone())
+            .merge(vendors::app::router(
+                repo.clone(),
+                Self::webhooks().allow_private_urls,
+            ))
+            .merge(sftp::app::router(repo.clone()))
+            .merge(agents::app::router(repo.clone()))
+            .merge(api_keys::app::router(repo.clone()))
+            .merge(rotation::app::router(repo.clone(), Self::key_rotation()))
+            .merge(retention::app::router(repo.clone(), Self::retention()))
+            .merge(webhooks::app::router(repo.clone(), Self::webhooks()))
+            .merge(bulk::app::router(repo.clone()))
+            .layer(middleware::from_fn(move |req, next| {
+                let token = token.clone();
+                let repo = auth_repo.clone();
+                async move { auth(req, next, repo, token).await }
+            }));
+        let router = versioned(api.clone(), api)
+            .merge(openapi::app::router())
+            .fallback(not_found)
+            .layer(middleware::from_fn(request_id));
+
+        Self { repo, router }
+    }
+
+    /// Rotates every key that exists when a sweep starts.
+    pub fn key_rotation() -> KeyRotationConfig {
+        KeyRotationConfig {
+            max_key_age_days: 0,
+            ..KeyRotationConfig::default()
+        }
+    }
+
+    /// Lets webhooks deliver to the local stub receivers.
+    pub fn webhooks() -> WebhookConfig {
+        WebhookConfig {
+            allow_private_urls: true,
+            ..WebhookConfig::default()
+        }
+    }
+
+    /// Purges every deleted client, however recently it was deleted.
+    pub fn retention() -> RetentionConfig {
+        RetentionConfig {
+            retention_days: 0,
+            ..RetentionConfig::default()
+        }
+    }
+
+    /// Sends a request and returns the status with the body parsed as JSON. Empty bodies
+    /// come back as `Null` and plain text errors as a JSON string.
+    pub async fn request(
+        &self,
+        method: Method,
+        uri: &str,
+        token: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let (status, _, body) = self.send(method, uri, token, &[], body).await;
+        (status, body)
+    }
+
+    /// Like [`TestApp::request`], with extra request headers and the response headers.
+    pub async fn send(
+        &self,
+        method: Method,
+        uri: &str,
+        token: &str,
+        headers: &[(HeaderName, &str)],
+        body: Option<Value>,
+    ) -> (StatusCode, HeaderMap, Value) {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", token));
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = if bytes.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&bytes)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).to_string()))
+        };
+        (status, headers, body)
+    }
+
+    /// Sends a request with a body that isn't JSON as the admin and returns the response
+    /// as text.
+    pub async fn send_text(
+        &self,
+        method: Method,
+        uri: &str,
+        content_type: Option<&str>,
+        body: &str,
+    ) -> (StatusCode, HeaderMap, String) {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", ADMIN));
+        if let Some(content_type) = content_type {
+            request = request.header(header::CONTENT_TYPE, content_type);
+        }
+        let request = request.body(Body::from(body.to_string())).unwrap();
+
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, headers, String::from_utf8(bytes.to_vec()).unwrap())
+    }
+
+    pub async fn get(&self, uri: &str) -> (StatusCode, Value) {
+        self.request(Method::GET, uri, ADMIN, None).await
+    }
+
+    pub async fn post(&self, uri: &str, body: Value) -> (StatusCode, Value) {
+        self.request(Method::POST, uri, ADMIN, Some(body)).await
+    }
+
+    pub async fn put(&self, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+        self.request(Method::PUT, uri, ADMIN, body).await
+    }
+
+    pub async fn delete(&self, uri: &str) -> (StatusCode, Value) {
+        self.request(Method::DELETE, uri, ADMIN, None).await
+    }
+
+    /// The `ETag` returned by a GET of `uri`.
+    pub async fn etag(&self, uri: &str) -> String {
+        let (status, headers, body) = self.send(Method::GET, uri, ADMIN, &[], None).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        headers[header::ETAG].to_str().unwrap().to_string()
+    }
+
+    /// Creates a client and returns its id.
+    pub async fn create_client(&self, name: &str) -> i64 {
+        let (status, id) = self
+            .post(
+                "/clients",
+                serde_json::json!({
+                    "name": name,
+                    "email": format!("{}@example.com", name),
+                    "bucket": format!("{}-bucket", name.to_lowercase()),
+                }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", id);
+        id.as_i64().unwrap()
+    }
+
+    /// Adds a vendor to a client and returns its id.
+    pub async fn create_vendor(&self, client_id: i64, name: &str) -> i64 {
+        let (status, id) = self
+            .post(
+                &format!("/clients/{}/vendor", client_id),
+                serde_json::json!({
+                    "id": null,
+                    "client_id": client_id,
+                    "name": name,
+                    "host": format!("sftp.{}.example.com", name),
+                    "port": 22,
+                    "username": "upload",
+                    "password": "hunter2",
+                    "ssh_key": null,
+                    "ssh_key_password": null,
+                }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", id);
+        id.as_i64().unwrap()
+    }
+
+    /// Creates an agent and returns its id.
+    pub async fn create_agent(&self, name: &str) -> i64 {
+        let (status, id) = self
+            .post(
+                "/agents",
+                serde_json::json!({
+                    "id": null,
+                    "name": name,
+                    "email": format!("{}@example.com", name),
+                }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", id);
+        id.as_i64().unwrap()
+    }
+
+    /// Issues an API key with the given scopes and returns its token.
+    pub async fn create_key(&self, scopes: &[&str], agent_id: Option<i64>) -> String {
+        let (status, key) = self
+            .post(
+                "/api-keys",
+                serde_json::json!({
+                    "name": "test",
+                    "scopes": scopes,
+                    "agent_id": agent_id,
+                    "expires_at": null,
+                }),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", key);
+        key["token"].as_str().unwrap().to_string()
+    }
+}
+
+/// The Postgres backend configured in `src/config/local.toml`, for the `#[ignore]`d tests
+/// that check the SQL the repos build. Run them with `cargo test -- --ignored` against a
+/// migrated database.
+pub async fn postgres() -> PostgresRepo {
+    let config = AppConfig::new().unwrap();
+    let keys = KeyRing::from_config(&config).unwrap();
+    PostgresRepo::new(&config.database_url, keys).await
+}
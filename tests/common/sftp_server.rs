@@ -0,0 +1,181 @@
+//! An in-process SFTP server for the vendor connection tests. It accepts one user with a
+//! password or an Ed25519 key and serves a login directory with two files.
+
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use russh::{
+    server::{self, Auth, Msg, Session},
+    Channel, ChannelId,
+};
+use russh_keys::{key, PublicKeyBase64};
+use russh_sftp::protocol::{File, FileAttributes, Handle, Name, Status, StatusCode, Version};
+use ssh_key::HashAlg;
+use tokio::net::TcpListener;
+
+pub const USERNAME: &str = "upload";
+pub const PASSWORD: &str = "hunter2";
+pub const HOME: &str = "/upload";
+pub const FILES: &[&str] = &["invoice.csv", "report.csv"];
+
+pub struct SftpServer {
+    pub port: u16,
+    /// The SHA256 fingerprint of the server's host key.
+    pub fingerprint: String,
+}
+
+impl SftpServer {
+    /// Starts a server that also accepts `authorized_key` (the base64 part of an OpenSSH
+    /// public key) for [`USERNAME`].
+    pub async fn start(authorized_key: Option<String>) -> Self {
+        let host_key = key::KeyPair::generate_ed25519().unwrap();
+        let public_key = ssh_key::PublicKey::from_bytes(
+            &host_key.clone_public_key().unwrap().public_key_bytes(),
+        )
+        .unwrap();
+        let config = Arc::new(server::Config {
+            auth_rejection_time: Duration::ZERO,
+            auth_rejection_time_initial: Some(Duration::ZERO),
+            keys: vec![host_key],
+            ..Default::default()
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let handler = SshSession {
+                    authorized_key: authorized_key.clone(),
+                    channels: HashMap::new(),
+                };
+                let config = config.clone();
+                tokio::spawn(async move {
+                    if let Ok(session) = server::run_stream(config, socket, handler).await {
+                        let _ = session.await;
+                    }
+                });
+            }
+        });
+
+        Self {
+            port,
+            fingerprint: public_key.fingerprint(HashAlg::Sha256).to_string(),
+        }
+    }
+}
+
+struct SshSession {
+    authorized_key: Option<String>,
+    channels: HashMap<ChannelId, Channel<Msg>>,
+}
+
+fn reject() -> Auth {
+    Auth::Reject {
+        proceed_with_methods: None,
+    }
+}
+
+#[async_trait]
+impl server::Handler for SshSession {
+    type Error = russh::Error;
+
+    async fn auth_password(&mut self, user: &str, password: &str) -> Result<Auth, Self::Error> {
+        Ok(if user == USERNAME && password == PASSWORD {
+            Auth::Accept
+        } else {
+            reject()
+        })
+    }
+
+    async fn auth_publickey(
+        &mut self,
+        user: &str,
+        public_key: &key::PublicKey,
+    ) -> Result<Auth, Self::Error> {
+        let authorized = self.authorized_key.as_deref() == Some(&public_key.public_key_base64());
+        Ok(if user == USERNAME && authorized {
+            Auth::Accept
+        } else {
+            reject()
+        })
+    }
+
+    async fn channel_open_session(
+        &mut self,
+        channel: Channel<Msg>,
+        _session: &mut Session,
+    ) -> Result<bool, Self::Error> {
+        self.channels.insert(channel.id(), channel);
+        Ok(true)
+    }
+
+    async fn subsystem_request(
+        &mut self,
+        channel_id: ChannelId,
+        name: &str,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        match self.channels.remove(&channel_id) {
+            Some(channel) if name == "sftp" => {
+                session.channel_success(channel_id);
+                russh_sftp::server::run(channel.into_stream(), SftpSession::default()).await;
+            }
+            _ => session.channel_failure(channel_id),
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct SftpSession {
+    listed: bool,
+}
+
+impl russh_sftp::server::Handler for SftpSession {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(
+        &mut self,
+        _version: u32,
+        _extensions: HashMap<String, String>,
+    ) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn realpath(&mut self, id: u32, _path: String) -> Result<Name, Self::Error> {
+        Ok(Name {
+            id,
+            files: vec![File::dummy(HOME)],
+        })
+    }
+
+    async fn opendir(&mut self, id: u32, path: String) -> Result<Handle, Self::Error> {
+        self.listed = false;
+        Ok(Handle { id, handle: path })
+    }
+
+    async fn readdir(&mut self, id: u32, _handle: String) -> Result<Name, Self::Error> {
+        if self.listed {
+            return Err(StatusCode::Eof);
+        }
+        self.listed = true;
+        let files = FILES
+            .iter()
+            .map(|name| File::new(*name, FileAttributes::default()))
+            .collect();
+        Ok(Name { id, files })
+    }
+
+    async fn close(&mut self, id: u32, _handle: String) -> Result<Status, Self::Error> {
+        Ok(Status {
+            id,
+            status_code: StatusCode::Ok,
+            error_message: "Ok".to_string(),
+            language_tag: "en-US".to_string(),
+        })
+    }
+}
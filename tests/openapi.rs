@@ -0,0 +1,115 @@
+mod common;
+
+use std::collections::BTreeSet;
+use std::fs;
+
+use axum::http::{Method, StatusCode};
+use common::TestApp;
+use user_manager_api::openapi::models::ApiDoc;
+use utoipa::OpenApi;
+
+const METHODS: [&str; 5] = ["get", "post", "put", "delete", "patch"];
+
+/// Replaces path parameter names, `:id` in axum and `{id}` in OpenAPI, with `{}`.
+fn normalize(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            if segment.starts_with(':') || segment.starts_with('{') {
+                "{}"
+            } else {
+                segment
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The routes registered with `.route("/path", method(handler))` in the `app.rs` of every
+/// module, as `METHOD /path`.
+fn routes() -> BTreeSet<String> {
+    let mut routes = BTreeSet::new();
+    for entry in fs::read_dir("src").unwrap() {
+        let Ok(source) = fs::read_to_string(entry.unwrap().path().join("app.rs")) else {
+            continue;
+        };
+        for call in source.split(".route(").skip(1) {
+            // The arguments of the call, up to its closing parenthesis.
+            let mut depth = 1;
+            let end = call
+                .char_indices()
+                .find(|(_, c)| {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    depth == 0
+                })
+                .unwrap()
+                .0;
+            let args = &call[..end];
+            let path = args.split('"').nth(1).unwrap();
+            for method in METHODS {
+                let called = args.match_indices(&format!("{}(", method)).any(|(i, _)| {
+                    let before = args[..i].chars().last().unwrap_or(' ');
+                    !(before.is_alphanumeric() || before == '_')
+                });
+                if called {
+                    routes.insert(format!("{} {}", method.to_uppercase(), normalize(path)));
+                }
+            }
+        }
+    }
+    routes
+}
+
+fn documented() -> BTreeSet<String> {
+    let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+    let mut documented = BTreeSet::new();
+    for (path, item) in spec["paths"].as_object().unwrap() {
+        for method in item.as_object().unwrap().keys() {
+            if METHODS.contains(&method.as_str()) {
+                documented.insert(format!("{} {}", method.to_uppercase(), normalize(path)));
+            }
+        }
+    }
+    documented
+}
+
+#[test]
+fn test_every_route_is_documented() {
+    let routes = routes();
+    let documented = documented();
+    assert!(routes.contains("GET /clients/{}"), "{:?}", routes);
+
+    let undocumented: Vec<_> = routes.difference(&documented).collect();
+    assert!(
+        undocumented.is_empty(),
+        "Not in the spec: {:?}",
+        undocumented
+    );
+    let unrouted: Vec<_> = documented.difference(&routes).collect();
+    assert!(unrouted.is_empty(), "Not a route: {:?}", unrouted);
+}
+
+#[tokio::test]
+async fn test_docs_are_public() {
+    let app = TestApp::new();
+
+    let (status, spec) = app.request(Method::GET, "/openapi.json", "", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(spec["info"]["title"], "User Manager API");
+    let get_client = &spec["paths"]["/clients/{id}"]["get"];
+    assert_eq!(
+        get_client["responses"]["200"]["content"]["application/json"]["schema"]["$ref"],
+        "#/components/schemas/Client"
+    );
+    assert_eq!(
+        get_client["responses"]["default"]["content"]["application/json"]["schema"]["$ref"],
+        "#/components/schemas/ErrorBody"
+    );
+
+    let (status, _, page) = app.send(Method::GET, "/docs/", "", &[], None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.as_str().unwrap().contains("swagger-ui"));
+}
@@ -0,0 +1,100 @@
+mod common;
+
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use common::TestApp;
+use serde_json::json;
+use user_manager_api::{
+    rotation::models::run,
+    utils::ssh::{KeyType, SSHKeyPair},
+};
+
+#[tokio::test]
+async fn test_run_now_rotates_old_keys() {
+    let app = TestApp::new();
+    let acme = app.create_client("acme").await;
+    let globex = app.create_client("globex").await;
+    let (_, generated) = app
+        .post(
+            &format!("/clients/{}/sftp", acme),
+            json!({
+                "username": "acme",
+                "bucket_name": "acme-bucket",
+                "aws_role_arn": "arn:aws:iam::123456789012:role/sftp",
+                "allowed_source_ips": null,
+            }),
+        )
+        .await;
+    let uploaded = SSHKeyPair::generate(KeyType::Ed25519).unwrap().public_key;
+    let (_, byo) = app
+        .post(
+            &format!("/clients/{}/sftp", globex),
+            json!({
+                "username": "globex",
+                "bucket_name": "globex-bucket",
+                "aws_role_arn": "arn:aws:iam::123456789012:role/sftp",
+                "allowed_source_ips": null,
+                "public_keys": [uploaded],
+            }),
+        )
+        .await;
+    let generated_etag = app.etag(&format!("/sftp/{}", generated["id"])).await;
+    let byo_etag = app.etag(&format!("/sftp/{}", byo["id"])).await;
+
+    let (status, report) = app.post("/key-rotation/run", json!({})).await;
+    assert_eq!(status, StatusCode::OK, "{}", report);
+    assert_eq!(report["rotated_accounts"], json!([generated["id"]]));
+    assert_eq!(
+        report["expiring_keys"],
+        json!([byo["public_keys"][0]["id"]])
+    );
+
+    // The generated key is replaced like on a reset, and keeps working until it expires.
+    let (_, keys) = app
+        .get(&format!("/sftp/{}/public-keys", generated["id"]))
+        .await;
+    assert_eq!(keys.as_array().unwrap().len(), 2);
+    assert_eq!(keys[0]["public_key"], generated["public_key"]);
+    assert!(keys[0]["expires_at"].is_string());
+    assert_eq!(keys[1]["source"], "generated");
+    assert!(keys[1]["expires_at"].is_null());
+
+    // The uploaded key still works until it expires, and no key pair is generated for it.
+    let (_, keys) = app.get(&format!("/sftp/{}/public-keys", byo["id"])).await;
+    assert_eq!(keys.as_array().unwrap().len(), 1);
+    assert_eq!(keys[0]["public_key"], uploaded);
+    assert!(keys[0]["expires_at"].is_string());
+
+    // Both accounts changed, so their ETags did too.
+    assert_ne!(
+        app.etag(&format!("/sftp/{}", generated["id"])).await,
+        generated_etag
+    );
+    assert_ne!(app.etag(&format!("/sftp/{}", byo["id"])).await, byo_etag);
+
+    // Keys that already expire aren't picked up again. The test config rotates keys of any
+    // age, so only the key generated by the first sweep is.
+    let (_, report) = app.post("/key-rotation/run", json!({})).await;
+    assert_eq!(report["rotated_accounts"], json!([generated["id"]]));
+    assert_eq!(report["expiring_keys"], json!([]));
+    let (_, keys) = app.get(&format!("/sftp/{}/public-keys", byo["id"])).await;
+    assert_eq!(keys.as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_background_job_stops_on_shutdown() {
+    let app = TestApp::new();
+    let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+    let job = tokio::spawn(run(app.repo.clone(), TestApp::key_rotation(), async move {
+        let _ = shutdown_rx.await;
+    }));
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!job.is_finished());
+    shutdown_tx.send(()).unwrap();
+    tokio::time::timeout(Duration::from_secs(5), job)
+        .await
+        .expect("the job didn't stop")
+        .unwrap();
+}
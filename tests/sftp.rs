@@ -0,0 +1,673 @@
+mod common;
+
+use axum::http::{
+    header::{ETAG, IF_MATCH, LOCATION},
+    Method, StatusCode,
+};
+use common::{TestApp, ADMIN};
+use serde_json::json;
+use user_manager_api::clients::models::{Client, ClientRepo};
+use user_manager_api::errors::models::AppError;
+use user_manager_api::sftp::models::{NewSftp, Rotation, SftpRepo, SftpUpdate};
+use user_manager_api::utils::etag::IfMatch;
+use user_manager_api::utils::ssh::{KeyType, SSHKeyPair};
+
+#[tokio::test]
+async fn test_sftp_accounts() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+
+    let (status, created) = app
+        .post(
+            &format!("/clients/{}/sftp", client_id),
+            json!({
+                "username": "acme",
+                "bucket_name": "acme-bucket",
+                "aws_role_arn": "arn:aws:iam::123456789012:role/sftp",
+                "allowed_source_ips": ["10.0.0.0/8"],
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", created);
+    let sftp_id = created["id"].as_i64().unwrap();
+    let public_key = created["public_key"].as_str().unwrap().to_string();
+    assert!(!created["private_key"].as_str().unwrap().is_empty());
+
+    let (_, identity) = app.get("/sftp/identity/acme?source_ip=10.1.2.3").await;
+    assert_eq!(identity["PublicKeys"], json!([public_key]));
+    let (status, _) = app.get("/sftp/identity/acme?source_ip=192.0.2.1").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = app
+        .put(
+            &format!("/sftp/{}", sftp_id),
+            Some(json!({
+                "username": null,
+                "bucket_name": "new-bucket",
+                "aws_role_arn": null,
+                "allowed_source_ips": null,
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, sftp) = app.get(&format!("/sftp/{}", sftp_id)).await;
+    assert_eq!(sftp["bucket_name"], "new-bucket");
+
+    let (status, _) = app
+        .put(&format!("/clients/{}/reset-sftp-keys", client_id), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, identity) = app.get("/sftp/identity/acme?source_ip=10.1.2.3").await;
+    assert_ne!(identity["PublicKeys"], json!([public_key]));
+
+    let (_, page) = app.get(&format!("/sftp?client_id={}", client_id)).await;
+    assert_eq!(page["total"], 1);
+
+    let (status, _) = app.delete(&format!("/sftp/{}", sftp_id)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.get("/sftp/identity/acme").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app
+        .put(&format!("/clients/{}/reset-sftp-keys", client_id), None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_sftp_validation() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+
+    let (status, _) = app
+        .post(
+            &format!("/clients/{}/sftp", client_id),
+            json!({
+                "username": "acme",
+                "bucket_name": "acme-bucket",
+                "aws_role_arn": "arn:aws:iam::123456789012:role/sftp",
+                "allowed_source_ips": ["not-an-ip"],
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let (status, _) = app
+        .post(
+            "/clients/42/sftp",
+            json!({
+                "username": "acme",
+                "bucket_name": "acme-bucket",
+                "aws_role_arn": "arn:aws:iam::123456789012:role/sftp",
+                "allowed_source_ips": null,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = app.get("/sftp/1").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+async fn create_sftp(app: &TestApp, client_id: i64, username: &str) -> i64 {
+    let (status, created) = app
+        .post(
+            &format!("/clients/{}/sftp", client_id),
+            json!({
+                "username": username,
+                "bucket_name": format!("{}-bucket", username),
+                "aws_role_arn": "arn:aws:iam::123456789012:role/sftp",
+                "allowed_source_ips": null,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", created);
+    created["id"].as_i64().unwrap()
+}
+
+#[tokio::test]
+async fn test_sftp_of_a_deleted_client_is_hidden() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+    let sftp_id = create_sftp(&app, client_id, "acme").await;
+    let uri = format!("/sftp/{}", sftp_id);
+
+    app.delete(&format!("/clients/{}", client_id)).await;
+    let (status, _) = app.get(&uri).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.delete(&uri).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    app.post(&format!("/clients/{}/restore", client_id), json!(null))
+        .await;
+    let (status, sftp) = app.get(&uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(sftp["username"], "acme");
+}
+
+#[tokio::test]
+async fn test_sftp_routes_under_a_client() {
+    let app = TestApp::new();
+    let acme = app.create_client("acme").await;
+    let globex = app.create_client("globex").await;
+    let upload = create_sftp(&app, acme, "acme-upload").await;
+    let download = create_sftp(&app, acme, "acme-download").await;
+    let other = create_sftp(&app, globex, "globex").await;
+
+    let (status, page) = app
+        .get(&format!("/clients/{}/sftp?sort=username", acme))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["total"], 2);
+    assert_eq!(page["items"][0]["id"], download);
+    let (status, _) = app.get("/clients/42/sftp").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, sftp) = app.get(&format!("/clients/{}/sftp/{}", acme, upload)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(sftp["username"], "acme-upload");
+
+    // Another client's account can't be reached through this client's path.
+    let uri = format!("/clients/{}/sftp/{}", acme, other);
+    assert_eq!(app.get(&uri).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(app.delete(&uri).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(
+        app.put(&format!("{}/reset-keys", uri), None).await.0,
+        StatusCode::NOT_FOUND
+    );
+
+    // Resetting one account leaves the client's other accounts alone.
+    let (_, before) = app.get("/sftp/identity/acme-download").await;
+    let (status, reset) = app
+        .put(
+            &format!(
+                "/clients/{}/sftp/{}/reset-keys?grace_period_hours=0",
+                acme, upload
+            ),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!reset["private_key"].as_str().unwrap().is_empty());
+    let (_, identity) = app.get("/sftp/identity/acme-upload").await;
+    assert_eq!(identity["PublicKeys"], json!([reset["public_key"]]));
+    let (_, after) = app.get("/sftp/identity/acme-download").await;
+    assert_eq!(before, after);
+
+    let (status, _) = app
+        .delete(&format!("/clients/{}/sftp/{}", acme, upload))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, page) = app.get(&format!("/clients/{}/sftp", acme)).await;
+    assert_eq!(page["total"], 1);
+    let (status, _) = app.get(&format!("/sftp/{}", other)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_sftp_key_types() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+    let sftp_id = create_sftp(&app, client_id, "acme").await;
+
+    let (_, sftp) = app.get(&format!("/sftp/{}", sftp_id)).await;
+    assert_eq!(sftp["key_type"], "ssh-ed25519");
+
+    let uri = format!("/clients/{}/sftp/{}/reset-keys", client_id, sftp_id);
+    let (status, reset) = app
+        .put(&format!("{}?key_type=ecdsa-sha2-nistp256", uri), None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", reset);
+    assert_eq!(reset["key_type"], "ecdsa-sha2-nistp256");
+    assert!(reset["public_key"]
+        .as_str()
+        .unwrap()
+        .starts_with("ecdsa-sha2-nistp256 "));
+    assert!(!reset["private_key_openssh"].as_str().unwrap().is_empty());
+    let (_, sftp) = app.get(&format!("/sftp/{}", sftp_id)).await;
+    assert_eq!(sftp["key_type"], "ecdsa-sha2-nistp256");
+
+    // New 2048-bit RSA keys are no longer handed out.
+    let (status, _) = app.put(&format!("{}?key_type=rsa-2048", uri), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = app
+        .put(
+            &format!("/clients/{}/reset-sftp-keys?key_type=dsa", client_id),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+fn public_key() -> String {
+    SSHKeyPair::generate(KeyType::Ed25519).unwrap().public_key
+}
+
+#[tokio::test]
+async fn test_sftp_uploaded_public_keys() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+    let laptop = format!("{} laptop", public_key());
+
+    let (status, created) = app
+        .post(
+            &format!("/clients/{}/sftp", client_id),
+            json!({
+                "username": "acme",
+                "bucket_name": "acme-bucket",
+                "aws_role_arn": "arn:aws:iam::123456789012:role/sftp",
+                "allowed_source_ips": null,
+                "public_keys": [laptop],
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", created);
+    let sftp_id = created["id"].as_i64().unwrap();
+    // Nothing private is generated or returned.
+    assert!(created.get("private_key").is_none());
+    assert_eq!(created["public_keys"][0]["key_type"], "ssh-ed25519");
+    assert!(created["public_keys"][0]["fingerprint"]
+        .as_str()
+        .unwrap()
+        .starts_with("SHA256:"));
+    let (_, identity) = app.get("/sftp/identity/acme").await;
+    assert_eq!(identity["PublicKeys"], json!([laptop]));
+
+    // Uploading replaces the account's keys.
+    let desktop = public_key();
+    let uri = format!("/sftp/{}/public-keys", sftp_id);
+    let (status, keys) = app
+        .put(&uri, Some(json!({ "public_keys": [desktop] })))
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", keys);
+    assert_eq!(keys.as_array().unwrap().len(), 1);
+    let (_, identity) = app.get("/sftp/identity/acme").await;
+    assert_eq!(identity["PublicKeys"], json!([desktop]));
+
+    // Weak, malformed and repeated keys are rejected and leave the keys alone.
+    let rsa_1024 = "ssh-rsa AAAAB3NzaC1yc2EAAAADAQABAAAAgQDHqwW0ygCOxu7G3JbDU+nyrhPvLxqN05av5BtOPHteEXVIaZMII+M9JLHEWgziz008YzaB3S9wdl9sC3sl/6XF9U20II5PzGr6NxD0AV2MahfoYKfzbKnSWcNJSag75YqJKE5ECO4tpbM84hlTkhErh7U+CM0kJTD1YFaSsndyiQ== rsa-1024";
+    for (public_keys, expected) in [
+        (json!([rsa_1024]), StatusCode::UNPROCESSABLE_ENTITY),
+        (
+            json!(["ssh-ed25519 garbage"]),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+        (
+            json!([desktop, format!("{} again", desktop)]),
+            StatusCode::BAD_REQUEST,
+        ),
+        (json!([]), StatusCode::UNPROCESSABLE_ENTITY),
+    ] {
+        let (status, _) = app
+            .put(&uri, Some(json!({ "public_keys": public_keys })))
+            .await;
+        assert_eq!(status, expected, "{}", public_keys);
+    }
+    let (_, identity) = app.get("/sftp/identity/acme").await;
+    assert_eq!(identity["PublicKeys"], json!([desktop]));
+
+    let (status, _) = app
+        .put(
+            "/sftp/42/public-keys",
+            Some(json!({ "public_keys": [desktop] })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_client_key_reset_skips_uploaded_keys() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+    let first = create_sftp(&app, client_id, "acme").await;
+    let second = create_sftp(&app, client_id, "acme2").await;
+    let laptop = public_key();
+    let (status, _) = app
+        .post(
+            &format!("/clients/{}/sftp", client_id),
+            json!({
+                "username": "acme3",
+                "bucket_name": "acme-bucket",
+                "aws_role_arn": "arn:aws:iam::123456789012:role/sftp",
+                "public_keys": [laptop],
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, reset) = app
+        .put(&format!("/clients/{}/reset-sftp-keys", client_id), None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", reset);
+    let reset = reset.as_array().unwrap();
+    let ids: Vec<i64> = reset
+        .iter()
+        .map(|sftp| sftp["id"].as_i64().unwrap())
+        .collect();
+    assert_eq!(ids, [first, second]);
+    // Each account gets a key pair of its own, returned this once.
+    assert_ne!(reset[0]["public_key"], reset[1]["public_key"]);
+    assert!(!reset[0]["private_key"].as_str().unwrap().is_empty());
+    let (_, identity) = app.get("/sftp/identity/acme2").await;
+    assert!(identity["PublicKeys"]
+        .as_array()
+        .unwrap()
+        .contains(&reset[1]["public_key"]));
+
+    let (_, identity) = app.get("/sftp/identity/acme3").await;
+    assert_eq!(identity["PublicKeys"], json!([laptop]));
+}
+
+#[tokio::test]
+async fn test_sftp_key_rotation_overlap() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+    let sftp_id = create_sftp(&app, client_id, "acme").await;
+    let (_, identity) = app.get("/sftp/identity/acme").await;
+    let old_key = identity["PublicKeys"][0].clone();
+
+    // The replaced key keeps working through the grace period.
+    let uri = format!("/clients/{}/sftp/{}/reset-keys", client_id, sftp_id);
+    let (status, reset) = app.put(&uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{}", reset);
+    let (_, identity) = app.get("/sftp/identity/acme").await;
+    assert_eq!(
+        identity["PublicKeys"],
+        json!([old_key, reset["public_key"]])
+    );
+
+    let (status, keys) = app.get(&format!("/sftp/{}/public-keys", sftp_id)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(keys[0]["public_key"], old_key);
+    assert!(keys[0]["expires_at"].is_string());
+    assert_eq!(keys[1]["fingerprint"], reset["fingerprint"]);
+    assert_eq!(keys[1]["source"], "generated");
+    assert!(keys[1]["expires_at"].is_null());
+
+    // Without a grace period every earlier key stops working at once.
+    let (_, reset) = app
+        .put(&format!("{}?grace_period_hours=0", uri), None)
+        .await;
+    let (_, identity) = app.get("/sftp/identity/acme").await;
+    assert_eq!(identity["PublicKeys"], json!([reset["public_key"]]));
+
+    // Uploaded keys aren't touched by rotations.
+    let uploaded = public_key();
+    app.put(
+        &format!("/sftp/{}/public-keys", sftp_id),
+        Some(json!({ "public_keys": [uploaded] })),
+    )
+    .await;
+    app.put(
+        &format!(
+            "/clients/{}/reset-sftp-keys?grace_period_hours=0",
+            client_id
+        ),
+        None,
+    )
+    .await;
+    let (_, keys) = app.get(&format!("/sftp/{}/public-keys", sftp_id)).await;
+    assert_eq!(keys.as_array().unwrap().len(), 2);
+    assert_eq!(keys[0]["public_key"], uploaded);
+
+    let (status, _) = app
+        .put(&format!("{}?grace_period_hours=100000", uri), None)
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_if_match_guards_sftp_changes() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+    let (_, created) = app
+        .post(
+            &format!("/clients/{}/sftp", client_id),
+            json!({
+                "username": "acme",
+                "bucket_name": "acme-bucket",
+                "aws_role_arn": "arn:aws:iam::123456789012:role/sftp",
+            }),
+        )
+        .await;
+    let uri = format!("/sftp/{}", created["id"]);
+    let etag = app.etag(&uri).await;
+    let update = json!({"bucket_name": "new-bucket"});
+
+    let (status, _, _) = app
+        .send(
+            Method::PUT,
+            &uri,
+            ADMIN,
+            &[(IF_MATCH, &etag)],
+            Some(update.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = app
+        .send(Method::PUT, &uri, ADMIN, &[(IF_MATCH, &etag)], Some(update))
+        .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+
+    let etag = app
+        .etag(&format!("/clients/{}/sftp/{}", client_id, created["id"]))
+        .await;
+    assert_eq!(etag, "\"2\"");
+    let reset = format!("/clients/{}/sftp/{}/reset-keys", client_id, created["id"]);
+    let (status, _, _) = app
+        .send(Method::PUT, &reset, ADMIN, &[(IF_MATCH, "\"1\"")], None)
+        .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    let (status, _, _) = app
+        .send(Method::PUT, &reset, ADMIN, &[(IF_MATCH, &etag)], None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _, _) = app
+        .send(Method::DELETE, &uri, ADMIN, &[(IF_MATCH, &etag)], None)
+        .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    let etag = app.etag(&uri).await;
+    assert_eq!(etag, "\"3\"");
+    let (status, _, _) = app
+        .send(Method::DELETE, &uri, ADMIN, &[(IF_MATCH, &etag)], None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_if_match_guards_key_uploads_and_client_resets() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+    let sftp_id = create_sftp(&app, client_id, "acme").await;
+    let uri = format!("/sftp/{}", sftp_id);
+    let etag = app.etag(&uri).await;
+
+    let upload = format!("/sftp/{}/public-keys", sftp_id);
+    let body = json!({ "public_keys": [public_key()] });
+    let (status, _, _) = app
+        .send(
+            Method::PUT,
+            &upload,
+            ADMIN,
+            &[(IF_MATCH, &etag)],
+            Some(body.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = app
+        .send(
+            Method::PUT,
+            &upload,
+            ADMIN,
+            &[(IF_MATCH, &etag)],
+            Some(body),
+        )
+        .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    let etag = app.etag(&uri).await;
+    assert_eq!(etag, "\"2\"");
+
+    let client = format!("/clients/{}", client_id);
+    let client_etag = app.etag(&client).await;
+    let reset = format!("/clients/{}/reset-sftp-keys", client_id);
+    let (status, _, _) = app
+        .send(Method::PUT, &reset, ADMIN, &[(IF_MATCH, "\"7\"")], None)
+        .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    let (status, _, _) = app
+        .send(
+            Method::PUT,
+            &reset,
+            ADMIN,
+            &[(IF_MATCH, &client_etag)],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(app.etag(&client).await, client_etag);
+    assert_ne!(app.etag(&uri).await, etag);
+}
+
+/// Deletes a client behind the handlers' back, as if it happened between their access
+/// check and the change, and tries to change its account.
+async fn accounts_of_deleted_clients_are_not_changed<T: ClientRepo + SftpRepo>(repo: &T) {
+    let name = format!("acme-{}", rand::random::<u32>());
+    let client_id = repo
+        .create(Client {
+            id: None,
+            name: name.clone(),
+            email: format!("{}@example.com", name),
+            bucket: format!("{}-bucket", name),
+            deleted_at: None,
+            version: 0,
+        })
+        .await
+        .unwrap();
+    let update = SftpUpdate {
+        username: Some(name.clone()),
+        bucket_name: Some(format!("{}-bucket", name)),
+        aws_role_arn: Some("arn:aws:iam::123456789012:role/sftp".to_string()),
+        allowed_source_ips: None,
+    };
+    let new_sftp = NewSftp {
+        sftp: update.clone(),
+        public_keys: None,
+    };
+    let sftp_id = repo
+        .add_sftp(client_id, new_sftp, KeyType::Ed25519)
+        .await
+        .unwrap()
+        .id;
+    assert!(!repo.get_public_keys(sftp_id).await.unwrap().is_empty());
+    ClientRepo::delete(repo, client_id, IfMatch::any())
+        .await
+        .unwrap();
+
+    let rotation = Rotation {
+        key_type: KeyType::Ed25519,
+        grace_period_hours: 0,
+    };
+    let results = [
+        SftpRepo::update(repo, sftp_id, update, IfMatch::any()).await,
+        SftpRepo::reset_keys(repo, sftp_id, rotation, IfMatch::any())
+            .await
+            .map(|_| ()),
+        repo.set_public_keys(sftp_id, vec![public_key()], IfMatch::any())
+            .await
+            .map(|_| ()),
+        SftpRepo::delete(repo, sftp_id, IfMatch::any()).await,
+    ];
+    for result in results {
+        assert!(matches!(result, Err(AppError::NotFound(_))), "{:?}", result);
+    }
+    assert!(repo.get_public_keys(sftp_id).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_accounts_of_deleted_clients_are_not_changed() {
+    accounts_of_deleted_clients_are_not_changed(&TestApp::new().repo).await;
+}
+
+#[tokio::test]
+#[ignore = "needs a migrated Postgres database"]
+async fn test_accounts_of_deleted_clients_are_not_changed_in_postgres() {
+    accounts_of_deleted_clients_are_not_changed(&common::postgres().await).await;
+}
+
+#[tokio::test]
+async fn test_taken_username_is_a_conflict() {
+    let app = TestApp::new();
+    let acme = app.create_client("acme").await;
+    let globex = app.create_client("globex").await;
+    create_sftp(&app, acme, "shared").await;
+
+    let (status, error) = app
+        .post(
+            &format!("/clients/{}/sftp", globex),
+            json!({
+                "username": "shared",
+                "bucket_name": "globex-bucket",
+                "aws_role_arn": "arn:aws:iam::123456789012:role/sftp",
+                "allowed_source_ips": null,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(error["code"], "conflict");
+    assert_eq!(
+        error["details"],
+        json!([{"field": "username", "message": "already exists"}])
+    );
+}
+
+#[tokio::test]
+async fn test_v2_status_codes() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+
+    let (status, headers, created) = app
+        .send(
+            Method::POST,
+            &format!("/v2/clients/{}/sftp", client_id),
+            ADMIN,
+            &[],
+            Some(json!({
+                "username": "acme",
+                "bucket_name": "acme-bucket",
+                "aws_role_arn": "arn:aws:iam::123456789012:role/sftp",
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let id = created["id"].as_i64().unwrap();
+    assert_eq!(headers[LOCATION], format!("/v2/sftp/{}", id));
+    assert!(!created["private_key"].as_str().unwrap().is_empty());
+
+    let (status, headers, sftp) = app
+        .send(
+            Method::PUT,
+            &format!("/v2/sftp/{}", id),
+            ADMIN,
+            &[],
+            Some(json!({"bucket_name": "new-bucket"})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[ETAG], "\"2\"");
+    assert_eq!(sftp["bucket_name"], "new-bucket");
+
+    let (status, _) = app
+        .request(
+            Method::PUT,
+            &format!("/v2/clients/{}/reset-sftp-keys", client_id),
+            ADMIN,
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = app
+        .request(Method::DELETE, &format!("/v2/sftp/{}", id), ADMIN, None)
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
@@ -0,0 +1,602 @@
+mod common;
+
+use axum::http::{
+    header::{ETAG, IF_MATCH, LOCATION},
+    Method, StatusCode,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use common::{
+    sftp_server::{SftpServer, FILES, HOME, PASSWORD, USERNAME},
+    TestApp, ADMIN,
+};
+use serde_json::{json, Value};
+use user_manager_api::utils::list::{Cursor, CursorValue, ListQuery, Sort};
+use user_manager_api::utils::ssh::{KeyType, SSHKeyPair};
+use user_manager_api::vendors::models::{VendorFilter, VendorRepo};
+
+#[tokio::test]
+async fn test_vendor_crud() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+    let vendor_id = app.create_vendor(client_id, "bank").await;
+
+    let (status, vendor) = app.get(&format!("/vendors/{}", vendor_id)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(vendor["name"], "bank");
+    assert_eq!(vendor["client_id"], client_id);
+    // The overview never includes credentials.
+    assert!(vendor.get("password").is_none());
+
+    let update = json!({
+        "id": null,
+        "client_id": client_id,
+        "name": "bank2",
+        "host": "sftp.bank2.example.com",
+        "port": 2222,
+        "username": null,
+        "password": null,
+        "ssh_key": null,
+        "ssh_key_password": null,
+    });
+    let (status, _) = app
+        .put(&format!("/vendors/{}", vendor_id), Some(update.clone()))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, vendor) = app.get(&format!("/vendors/{}", vendor_id)).await;
+    assert_eq!(vendor["port"], 2222);
+
+    // Updating through the client checks the vendor belongs to it.
+    let other_id = app.create_client("other").await;
+    let (status, _) = app
+        .put(
+            &format!("/clients/{}/vendor/{}", other_id, vendor_id),
+            Some(update),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = app.delete(&format!("/vendors/{}", vendor_id)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.delete(&format!("/vendors/{}", vendor_id)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_vendor_of_a_deleted_client_is_hidden() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+    let vendor_id = app.create_vendor(client_id, "bank").await;
+    let uri = format!("/vendors/{}", vendor_id);
+    let update = json!({
+        "id": null,
+        "client_id": client_id,
+        "name": "bank2",
+        "host": "sftp.bank2.example.com",
+        "port": 22,
+        "username": null,
+        "password": null,
+        "ssh_key": null,
+        "ssh_key_password": null,
+    });
+
+    app.delete(&format!("/clients/{}", client_id)).await;
+    let (status, _) = app.get(&uri).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.put(&uri, Some(update.clone())).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app
+        .put(
+            &format!("/clients/{}/vendor/{}", client_id, vendor_id),
+            Some(update),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.get(&format!("{}/host-keys", uri)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app.delete(&uri).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    app.post(&format!("/clients/{}/restore", client_id), json!(null))
+        .await;
+    let (status, vendor) = app.get(&uri).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(vendor["name"], "bank");
+}
+
+#[tokio::test]
+async fn test_adding_a_vendor_to_a_missing_client() {
+    let app = TestApp::new();
+    let (status, _) = app
+        .post(
+            "/clients/42/vendor",
+            json!({
+                "id": null,
+                "client_id": 42,
+                "name": "bank",
+                "host": "sftp.bank.example.com",
+                "port": 22,
+                "username": null,
+                "password": null,
+                "ssh_key": null,
+                "ssh_key_password": null,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_hostile_name_filter_matches_nothing() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+    app.create_vendor(client_id, "bank").await;
+
+    let (status, page) = app.get("/vendors?name=%27%20OR%20%271%27%3D%271").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["total"], 0);
+
+    let (_, page) = app.get(&format!("/vendors?client_id={}", client_id)).await;
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["items"][0]["password"], "hunter2");
+}
+
+#[tokio::test]
+#[ignore = "needs a migrated Postgres database"]
+async fn test_hostile_list_params_against_postgres() {
+    let repo = common::postgres().await;
+    let all = repo
+        .get_all(ListQuery::default(), None)
+        .await
+        .unwrap()
+        .total;
+
+    let hostile = "x' OR '1'='1'; DROP TABLE vendors; --";
+    let cursor = Cursor {
+        sort: Sort {
+            field: "name".to_string(),
+            descending: false,
+        },
+        value: CursorValue::Text(hostile.to_string()),
+        id: 0,
+    };
+    let query = ListQuery::<VendorFilter>::from_query_string(&format!(
+        "sort=name&host__icontains=%27%29%20OR%20TRUE%20--&name__in=a%27,b&cursor={}",
+        cursor.encode()
+    ))
+    .unwrap();
+    let page = repo.get_all(query, Some(vec![-1])).await.unwrap();
+    assert_eq!(page.total, 0);
+
+    // Nothing was dropped or matched by the injected conditions.
+    let query =
+        ListQuery::<VendorFilter>::from_query_string("name=%27%20OR%20%271%27%3D%271").unwrap();
+    assert_eq!(repo.get_all(query, None).await.unwrap().total, 0);
+    let page = repo.get_all(ListQuery::default(), None).await.unwrap();
+    assert_eq!(page.total, all);
+}
+
+/// Points a vendor at the local SFTP server with the given credentials.
+async fn point_at(
+    app: &TestApp,
+    client_id: i64,
+    vendor_id: i64,
+    server: &SftpServer,
+    password: Option<&str>,
+    ssh_key: Option<String>,
+) {
+    let (status, _) = app
+        .put(
+            &format!("/vendors/{}", vendor_id),
+            Some(json!({
+                "id": null,
+                "client_id": client_id,
+                "name": "bank",
+                "host": "127.0.0.1",
+                "port": server.port,
+                "username": USERNAME,
+                "password": password,
+                "ssh_key": ssh_key,
+                "ssh_key_password": null,
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+async fn test_connection(app: &TestApp, vendor_id: i64) -> Value {
+    let (status, report) = app
+        .post(
+            &format!("/vendors/{}/test-connection?timeout_seconds=5", vendor_id),
+            json!({}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", report);
+    report
+}
+
+#[tokio::test]
+async fn test_connection_with_password() {
+    let app = TestApp::new();
+    let server = SftpServer::start(None).await;
+    let client_id = app.create_client("acme").await;
+    let vendor_id = app.create_vendor(client_id, "bank").await;
+    point_at(&app, client_id, vendor_id, &server, Some(PASSWORD), None).await;
+
+    let report = test_connection(&app, vendor_id).await;
+    assert_eq!(report["ok"], true, "{}", report);
+    assert_eq!(report["reachable"], true);
+    assert_eq!(report["host_key"]["key_type"], "ssh-ed25519");
+    assert_eq!(report["host_key"]["fingerprint"], server.fingerprint);
+    assert_eq!(report["authenticated"], true);
+    assert_eq!(report["auth_method"], "password");
+    assert_eq!(report["directory"], HOME);
+    assert_eq!(report["entries"], json!(FILES));
+    assert!(report["error"].is_null());
+}
+
+#[tokio::test]
+async fn test_connection_with_ssh_key() {
+    let app = TestApp::new();
+    let keys = SSHKeyPair::generate(KeyType::Ed25519).unwrap();
+    let authorized_key = keys.public_key.split(' ').nth(1).unwrap().to_string();
+    let server = SftpServer::start(Some(authorized_key)).await;
+    let ssh_key = String::from_utf8(STANDARD.decode(&keys.private_key_openssh).unwrap()).unwrap();
+    let client_id = app.create_client("acme").await;
+    let vendor_id = app.create_vendor(client_id, "bank").await;
+    point_at(
+        &app,
+        client_id,
+        vendor_id,
+        &server,
+        Some("wrong"),
+        Some(ssh_key),
+    )
+    .await;
+
+    let report = test_connection(&app, vendor_id).await;
+    assert_eq!(report["ok"], true, "{}", report);
+    assert_eq!(report["auth_method"], "publickey");
+}
+
+#[tokio::test]
+async fn test_connection_with_wrong_password() {
+    let app = TestApp::new();
+    let server = SftpServer::start(None).await;
+    let client_id = app.create_client("acme").await;
+    let vendor_id = app.create_vendor(client_id, "bank").await;
+    point_at(&app, client_id, vendor_id, &server, Some("wrong"), None).await;
+
+    let report = test_connection(&app, vendor_id).await;
+    assert_eq!(report["ok"], false);
+    assert_eq!(report["reachable"], true);
+    assert_eq!(report["host_key"]["fingerprint"], server.fingerprint);
+    assert_eq!(report["authenticated"], false);
+    assert_eq!(report["entries"], json!([]));
+    assert!(report["error"].as_str().unwrap().contains("rejected"));
+
+    let (status, _) = app.post("/vendors/999/test-connection", json!({})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = app
+        .post(
+            &format!("/vendors/{}/test-connection?timeout_seconds=600", vendor_id),
+            json!({}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_captured_host_key_is_enforced_once_approved() {
+    let app = TestApp::new();
+    let server = SftpServer::start(None).await;
+    let client_id = app.create_client("acme").await;
+    let vendor_id = app.create_vendor(client_id, "bank").await;
+    point_at(&app, client_id, vendor_id, &server, Some(PASSWORD), None).await;
+
+    let capture = format!("/vendors/{}/host-keys/capture?timeout_seconds=5", vendor_id);
+    let (status, captured) = app.post(&capture, json!({})).await;
+    assert_eq!(status, StatusCode::OK, "{}", captured);
+    assert_eq!(captured["fingerprint"], server.fingerprint);
+    assert_eq!(captured["source"], "probe");
+    assert!(captured["approved_at"].is_null());
+
+    // A pending key isn't enforced yet.
+    let report = test_connection(&app, vendor_id).await;
+    assert_eq!(report["ok"], true, "{}", report);
+    assert_eq!(report["host_key_pinned"], false);
+
+    let (status, approved) = app
+        .put(
+            &format!(
+                "/vendors/{}/host-keys/{}/approve",
+                vendor_id, captured["id"]
+            ),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(approved["approved_at"].is_string());
+    let report = test_connection(&app, vendor_id).await;
+    assert_eq!(report["ok"], true, "{}", report);
+    assert_eq!(report["host_key_pinned"], true);
+
+    // Capturing the same key again keeps the approved pin.
+    let (_, recaptured) = app.post(&capture, json!({})).await;
+    assert_eq!(recaptured, approved);
+    let (_, host_keys) = app.get(&format!("/vendors/{}/host-keys", vendor_id)).await;
+    assert_eq!(host_keys, json!([approved]));
+
+    let (status, _) = app
+        .delete(&format!(
+            "/vendors/{}/host-keys/{}",
+            vendor_id, captured["id"]
+        ))
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, host_keys) = app.get(&format!("/vendors/{}/host-keys", vendor_id)).await;
+    assert_eq!(host_keys, json!([]));
+}
+
+#[tokio::test]
+async fn test_capturing_from_an_unreachable_server() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+    let vendor_id = app.create_vendor(client_id, "bank").await;
+    // A port that was just free, so nothing is listening on it.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let server = SftpServer {
+        port: listener.local_addr().unwrap().port(),
+        fingerprint: String::new(),
+    };
+    drop(listener);
+    point_at(&app, client_id, vendor_id, &server, Some(PASSWORD), None).await;
+
+    let (status, body) = app
+        .post(
+            &format!("/vendors/{}/host-keys/capture?timeout_seconds=5", vendor_id),
+            json!({}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY, "{}", body);
+    assert_eq!(body["code"], "upstream_error");
+}
+
+#[tokio::test]
+async fn test_host_key_mismatch() {
+    let app = TestApp::new();
+    let server = SftpServer::start(None).await;
+    let client_id = app.create_client("acme").await;
+    let vendor_id = app.create_vendor(client_id, "bank").await;
+    point_at(&app, client_id, vendor_id, &server, Some(PASSWORD), None).await;
+    let host_keys = format!("/vendors/{}/host-keys", vendor_id);
+
+    let (status, pinned) = app
+        .post(
+            &host_keys,
+            json!({"public_key": "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIJdD7y3aLq454yWBdwLWbieU1ebz9/cu7/QEXn9OIeZJ old-server"}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", pinned);
+    assert_eq!(pinned["source"], "manual");
+    assert!(pinned["approved_at"].is_string());
+
+    let (status, headers, pinned) = app
+        .send(
+            Method::POST,
+            &format!("/v2{}", host_keys),
+            ADMIN,
+            &[],
+            Some(json!({"public_key": SSHKeyPair::generate(KeyType::Ed25519).unwrap().public_key})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", pinned);
+    let location = format!("/v2{}/{}", host_keys, pinned["id"]);
+    assert_eq!(headers[LOCATION], location);
+    let (status, fetched) = app.get(&location).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(fetched, pinned);
+
+    let (status, error) = app
+        .post(
+            &format!("/vendors/{}/test-connection?timeout_seconds=5", vendor_id),
+            json!({}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert!(error.to_string().contains(&server.fingerprint), "{}", error);
+
+    // Replacing the pins with the server's key fixes it.
+    let (_, captured) = app.post(&format!("{}/capture", host_keys), json!({})).await;
+    let (status, replaced) = app
+        .put(
+            &host_keys,
+            Some(json!({"public_keys": [captured["public_key"]]})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(replaced.as_array().unwrap().len(), 1);
+    assert_eq!(replaced[0]["fingerprint"], server.fingerprint);
+    let report = test_connection(&app, vendor_id).await;
+    assert_eq!(report["host_key_pinned"], true);
+
+    let (status, _) = app
+        .post(&host_keys, json!({"public_key": "ssh-ed25519 nope"}))
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = app.put(&format!("{}/999/approve", host_keys), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_stale_vendor_edit_is_rejected() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+    let vendor_id = app.create_vendor(client_id, "bank").await;
+    let uri = format!("/vendors/{}", vendor_id);
+    let vendor = |name: &str| {
+        json!({
+            "id": null,
+            "client_id": client_id,
+            "name": name,
+            "host": "sftp.bank.example.com",
+            "port": 22,
+            "username": "upload",
+            "password": "hunter2",
+            "ssh_key": null,
+            "ssh_key_password": null,
+        })
+    };
+
+    // Two operators load the vendor, then both save their edits.
+    let etag = app.etag(&uri).await;
+    let (status, _, _) = app
+        .send(
+            Method::PUT,
+            &uri,
+            ADMIN,
+            &[(IF_MATCH, &etag)],
+            Some(vendor("first")),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = app
+        .send(
+            Method::PUT,
+            &format!("/clients/{}/vendor/{}", client_id, vendor_id),
+            ADMIN,
+            &[(IF_MATCH, &etag)],
+            Some(vendor("second")),
+        )
+        .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+
+    let (_, current) = app.get(&uri).await;
+    assert_eq!(current["name"], "first");
+    assert_eq!(current["version"], 2);
+    let (status, _, _) = app
+        .send(Method::DELETE, &uri, ADMIN, &[(IF_MATCH, &etag)], None)
+        .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+}
+
+#[tokio::test]
+async fn test_moving_a_vendor_to_a_missing_client() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+    let vendor_id = app.create_vendor(client_id, "bank").await;
+
+    let (status, error) = app
+        .put(
+            &format!("/vendors/{}", vendor_id),
+            Some(json!({
+                "id": null,
+                "client_id": 42,
+                "name": "bank",
+                "host": "sftp.bank.example.com",
+                "port": 22,
+                "username": "upload",
+                "password": "hunter2",
+                "ssh_key": null,
+                "ssh_key_password": null,
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error["code"], "invalid_reference");
+    assert_eq!(error["details"][0]["field"], "client_id");
+}
+
+#[tokio::test]
+async fn test_invalid_vendor_is_rejected() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+
+    let (status, error) = app
+        .post(
+            &format!("/clients/{}/vendor", client_id),
+            json!({
+                "id": null,
+                "client_id": client_id,
+                "name": "bank",
+                "host": "sftp bank",
+                "port": -1,
+                "username": "upload",
+                "password": null,
+                "ssh_key": "not a key",
+                "ssh_key_password": null,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let fields: Vec<&str> = error["details"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|detail| detail["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, ["host", "port", "ssh_key"]);
+}
+
+#[tokio::test]
+async fn test_v2_status_codes() {
+    let app = TestApp::new();
+    let client_id = app.create_client("acme").await;
+    let id = app.create_vendor(client_id, "bank").await;
+
+    let (status, headers, vendor) = app
+        .send(
+            Method::PUT,
+            &format!("/v2/vendors/{}", id),
+            ADMIN,
+            &[],
+            Some(json!({
+                "client_id": client_id,
+                "name": "bank",
+                "host": "sftp2.bank.example.com",
+                "port": 2222,
+                "username": "upload",
+                "password": "hunter2",
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers[ETAG], "\"2\"");
+    assert_eq!(vendor["port"], 2222);
+
+    let server = SftpServer::start(None).await;
+    point_at(&app, client_id, id, &server, Some(PASSWORD), None).await;
+    let (status, headers, captured) = app
+        .send(
+            Method::POST,
+            &format!("/v2/vendors/{}/host-keys/capture?timeout_seconds=5", id),
+            ADMIN,
+            &[],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::CREATED, "{}", captured);
+    assert_eq!(
+        headers[LOCATION],
+        format!("/v2/vendors/{}/host-keys/{}", id, captured["id"])
+    );
+    let (status, _) = app
+        .get(&format!(
+            "/vendors/{}/host-keys/{}",
+            id,
+            captured["id"].as_i64().unwrap() + 1
+        ))
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _, body) = app
+        .send(
+            Method::DELETE,
+            &format!("/v2/vendors/{}", id),
+            ADMIN,
+            &[],
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert_eq!(body, Value::Null);
+}
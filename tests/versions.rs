@@ -0,0 +1,112 @@
+mod common;
+
+use axum::http::{
+    header::{LINK, LOCATION},
+    HeaderName, Method, StatusCode,
+};
+use common::{TestApp, ADMIN};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn test_versioned_paths() {
+    let app = TestApp::new();
+    let deprecation = HeaderName::from_static("deprecation");
+
+    let (status, headers, id) = app
+        .send(
+            Method::POST,
+            "/v1/clients",
+            ADMIN,
+            &[],
+            Some(json!({"id": null, "name": "acme", "email": "a@example.com", "bucket": "acme-bucket"})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!headers.contains_key(&deprecation));
+    assert!(!headers.contains_key("sunset"));
+    let id = id.as_i64().unwrap();
+
+    for version in ["v1", "v2"] {
+        let (status, headers, client) = app
+            .send(
+                Method::GET,
+                &format!("/{}/clients/{}", version, id),
+                ADMIN,
+                &[],
+                None,
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{}", version);
+        assert_eq!(client["name"], "acme");
+        assert!(!headers.contains_key(&deprecation));
+    }
+
+    // Scopes are checked on the path within the version.
+    let reader = app.create_key(&["clients:read"], None).await;
+    let (status, _) = app.request(Method::GET, "/v2/clients", &reader, None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app
+        .request(
+            Method::DELETE,
+            &format!("/v1/clients/{}", id),
+            &reader,
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_unversioned_paths_are_deprecated() {
+    let app = TestApp::new();
+    app.create_client("acme").await;
+
+    let (status, headers, page) = app
+        .send(Method::GET, "/clients?name=acme", ADMIN, &[], None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["total"], 1);
+    assert_eq!(headers["deprecation"], "@1792281600");
+    assert_eq!(headers["sunset"], "Sun, 18 Apr 2027 00:00:00 GMT");
+    assert_eq!(
+        headers[LINK],
+        "</v1/clients?name=acme>; rel=\"successor-version\""
+    );
+
+    // Errors are flagged too.
+    let (status, headers, _) = app.send(Method::GET, "/clients/42", ADMIN, &[], None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(headers[LINK], "</v1/clients/42>; rel=\"successor-version\"");
+
+    let (status, _) = app.request(Method::GET, "/v3/clients", ADMIN, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_v1_responses_are_frozen() {
+    let app = TestApp::new();
+    let client = json!({"name": "acme", "email": "a@example.com", "bucket": "acme-bucket"});
+
+    // v1 answers a create with just the id, where v2 answers 201 with the client.
+    let (status, headers, id) = app
+        .send(
+            Method::POST,
+            "/v1/clients",
+            ADMIN,
+            &[],
+            Some(client.clone()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(!headers.contains_key(LOCATION));
+    let id = id.as_i64().unwrap();
+
+    let uri = format!("/v1/clients/{}", id);
+    let (status, body) = app.request(Method::PUT, &uri, ADMIN, Some(client)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, Value::Null);
+
+    let (status, body) = app.request(Method::DELETE, &uri, ADMIN, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, Value::Null);
+}
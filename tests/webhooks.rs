@@ -0,0 +1,257 @@
+mod common;
+
+use std::sync::{
+    atomic::{AtomicU16, Ordering},
+    Arc, Mutex,
+};
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::post,
+    Router,
+};
+use common::TestApp;
+use serde_json::{json, Value};
+use user_manager_api::{
+    config::models::WebhookConfig,
+    webhooks::models::{dispatch, http_client, sign, DispatchReport},
+};
+
+/// A receiver listening on a local port. It records every request and answers with
+/// `status`.
+#[derive(Clone)]
+struct Stub {
+    url: String,
+    status: Arc<AtomicU16>,
+    received: Arc<Mutex<Vec<(HeaderMap, Bytes)>>>,
+}
+
+impl Stub {
+    async fn start() -> Self {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let stub = Stub {
+            url: format!("http://{}/hook", listener.local_addr().unwrap()),
+            status: Arc::new(AtomicU16::new(200)),
+            received: Arc::default(),
+        };
+        let router = Router::new()
+            .route("/hook", post(receive))
+            .with_state(stub.clone());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        stub
+    }
+
+    fn respond_with(&self, status: StatusCode) {
+        self.status.store(status.as_u16(), Ordering::SeqCst);
+    }
+
+    fn received(&self) -> Vec<(HeaderMap, Bytes)> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+async fn receive(State(stub): State<Stub>, headers: HeaderMap, body: Bytes) -> StatusCode {
+    stub.received.lock().unwrap().push((headers, body));
+    StatusCode::from_u16(stub.status.load(Ordering::SeqCst)).unwrap()
+}
+
+fn config() -> WebhookConfig {
+    WebhookConfig {
+        max_attempts: 2,
+        initial_backoff_seconds: 0,
+        ..TestApp::webhooks()
+    }
+}
+
+async fn run_dispatch(app: &TestApp) -> DispatchReport {
+    let config = config();
+    dispatch(&app.repo, &http_client(&config).unwrap(), &config)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_webhook_crud() {
+    let app = TestApp::new();
+
+    let (status, created) = app
+        .post(
+            "/webhooks",
+            json!({"url": "https://example.com/hook", "event_types": ["client.create"]}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{}", created);
+    assert!(created["secret"].as_str().unwrap().len() >= 16);
+    let id = created["id"].as_i64().unwrap();
+
+    // The secret is only returned on create.
+    let (_, webhooks) = app.get("/webhooks").await;
+    assert_eq!(webhooks.as_array().unwrap().len(), 1);
+    assert!(webhooks[0].get("secret").is_none());
+
+    let (status, updated) = app
+        .put(
+            &format!("/webhooks/{}", id),
+            Some(json!({"url": "https://example.com/hook2", "event_types": []})),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(updated["url"], "https://example.com/hook2");
+
+    for body in [
+        json!({"url": "ftp://example.com/hook"}),
+        json!({"url": "https://example.com/hook", "event_types": ["client.renamed"]}),
+        json!({"url": "https://example.com/hook", "secret": "short"}),
+    ] {
+        let (status, _) = app.post("/webhooks", body).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    let (status, _) = app.delete(&format!("/webhooks/{}", id)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.get(&format!("/webhooks/{}", id)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_events_are_signed_and_delivered() {
+    let app = TestApp::new();
+    let stub = Stub::start().await;
+    let secret = "whsec_0123456789abcdef";
+    let (status, _) = app
+        .post(
+            "/webhooks",
+            json!({
+                "url": stub.url,
+                "event_types": ["client.create", "vendor.update"],
+                "secret": secret,
+            }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let client_id = app.create_client("acme").await;
+    let vendor_id = app.create_vendor(client_id, "bank").await;
+    let (status, _) = app
+        .put(
+            &format!("/vendors/{}", vendor_id),
+            Some(json!({
+                "id": null,
+                "client_id": client_id,
+                "name": "bank",
+                "host": "sftp2.bank.example.com",
+                "port": 22,
+                "username": "upload",
+                "password": "hunter2",
+                "ssh_key": null,
+                "ssh_key_password": null,
+            })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let report = run_dispatch(&app).await;
+    assert_eq!(report.delivered, 2);
+    let received = stub.received();
+    assert_eq!(received.len(), 2);
+
+    for (headers, body) in &received {
+        let signature = headers["x-webhook-signature"].to_str().unwrap();
+        let timestamp: i64 = signature
+            .strip_prefix("t=")
+            .and_then(|rest| rest.split(',').next())
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(signature, sign(secret, timestamp, body));
+    }
+
+    let events: Vec<Value> = received
+        .iter()
+        .map(|(_, body)| serde_json::from_slice(body).unwrap())
+        .collect();
+    assert_eq!(events[0]["type"], "client.create");
+    assert_eq!(events[0]["entity_id"], client_id);
+    assert_eq!(events[0]["data"]["name"], "acme");
+    assert_eq!(events[1]["type"], "vendor.update");
+    assert_eq!(
+        events[1]["changes"],
+        json!({"host": {"before": "sftp.bank.example.com", "after": "sftp2.bank.example.com"}})
+    );
+    assert_eq!(events[1]["data"]["password"], "[REDACTED]");
+    assert_eq!(
+        received[1].0["x-webhook-id"].to_str().unwrap(),
+        events[1]["id"].to_string()
+    );
+
+    // Delivered events leave the outbox.
+    assert_eq!(run_dispatch(&app).await, DispatchReport::default());
+}
+
+#[tokio::test]
+async fn test_failed_deliveries_are_retried_then_dead_lettered() {
+    let app = TestApp::new();
+    let stub = Stub::start().await;
+    stub.respond_with(StatusCode::INTERNAL_SERVER_ERROR);
+    let (_, webhook) = app.post("/webhooks", json!({"url": stub.url})).await;
+    let webhook_id = webhook["id"].as_i64().unwrap();
+    app.create_client("acme").await;
+
+    let report = run_dispatch(&app).await;
+    assert_eq!(report.retrying, 1);
+    let report = run_dispatch(&app).await;
+    assert_eq!(report.dead_lettered, 1);
+    assert_eq!(stub.received().len(), 2);
+
+    let (_, dead_letters) = app
+        .get(&format!("/webhooks/{}/dead-letters", webhook_id))
+        .await;
+    assert_eq!(dead_letters.as_array().unwrap().len(), 1);
+    assert_eq!(dead_letters[0]["event_type"], "client.create");
+    assert_eq!(dead_letters[0]["attempts"], 2);
+    assert!(dead_letters[0]["last_error"]
+        .as_str()
+        .unwrap()
+        .contains("500"));
+
+    // Once the receiver is fixed, the dead letter can be sent again.
+    stub.respond_with(StatusCode::NO_CONTENT);
+    let (status, _) = app
+        .post(
+            &format!(
+                "/webhooks/{}/dead-letters/{}/retry",
+                webhook_id, dead_letters[0]["id"]
+            ),
+            json!({}),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let report = run_dispatch(&app).await;
+    assert_eq!(report.delivered, 1);
+    let (_, dead_letters) = app
+        .get(&format!("/webhooks/{}/dead-letters", webhook_id))
+        .await;
+    assert_eq!(dead_letters, json!([]));
+}
+
+#[tokio::test]
+async fn test_internal_urls_are_refused() {
+    let app = TestApp::new();
+    let stub = Stub::start().await;
+    let (status, _) = app.post("/webhooks", json!({"url": stub.url})).await;
+    assert_eq!(status, StatusCode::OK);
+    app.create_client("acme").await;
+
+    // Without allow_private_urls, a stored internal url is never called.
+    let config = WebhookConfig {
+        max_attempts: 1,
+        ..WebhookConfig::default()
+    };
+    let report = dispatch(&app.repo, &http_client(&config).unwrap(), &config)
+        .await
+        .unwrap();
+    assert_eq!(report.dead_lettered, 1);
+    assert!(stub.received().is_empty());
+}